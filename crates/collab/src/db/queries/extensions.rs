@@ -370,10 +370,12 @@ fn metadata_from_extension_and_version(
             repository: version.repository,
             schema_version: Some(version.schema_version),
             wasm_api_version: version.wasm_api_version,
+            tags: Vec::new(),
         },
 
         published_at: convert_time_to_chrono(version.published_at),
         download_count: extension.total_download_count as u64,
+        archive_size: None,
     }
 }
 
@@ -97,9 +97,11 @@ async fn test_extensions(db: &Arc<Database>) {
                     repository: "ext1/repo".into(),
                     schema_version: Some(1),
                     wasm_api_version: None,
+                    tags: Vec::new(),
                 },
                 published_at: t0_chrono,
                 download_count: 0,
+                archive_size: None,
             },
             ExtensionMetadata {
                 id: "ext2".into(),
@@ -111,9 +113,11 @@ async fn test_extensions(db: &Arc<Database>) {
                     repository: "ext2/repo".into(),
                     schema_version: Some(0),
                     wasm_api_version: None,
+                    tags: Vec::new(),
                 },
                 published_at: t0_chrono,
-                download_count: 0
+                download_count: 0,
+                archive_size: None,
             },
         ]
     );
@@ -132,9 +136,11 @@ async fn test_extensions(db: &Arc<Database>) {
                 repository: "ext2/repo".into(),
                 schema_version: Some(0),
                 wasm_api_version: None,
+                tags: Vec::new(),
             },
             published_at: t0_chrono,
-            download_count: 0
+            download_count: 0,
+            archive_size: None,
         },]
     );
 
@@ -172,9 +178,11 @@ async fn test_extensions(db: &Arc<Database>) {
                     repository: "ext2/repo".into(),
                     schema_version: Some(0),
                     wasm_api_version: None,
+                    tags: Vec::new(),
                 },
                 published_at: t0_chrono,
-                download_count: 7
+                download_count: 7,
+                archive_size: None,
             },
             ExtensionMetadata {
                 id: "ext1".into(),
@@ -186,9 +194,11 @@ async fn test_extensions(db: &Arc<Database>) {
                     repository: "ext1/repo".into(),
                     schema_version: Some(1),
                     wasm_api_version: None,
+                    tags: Vec::new(),
                 },
                 published_at: t0_chrono,
                 download_count: 5,
+                archive_size: None,
             },
         ]
     );
@@ -258,9 +268,11 @@ async fn test_extensions(db: &Arc<Database>) {
                     repository: "ext2/repo".into(),
                     schema_version: Some(0),
                     wasm_api_version: None,
+                    tags: Vec::new(),
                 },
                 published_at: t0_chrono,
-                download_count: 7
+                download_count: 7,
+                archive_size: None,
             },
             ExtensionMetadata {
                 id: "ext1".into(),
@@ -272,9 +284,11 @@ async fn test_extensions(db: &Arc<Database>) {
                     repository: "ext1/repo".into(),
                     schema_version: Some(1),
                     wasm_api_version: None,
+                    tags: Vec::new(),
                 },
                 published_at: t0_chrono,
                 download_count: 5,
+                archive_size: None,
             },
         ]
     );
@@ -378,9 +392,11 @@ async fn test_extensions_by_id(db: &Arc<Database>) {
                 repository: "ext1/repo".into(),
                 schema_version: Some(1),
                 wasm_api_version: Some("0.0.4".into()),
+                tags: Vec::new(),
             },
             published_at: t0_chrono,
             download_count: 0,
+            archive_size: None,
         }]
     );
 }
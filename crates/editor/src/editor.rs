@@ -12113,11 +12113,24 @@ impl CompletionProvider for Model<Project> {
         }
 
         let buffer = buffer.read(cx);
-        let scope = buffer.snapshot().language_scope_at(position);
+        let snapshot = buffer.snapshot();
+        let scope = snapshot.language_scope_at(position);
         if trigger_in_words && char_kind(&scope, char) == CharKind::Word {
             return true;
         }
 
+        if let Some(scope) = &scope {
+            let offset = text::ToOffset::to_offset(&position, &snapshot);
+            let is_language_trigger = scope.completion_query_characters().iter().any(|trigger| {
+                trigger.ends_with(text)
+                    && offset >= trigger.len()
+                    && snapshot.contains_str_at(offset - trigger.len(), trigger)
+            });
+            if is_language_trigger {
+                return true;
+            }
+        }
+
         buffer
             .completion_triggers()
             .iter()
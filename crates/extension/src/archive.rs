@@ -0,0 +1,67 @@
+use anyhow::Result;
+use async_compression::futures::bufread::{GzipDecoder, XzDecoder, ZstdDecoder};
+use async_tar::Archive;
+use fs::Fs;
+use futures::{AsyncBufRead, AsyncRead};
+use std::path::Path;
+
+/// The formats of the archives that extensions can download and extract.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ArchiveFormat {
+    TarGz,
+    TarXz,
+    TarZst,
+    Zip,
+}
+
+impl ArchiveFormat {
+    /// Detects the format of an archive from its first bytes, falling back to
+    /// the extension of the file name in the URL it was downloaded from.
+    pub fn detect(url: &str, header: &[u8]) -> Option<Self> {
+        if header.starts_with(b"\x1f\x8b") {
+            return Some(Self::TarGz);
+        } else if header.starts_with(b"\xfd7zXZ\x00") {
+            return Some(Self::TarXz);
+        } else if header.starts_with(b"\x28\xb5\x2f\xfd") {
+            return Some(Self::TarZst);
+        } else if header.starts_with(b"PK\x03\x04") || header.starts_with(b"PK\x05\x06") {
+            return Some(Self::Zip);
+        }
+
+        let path = url.split(['?', '#']).next().unwrap_or(url);
+        if path.ends_with(".tar.gz") || path.ends_with(".tgz") {
+            Some(Self::TarGz)
+        } else if path.ends_with(".tar.xz") || path.ends_with(".txz") {
+            Some(Self::TarXz)
+        } else if path.ends_with(".tar.zst") || path.ends_with(".tzst") {
+            Some(Self::TarZst)
+        } else if path.ends_with(".zip") {
+            Some(Self::Zip)
+        } else {
+            None
+        }
+    }
+}
+
+/// Extracts an archive of the given format into the destination directory.
+///
+/// The permissions of the archived files are preserved on Unix, so that the
+/// binaries in the archive remain executable.
+pub(crate) async fn extract_archive(
+    fs: &dyn Fs,
+    format: ArchiveFormat,
+    reader: impl AsyncBufRead + Send + Unpin,
+    destination: &Path,
+) -> Result<()> {
+    match format {
+        ArchiveFormat::TarGz => extract_tar(fs, GzipDecoder::new(reader), destination).await,
+        ArchiveFormat::TarXz => extract_tar(fs, XzDecoder::new(reader), destination).await,
+        ArchiveFormat::TarZst => extract_tar(fs, ZstdDecoder::new(reader), destination).await,
+        ArchiveFormat::Zip => node_runtime::extract_zip(destination, reader).await,
+    }
+}
+
+async fn extract_tar(fs: &dyn Fs, reader: impl AsyncRead + Send, destination: &Path) -> Result<()> {
+    futures::pin_mut!(reader);
+    fs.extract_tar_file(destination, Archive::new(reader)).await
+}
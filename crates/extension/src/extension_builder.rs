@@ -1,3 +1,4 @@
+use crate::oci::{self, OciReference};
 use crate::wasm_host::parse_wasm_extension_version;
 use crate::ExtensionManifest;
 use crate::{extension_manifest::ExtensionLibraryKind, GrammarManifestEntry};
@@ -8,6 +9,7 @@ use futures::io::BufReader;
 use futures::AsyncReadExt;
 use http_client::{self, AsyncBody, HttpClient};
 use serde::Deserialize;
+use sha2::{Digest as _, Sha256};
 use std::{
     env, fs, mem,
     path::{Path, PathBuf},
@@ -96,6 +98,20 @@ impl ExtensionBuilder {
         }
 
         for (grammar_name, grammar_metadata) in &extension_manifest.grammars {
+            if let Some(oci_reference) = &grammar_metadata.oci {
+                self.fetch_oci_grammar(
+                    extension_dir,
+                    grammar_name.as_ref(),
+                    oci_reference,
+                    grammar_metadata.sha256.as_deref(),
+                )
+                .await
+                .with_context(|| {
+                    format!("failed to fetch grammar '{grammar_name}' from {oci_reference}")
+                })?;
+                continue;
+            }
+
             self.compile_grammar(extension_dir, grammar_name.as_ref(), grammar_metadata)
                 .await
                 .with_context(|| format!("failed to compile grammar '{grammar_name}'"))?;
@@ -225,6 +241,58 @@ impl ExtensionBuilder {
             );
         }
 
+        let wasm_bytes = fs::read(&grammar_wasm_path)
+            .with_context(|| format!("failed to read {}", grammar_wasm_path.display()))?;
+        validate_grammar_wasm(grammar_name, &wasm_bytes)
+    }
+
+    /// Fetches the grammar with the given OCI reference into the extension's
+    /// `grammars` directory.
+    ///
+    /// When a checksum is given, a previously fetched grammar that matches it is
+    /// reused, and a fetched grammar that doesn't is fetched once more before failing.
+    async fn fetch_oci_grammar(
+        &self,
+        extension_dir: &Path,
+        grammar_name: &str,
+        oci_reference: &str,
+        expected_sha256: Option<&str>,
+    ) -> Result<()> {
+        let reference = OciReference::parse(oci_reference)?;
+        let grammars_dir = extension_dir.join("grammars");
+        let grammar_wasm_path = grammars_dir.join(format!("{grammar_name}.wasm"));
+
+        if let Some(expected_sha256) = expected_sha256 {
+            if let Ok(cached_bytes) = fs::read(&grammar_wasm_path) {
+                if sha256_hex(&cached_bytes).eq_ignore_ascii_case(expected_sha256) {
+                    log::info!("using previously fetched {grammar_name} parser");
+                    return Ok(());
+                }
+                log::warn!("previously fetched {grammar_name} parser does not match its checksum");
+            }
+        }
+
+        log::info!("fetching {grammar_name} parser from {reference}");
+        let mut wasm_bytes = oci::fetch_wasm_layer(self.http.as_ref(), &reference).await?;
+        if let Some(expected_sha256) = expected_sha256 {
+            if !sha256_hex(&wasm_bytes).eq_ignore_ascii_case(expected_sha256) {
+                log::warn!("{grammar_name} parser does not match its checksum, fetching it again");
+                wasm_bytes = oci::fetch_wasm_layer(self.http.as_ref(), &reference).await?;
+                let actual_sha256 = sha256_hex(&wasm_bytes);
+                if !actual_sha256.eq_ignore_ascii_case(expected_sha256) {
+                    bail!(
+                        "checksum mismatch for {reference}: expected {expected_sha256}, got {actual_sha256}"
+                    );
+                }
+            }
+        }
+        validate_grammar_wasm(grammar_name, &wasm_bytes)
+            .with_context(|| format!("{reference} does not contain a valid grammar"))?;
+
+        fs::create_dir_all(&grammars_dir).context("failed to create grammars dir")?;
+        fs::write(&grammar_wasm_path, &wasm_bytes)
+            .with_context(|| format!("failed to write {}", grammar_wasm_path.display()))?;
+
         Ok(())
     }
 
@@ -562,6 +630,8 @@ fn populate_defaults(manifest: &mut ExtensionManifest, extension_path: &Path) ->
                                 repository: grammar_config.repository,
                                 rev: grammar_config.commit,
                                 path: grammar_config.path,
+                                oci: None,
+                                sha256: None,
                             },
                         );
                     }
@@ -572,3 +642,51 @@ fn populate_defaults(manifest: &mut ExtensionManifest, extension_path: &Path) ->
 
     Ok(())
 }
+
+/// Checks that the given WASM module is a Tree-sitter grammar with the given name,
+/// by looking for the function that returns the grammar's language.
+///
+/// Grammars with an external scanner also export the scanner's functions and
+/// import the parts of libc that the scanner uses. These are left for Tree-sitter
+/// to resolve when the grammar is loaded.
+pub(crate) fn validate_grammar_wasm(grammar_name: &str, wasm_bytes: &[u8]) -> Result<()> {
+    if !Parser::is_core_wasm(wasm_bytes) {
+        bail!("grammar {grammar_name} is not a wasm module");
+    }
+
+    let language_function_name = format!("tree_sitter_{grammar_name}");
+    for payload in Parser::new(0).parse_all(wasm_bytes) {
+        if let wasmparser::Payload::ExportSection(exports) = payload? {
+            for export in exports {
+                let export = export?;
+                if export.name == language_function_name
+                    && export.kind == wasmparser::ExternalKind::Func
+                {
+                    return Ok(());
+                }
+            }
+        }
+    }
+    bail!("grammar {grammar_name} does not export {language_function_name}")
+}
+
+fn sha256_hex(bytes: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::extension_store_test::grammar_wasm;
+
+    #[test]
+    fn test_validate_grammar_wasm() {
+        validate_grammar_wasm("foo", &grammar_wasm("foo", false)).unwrap();
+        // The scanner's exports and imports do not get the grammar rejected.
+        validate_grammar_wasm("foo", &grammar_wasm("foo", true)).unwrap();
+
+        assert!(validate_grammar_wasm("foo", &grammar_wasm("bar", true)).is_err());
+        assert!(validate_grammar_wasm("foo", b"\0asm\x01\0\0\0").is_err());
+        assert!(validate_grammar_wasm("foo", b"").is_err());
+    }
+}
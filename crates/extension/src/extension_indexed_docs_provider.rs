@@ -1,5 +1,6 @@
 use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::Duration;
 
 use anyhow::{anyhow, Result};
 use async_trait::async_trait;
@@ -9,6 +10,10 @@ use wasmtime_wasi::WasiView;
 
 use crate::wasm_host::{WasmExtension, WasmHost};
 
+/// How long an extension may take to index a package's docs, which can
+/// involve fetching and processing many pages.
+pub(crate) const INDEX_DOCS_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+
 pub struct ExtensionIndexedDocsProvider {
     pub(crate) extension: WasmExtension,
     pub(crate) host: Arc<WasmHost>,
@@ -32,7 +37,7 @@ impl IndexedDocsProvider for ExtensionIndexedDocsProvider {
 
     async fn index(&self, package: PackageName, database: Arc<IndexedDocsDatabase>) -> Result<()> {
         self.extension
-            .call({
+            .call_with_timeout(INDEX_DOCS_TIMEOUT, {
                 let id = self.id.clone();
                 |extension, store| {
                     async move {
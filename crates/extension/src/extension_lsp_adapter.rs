@@ -1,8 +1,9 @@
+use crate::extension_manifest::Runtime;
 use crate::wasm_host::{
     wit::{self, LanguageServerConfig},
     WasmExtension, WasmHost,
 };
-use anyhow::{anyhow, Context, Result};
+use anyhow::{anyhow, bail, Context, Result};
 use async_trait::async_trait;
 use collections::HashMap;
 use futures::{Future, FutureExt};
@@ -19,10 +20,16 @@ use std::{
     path::{Path, PathBuf},
     pin::Pin,
     sync::Arc,
+    time::Duration,
 };
 use util::{maybe, ResultExt};
 use wasmtime_wasi::WasiView as _;
 
+/// How long an extension may take to provide a language server's command,
+/// which is longer than for other calls because it often involves
+/// downloading the language server.
+const LANGUAGE_SERVER_COMMAND_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+
 pub struct ExtensionLspAdapter {
     pub(crate) extension: WasmExtension,
     pub(crate) language_server_id: LanguageServerName,
@@ -30,6 +37,55 @@ pub struct ExtensionLspAdapter {
     pub(crate) host: Arc<WasmHost>,
 }
 
+/// Checks that the runtimes that the given language server requires are on the
+/// `PATH`, so that a missing runtime is reported as such, rather than by the
+/// command that fails to install or start the language server without it.
+async fn check_required_runtimes(
+    language_server_name: &LanguageServerName,
+    runtimes: &[Runtime],
+    delegate: &dyn LspAdapterDelegate,
+) -> Result<()> {
+    for runtime in runtimes {
+        if delegate
+            .which(runtime.binary_name().as_ref())
+            .await
+            .is_none()
+        {
+            bail!(
+                "language server {} requires {runtime_name}, but `{}` was not found. Please install {runtime_name}, and make sure that it is on your PATH.",
+                language_server_name.0,
+                runtime.binary_name(),
+                runtime_name = runtime.display_name(),
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Makes the file at the given path executable, if it exists and is not
+/// executable by its owner already. Everyone who can read the file may then
+/// execute it.
+#[cfg_attr(windows, allow(unused_variables))]
+pub(crate) fn ensure_executable(path: &Path) -> Result<()> {
+    #[cfg(not(windows))]
+    {
+        use std::fs::{self, Permissions};
+        use std::os::unix::fs::PermissionsExt;
+
+        let Ok(metadata) = fs::metadata(path) else {
+            return Ok(());
+        };
+        let mode = metadata.permissions().mode();
+        if metadata.is_file() && mode & 0o100 == 0 {
+            let mode = mode | 0o100 | (mode & 0o044) >> 2;
+            fs::set_permissions(path, Permissions::from_mode(mode))
+                .context("failed to set file permissions")?;
+        }
+    }
+
+    Ok(())
+}
+
 #[async_trait(?Send)]
 impl LspAdapter for ExtensionLspAdapter {
     fn name(&self) -> LanguageServerName {
@@ -45,9 +101,27 @@ impl LspAdapter for ExtensionLspAdapter {
         _: &'a mut AsyncAppContext,
     ) -> Pin<Box<dyn 'a + Future<Output = Result<LanguageServerBinary>>>> {
         async move {
+            if let Some(entry) = self
+                .extension
+                .manifest
+                .language_servers
+                .get(&self.language_server_id)
+            {
+                check_required_runtimes(
+                    &self.language_server_id,
+                    &entry.required_runtimes,
+                    delegate.as_ref(),
+                )
+                .await
+                .inspect_err(|error| {
+                    self.host
+                        .report_call_error(&self.extension.manifest.id, error)
+                })?;
+            }
+
             let command = self
                 .extension
-                .call({
+                .call_with_timeout(LANGUAGE_SERVER_COMMAND_TIMEOUT, {
                     let this = self.clone();
                     |extension, store| {
                         async move {
@@ -66,30 +140,28 @@ impl LspAdapter for ExtensionLspAdapter {
                         .boxed()
                     }
                 })
-                .await?;
+                .await
+                .with_context(|| {
+                    format!(
+                        "failed to get the command for language server {}",
+                        self.language_server_id.0
+                    )
+                })
+                .inspect_err(|error| {
+                    self.host
+                        .report_call_error(&self.extension.manifest.id, error)
+                })?;
 
             let path = self
                 .host
                 .path_from_extension(&self.extension.manifest.id, command.command.as_ref());
 
-            // TODO: This should now be done via the `zed::make_file_executable` function in
-            // Zed extension API, but we're leaving these existing usages in place temporarily
-            // to avoid any compatibility issues between Zed and the extension versions.
-            //
-            // We can remove once the following extension versions no longer see any use:
-            // - toml@0.0.2
-            // - zig@0.0.1
-            if ["toml", "zig"].contains(&self.extension.manifest.id.as_ref())
-                && path.starts_with(&self.host.work_dir)
-            {
-                #[cfg(not(windows))]
-                {
-                    use std::fs::{self, Permissions};
-                    use std::os::unix::fs::PermissionsExt;
-
-                    fs::set_permissions(&path, Permissions::from_mode(0o755))
-                        .context("failed to set file permissions")?;
-                }
+            // Extensions should make the binaries they download executable via the
+            // `zed::make_file_executable` function in the Zed extension API, but not
+            // every extension does (e.g. toml@0.0.2 and zig@0.0.1), and archives do
+            // not always preserve the executable bit of the files they contain.
+            if path.starts_with(&self.host.work_dir) {
+                ensure_executable(&path)?;
             }
 
             Ok(LanguageServerBinary {
@@ -591,3 +663,111 @@ fn test_build_code_label_with_invalid_ranges() {
     );
     assert!(label.is_none());
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gpui::AppContext;
+    use http_client::{FakeHttpClient, HttpClient};
+    use language::LanguageServerBinaryStatus;
+    use serde_json::json;
+    use std::ffi::OsStr;
+    use util::test::temp_tree;
+
+    /// A delegate that finds only the given commands on the `PATH`.
+    struct FakeDelegate {
+        commands: Vec<&'static str>,
+    }
+
+    #[async_trait]
+    impl LspAdapterDelegate for FakeDelegate {
+        fn show_notification(&self, _: &str, _: &mut AppContext) {}
+
+        fn http_client(&self) -> Arc<dyn HttpClient> {
+            FakeHttpClient::with_404_response()
+        }
+
+        fn worktree_id(&self) -> u64 {
+            0
+        }
+
+        fn worktree_root_path(&self) -> &Path {
+            Path::new("/the-worktree")
+        }
+
+        fn update_status(&self, _: LanguageServerName, _: LanguageServerBinaryStatus) {}
+
+        async fn which(&self, command: &OsStr) -> Option<PathBuf> {
+            self.commands
+                .iter()
+                .find(|candidate| OsStr::new(candidate) == command)
+                .map(|command| Path::new("/usr/bin").join(command))
+        }
+
+        async fn shell_env(&self) -> HashMap<String, String> {
+            HashMap::default()
+        }
+
+        async fn read_text_file(&self, path: PathBuf) -> Result<String> {
+            Err(anyhow!("no such file {path:?}"))
+        }
+    }
+
+    #[test]
+    fn test_check_required_runtimes() {
+        let delegate = FakeDelegate {
+            commands: vec!["go"],
+        };
+        let check = |runtimes: &[Runtime]| {
+            futures::executor::block_on(check_required_runtimes(
+                &LanguageServerName("the-server".into()),
+                runtimes,
+                &delegate,
+            ))
+        };
+
+        check(&[]).unwrap();
+        check(&[Runtime::Go]).unwrap();
+        let error = check(&[Runtime::Go, Runtime::Node]).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "language server the-server requires Node.js, but `node` was not found. Please install Node.js, and make sure that it is on your PATH."
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_ensure_executable() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = temp_tree(json!({
+            "the-server": "",
+            "the-private-server": "",
+            "the-executable-server": "",
+        }));
+        let mode = |name: &str| {
+            std::fs::metadata(dir.path().join(name))
+                .unwrap()
+                .permissions()
+                .mode()
+                & 0o777
+        };
+        for (name, mode) in [
+            ("the-server", 0o644),
+            ("the-private-server", 0o600),
+            ("the-executable-server", 0o744),
+        ] {
+            std::fs::set_permissions(dir.path().join(name), std::fs::Permissions::from_mode(mode))
+                .unwrap();
+            ensure_executable(&dir.path().join(name)).unwrap();
+        }
+
+        assert_eq!(mode("the-server"), 0o755);
+        assert_eq!(mode("the-private-server"), 0o700);
+        // A file that is already executable is left alone.
+        assert_eq!(mode("the-executable-server"), 0o744);
+
+        // Missing files are ignored.
+        ensure_executable(&dir.path().join("missing")).unwrap();
+    }
+}
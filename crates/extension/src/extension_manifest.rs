@@ -1,4 +1,5 @@
-use anyhow::{anyhow, Context, Result};
+use crate::ExtensionSettings;
+use anyhow::{anyhow, bail, Context, Result};
 use collections::{BTreeMap, HashMap};
 use fs::Fs;
 use language::LanguageServerName;
@@ -15,6 +16,8 @@ use std::{
 #[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
 pub struct OldExtensionManifest {
     pub name: String,
+    /// When omitted, the version is read from the extension's `VERSION` file.
+    #[serde(default)]
     pub version: Arc<str>,
 
     #[serde(default)]
@@ -54,8 +57,13 @@ impl SchemaVersion {
 pub struct ExtensionManifest {
     pub id: Arc<str>,
     pub name: String,
+    /// When omitted, the version is read from the extension's `VERSION` file.
+    #[serde(default)]
     pub version: Arc<str>,
     pub schema_version: SchemaVersion,
+    /// The oldest version of Zed that the extension can be loaded in.
+    #[serde(default)]
+    pub min_zed_version: Option<SemanticVersion>,
 
     #[serde(default)]
     pub description: Option<String>,
@@ -63,6 +71,13 @@ pub struct ExtensionManifest {
     pub repository: Option<String>,
     #[serde(default)]
     pub authors: Vec<String>,
+    /// The categories under which the extension is listed, e.g. `"Themes"`.
+    #[serde(default)]
+    pub categories: Vec<String>,
+    /// Keywords that describe the extension, e.g. `"dark"`, which are matched
+    /// when searching for extensions.
+    #[serde(default)]
+    pub tags: Vec<String>,
     #[serde(default)]
     pub lib: LibManifestEntry,
 
@@ -72,6 +87,10 @@ pub struct ExtensionManifest {
     pub languages: Vec<PathBuf>,
     #[serde(default)]
     pub grammars: BTreeMap<Arc<str>, GrammarManifestEntry>,
+    /// Additional queries for languages provided by other extensions, keyed by
+    /// language name.
+    #[serde(default)]
+    pub language_queries: BTreeMap<Arc<str>, LanguageQueriesManifestEntry>,
     #[serde(default)]
     pub language_servers: BTreeMap<LanguageServerName, LanguageServerManifestEntry>,
     #[serde(default)]
@@ -80,6 +99,26 @@ pub struct ExtensionManifest {
     pub indexed_docs_providers: BTreeMap<Arc<str>, IndexedDocsProviderEntry>,
     #[serde(default)]
     pub snippets: Option<PathBuf>,
+    #[serde(default)]
+    pub features: BTreeMap<Arc<str>, FeatureManifestEntry>,
+    /// The IDs of the extensions that cannot be loaded together with this one.
+    #[serde(default)]
+    pub conflicts: Vec<Arc<str>>,
+    /// Decides which of two conflicting extensions is loaded: the one with the
+    /// higher priority, or, if both have the same priority, the one whose ID
+    /// comes first.
+    #[serde(default)]
+    pub priority: i32,
+}
+
+/// An experimental capability of an extension that users must opt into.
+#[derive(Clone, Default, PartialEq, Eq, Debug, Deserialize, Serialize)]
+pub struct FeatureManifestEntry {
+    #[serde(default)]
+    pub description: Option<String>,
+    /// Whether the feature is enabled when the user has not configured it.
+    #[serde(default)]
+    pub enabled_by_default: bool,
 }
 
 #[derive(Clone, Default, PartialEq, Eq, Debug, Deserialize, Serialize)]
@@ -95,11 +134,21 @@ pub enum ExtensionLibraryKind {
 
 #[derive(Clone, Default, PartialEq, Eq, Debug, Deserialize, Serialize)]
 pub struct GrammarManifestEntry {
+    #[serde(default)]
     pub repository: String,
-    #[serde(alias = "commit")]
+    #[serde(alias = "commit", default)]
     pub rev: String,
     #[serde(default)]
     pub path: Option<String>,
+    /// A reference to an OCI artifact containing the compiled grammar, e.g.
+    /// `ghcr.io/owner/tree-sitter-foo:1.0.0`, to use instead of building it
+    /// from `repository`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub oci: Option<String>,
+    /// The hex-encoded SHA-256 checksum of the grammar fetched from `oci`. When
+    /// present, a previously fetched grammar with this checksum is reused.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
 }
 
 #[derive(Clone, PartialEq, Eq, Debug, Deserialize, Serialize)]
@@ -114,6 +163,62 @@ pub struct LanguageServerManifestEntry {
     pub language_ids: HashMap<String, String>,
     #[serde(default)]
     pub code_action_kinds: Option<Vec<lsp::CodeActionKind>>,
+    /// The feature that must be enabled for this language server to be registered.
+    #[serde(default)]
+    pub feature: Option<Arc<str>>,
+    /// The names of worktree files (e.g., `.go-version`) that the extension reads
+    /// to determine which version of the language server to use.
+    ///
+    /// The language server's version should be resolved again when one of these
+    /// files changes.
+    #[serde(default)]
+    pub version_files: Vec<String>,
+    /// The SHA-256 checksums of the files downloaded while installing the language
+    /// server, keyed by the file name at the end of the download URL.
+    #[serde(default)]
+    pub sha256: BTreeMap<String, String>,
+    /// Whether the checksum of a downloaded file without an entry in `sha256` should
+    /// be read from the `.sha256` file published alongside it.
+    #[serde(default)]
+    pub sha256_assets: bool,
+    /// The runtimes that must be installed for the language server to be
+    /// installed, e.g. because it is installed with `npm` or `go install`.
+    #[serde(default)]
+    pub required_runtimes: Vec<Runtime>,
+}
+
+/// A runtime that a language server may require to be installed.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Runtime {
+    Node,
+    Go,
+}
+
+impl Runtime {
+    /// Returns the name of the runtime's binary, which is looked up on the `PATH`.
+    pub fn binary_name(&self) -> &'static str {
+        match self {
+            Self::Node => "node",
+            Self::Go => "go",
+        }
+    }
+
+    /// Returns the name under which the runtime is installed.
+    pub fn display_name(&self) -> &'static str {
+        match self {
+            Self::Node => "Node.js",
+            Self::Go => "Go",
+        }
+    }
+}
+
+/// The checksum against which a file downloaded by a language server is verified.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum DownloadChecksum {
+    Sha256(String),
+    /// The URL of a `.sha256` file containing the checksum.
+    Asset(String),
 }
 
 impl LanguageServerManifestEntry {
@@ -134,6 +239,18 @@ impl LanguageServerManifestEntry {
     }
 }
 
+/// A directory of query files (e.g. `highlights.scm`) that extend a language
+/// provided by another extension.
+#[derive(Clone, PartialEq, Eq, Debug, Deserialize, Serialize)]
+pub struct LanguageQueriesManifestEntry {
+    pub path: PathBuf,
+    /// When several extensions extend the same language, their queries are
+    /// appended in order of ascending priority, and then of extension ID, so
+    /// that the queries with the highest priority come last.
+    #[serde(default)]
+    pub priority: i32,
+}
+
 #[derive(Clone, PartialEq, Eq, Debug, Deserialize, Serialize)]
 pub struct SlashCommandManifestEntry {
     pub description: String,
@@ -145,6 +262,59 @@ pub struct SlashCommandManifestEntry {
 pub struct IndexedDocsProviderEntry {}
 
 impl ExtensionManifest {
+    /// Returns whether the given feature is enabled, either explicitly in the
+    /// user's settings or by the feature's default.
+    ///
+    /// Features that are not declared by the extension are never enabled.
+    pub fn is_feature_enabled(&self, feature: &str, settings: &ExtensionSettings) -> bool {
+        let Some(entry) = self.features.get(feature) else {
+            return false;
+        };
+        settings
+            .feature_enabled(&self.id, feature)
+            .unwrap_or(entry.enabled_by_default)
+    }
+
+    /// Returns the language servers that should be registered, skipping any
+    /// that are gated behind a feature that is not enabled.
+    pub fn enabled_language_servers<'a>(
+        &'a self,
+        settings: &'a ExtensionSettings,
+    ) -> impl Iterator<Item = (&'a LanguageServerName, &'a LanguageServerManifestEntry)> + 'a {
+        self.language_servers.iter().filter(move |(_, entry)| {
+            entry
+                .feature
+                .as_ref()
+                .map_or(true, |feature| self.is_feature_enabled(feature, settings))
+        })
+    }
+
+    /// Returns the checksum that a file downloaded from the given URL by one of
+    /// the extension's language servers must match, if one is configured.
+    pub fn download_checksum(&self, url: &str) -> Option<DownloadChecksum> {
+        let mut url = url::Url::parse(url).ok()?;
+        let file_name = url.path_segments()?.last()?.to_string();
+
+        if let Some(sha256) = self
+            .language_servers
+            .values()
+            .find_map(|entry| entry.sha256.get(&file_name))
+        {
+            return Some(DownloadChecksum::Sha256(sha256.to_ascii_lowercase()));
+        }
+
+        if self
+            .language_servers
+            .values()
+            .any(|entry| entry.sha256_assets)
+        {
+            url.set_path(&format!("{}.sha256", url.path()));
+            return Some(DownloadChecksum::Asset(url.to_string()));
+        }
+
+        None
+    }
+
     pub async fn load(fs: Arc<dyn Fs>, extension_dir: &Path) -> Result<Self> {
         let extension_name = extension_dir
             .file_name()
@@ -152,29 +322,191 @@ impl ExtensionManifest {
             .ok_or_else(|| anyhow!("invalid extension name"))?;
 
         let mut extension_manifest_path = extension_dir.join("extension.json");
-        if fs.is_file(&extension_manifest_path).await {
+        let mut manifest = if fs.is_file(&extension_manifest_path).await {
             let manifest_content = fs
                 .load(&extension_manifest_path)
                 .await
                 .with_context(|| format!("failed to load {extension_name} extension.json"))?;
-            let manifest_json = serde_json::from_str::<OldExtensionManifest>(&manifest_content)
-                .with_context(|| {
-                    format!("invalid extension.json for extension {extension_name}")
-                })?;
+            let manifest_json = serde_json::from_str::<serde_json::Value>(&manifest_content)
+                .map_err(|error| ManifestError::new(extension_dir, None, error.to_string()))?;
+            validate_manifest_entry(extension_dir, &manifest_json)?;
+            let manifest_json = serde_json::from_value::<OldExtensionManifest>(manifest_json)
+                .map_err(|error| ManifestError::new(extension_dir, None, error.to_string()))?;
 
-            Ok(manifest_from_old_manifest(manifest_json, extension_name))
+            let extension_id = parse_versioned_dir_name(extension_name)
+                .map_or(extension_name, |(extension_id, _)| extension_id);
+            manifest_from_old_manifest(manifest_json, extension_id)
         } else {
             extension_manifest_path.set_extension("toml");
             let manifest_content = fs
                 .load(&extension_manifest_path)
                 .await
                 .with_context(|| format!("failed to load {extension_name} extension.toml"))?;
-            toml::from_str(&manifest_content)
-                .with_context(|| format!("invalid extension.json for extension {extension_name}"))
+            toml::from_str::<ExtensionManifest>(&manifest_content)
+                .with_context(|| format!("invalid extension.json for extension {extension_name}"))?
+        };
+
+        if manifest.version.is_empty() {
+            let version = fs
+                .load(&extension_dir.join("VERSION"))
+                .await
+                .with_context(|| format!("no version found for extension {extension_name}"))?;
+            let version = version.trim();
+            if version.is_empty() {
+                bail!("VERSION file for extension {extension_name} is empty");
+            }
+            manifest.version = version.into();
         }
+
+        Ok(manifest)
+    }
+}
+
+/// A problem with an extension's `extension.json` that prevented the extension
+/// from being loaded.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ManifestError {
+    /// The directory of the extension whose manifest is invalid.
+    pub extension_dir: PathBuf,
+    /// The field that failed validation, or `None` if the manifest could not
+    /// be parsed at all.
+    pub field: Option<Arc<str>>,
+    pub message: String,
+}
+
+impl ManifestError {
+    fn new(extension_dir: &Path, field: Option<&str>, message: impl Into<String>) -> Self {
+        Self {
+            extension_dir: extension_dir.to_path_buf(),
+            field: field.map(Arc::from),
+            message: message.into(),
+        }
+    }
+
+    /// Returns the path of the invalid manifest.
+    pub fn manifest_path(&self) -> PathBuf {
+        self.extension_dir.join("extension.json")
     }
 }
 
+impl fmt::Display for ManifestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.field {
+            Some(field) => write!(
+                f,
+                "invalid `{field}` in {:?}: {}",
+                self.manifest_path(),
+                self.message
+            ),
+            None => write!(f, "invalid {:?}: {}", self.manifest_path(), self.message),
+        }
+    }
+}
+
+impl std::error::Error for ManifestError {}
+
+/// The kind of JSON value that a field of `extension.json` must have.
+#[derive(Clone, Copy)]
+enum FieldKind {
+    String,
+    StringArray,
+    StringMap,
+}
+
+/// The fields of `extension.json` that are checked by [`validate_manifest_entry`],
+/// along with whether they are required.
+///
+/// The `id` field is optional, as the ID is taken from the extension's directory.
+const MANIFEST_FIELDS: &[(&str, FieldKind, bool)] = &[
+    ("id", FieldKind::String, false),
+    ("name", FieldKind::String, true),
+    ("version", FieldKind::String, false),
+    ("description", FieldKind::String, false),
+    ("repository", FieldKind::String, false),
+    ("authors", FieldKind::StringArray, false),
+    ("tags", FieldKind::StringArray, false),
+    ("themes", FieldKind::StringMap, false),
+    ("languages", FieldKind::StringMap, false),
+    ("grammars", FieldKind::StringMap, false),
+];
+
+/// Checks that the fields of the given `extension.json` have the expected types,
+/// returning an error naming the first field that doesn't.
+pub fn validate_manifest_entry(
+    extension_dir: &Path,
+    manifest: &serde_json::Value,
+) -> Result<(), ManifestError> {
+    let Some(manifest) = manifest.as_object() else {
+        return Err(ManifestError::new(
+            extension_dir,
+            None,
+            "expected a JSON object",
+        ));
+    };
+
+    for (field, kind, required) in MANIFEST_FIELDS {
+        let Some(value) = manifest.get(*field) else {
+            if *required {
+                return Err(ManifestError::new(
+                    extension_dir,
+                    Some(*field),
+                    "missing required field",
+                ));
+            }
+            continue;
+        };
+
+        let (is_valid, expected) = match kind {
+            FieldKind::String => (value.is_string(), "a string"),
+            FieldKind::StringArray => (
+                value
+                    .as_array()
+                    .map_or(false, |items| items.iter().all(|item| item.is_string())),
+                "an array of strings",
+            ),
+            FieldKind::StringMap => (
+                value.as_object().map_or(false, |entries| {
+                    entries.values().all(|entry| entry.is_string())
+                }),
+                "an object with string values",
+            ),
+        };
+        if !is_valid {
+            return Err(ManifestError::new(
+                extension_dir,
+                Some(*field),
+                format!("expected {expected}, found {value}"),
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Checks that the given extension ID can be used as the name of the
+/// extension's directory: it must be non-empty, and must not contain path
+/// separators or be a relative path component like `..`.
+pub(crate) fn validate_extension_id(extension_id: &str) -> Result<()> {
+    if extension_id.is_empty()
+        || extension_id == "."
+        || extension_id.contains(['/', '\\'])
+        || extension_id.contains("..")
+    {
+        bail!("invalid extension id {extension_id:?}");
+    }
+    Ok(())
+}
+
+/// Splits the name of an installed extension's directory into the extension's
+/// ID and version, if the directory is named `<id>-<version>`.
+pub(crate) fn parse_versioned_dir_name(dir_name: &str) -> Option<(&str, SemanticVersion)> {
+    let (extension_id, version) = dir_name.rsplit_once('-')?;
+    if extension_id.is_empty() {
+        return None;
+    }
+    Some((extension_id, version.parse().ok()?))
+}
+
 fn manifest_from_old_manifest(
     manifest_json: OldExtensionManifest,
     extension_id: &str,
@@ -186,7 +518,10 @@ fn manifest_from_old_manifest(
         description: manifest_json.description,
         repository: manifest_json.repository,
         authors: manifest_json.authors,
+        categories: Vec::new(),
+        tags: Vec::new(),
         schema_version: SchemaVersion::ZERO,
+        min_zed_version: None,
         lib: Default::default(),
         themes: {
             let mut themes = manifest_json.themes.into_values().collect::<Vec<_>>();
@@ -205,9 +540,68 @@ fn manifest_from_old_manifest(
             .into_keys()
             .map(|grammar_name| (grammar_name, Default::default()))
             .collect(),
+        language_queries: BTreeMap::default(),
         language_servers: Default::default(),
         slash_commands: BTreeMap::default(),
         indexed_docs_providers: BTreeMap::default(),
         snippets: None,
+        features: BTreeMap::default(),
+        conflicts: Vec::new(),
+        priority: 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_feature_gated_language_servers() {
+        let manifest: ExtensionManifest = toml::from_str(
+            r#"
+                id = "the-lsp-extension"
+                name = "The LSP Extension"
+                version = "0.1.0"
+                schema_version = 1
+
+                [features.experimental-server]
+                description = "An experimental language server"
+
+                [language_servers.the-server]
+                language = "Ruby"
+
+                [language_servers.the-experimental-server]
+                language = "Ruby"
+                feature = "experimental-server"
+            "#,
+        )
+        .unwrap();
+
+        let enabled_servers = |settings: &ExtensionSettings| {
+            manifest
+                .enabled_language_servers(settings)
+                .map(|(name, _)| name.0.to_string())
+                .collect::<Vec<_>>()
+        };
+
+        let mut settings = ExtensionSettings::default();
+        assert_eq!(enabled_servers(&settings), ["the-server"]);
+
+        settings.extension_features.insert(
+            "the-lsp-extension".into(),
+            [("experimental-server".into(), true)].into_iter().collect(),
+        );
+        assert_eq!(
+            enabled_servers(&settings),
+            ["the-experimental-server", "the-server"]
+        );
+
+        settings.extension_features.insert(
+            "the-lsp-extension".into(),
+            [("experimental-server".into(), false)]
+                .into_iter()
+                .collect(),
+        );
+        assert_eq!(enabled_servers(&settings), ["the-server"]);
     }
 }
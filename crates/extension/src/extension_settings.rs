@@ -4,7 +4,10 @@ use gpui::AppContext;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use settings::{Settings, SettingsSources};
-use std::sync::Arc;
+use std::{fmt, path::PathBuf, sync::Arc};
+use theme::AppearanceContent;
+
+const DEFAULT_MAX_CONCURRENT_EXTENSION_CALLS: usize = 8;
 
 #[derive(Deserialize, Serialize, Debug, Default, Clone, JsonSchema)]
 pub struct ExtensionSettings {
@@ -16,6 +19,75 @@ pub struct ExtensionSettings {
     pub auto_install_extensions: HashMap<Arc<str>, bool>,
     #[serde(default)]
     pub auto_update_extensions: HashMap<Arc<str>, bool>,
+    /// The experimental extension features that should be enabled or disabled,
+    /// keyed by extension ID and then by feature name.
+    #[serde(default)]
+    pub extension_features: HashMap<Arc<str>, HashMap<Arc<str>, bool>>,
+    /// The credentials to use for private extension registries, keyed by host.
+    #[serde(default)]
+    pub registry_auth: HashMap<String, RegistryAuth>,
+    /// The extensions that should be loaded even though they require a newer
+    /// version of Zed.
+    #[serde(default)]
+    pub load_incompatible_extensions: HashMap<Arc<str>, bool>,
+    /// The maximum number of extension calls (e.g. fetching a language server)
+    /// that may run at once, across all extensions.
+    ///
+    /// Changes take effect after restarting Zed.
+    #[serde(default)]
+    pub max_concurrent_extension_calls: Option<usize>,
+    /// The path to a local file listing the available extensions, which is
+    /// read instead of querying the extension registry.
+    ///
+    /// This is used by air-gapped setups that maintain a mirror of the registry.
+    /// The file has the same format as the registry's `/extensions` response.
+    #[serde(default)]
+    pub extension_index_path: Option<PathBuf>,
+    /// The path to a lockfile that records the exact versions of the installed
+    /// extensions, and of the language servers that they resolve.
+    ///
+    /// When set, installs are reproducible: the versions in the lockfile are
+    /// used instead of the latest ones, so that another machine with the same
+    /// lockfile installs exactly the same versions.
+    ///
+    /// Changes take effect after restarting Zed.
+    #[serde(default)]
+    pub extension_lockfile_path: Option<PathBuf>,
+    /// The maximum number of grammars that may be loaded at once when they are
+    /// preloaded. Defaults to the number of CPUs.
+    #[serde(default)]
+    pub max_concurrent_grammar_loads: Option<usize>,
+    /// The versions to use of the extensions that are installed side by side in
+    /// directories named `<id>-<version>`, keyed by extension ID. The highest
+    /// installed version of the other extensions is used.
+    #[serde(default)]
+    pub pinned_extension_versions: HashMap<Arc<str>, String>,
+    /// The appearance of the themes provided by extensions that don't specify
+    /// one. Defaults to dark.
+    ///
+    /// Changes take effect when the extensions are reloaded.
+    #[serde(default)]
+    pub fallback_theme_appearance: Option<AppearanceContent>,
+}
+
+/// The credentials for a private extension registry.
+#[derive(Deserialize, Serialize, Default, Clone, JsonSchema)]
+pub struct RegistryAuth {
+    /// The bearer token to send to the registry.
+    #[serde(default)]
+    pub token: Option<String>,
+    /// The environment variable to read the bearer token from when `token` is not set.
+    #[serde(default)]
+    pub token_env: Option<String>,
+}
+
+impl fmt::Debug for RegistryAuth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RegistryAuth")
+            .field("token", &self.token.as_ref().map(|_| "<redacted>"))
+            .field("token_env", &self.token_env)
+            .finish()
+    }
 }
 
 impl ExtensionSettings {
@@ -33,6 +105,54 @@ impl ExtensionSettings {
             .copied()
             .unwrap_or(true)
     }
+
+    /// Returns whether the given extension should be loaded even if it requires
+    /// a newer version of Zed.
+    pub fn should_load_incompatible(&self, extension_id: &str) -> bool {
+        self.load_incompatible_extensions
+            .get(extension_id)
+            .copied()
+            .unwrap_or(false)
+    }
+
+    /// Returns whether the user has explicitly enabled or disabled the given
+    /// feature of the given extension.
+    pub fn feature_enabled(&self, extension_id: &str, feature: &str) -> Option<bool> {
+        self.extension_features
+            .get(extension_id)?
+            .get(feature)
+            .copied()
+    }
+
+    /// Returns the maximum number of extension calls that may run at once.
+    pub fn max_concurrent_calls(&self) -> usize {
+        self.max_concurrent_extension_calls
+            .unwrap_or(DEFAULT_MAX_CONCURRENT_EXTENSION_CALLS)
+            .max(1)
+    }
+
+    /// Returns the maximum number of grammars that may be preloaded at once.
+    pub fn max_concurrent_grammar_loads(&self) -> usize {
+        self.max_concurrent_grammar_loads
+            .unwrap_or_else(|| {
+                std::thread::available_parallelism().map_or(1, |parallelism| parallelism.get())
+            })
+            .max(1)
+    }
+
+    /// Returns the appearance of the extension themes that don't specify one.
+    pub fn fallback_theme_appearance(&self) -> AppearanceContent {
+        self.fallback_theme_appearance
+            .unwrap_or(theme::DEFAULT_FALLBACK_APPEARANCE)
+    }
+
+    /// Returns the bearer token configured for the registry at the given host.
+    pub fn registry_token(&self, host: &str) -> Option<String> {
+        let auth = self.registry_auth.get(host)?;
+        auth.token
+            .clone()
+            .or_else(|| std::env::var(auth.token_env.as_ref()?).ok())
+    }
 }
 
 impl Settings for ExtensionSettings {
@@ -1,4 +1,5 @@
 use std::sync::{atomic::AtomicBool, Arc};
+use std::time::Duration;
 
 use anyhow::{anyhow, Result};
 use assistant_slash_command::{
@@ -13,6 +14,10 @@ use workspace::Workspace;
 
 use crate::wasm_host::{WasmExtension, WasmHost};
 
+/// How long an extension may take to run a slash command, which can involve
+/// fetching from the network or doing other long-running work.
+const RUN_SLASH_COMMAND_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+
 pub struct ExtensionSlashCommand {
     pub(crate) extension: WasmExtension,
     #[allow(unused)]
@@ -87,7 +92,7 @@ impl SlashCommand for ExtensionSlashCommand {
         let argument = argument.map(|arg| arg.to_string());
         let output = cx.background_executor().spawn(async move {
             self.extension
-                .call({
+                .call_with_timeout(RUN_SLASH_COMMAND_TIMEOUT, {
                     let this = self.clone();
                     move |extension, store| {
                         async move {
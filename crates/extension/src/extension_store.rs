@@ -1,9 +1,13 @@
+mod archive;
 pub mod extension_builder;
 mod extension_indexed_docs_provider;
 mod extension_lsp_adapter;
 mod extension_manifest;
 mod extension_settings;
 mod extension_slash_command;
+mod lockfile;
+mod oci;
+mod retry;
 mod wasm_host;
 
 #[cfg(test)]
@@ -18,56 +22,125 @@ use assistant_slash_command::SlashCommandRegistry;
 use async_compression::futures::bufread::GzipDecoder;
 use async_tar::Archive;
 use client::{telemetry::Telemetry, Client, ExtensionMetadata, GetExtensionsResponse};
-use collections::{btree_map, BTreeMap, HashSet};
+use collections::{btree_map, BTreeMap, BTreeSet, HashMap, HashSet};
 use extension_builder::{CompileExtensionOptions, ExtensionBuilder};
-use fs::{Fs, RemoveOptions};
+use fs::{copy_recursive, CopyOptions, Fs, RemoveOptions, RenameOptions};
 use futures::{
     channel::{
         mpsc::{unbounded, UnboundedSender},
         oneshot,
     },
     io::BufReader,
+    lock::Mutex,
     select_biased, AsyncReadExt as _, Future, FutureExt as _, StreamExt as _,
 };
+use fuzzy::{match_strings, StringMatchCandidate};
 use gpui::{
-    actions, AppContext, AsyncAppContext, Context, EventEmitter, Global, Model, ModelContext, Task,
-    WeakModel,
+    actions, AppContext, AsyncAppContext, Context, EventEmitter, Global, Model, ModelContext,
+    SharedString, Task, WeakModel,
 };
-use http_client::{AsyncBody, HttpClient, HttpClientWithUrl};
+use http_client::{AsyncBody, HttpClient, HttpClientWithUrl, Method, Request, Response};
 use indexed_docs::{IndexedDocsRegistry, ProviderId};
+use isahc::config::{Configurable as _, RedirectPolicy};
 use language::{
-    LanguageConfig, LanguageMatcher, LanguageQueries, LanguageRegistry, QUERY_FILENAME_PREFIXES,
+    ContextProvider, GrammarLoadFailure, LanguageConfig, LanguageMatcher, LanguageQueries,
+    LanguageRegistry, LanguageServerName, QUERY_FILENAME_PREFIXES,
 };
-use node_runtime::NodeRuntime;
+use lockfile::ResolvedVersion;
+use node_runtime::{extract_zip, NodeRuntime};
 use project::ContextProviderWithTasks;
-use release_channel::ReleaseChannel;
+use regex::Regex;
+use release_channel::{AppVersion, ReleaseChannel};
 use semantic_version::SemanticVersion;
 use serde::{Deserialize, Serialize};
-use settings::Settings;
+use settings::{Settings, SettingsStore};
+use sha2::{Digest as _, Sha256};
 use snippet_provider::SnippetRegistry;
 use std::ops::RangeInclusive;
 use std::str::FromStr;
 use std::{
-    cmp::Ordering,
+    cmp::{Ordering, Reverse},
+    hash::{DefaultHasher, Hash as _, Hasher as _},
+    iter, mem,
     path::{self, Path, PathBuf},
     sync::Arc,
-    time::{Duration, Instant},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use theme::{
+    Appearance, AppearanceContent, ThemeContent, ThemeFamilyContent, ThemeRegistry, ThemeSettings,
 };
-use theme::{ThemeRegistry, ThemeSettings};
 use url::Url;
 use util::{maybe, ResultExt};
 use wasm_host::{
     wit::{is_supported_wasm_api_version, wasm_api_version_range},
-    WasmExtension, WasmHost,
+    ProducedGrammar, WasmExtension, WasmHost,
 };
 
+use extension_manifest::{parse_versioned_dir_name, validate_extension_id};
 pub use extension_manifest::{
-    ExtensionLibraryKind, ExtensionManifest, GrammarManifestEntry, OldExtensionManifest,
+    validate_manifest_entry, ExtensionLibraryKind, ExtensionManifest, GrammarManifestEntry,
+    LanguageQueriesManifestEntry, ManifestError, OldExtensionManifest,
 };
-pub use extension_settings::ExtensionSettings;
+pub use extension_settings::{ExtensionSettings, RegistryAuth};
+pub use lockfile::ExtensionLockfile;
+pub use retry::RetryPolicy;
 
 const RELOAD_DEBOUNCE_DURATION: Duration = Duration::from_millis(200);
 const FS_WATCH_LATENCY: Duration = Duration::from_millis(100);
+/// How long to wait before writing the extension index to disk, so that the
+/// indexes of reloads in quick succession are written once.
+const INDEX_WRITE_DELAY: Duration = Duration::from_millis(100);
+
+/// The file extension of extension bundles: zip archives whose root has the same
+/// layout as an installed extension's directory.
+pub const EXTENSION_BUNDLE_EXTENSION: &str = "zed-extension";
+
+/// The bytes that every zip archive starts with.
+const ZIP_MAGIC: &[u8] = b"PK\x03\x04";
+
+/// The response header with which the registry reports who signed a downloaded extension.
+const SIGNED_BY_HEADER: &str = "x-zed-extension-signed-by";
+
+/// The file, within an installed extension's directory, that records its [`ExtensionProvenance`].
+const PROVENANCE_FILE_NAME: &str = "provenance.json";
+
+/// The file, within an installed extension's directory, that records when the
+/// extension was installed, in seconds since the Unix epoch.
+const INSTALLED_AT_FILE_NAME: &str = "installed_at";
+
+/// The file, within an installed extension's directory, whose presence marks the
+/// extension as disabled.
+const DISABLED_FILE_NAME: &str = ".disabled";
+
+/// The directory, within an installed extension's directory, in which the
+/// extension stores user data that can be kept when it is uninstalled.
+const EXTENSION_DATA_DIR_NAME: &str = "data";
+
+/// The file, within an installed extension's directory, that holds the default
+/// settings the extension provides.
+const DEFAULT_SETTINGS_FILE_NAME: &str = "default_settings.json";
+
+/// The directory, within the work directory, into which extensions are extracted
+/// before they are moved into the installed extensions directory.
+const STAGING_DIR_NAME: &str = ".staging";
+
+/// The directory, within the work directory, where the installed versions of
+/// extensions are kept while they are being replaced.
+const REPLACED_DIR_NAME: &str = ".replaced";
+
+/// The directory, within the extensions directory, that holds a single copy of
+/// each distinct grammar shipped by installed extensions, named by its SHA-256 hash.
+const GRAMMAR_STORE_DIR_NAME: &str = "grammars";
+
+/// The file extension of the files that take the place of an installed extension's
+/// grammars once they have been moved into the grammar store, which contain the
+/// hash of the grammar.
+const GRAMMAR_HASH_FILE_EXTENSION: &str = "sha256";
+
+/// The version of the format of the extension index that this version of Zed
+/// writes. It is increased whenever the format changes in a way that requires
+/// the indexes written by other versions to be rebuilt.
+const CURRENT_INDEX_SCHEMA_VERSION: u32 = 2;
 
 /// The current extension [`SchemaVersion`] supported by Zed.
 const CURRENT_SCHEMA_VERSION: SchemaVersion = SchemaVersion(1);
@@ -101,16 +174,128 @@ pub fn is_version_compatible(
     true
 }
 
+/// Returns why the extension cannot be loaded in the given version of Zed, if
+/// it cannot.
+fn incompatibility_reason(
+    manifest: &ExtensionManifest,
+    app_version: SemanticVersion,
+) -> Option<String> {
+    if manifest.schema_version > CURRENT_SCHEMA_VERSION {
+        return Some(format!(
+            "uses schema version {} (Zed supports up to {CURRENT_SCHEMA_VERSION})",
+            manifest.schema_version
+        ));
+    }
+
+    let min_zed_version = manifest.min_zed_version?;
+    if app_version >= min_zed_version {
+        return None;
+    }
+    Some(format!(
+        "requires Zed {min_zed_version} or later (running {app_version})"
+    ))
+}
+
+/// Returns the extensions in the index that are not loaded because they conflict
+/// with another extension that takes precedence, along with the reason why.
+///
+/// Extensions are considered in order of descending priority, and then of ID,
+/// and each is loaded unless it conflicts with one that is loaded before it.
+fn find_conflicting_extensions(index: &ExtensionIndex) -> BTreeMap<Arc<str>, String> {
+    let mut extensions = index.extensions.values().collect::<Vec<_>>();
+    extensions.sort_by_key(|extension| Reverse(extension.manifest.priority));
+
+    let mut loaded = Vec::<&ExtensionManifest>::new();
+    let mut conflicting = BTreeMap::default();
+    for extension in extensions {
+        let manifest = extension.manifest.as_ref();
+        let conflict = loaded.iter().find(|loaded| {
+            loaded.conflicts.contains(&manifest.id) || manifest.conflicts.contains(&loaded.id)
+        });
+        if let Some(conflict) = conflict {
+            conflicting.insert(
+                manifest.id.clone(),
+                format!(
+                    "conflicts with extension {}, which takes precedence",
+                    conflict.id
+                ),
+            );
+        } else {
+            loaded.push(manifest);
+        }
+    }
+    conflicting
+}
+
+/// Parses the version of an installed extension, logging the first invalid
+/// version of each extension.
+fn parse_extension_version(
+    extension_id: &Arc<str>,
+    version: &Arc<str>,
+    malformed_versions: &mut HashSet<Arc<str>>,
+) -> Option<semver::Version> {
+    let error = match semver::Version::parse(version) {
+        Ok(version) => return Some(version),
+        Err(error) => error,
+    };
+    if malformed_versions.insert(extension_id.clone()) {
+        log::warn!("extension {extension_id} has an invalid version {version:?}: {error}");
+    }
+    None
+}
+
+/// Returns whether the extension can be loaded in the given version of Zed,
+/// either because it supports it or because the user has chosen to load it
+/// regardless.
+fn should_load_extension(
+    manifest: &ExtensionManifest,
+    app_version: SemanticVersion,
+    extension_settings: &ExtensionSettings,
+) -> bool {
+    incompatibility_reason(manifest, app_version).is_none()
+        || extension_settings.should_load_incompatible(&manifest.id)
+}
+
+/// Returns whether the themes, languages, and grammars of the extension should
+/// be registered, ignoring conflicts with other extensions.
+fn should_register_extension(
+    extension: &ExtensionIndexEntry,
+    app_version: SemanticVersion,
+    extension_settings: &ExtensionSettings,
+) -> bool {
+    extension.enabled && should_load_extension(&extension.manifest, app_version, extension_settings)
+}
+
 pub struct ExtensionStore {
     builder: Arc<ExtensionBuilder>,
     extension_index: ExtensionIndex,
+    /// The index of the extensions available from the extension registry.
+    remote_extension_index: ExtensionIndex,
     fs: Arc<dyn Fs>,
     http_client: Arc<HttpClientWithUrl>,
+    retry_policy: RetryPolicy,
     telemetry: Option<Arc<Telemetry>>,
     reload_tx: UnboundedSender<Option<Arc<str>>>,
     reload_complete_senders: Vec<oneshot::Sender<()>>,
+    grammar_store_dir: PathBuf,
+    /// How long the most recent reload took, from rebuilding the index to
+    /// loading the updated extensions.
+    last_reload_duration: Option<Duration>,
+    /// The installed extensions that are not loaded because they are not
+    /// compatible with this version of Zed, along with the reason why.
+    incompatible_extensions: BTreeMap<Arc<str>, String>,
+    /// The installed extensions that are loaded even though they are not
+    /// compatible with this version of Zed, because the user has chosen to.
+    loaded_incompatible_extensions: BTreeSet<Arc<str>>,
+    /// The installed extensions that are not loaded because they conflict with
+    /// another installed extension, along with the reason why.
+    conflicting_extensions: BTreeMap<Arc<str>, String>,
     installed_dir: PathBuf,
     outstanding_operations: BTreeMap<Arc<str>, ExtensionOperation>,
+    deferred_operations: Vec<DeferredOperation>,
+    /// The number of extension bundles whose installation has started, which
+    /// gives each of them its own staging directory.
+    bundle_install_count: usize,
     index_path: PathBuf,
     language_registry: Arc<LanguageRegistry>,
     theme_registry: Arc<ThemeRegistry>,
@@ -118,11 +303,65 @@ pub struct ExtensionStore {
     indexed_docs_registry: Arc<IndexedDocsRegistry>,
     snippet_registry: Arc<SnippetRegistry>,
     modified_extensions: HashSet<Arc<str>>,
+    /// Whether the next reload must re-read every installed extension, rather
+    /// than only those that have been modified or added.
+    full_reload_requested: bool,
     wasm_host: Arc<WasmHost>,
     wasm_extensions: Vec<(Arc<ExtensionManifest>, WasmExtension)>,
+    registered: ExtensionArtifacts,
+    /// The archive sizes declared by the extension registry, by extension ID and version.
+    declared_archive_sizes: HashMap<(Arc<str>, Arc<str>), u64>,
+    /// The grammars produced by each extension while it was running.
+    produced_grammars: HashMap<Arc<str>, Vec<Arc<str>>>,
+    load_errors: Vec<ExtensionLoadError>,
+    /// The invalid manifests found by the most recent rebuild of the index,
+    /// which are also reported in the load errors.
+    manifest_errors: Vec<ManifestError>,
+    /// The extensions with a version that could not be parsed, which is only
+    /// logged for the first such version of each extension.
+    malformed_versions: HashSet<Arc<str>>,
+    /// Hashes of the contents of the registered extension themes, used to avoid
+    /// re-registering themes that have not changed when an extension is reloaded.
+    theme_content_hashes: HashMap<Arc<str>, u64>,
+    /// The languages and themes that could not be registered because their
+    /// names are taken, keyed by the extensions that provide them. They are left
+    /// out of the index until their extensions are loaded again.
+    rejected_artifacts: HashMap<Arc<str>, ExtensionArtifacts>,
+    theme_changes: ThemeChanges,
+    /// The theme changes of the extensions that were loaded while the index is
+    /// being rebuilt, which are reported along with those of the rebuilt index.
+    pending_theme_changes: Option<ThemeChanges>,
+    /// The versions of the language servers that were resolved from the
+    /// version files of a worktree, keyed by language server name and
+    /// worktree root.
+    language_server_versions: HashMap<(LanguageServerName, PathBuf), Option<Arc<str>>>,
+    index_write_delay: Duration,
+    pending_index_json: Option<String>,
+    index_write_task: Option<Task<()>>,
+    index_write_lock: Arc<Mutex<()>>,
+    /// The path of the lockfile that installs are recorded in, if they are
+    /// reproducible.
+    lockfile_path: Option<PathBuf>,
+    lockfile: Arc<parking_lot::Mutex<ExtensionLockfile>>,
+    lockfile_write_lock: Arc<Mutex<()>>,
+    installed_dir_watcher: Task<()>,
+    /// The watchers of the source directories of the installed dev extensions,
+    /// which reload the extensions when their files change.
+    dev_extension_watchers: HashMap<Arc<str>, Task<()>>,
+    /// The extension features in the settings, as of when the language servers
+    /// of the loaded extensions were last registered.
+    extension_features: HashMap<Arc<str>, HashMap<Arc<str>, bool>>,
     tasks: Vec<Task<()>>,
 }
 
+/// An operation that failed because the extension registry could not be
+/// reached, and that is retried once the network is available again.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum DeferredOperation {
+    CheckForUpdates,
+    InstallLatest(Arc<str>),
+}
+
 #[derive(Clone, Copy)]
 pub enum ExtensionOperation {
     Upgrade,
@@ -135,7 +374,45 @@ pub enum Event {
     ExtensionsUpdated,
     StartedReloading,
     ExtensionInstalled(Arc<str>),
+    ExtensionUninstalled(Arc<str>),
+    /// An installed extension was unloaded and loaded again, e.g. because its
+    /// files changed.
+    ExtensionReloaded(Arc<str>),
     ExtensionFailedToLoad(Arc<str>),
+    /// The artifacts of a newly discovered extension were registered while
+    /// the rest of the extension index is still being rebuilt.
+    ExtensionRegistered(Arc<str>),
+    /// A reload found a higher version of an installed extension than the one
+    /// that was previously loaded.
+    ExtensionUpgraded {
+        extension_id: Arc<str>,
+        from: Arc<str>,
+        to: Arc<str>,
+    },
+    ReloadProgress(ReloadPhase),
+    /// A grammar provided by an extension failed to load when it was first used.
+    GrammarFailedToLoad {
+        extension_id: Arc<str>,
+        grammar_name: Arc<str>,
+    },
+}
+
+/// A phase of an extension reload, reported via [`Event::ReloadProgress`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReloadPhase {
+    /// The installed extensions directory is being scanned.
+    Scanning,
+    /// The manifests of the given number of extensions are being parsed.
+    ParsingManifests { extension_count: usize },
+    /// The given number of grammars are being registered. While the index is
+    /// being rebuilt, this happens for each new extension as soon as it has
+    /// been parsed.
+    RegisteringGrammars { grammar_count: usize },
+    /// The given number of theme files are being loaded and registered, which
+    /// also happens for each new extension.
+    RegisteringThemes { theme_count: usize },
+    /// The reload has completed.
+    Done,
 }
 
 impl EventEmitter<Event> for ExtensionStore {}
@@ -144,23 +421,361 @@ struct GlobalExtensionStore(Model<ExtensionStore>);
 
 impl Global for GlobalExtensionStore {}
 
-#[derive(Debug, Deserialize, Serialize, Default, PartialEq, Eq)]
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
 pub struct ExtensionIndex {
+    /// The version of the index's format. Indexes written before it was
+    /// recorded have version 0.
+    #[serde(default)]
+    pub schema_version: u32,
     pub extensions: BTreeMap<Arc<str>, ExtensionIndexEntry>,
     pub themes: BTreeMap<Arc<str>, ExtensionIndexThemeEntry>,
+    /// The theme families provided by extensions, keyed by family name. Each
+    /// of their themes also has an entry in `themes`.
+    #[serde(default)]
+    pub theme_families: BTreeMap<Arc<str>, ExtensionIndexThemeFamilyEntry>,
     pub languages: BTreeMap<Arc<str>, ExtensionIndexLanguageEntry>,
+    /// The languages that are not registered, keyed by name, because an
+    /// extension that takes precedence provides a language with the same name.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub shadowed_languages: BTreeMap<Arc<str>, Vec<ExtensionIndexLanguageEntry>>,
+}
+
+impl Default for ExtensionIndex {
+    fn default() -> Self {
+        Self {
+            schema_version: CURRENT_INDEX_SCHEMA_VERSION,
+            extensions: BTreeMap::default(),
+            themes: BTreeMap::default(),
+            theme_families: BTreeMap::default(),
+            languages: BTreeMap::default(),
+            shadowed_languages: BTreeMap::default(),
+        }
+    }
+}
+
+impl ExtensionIndex {
+    /// Returns an index containing only the given extension and the languages
+    /// and themes that it provides.
+    fn extension_subset(&self, extension_id: &Arc<str>, extension: &ExtensionIndexEntry) -> Self {
+        Self {
+            schema_version: self.schema_version,
+            extensions: BTreeMap::from_iter([(extension_id.clone(), extension.clone())]),
+            themes: self
+                .themes
+                .iter()
+                .filter(|(_, theme)| theme.extension == *extension_id)
+                .map(|(name, theme)| (name.clone(), theme.clone()))
+                .collect(),
+            theme_families: self
+                .theme_families
+                .iter()
+                .filter(|(_, family)| family.extension == *extension_id)
+                .map(|(name, family)| (name.clone(), family.clone()))
+                .collect(),
+            languages: self
+                .languages
+                .iter()
+                .filter(|(_, language)| language.extension == *extension_id)
+                .map(|(name, language)| (name.clone(), language.clone()))
+                .collect(),
+            shadowed_languages: self
+                .shadowed_languages
+                .iter()
+                .filter_map(|(name, languages)| {
+                    let languages = languages
+                        .iter()
+                        .filter(|language| language.extension == *extension_id)
+                        .cloned()
+                        .collect::<Vec<_>>();
+                    (!languages.is_empty()).then(|| (name.clone(), languages))
+                })
+                .collect(),
+        }
+    }
+
+    /// Adds the extensions of the given index to this one, along with the
+    /// languages, themes, and theme families that they provide.
+    ///
+    /// When two extensions provide a theme with the same name, the extension
+    /// whose ID sorts first keeps the name, whichever is added first, and the
+    /// other's theme is renamed to `<name> (<extension ID>)`. Languages with
+    /// the same name are decided in the same way, and the other's language is
+    /// shadowed.
+    fn merge(&mut self, other: ExtensionIndex) {
+        self.extensions.extend(other.extensions);
+        for (language_name, language) in other.languages {
+            self.insert_language(language_name, language);
+        }
+        for (language_name, languages) in other.shadowed_languages {
+            for language in languages {
+                self.insert_language(language_name.clone(), language);
+            }
+        }
+        self.theme_families.extend(other.theme_families);
+        for (theme_name, theme) in other.themes {
+            self.insert_theme(theme_name, theme);
+        }
+    }
+
+    fn insert_language(&mut self, name: Arc<str>, language: ExtensionIndexLanguageEntry) {
+        if let Some(existing) = self.languages.get(&name) {
+            if existing.extension < language.extension {
+                self.shadowed_languages
+                    .entry(name)
+                    .or_default()
+                    .push(language);
+                return;
+            }
+        }
+
+        let extension_id = language.extension.clone();
+        if let Some(existing) = self.languages.insert(name.clone(), language) {
+            if existing.extension != extension_id {
+                self.shadowed_languages
+                    .entry(name)
+                    .or_default()
+                    .push(existing);
+            }
+        }
+    }
+
+    fn insert_theme(&mut self, key: Arc<str>, mut theme: ExtensionIndexThemeEntry) {
+        // A theme that was renamed when it was last indexed may no longer need
+        // to be, so its name is always decided again.
+        let name = theme.original_name.take().unwrap_or_else(|| key.clone());
+        let is_preceded = self
+            .themes
+            .get(&name)
+            .is_some_and(|existing| existing.extension < theme.extension);
+        if is_preceded {
+            self.insert_renamed_theme(&key, name, theme);
+            return;
+        }
+
+        if let Some(existing) = self.themes.remove(&name) {
+            if existing.extension != theme.extension {
+                self.insert_renamed_theme(&name, name.clone(), existing);
+            }
+        }
+        self.rename_family_theme(&theme.extension, &key, &name);
+        self.themes.insert(name, theme);
+    }
+
+    fn insert_renamed_theme(
+        &mut self,
+        key: &Arc<str>,
+        name: Arc<str>,
+        mut theme: ExtensionIndexThemeEntry,
+    ) {
+        let renamed_name: Arc<str> = format!("{name} ({})", theme.extension).into();
+        self.rename_family_theme(&theme.extension, key, &renamed_name);
+        theme.original_name = Some(name);
+        self.themes.insert(renamed_name, theme);
+    }
+
+    fn rename_family_theme(&mut self, extension_id: &Arc<str>, from: &Arc<str>, to: &Arc<str>) {
+        if from == to {
+            return;
+        }
+        for family in self.theme_families.values_mut() {
+            if family.extension == *extension_id {
+                for theme_name in &mut family.themes {
+                    if theme_name == from {
+                        *theme_name = to.clone();
+                    }
+                }
+            }
+        }
+    }
+
+    /// Returns the names under which the themes of the given extensions are
+    /// registered, keyed by extension ID and then by the themes' names in their
+    /// files, for the themes that are renamed.
+    fn theme_renames(
+        &self,
+        extension_ids: &[Arc<str>],
+    ) -> HashMap<Arc<str>, HashMap<Arc<str>, Arc<str>>> {
+        let mut renames = HashMap::<Arc<str>, HashMap<_, _>>::default();
+        for (theme_name, theme) in &self.themes {
+            if let Some(original_name) = &theme.original_name {
+                if extension_ids.contains(&theme.extension) {
+                    renames
+                        .entry(theme.extension.clone())
+                        .or_default()
+                        .insert(original_name.clone(), theme_name.clone());
+                }
+            }
+        }
+        renames
+    }
+
+    /// Removes the given languages and themes, keyed by the extensions that
+    /// provide them, from the index.
+    fn remove_rejected(&mut self, rejected: &HashMap<Arc<str>, ExtensionArtifacts>) {
+        self.languages.retain(|name, language| {
+            !rejected
+                .get(&language.extension)
+                .is_some_and(|artifacts| artifacts.languages.contains(name))
+        });
+        self.themes.retain(|name, theme| {
+            !rejected
+                .get(&theme.extension)
+                .is_some_and(|artifacts| artifacts.themes.contains(name))
+        });
+    }
+
+    /// Removes the given extensions from the index, along with the languages,
+    /// themes, and theme families that they provide.
+    fn remove_extensions(&mut self, extension_ids: &[Arc<str>]) {
+        self.extensions
+            .retain(|extension_id, _| !extension_ids.contains(extension_id));
+        self.themes
+            .retain(|_, theme| !extension_ids.contains(&theme.extension));
+        self.theme_families
+            .retain(|_, family| !extension_ids.contains(&family.extension));
+        self.languages
+            .retain(|_, language| !extension_ids.contains(&language.extension));
+
+        // The languages that the removed extensions shadowed take their place.
+        for (language_name, languages) in mem::take(&mut self.shadowed_languages) {
+            for language in languages {
+                if !extension_ids.contains(&language.extension) {
+                    self.insert_language(language_name.clone(), language);
+                }
+            }
+        }
+    }
+
+    /// Returns the grammars that are not registered, as pairs of the extension
+    /// ID and grammar name, because an extension that takes precedence provides
+    /// a grammar with the same name.
+    ///
+    /// As with themes and languages, the extension whose ID sorts first takes
+    /// precedence.
+    fn shadowed_grammars(&self) -> BTreeSet<(Arc<str>, Arc<str>)> {
+        let mut grammar_names = HashSet::default();
+        let mut shadowed_grammars = BTreeSet::default();
+        for (extension_id, extension) in &self.extensions {
+            for grammar_name in extension.manifest.grammars.keys() {
+                if !grammar_names.insert(grammar_name) {
+                    shadowed_grammars.insert((extension_id.clone(), grammar_name.clone()));
+                }
+            }
+        }
+        shadowed_grammars
+    }
+
+    /// Returns the directory of the given extension within the given installed
+    /// extensions directory.
+    fn extension_dir(&self, installed_dir: &Path, extension_id: &str) -> PathBuf {
+        let dir_name = self
+            .extensions
+            .get(extension_id)
+            .map_or(extension_id, |extension| extension.installed_dir_name());
+        installed_dir.join(dir_name)
+    }
+
+    /// Makes all of the index's references to each extension ID share a single
+    /// allocation. An index that is built from the extensions' directories already
+    /// does this, but an index that is read from disk has a copy of the ID in
+    /// every entry.
+    fn intern_extension_ids(&mut self) {
+        let mut extension_ids = HashSet::<Arc<str>>::default();
+        let mut intern = |id: &mut Arc<str>| match extension_ids.get(id) {
+            Some(interned_id) => *id = interned_id.clone(),
+            None => {
+                extension_ids.insert(id.clone());
+            }
+        };
+
+        self.extensions = mem::take(&mut self.extensions)
+            .into_iter()
+            .map(|(mut extension_id, mut extension)| {
+                intern(&mut extension_id);
+                intern(&mut Arc::make_mut(&mut extension.manifest).id);
+                (extension_id, extension)
+            })
+            .collect();
+        for theme in self.themes.values_mut() {
+            intern(&mut theme.extension);
+        }
+        for family in self.theme_families.values_mut() {
+            intern(&mut family.extension);
+        }
+        for language in self
+            .languages
+            .values_mut()
+            .chain(self.shadowed_languages.values_mut().flatten())
+        {
+            intern(&mut language.extension);
+        }
+    }
 }
 
 #[derive(Clone, PartialEq, Eq, Debug, Deserialize, Serialize)]
 pub struct ExtensionIndexEntry {
     pub manifest: Arc<ExtensionManifest>,
     pub dev: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provenance: Option<ExtensionProvenance>,
+    /// The hashes of the extension's grammars that are kept in the grammar store,
+    /// keyed by grammar name.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub grammar_hashes: BTreeMap<Arc<str>, Arc<str>>,
+    /// Whether the extension is loaded. A disabled extension stays installed,
+    /// but none of the things that it provides are registered.
+    #[serde(default = "util::serde::default_true")]
+    pub enabled: bool,
+    /// The name of the extension's directory within the installed extensions
+    /// directory, if it is named `<id>-<version>` rather than after the
+    /// extension's ID.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dir_name: Option<Arc<str>>,
+    /// When the extension was first installed. This is unknown for dev
+    /// extensions, and for extensions installed before it was recorded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub installed_at: Option<SystemTime>,
+}
+
+impl ExtensionIndexEntry {
+    /// Returns the name of the extension's directory within the installed
+    /// extensions directory.
+    pub fn installed_dir_name(&self) -> &str {
+        self.dir_name.as_deref().unwrap_or(&self.manifest.id)
+    }
+}
+
+/// Where an installed extension came from, recorded when it is installed from
+/// a signed source.
+#[derive(Clone, PartialEq, Eq, Debug, Deserialize, Serialize)]
+pub struct ExtensionProvenance {
+    /// The party that signed the extension.
+    pub signed_by: String,
 }
 
 #[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Deserialize, Serialize)]
 pub struct ExtensionIndexThemeEntry {
     extension: Arc<str>,
     path: PathBuf,
+    /// The appearance of the theme, which is missing from indexes written
+    /// before it was recorded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    appearance: Option<AppearanceContent>,
+    /// The theme's name in its file, if it is registered under another name
+    /// because an extension that takes precedence provides a theme with the
+    /// same name.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    original_name: Option<Arc<str>>,
+}
+
+/// A theme file provided by an extension, along with the themes that it defines.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Deserialize, Serialize)]
+pub struct ExtensionIndexThemeFamilyEntry {
+    pub extension: Arc<str>,
+    pub path: PathBuf,
+    pub author: Arc<str>,
+    /// The names of the themes in the family, in the order in which the file defines them.
+    pub themes: Vec<Arc<str>>,
 }
 
 #[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Deserialize, Serialize)]
@@ -169,6 +784,355 @@ pub struct ExtensionIndexLanguageEntry {
     path: PathBuf,
     matcher: LanguageMatcher,
     grammar: Option<Arc<str>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    grammar_variants: Vec<GrammarVariant>,
+    /// The position of the language in the `[[languages]]` array of its
+    /// `config.toml`, if the file configures several languages.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    variant: Option<usize>,
+}
+
+/// A grammar that is used in place of a language's default grammar for files
+/// whose content matches `content_regex`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct GrammarVariant {
+    #[serde(
+        serialize_with = "serialize_content_regex",
+        deserialize_with = "deserialize_content_regex"
+    )]
+    pub content_regex: Regex,
+    pub grammar: Arc<str>,
+}
+
+impl GrammarVariant {
+    fn key(&self) -> (&str, &str) {
+        (self.content_regex.as_str(), &self.grammar)
+    }
+}
+
+impl PartialEq for GrammarVariant {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for GrammarVariant {}
+
+impl Ord for GrammarVariant {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.key().cmp(&other.key())
+    }
+}
+
+impl PartialOrd for GrammarVariant {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+fn serialize_content_regex<S: serde::Serializer>(
+    regex: &Regex,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(regex.as_str())
+}
+
+fn deserialize_content_regex<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Regex, D::Error> {
+    let source = String::deserialize(deserializer)?;
+    Regex::new(&source).map_err(serde::de::Error::custom)
+}
+
+/// Returns the configurations of the languages in the given `config.toml`.
+///
+/// The file either configures a single language, or several variants of a
+/// language (e.g. dialects that share a grammar) in a `[[languages]]` array of
+/// tables. The keys outside of the array are shared by all of the variants.
+fn language_config_tables(config: &str) -> Result<Vec<::toml::Table>> {
+    let mut shared = ::toml::from_str::<::toml::Table>(config)?;
+    let Some(variants) = shared.remove("languages") else {
+        return Ok(vec![shared]);
+    };
+    let ::toml::Value::Array(variants) = variants else {
+        bail!("languages must be an array of tables");
+    };
+    variants
+        .into_iter()
+        .map(|variant| {
+            let ::toml::Value::Table(variant) = variant else {
+                bail!("languages must be an array of tables");
+            };
+            let mut table = shared.clone();
+            table.extend(variant);
+            Ok(table)
+        })
+        .collect()
+}
+
+/// Parses the configuration of the language with the given position in the
+/// `[[languages]]` array of the given `config.toml`, if it has one.
+fn parse_language_config(config: &str, variant: Option<usize>) -> Result<LanguageConfig> {
+    let table = language_config_tables(config)?
+        .into_iter()
+        .nth(variant.unwrap_or(0))
+        .ok_or_else(|| anyhow!("no language at position {variant:?} in config.toml"))?;
+    Ok(::toml::Value::Table(table).try_into()?)
+}
+
+/// The parts of a language's `config.toml` that only apply to extensions.
+#[derive(Deserialize)]
+struct ExtensionLanguageConfig {
+    #[serde(default)]
+    grammar_variants: Vec<GrammarVariant>,
+}
+
+/// The names of the languages, themes, and grammars provided by extensions.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ExtensionArtifacts {
+    pub languages: BTreeSet<Arc<str>>,
+    pub themes: BTreeSet<Arc<str>>,
+    pub grammars: BTreeSet<Arc<str>>,
+}
+
+/// The changes made by [`ExtensionStore::reconcile`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ReconcileReport {
+    pub added: ExtensionArtifacts,
+    pub removed: ExtensionArtifacts,
+}
+
+/// The themes that the most recent reload added, removed, or changed, as
+/// returned by [`ExtensionStore::theme_changes`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ThemeChanges {
+    pub added: BTreeSet<Arc<str>>,
+    pub removed: BTreeSet<Arc<str>>,
+    /// The themes that are provided both before and after the reload, but
+    /// whose contents are different.
+    pub changed: BTreeSet<Arc<str>>,
+}
+
+impl ThemeChanges {
+    /// Returns the themes that are added and removed between the two indices.
+    /// The changed themes are only known once the themes' files are read.
+    fn between(old_index: &ExtensionIndex, new_index: &ExtensionIndex) -> Self {
+        Self {
+            added: new_index
+                .themes
+                .keys()
+                .filter(|theme_name| !old_index.themes.contains_key(*theme_name))
+                .cloned()
+                .collect(),
+            removed: old_index
+                .themes
+                .keys()
+                .filter(|theme_name| !new_index.themes.contains_key(*theme_name))
+                .cloned()
+                .collect(),
+            changed: BTreeSet::default(),
+        }
+    }
+
+    /// Combines these changes with those of a later update.
+    fn followed_by(self, later: Self) -> Self {
+        let changed = self
+            .changed
+            .iter()
+            .chain(later.changed.iter())
+            .chain(self.removed.intersection(&later.added))
+            .filter(|theme_name| !later.removed.contains(*theme_name))
+            .cloned()
+            .collect();
+        Self {
+            added: self
+                .added
+                .difference(&later.removed)
+                .chain(later.added.difference(&self.removed))
+                .cloned()
+                .collect(),
+            removed: self
+                .removed
+                .difference(&later.added)
+                .chain(later.removed.difference(&self.added))
+                .cloned()
+                .collect(),
+            changed,
+        }
+    }
+}
+
+/// The extensions that [`ExtensionStore::extensions_updated`] unloads and
+/// loads. An extension that is reloaded is in both.
+#[derive(Debug, Default)]
+struct ExtensionChanges {
+    to_unload: Vec<Arc<str>>,
+    to_load: Vec<Arc<str>>,
+}
+
+impl ExtensionChanges {
+    fn is_empty(&self) -> bool {
+        self.to_unload.is_empty() && self.to_load.is_empty()
+    }
+
+    fn reloaded_extensions(&self) -> Vec<Arc<str>> {
+        self.to_unload
+            .iter()
+            .filter(|id| self.to_load.contains(id))
+            .cloned()
+            .collect()
+    }
+}
+
+/// The files of the loaded extensions that are read in the background.
+#[derive(Default)]
+struct ExtensionFiles {
+    /// The theme files, along with the ID of the extension providing them.
+    themes: Vec<(Arc<str>, PathBuf)>,
+    /// The themes in those files that are not registered, because their names
+    /// are taken.
+    rejected_themes: HashSet<Arc<str>>,
+    snippets: Vec<PathBuf>,
+    /// The default settings files, along with the ID of their extension.
+    default_settings: Vec<(Arc<str>, PathBuf)>,
+    extensions: Vec<ExtensionIndexEntry>,
+}
+
+/// The results of [`load_theme_files`].
+#[derive(Default)]
+struct LoadedThemes {
+    warnings: Vec<ExtensionLoadError>,
+    hashes: Vec<(Arc<str>, u64)>,
+    /// The themes whose contents differ from their previous hashes.
+    changed: Vec<Arc<str>>,
+}
+
+/// How [`ExtensionStore::uninstall_extension`] uninstalls an extension.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct UninstallOptions {
+    /// Preserve the extension's data directory, to be restored if the
+    /// extension is installed again.
+    pub keep_data: bool,
+    /// Remove nothing, and only report what would be removed.
+    pub dry_run: bool,
+}
+
+/// What [`ExtensionStore::uninstall_extension`] removes, or would remove.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct UninstallReport {
+    /// The languages, themes, and grammars that are no longer provided by any
+    /// installed extension.
+    pub artifacts: ExtensionArtifacts,
+    /// The directory the extension is installed in.
+    pub extension_dir: PathBuf,
+    /// The files in the grammar store that no other extension shares.
+    pub grammar_store_paths: Vec<PathBuf>,
+}
+
+/// An installed extension, as listed by [`ExtensionStore::installed_extensions`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ExtensionInfo {
+    pub id: Arc<str>,
+    pub name: String,
+    pub version: Arc<str>,
+    /// Whether the extension is a dev extension, rather than one installed
+    /// from the extension registry or a bundle.
+    pub dev: bool,
+    pub provenance: Option<ExtensionProvenance>,
+    /// When the extension was first installed, if known.
+    pub installed_at: Option<SystemTime>,
+    pub enabled: bool,
+    pub load_status: ExtensionLoadStatus,
+    pub theme_count: usize,
+    pub language_count: usize,
+    pub grammar_count: usize,
+    pub language_server_count: usize,
+}
+
+/// Whether an installed extension is loaded, and if not, why.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ExtensionLoadStatus {
+    Loaded,
+    /// The extension was loaded, but part of it failed to load.
+    Failed,
+    /// The user has disabled the extension.
+    Disabled,
+    /// The extension isn't compatible with this version of Zed.
+    Incompatible {
+        reason: String,
+    },
+    /// The extension conflicts with another installed extension.
+    Conflicting {
+        reason: String,
+    },
+}
+
+/// What an installed extension provides, as returned by
+/// [`ExtensionStore::describe_extension`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ExtensionReport {
+    /// The directories of the extension's languages, keyed by language name.
+    pub languages: BTreeMap<Arc<str>, PathBuf>,
+    /// The files defining the extension's themes, keyed by theme name.
+    pub themes: BTreeMap<Arc<str>, PathBuf>,
+    /// The WASM files of the extension's grammars, keyed by grammar name.
+    pub grammars: BTreeMap<Arc<str>, PathBuf>,
+    /// The languages that each of the extension's language servers is used for.
+    pub language_servers: BTreeMap<LanguageServerName, Vec<Arc<str>>>,
+}
+
+/// The syntax tokens that every theme is expected to provide a style for.
+const STANDARD_SYNTAX_TOKENS: &[&str] = &[
+    "attribute",
+    "boolean",
+    "comment",
+    "constant",
+    "function",
+    "keyword",
+    "number",
+    "operator",
+    "property",
+    "punctuation",
+    "string",
+    "type",
+    "variable",
+];
+
+/// How severe an [`ExtensionLoadError`] is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LoadErrorSeverity {
+    /// The extension was loaded, but may not behave as intended.
+    Warning,
+    /// The extension, or part of it, could not be loaded.
+    Error,
+}
+
+/// A problem that was encountered while loading an extension.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ExtensionLoadError {
+    pub extension_id: Arc<str>,
+    pub severity: LoadErrorSeverity,
+    pub message: String,
+}
+
+/// The outcome of validating one of the theme files of an installed extension.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ThemeValidationResult {
+    pub extension_id: Arc<str>,
+    /// The path of the theme file, relative to the extension's directory.
+    pub path: PathBuf,
+    /// The names of the themes in the file, if it could be parsed.
+    pub theme_names: Vec<String>,
+    pub issues: Vec<ExtensionLoadError>,
+}
+
+impl ThemeValidationResult {
+    /// Returns whether the theme file can be loaded, possibly with warnings.
+    pub fn is_valid(&self) -> bool {
+        self.issues
+            .iter()
+            .all(|issue| issue.severity != LoadErrorSeverity::Error)
+    }
 }
 
 actions!(zed, [ReloadExtensions]);
@@ -183,12 +1147,14 @@ pub fn init(
 ) {
     ExtensionSettings::register(cx);
 
+    let mut client_status = client.status();
     let store = cx.new_model(move |cx| {
         ExtensionStore::new(
             paths::extensions_dir().clone(),
             None,
             fs,
             client.http_client().clone(),
+            RetryPolicy::default(),
             Some(client.telemetry().clone()),
             node_runtime,
             language_registry,
@@ -200,6 +1166,23 @@ pub fn init(
         )
     });
 
+    // Retry the operations that failed while offline once the client connects.
+    cx.spawn({
+        let store = store.downgrade();
+        |mut cx| async move {
+            while let Some(status) = client_status.next().await {
+                if status.is_connected()
+                    && store
+                        .update(&mut cx, |store, cx| store.notify_network_available(cx))
+                        .is_err()
+                {
+                    break;
+                }
+            }
+        }
+    })
+    .detach();
+
     cx.on_action(|_: &ReloadExtensions, cx| {
         let store = cx.global::<GlobalExtensionStore>().0.clone();
         store.update(cx, |store, cx| drop(store.reload(None, cx)));
@@ -224,6 +1207,7 @@ impl ExtensionStore {
         build_dir: Option<PathBuf>,
         fs: Arc<dyn Fs>,
         http_client: Arc<HttpClientWithUrl>,
+        retry_policy: RetryPolicy,
         telemetry: Option<Arc<Telemetry>>,
         node_runtime: Arc<dyn NodeRuntime>,
         language_registry: Arc<LanguageRegistry>,
@@ -237,10 +1221,31 @@ impl ExtensionStore {
         let build_dir = build_dir.unwrap_or_else(|| extensions_dir.join("build"));
         let installed_dir = extensions_dir.join("installed");
         let index_path = extensions_dir.join("index.json");
+        let grammar_store_dir = extensions_dir.join(GRAMMAR_STORE_DIR_NAME);
 
         let (reload_tx, mut reload_rx) = unbounded();
+        let (produced_grammar_tx, mut produced_grammar_rx) = unbounded();
+        let (call_error_tx, mut call_error_rx) = unbounded();
+        let (resolved_version_tx, mut resolved_version_rx) = unbounded();
+
+        // Like the index, the lockfile is loaded synchronously on startup, so
+        // that the locked versions are used by the first installs.
+        let lockfile_path = ExtensionSettings::get_global(cx)
+            .extension_lockfile_path
+            .clone();
+        let lockfile = lockfile_path
+            .as_ref()
+            .and_then(|path| {
+                cx.background_executor()
+                    .block(ExtensionLockfile::load(fs.as_ref(), path))
+                    .log_err()
+            })
+            .unwrap_or_default();
+        let lockfile = Arc::new(parking_lot::Mutex::new(lockfile));
+
         let mut this = Self {
             extension_index: Default::default(),
+            remote_extension_index: Default::default(),
             installed_dir,
             index_path,
             builder: Arc::new(ExtensionBuilder::new(
@@ -250,19 +1255,52 @@ impl ExtensionStore {
                 build_dir,
             )),
             outstanding_operations: Default::default(),
+            deferred_operations: Vec::new(),
+            bundle_install_count: 0,
             modified_extensions: Default::default(),
+            full_reload_requested: false,
             reload_complete_senders: Vec::new(),
+            grammar_store_dir,
+            last_reload_duration: None,
+            incompatible_extensions: BTreeMap::default(),
+            loaded_incompatible_extensions: BTreeSet::default(),
+            conflicting_extensions: BTreeMap::default(),
             wasm_host: WasmHost::new(
                 fs.clone(),
                 http_client.clone(),
+                retry_policy,
                 node_runtime,
                 language_registry.clone(),
                 work_dir,
+                produced_grammar_tx,
+                call_error_tx,
+                lockfile_path.is_some().then(|| lockfile.clone()),
+                resolved_version_tx,
+                ExtensionSettings::get_global(cx).max_concurrent_calls(),
                 cx,
             ),
             wasm_extensions: Vec::new(),
+            registered: Default::default(),
+            declared_archive_sizes: HashMap::default(),
+            produced_grammars: HashMap::default(),
+            load_errors: Vec::new(),
+            manifest_errors: Vec::new(),
+            malformed_versions: HashSet::default(),
+            theme_content_hashes: HashMap::default(),
+            rejected_artifacts: HashMap::default(),
+            theme_changes: ThemeChanges::default(),
+            pending_theme_changes: None,
+            language_server_versions: HashMap::default(),
+            index_write_delay: INDEX_WRITE_DELAY,
+            pending_index_json: None,
+            index_write_task: None,
+            index_write_lock: Default::default(),
+            lockfile_path,
+            lockfile,
+            lockfile_write_lock: Default::default(),
             fs,
             http_client,
+            retry_policy,
             telemetry,
             language_registry,
             theme_registry,
@@ -270,14 +1308,90 @@ impl ExtensionStore {
             indexed_docs_registry,
             snippet_registry,
             reload_tx,
+            installed_dir_watcher: Task::ready(()),
+            dev_extension_watchers: HashMap::default(),
+            extension_features: ExtensionSettings::get_global(cx).extension_features.clone(),
             tasks: Vec::new(),
         };
 
+        cx.on_app_quit(|this, cx| {
+            let flush = this.flush(cx);
+            async move {
+                flush.await.log_err();
+            }
+        })
+        .detach();
+
+        cx.observe_global::<SettingsStore>(|this, cx| this.extension_settings_changed(cx))
+            .detach();
+
+        // Register the grammars that extensions produce while they are running.
+        this.tasks.push(cx.spawn(|this, mut cx| async move {
+            while let Some(grammar) = produced_grammar_rx.next().await {
+                if this
+                    .update(&mut cx, |this, _| this.register_produced_grammar(grammar))
+                    .is_err()
+                {
+                    break;
+                }
+            }
+        }));
+
+        // Record the errors that extensions encounter while they are running.
+        this.tasks.push(cx.spawn(|this, mut cx| async move {
+            while let Some(error) = call_error_rx.next().await {
+                if this
+                    .update(&mut cx, |this, cx| this.record_call_error(error, cx))
+                    .is_err()
+                {
+                    break;
+                }
+            }
+        }));
+
+        // Record the versions that extensions resolve in the lockfile.
+        this.tasks.push(cx.spawn(|this, mut cx| async move {
+            while let Some(resolved) = resolved_version_rx.next().await {
+                if this
+                    .update(&mut cx, |this, cx| {
+                        this.record_resolved_version(resolved, cx)
+                    })
+                    .is_err()
+                {
+                    break;
+                }
+            }
+        }));
+
+        // Record the grammars that fail to load when a language first needs them.
+        let mut grammar_load_failures = this.language_registry.grammar_load_failures();
+        this.tasks.push(cx.spawn(|this, mut cx| async move {
+            while let Some(failure) = grammar_load_failures.next().await {
+                if this
+                    .update(&mut cx, |this, cx| {
+                        this.record_grammar_load_failure(failure, cx)
+                    })
+                    .is_err()
+                {
+                    break;
+                }
+            }
+        }));
+
         // The extensions store maintains an index file, which contains a complete
         // list of the installed extensions and the resources that they provide.
         // This index is loaded synchronously on startup.
         let (index_content, index_metadata, extensions_metadata) =
             cx.background_executor().block(async {
+                // Installs that were interrupted are cleaned up first, so that the
+                // index reflects what they left installed.
+                clean_up_interrupted_installs(
+                    this.fs.as_ref(),
+                    &this.installed_dir,
+                    &this.wasm_host.work_dir,
+                )
+                .await
+                .log_err();
                 futures::join!(
                     this.fs.load(&this.index_path),
                     this.fs.metadata(&this.index_path),
@@ -291,12 +1405,37 @@ impl ExtensionStore {
         let mut extension_index = ExtensionIndex::default();
         let mut extension_index_needs_rebuild = true;
         if let Some(index_content) = index_content.ok() {
-            if let Some(index) = serde_json::from_str(&index_content).log_err() {
+            if let Some(mut index) = serde_json::from_str::<ExtensionIndex>(&index_content)
+                .log_err()
+                // An index written in an older format, or in a newer one that
+                // this version of Zed may misread, is rebuilt from scratch.
+                .filter(|index| {
+                    let is_current = index.schema_version == CURRENT_INDEX_SCHEMA_VERSION;
+                    if !is_current {
+                        log::info!(
+                            "rebuilding extension index with schema version {}",
+                            index.schema_version
+                        );
+                    }
+                    is_current
+                })
+            {
+                index.intern_extension_ids();
+                // Indexes written before theme appearances and families were
+                // recorded are rebuilt, so that the theme files are read once to
+                // find them.
+                let is_missing_theme_metadata = index
+                    .themes
+                    .values()
+                    .any(|theme| theme.appearance.is_none())
+                    || (index.theme_families.is_empty() && !index.themes.is_empty());
                 extension_index = index;
                 if let (Ok(Some(index_metadata)), Ok(Some(extensions_metadata))) =
                     (index_metadata, extensions_metadata)
                 {
-                    if index_metadata.mtime > extensions_metadata.mtime {
+                    if index_metadata.mtime > extensions_metadata.mtime
+                        && !is_missing_theme_metadata
+                    {
                         extension_index_needs_rebuild = false;
                     }
                 }
@@ -336,16 +1475,26 @@ impl ExtensionStore {
                 loop {
                     select_biased! {
                         _ = debounce_timer => {
-                            let index = this
+                            let start_time = Instant::now();
+                            let (index, manifest_errors) = this
                                 .update(&mut cx, |this, cx| this.rebuild_extension_index(cx))?
                                 .await;
                             this.update(&mut cx, |this, cx| this.extensions_updated(index, cx))?
                                 .await;
+                            this.update(&mut cx, |this, cx| {
+                                this.reload_complete_senders.clear();
+                                this.set_manifest_errors(manifest_errors, cx);
+                                this.last_reload_duration = Some(start_time.elapsed());
+                                cx.emit(Event::ReloadProgress(ReloadPhase::Done))
+                            })?;
                         }
                         extension_id = reload_rx.next() => {
                             let Some(extension_id) = extension_id else { break; };
-                            this.update(&mut cx, |this, _| {
-                                this.modified_extensions.extend(extension_id);
+                            this.update(&mut cx, |this, _| match extension_id {
+                                Some(extension_id) => {
+                                    this.modified_extensions.insert(extension_id);
+                                }
+                                None => this.full_reload_requested = true,
                             })?;
                             debounce_timer = cx
                                 .background_executor()
@@ -360,36 +1509,256 @@ impl ExtensionStore {
             .map(drop)
         }));
 
-        // Watch the installed extensions directory for changes. Whenever changes are
-        // detected, rebuild the extension index, and load/unload any extensions that
-        // have been added, removed, or modified.
-        this.tasks.push(cx.background_executor().spawn({
-            let fs = this.fs.clone();
-            let reload_tx = this.reload_tx.clone();
-            let installed_dir = this.installed_dir.clone();
-            async move {
-                let (mut paths, _) = fs.watch(&installed_dir, FS_WATCH_LATENCY).await;
-                while let Some(paths) = paths.next().await {
-                    for path in paths {
-                        let Ok(event_path) = path.strip_prefix(&installed_dir) else {
-                            continue;
-                        };
-
-                        if let Some(path::Component::Normal(extension_dir_name)) =
-                            event_path.components().next()
-                        {
-                            if let Some(extension_id) = extension_dir_name.to_str() {
-                                reload_tx.unbounded_send(Some(extension_id.into())).ok();
-                            }
-                        }
-                    }
-                }
-            }
-        }));
+        this.installed_dir_watcher = this.watch_installed_dir(cx);
 
         this
     }
 
+    /// Watches the installed extensions directory for changes. Whenever changes are
+    /// detected, rebuild the extension index, and load/unload any extensions that
+    /// have been added, removed, or modified.
+    fn watch_installed_dir(&self, cx: &ModelContext<Self>) -> Task<()> {
+        let fs = self.fs.clone();
+        let reload_tx = self.reload_tx.clone();
+        let installed_dir = self.installed_dir.clone();
+        cx.background_executor().spawn(async move {
+            let (mut paths, _) = fs.watch(&installed_dir, FS_WATCH_LATENCY).await;
+            while let Some(paths) = paths.next().await {
+                for path in paths {
+                    let Ok(event_path) = path.strip_prefix(&installed_dir) else {
+                        continue;
+                    };
+
+                    if let Some(path::Component::Normal(extension_dir_name)) =
+                        event_path.components().next()
+                    {
+                        if let Some(extension_dir_name) = extension_dir_name.to_str() {
+                            let extension_id = parse_versioned_dir_name(extension_dir_name)
+                                .map_or(extension_dir_name, |(extension_id, _)| extension_id);
+                            reload_tx.unbounded_send(Some(extension_id.into())).ok();
+                        }
+                    }
+                }
+            }
+        })
+    }
+
+    /// Watches the source directories of the dev extensions in the given index
+    /// that are not watched yet, and stops watching those of the extensions that
+    /// are no longer installed.
+    fn update_dev_extension_watchers(&mut self, index: &ExtensionIndex, cx: &ModelContext<Self>) {
+        self.dev_extension_watchers.retain(|extension_id, _| {
+            index
+                .extensions
+                .get(extension_id)
+                .map_or(false, |extension| extension.dev)
+        });
+        for (extension_id, extension) in &index.extensions {
+            if extension.dev && !self.dev_extension_watchers.contains_key(extension_id) {
+                let watcher = self.watch_dev_extension(extension_id.clone(), cx);
+                self.dev_extension_watchers
+                    .insert(extension_id.clone(), watcher);
+            }
+        }
+    }
+
+    /// Watches the source directory of the given dev extension, and reloads the
+    /// extension whenever its files change.
+    ///
+    /// Changes within the directory are not seen by the watcher of the installed
+    /// extensions directory, which only contains a symlink to it. Successive
+    /// changes are debounced into a single reload by the reload task.
+    fn watch_dev_extension(&self, extension_id: Arc<str>, cx: &ModelContext<Self>) -> Task<()> {
+        let fs = self.fs.clone();
+        let reload_tx = self.reload_tx.clone();
+        let installed_path = self.installed_dir.join(extension_id.as_ref());
+        cx.spawn(|this, mut cx| async move {
+            let Some(source_dir) = fs.canonicalize(&installed_path).await.log_err() else {
+                return;
+            };
+            let (mut paths, _) = fs.watch(&source_dir, FS_WATCH_LATENCY).await;
+            while let Some(paths) = paths.next().await {
+                // Building the extension's Rust crate writes to its `target`
+                // directory, which does not affect the extension.
+                let changed_paths = paths
+                    .iter()
+                    .filter_map(|path| path.strip_prefix(&source_dir).ok())
+                    .filter(|path| !path.starts_with("target"))
+                    .collect::<Vec<_>>();
+                if changed_paths.is_empty() {
+                    continue;
+                }
+
+                let Ok(reloaded_queries) = this.update(&mut cx, |this, _| {
+                    this.reload_language_queries(&extension_id, &changed_paths)
+                }) else {
+                    break;
+                };
+                if !reloaded_queries
+                    && reload_tx
+                        .unbounded_send(Some(extension_id.clone()))
+                        .is_err()
+                {
+                    break;
+                }
+            }
+        })
+    }
+
+    /// Reloads the languages of the given extension whose queries are among the
+    /// given changed paths, relative to the extension's directory, without
+    /// reloading their grammars, so that edits to the queries of dev extensions
+    /// take effect quickly.
+    ///
+    /// Returns `false`, without reloading anything, if any of the changed paths
+    /// is not a query of one of the extension's registered languages, in which
+    /// case the entire extension must be reloaded.
+    fn reload_language_queries(&self, extension_id: &Arc<str>, changed_paths: &[&Path]) -> bool {
+        let mut languages_to_reload = BTreeSet::default();
+        for path in changed_paths {
+            if path.extension() != Some("scm".as_ref()) {
+                return false;
+            }
+            // A `config.toml` can configure several languages, which share
+            // their queries.
+            let language_names = self
+                .extension_index
+                .languages
+                .iter()
+                .filter(|(language_name, language)| {
+                    language.extension == *extension_id
+                        && path.parent() == Some(language.path.as_path())
+                        && self.registered.languages.contains(*language_name)
+                })
+                .map(|(language_name, _)| language_name.clone())
+                .collect::<Vec<_>>();
+            if language_names.is_empty() {
+                return false;
+            }
+            languages_to_reload.extend(language_names);
+        }
+
+        self.language_registry
+            .reload_languages(&languages_to_reload.into_iter().collect::<Vec<_>>());
+        true
+    }
+
+    /// Moves the installed extensions, along with their work directories,
+    /// grammars and index, into the given directory, and reloads them from there.
+    ///
+    /// This fails if any extension is being installed, upgraded or removed.
+    pub fn set_extensions_dir(
+        &mut self,
+        extensions_dir: PathBuf,
+        cx: &mut ModelContext<Self>,
+    ) -> Task<Result<()>> {
+        if let Some(extension_id) = self.outstanding_operations.keys().next() {
+            return Task::ready(Err(anyhow!(
+                "cannot move the extensions directory while extension {extension_id} is being modified"
+            )));
+        }
+
+        let moves = [
+            (self.installed_dir.clone(), extensions_dir.join("installed")),
+            (self.wasm_host.work_dir.clone(), extensions_dir.join("work")),
+            (
+                self.grammar_store_dir.clone(),
+                extensions_dir.join(GRAMMAR_STORE_DIR_NAME),
+            ),
+            (self.index_path.clone(), extensions_dir.join("index.json")),
+        ];
+        let flush = self.flush(cx);
+        let fs = self.fs.clone();
+        cx.spawn(|this, mut cx| async move {
+            flush.await?;
+            fs.create_dir(&extensions_dir).await?;
+            for (old_path, new_path) in &moves {
+                if old_path == new_path || fs.metadata(old_path).await?.is_none() {
+                    continue;
+                }
+                fs.rename(old_path, new_path, RenameOptions::default())
+                    .await
+                    .with_context(|| format!("failed to move {old_path:?} to {new_path:?}"))?;
+            }
+
+            let reload = this.update(&mut cx, |this, cx| {
+                let [installed_dir, work_dir, grammar_store_dir, index_path] =
+                    moves.map(|(_, new_path)| new_path);
+                this.installed_dir = installed_dir;
+                this.grammar_store_dir = grammar_store_dir;
+                this.index_path = index_path;
+                this.wasm_host = this.wasm_host.relocated(work_dir, cx);
+                this.installed_dir_watcher = this.watch_installed_dir(cx);
+
+                // The registered languages and grammars refer to the extensions'
+                // previous paths, so every extension must be reloaded.
+                this.modified_extensions
+                    .extend(this.extension_index.extensions.keys().cloned());
+                this.reload(None, cx)
+            })?;
+            reload.await;
+            Ok(())
+        })
+    }
+
+    /// Reads the given installed extension again, and reloads it, without
+    /// reading the other installed extensions.
+    pub fn reload_extension(
+        &mut self,
+        extension_id: Arc<str>,
+        cx: &mut ModelContext<Self>,
+    ) -> impl Future<Output = ()> {
+        self.reload(Some(extension_id), cx)
+    }
+
+    /// Checks again which of the installed extensions are compatible with the
+    /// running version of Zed, such as after it has changed, and loads or
+    /// unloads those whose compatibility has changed.
+    pub fn recheck_compatibility(
+        &mut self,
+        cx: &mut ModelContext<Self>,
+    ) -> impl Future<Output = ()> {
+        self.reload(None, cx)
+    }
+
+    /// Enables or disables the given installed extension, and then reloads it.
+    ///
+    /// This is recorded in the extension's directory, so that it persists when
+    /// Zed is restarted.
+    pub fn set_extension_enabled(
+        &mut self,
+        extension_id: Arc<str>,
+        enabled: bool,
+        cx: &mut ModelContext<Self>,
+    ) -> Task<Result<()>> {
+        if !self.extension_index.extensions.contains_key(&extension_id) {
+            return Task::ready(Err(anyhow!("extension {extension_id} is not installed")));
+        }
+
+        let disabled_path = self
+            .extension_index
+            .extension_dir(&self.installed_dir, &extension_id)
+            .join(DISABLED_FILE_NAME);
+        let fs = self.fs.clone();
+        cx.spawn(move |this, mut cx| async move {
+            if enabled {
+                fs.remove_file(
+                    &disabled_path,
+                    RemoveOptions {
+                        ignore_if_not_exists: true,
+                        ..Default::default()
+                    },
+                )
+                .await?;
+            } else {
+                fs.atomic_write(disabled_path, String::new()).await?;
+            }
+            this.update(&mut cx, |this, cx| this.reload_extension(extension_id, cx))?
+                .await;
+            Ok(())
+        })
+    }
+
     fn reload(
         &mut self,
         modified_extension: Option<Arc<str>>,
@@ -411,14 +1780,232 @@ impl ExtensionStore {
         self.installed_dir.clone()
     }
 
+    /// Returns how long the most recent reload of the extensions took, or `None`
+    /// if they have not been reloaded yet.
+    pub fn last_reload_duration(&self) -> Option<Duration> {
+        self.last_reload_duration
+    }
+
+    /// Returns the installed extensions that are not loaded because they are not
+    /// compatible with this version of Zed, along with the reason why.
+    pub fn incompatible_extensions(&self) -> Vec<(Arc<str>, String)> {
+        self.incompatible_extensions
+            .iter()
+            .map(|(extension_id, reason)| (extension_id.clone(), reason.clone()))
+            .collect()
+    }
+
+    /// Returns the installed extensions that are not loaded because they
+    /// conflict with another installed extension, along with the reason why.
+    pub fn conflicting_extensions(&self) -> Vec<(Arc<str>, String)> {
+        self.conflicting_extensions
+            .iter()
+            .map(|(extension_id, reason)| (extension_id.clone(), reason.clone()))
+            .collect()
+    }
+
     pub fn outstanding_operations(&self) -> &BTreeMap<Arc<str>, ExtensionOperation> {
         &self.outstanding_operations
     }
 
-    pub fn installed_extensions(&self) -> &BTreeMap<Arc<str>, ExtensionIndexEntry> {
+    /// Returns the number of HTTP requests that the store has made, when it
+    /// uses a fake HTTP client.
+    #[cfg(test)]
+    pub fn http_request_count(&self) -> usize {
+        self.http_client.as_fake().request_count()
+    }
+
+    /// Replaces the HTTP client used to fetch and download extensions.
+    ///
+    /// Operations that are already in progress continue to use the previous client.
+    /// Extensions that have already been loaded keep making their own requests
+    /// with the client that the store was created with.
+    pub fn set_http_client(&mut self, http_client: Arc<HttpClientWithUrl>) {
+        self.http_client = http_client;
+    }
+
+    /// Sets how long to wait before writing the extension index to disk.
+    ///
+    /// Index writes requested within this window are coalesced, so that only
+    /// the most recent index is written.
+    pub fn set_index_write_delay(&mut self, delay: Duration) {
+        self.index_write_delay = delay;
+    }
+
+    /// Returns the problems that were encountered while loading the installed extensions.
+    pub fn load_errors(&self) -> &[ExtensionLoadError] {
+        &self.load_errors
+    }
+
+    /// Returns the themes that were added, removed, or changed by the most
+    /// recent reload, e.g. to tell the user that their themes were updated.
+    ///
+    /// The changed themes are only known once the reloaded themes have been read.
+    pub fn theme_changes(&self) -> &ThemeChanges {
+        &self.theme_changes
+    }
+
+    /// Returns the installed extensions whose `extension.json` is invalid, as found
+    /// by the most recent reload.
+    pub fn manifest_errors(&self) -> &[ManifestError] {
+        &self.manifest_errors
+    }
+
+    /// Returns the provenance of the given installed extension, if it was
+    /// installed from a signed source.
+    pub fn extension_provenance(&self, extension_id: &str) -> Option<&ExtensionProvenance> {
+        self.extension_index
+            .extensions
+            .get(extension_id)?
+            .provenance
+            .as_ref()
+    }
+
+    pub fn installed_extension_entries(&self) -> &BTreeMap<Arc<str>, ExtensionIndexEntry> {
         &self.extension_index.extensions
     }
 
+    /// Returns the installed extensions, sorted by name, along with how many
+    /// of each kind of thing they provide.
+    pub fn installed_extensions(&self) -> Vec<ExtensionInfo> {
+        let index = &self.extension_index;
+        let mut extensions = index
+            .extensions
+            .iter()
+            .map(|(extension_id, extension)| {
+                let load_status = if !extension.enabled {
+                    ExtensionLoadStatus::Disabled
+                } else if let Some(reason) = self.incompatible_extensions.get(extension_id) {
+                    ExtensionLoadStatus::Incompatible {
+                        reason: reason.clone(),
+                    }
+                } else if let Some(reason) = self.conflicting_extensions.get(extension_id) {
+                    ExtensionLoadStatus::Conflicting {
+                        reason: reason.clone(),
+                    }
+                } else if self.load_errors.iter().any(|error| {
+                    error.extension_id == *extension_id
+                        && error.severity == LoadErrorSeverity::Error
+                }) {
+                    ExtensionLoadStatus::Failed
+                } else {
+                    ExtensionLoadStatus::Loaded
+                };
+
+                ExtensionInfo {
+                    id: extension_id.clone(),
+                    name: extension.manifest.name.clone(),
+                    version: extension.manifest.version.clone(),
+                    dev: extension.dev,
+                    provenance: extension.provenance.clone(),
+                    installed_at: extension.installed_at,
+                    enabled: extension.enabled,
+                    load_status,
+                    theme_count: index
+                        .themes
+                        .values()
+                        .filter(|theme| theme.extension == *extension_id)
+                        .count(),
+                    language_count: index
+                        .languages
+                        .values()
+                        .filter(|language| language.extension == *extension_id)
+                        .count(),
+                    grammar_count: extension.manifest.grammars.len(),
+                    language_server_count: extension.manifest.language_servers.len(),
+                }
+            })
+            .collect::<Vec<_>>();
+        extensions.sort_by(|a, b| a.name.cmp(&b.name).then_with(|| a.id.cmp(&b.id)));
+        extensions
+    }
+
+    /// Returns the number of themes provided by the installed extensions.
+    pub fn theme_count(&self) -> usize {
+        self.extension_index.themes.len()
+    }
+
+    /// Returns the number of languages provided by the installed extensions.
+    pub fn language_count(&self) -> usize {
+        self.extension_index.languages.len()
+    }
+
+    /// Returns the number of distinct grammars provided by the installed extensions.
+    pub fn grammar_count(&self) -> usize {
+        self.extension_index
+            .extensions
+            .values()
+            .flat_map(|extension| extension.manifest.grammars.keys())
+            .collect::<HashSet<_>>()
+            .len()
+    }
+
+    /// Fuzzy-searches the installed extensions by name and ID, returning the
+    /// best matches first, followed by the extensions that only match by tag.
+    pub fn search_installed(
+        &self,
+        query: &str,
+        cx: &AppContext,
+    ) -> Task<Vec<Arc<ExtensionManifest>>> {
+        let manifests = self
+            .extension_index
+            .extensions
+            .values()
+            .map(|extension| extension.manifest.clone())
+            .collect::<Vec<_>>();
+        let candidates = manifests
+            .iter()
+            .enumerate()
+            .flat_map(|(ix, manifest)| {
+                [manifest.name.as_str(), manifest.id.as_ref()].map(|string| StringMatchCandidate {
+                    id: ix,
+                    string: string.to_string(),
+                    char_bag: string.into(),
+                })
+            })
+            .collect::<Vec<_>>();
+        let tag_candidates = manifests
+            .iter()
+            .enumerate()
+            .flat_map(|(ix, manifest)| {
+                manifest.tags.iter().map(move |tag| StringMatchCandidate {
+                    id: ix,
+                    string: tag.clone(),
+                    char_bag: tag.as_str().into(),
+                })
+            })
+            .collect::<Vec<_>>();
+        let query = query.to_string();
+        let executor = cx.background_executor().clone();
+        cx.background_executor().spawn(async move {
+            let matches = match_strings(
+                &candidates,
+                &query,
+                false,
+                candidates.len(),
+                &Default::default(),
+                executor.clone(),
+            )
+            .await;
+            let tag_matches = match_strings(
+                &tag_candidates,
+                &query,
+                false,
+                tag_candidates.len(),
+                &Default::default(),
+                executor,
+            )
+            .await;
+            let mut matched_extensions = HashSet::default();
+            matches
+                .into_iter()
+                .chain(tag_matches)
+                .filter(|mat| matched_extensions.insert(mat.candidate_id))
+                .map(|mat| manifests[mat.candidate_id].clone())
+                .collect()
+        })
+    }
+
     pub fn dev_extensions(&self) -> impl Iterator<Item = &Arc<ExtensionManifest>> {
         self.extension_index
             .extensions
@@ -426,6 +2013,23 @@ impl ExtensionStore {
             .filter_map(|extension| extension.dev.then_some(&extension.manifest))
     }
 
+    /// Returns the installed extensions listed under the given category.
+    pub fn extensions_in_category<'a>(
+        &'a self,
+        category: &'a str,
+    ) -> impl Iterator<Item = &'a Arc<ExtensionManifest>> {
+        self.extension_index
+            .extensions
+            .values()
+            .map(|extension| &extension.manifest)
+            .filter(move |manifest| {
+                manifest
+                    .categories
+                    .iter()
+                    .any(|candidate| candidate.eq_ignore_ascii_case(category))
+            })
+    }
+
     /// Returns the names of themes provided by extensions.
     pub fn extension_themes<'a>(
         &'a self,
@@ -437,95 +2041,544 @@ impl ExtensionStore {
             .filter_map(|(name, theme)| theme.extension.as_ref().eq(extension_id).then_some(name))
     }
 
-    pub fn fetch_extensions(
-        &self,
-        search: Option<&str>,
-        cx: &mut ModelContext<Self>,
-    ) -> Task<Result<Vec<ExtensionMetadata>>> {
-        let version = CURRENT_SCHEMA_VERSION.to_string();
-        let mut query = vec![("max_schema_version", version.as_str())];
-        if let Some(search) = search {
-            query.push(("filter", search));
-        }
+    /// Returns the names of the themes provided by extensions that have the given appearance.
+    pub fn themes_with_appearance(&self, appearance: Appearance) -> Vec<Arc<str>> {
+        let appearance = match appearance {
+            Appearance::Light => AppearanceContent::Light,
+            Appearance::Dark => AppearanceContent::Dark,
+        };
+        self.extension_index
+            .themes
+            .iter()
+            .filter(|(_, theme)| theme.appearance == Some(appearance))
+            .map(|(name, _)| name.clone())
+            .collect()
+    }
 
-        self.fetch_extensions_from_api("/extensions", &query, cx)
+    /// Returns the theme families provided by extensions, keyed by family name.
+    pub fn theme_families(&self) -> &BTreeMap<Arc<str>, ExtensionIndexThemeFamilyEntry> {
+        &self.extension_index.theme_families
     }
 
-    pub fn fetch_extensions_with_update_available(
-        &mut self,
-        cx: &mut ModelContext<Self>,
-    ) -> Task<Result<Vec<ExtensionMetadata>>> {
-        let schema_versions = schema_version_range();
-        let wasm_api_versions = wasm_api_version_range(ReleaseChannel::global(cx));
-        let extension_settings = ExtensionSettings::get_global(cx);
-        let extension_ids = self
+    /// Returns the ID of the installed extension that provides the given language,
+    /// or `None` if no extension provides it, e.g. because it is built in.
+    pub fn extension_for_language(&self, language_name: &str) -> Option<Arc<str>> {
+        self.extension_index
+            .languages
+            .get(language_name)
+            .map(|language| language.extension.clone())
+    }
+
+    /// Returns the ID of the installed extension that provides the given theme,
+    /// or `None` if no extension provides it.
+    pub fn extension_for_theme(&self, theme_name: &str) -> Option<Arc<str>> {
+        self.extension_index
+            .themes
+            .get(theme_name)
+            .map(|theme| theme.extension.clone())
+    }
+
+    /// Returns what the given installed extension provides, with the paths that
+    /// each of its languages, themes, and grammars are loaded from.
+    pub fn describe_extension(&self, extension_id: &str) -> Option<ExtensionReport> {
+        let (extension_id, extension) = self
             .extension_index
             .extensions
-            .iter()
-            .filter(|(id, entry)| !entry.dev && extension_settings.should_auto_update(id))
-            .map(|(id, _)| id.as_ref())
-            .collect::<Vec<_>>()
-            .join(",");
-        let task = self.fetch_extensions_from_api(
-            "/extensions/updates",
-            &[
-                ("min_schema_version", &schema_versions.start().to_string()),
-                ("max_schema_version", &schema_versions.end().to_string()),
-                (
-                    "min_wasm_api_version",
-                    &wasm_api_versions.start().to_string(),
-                ),
-                ("max_wasm_api_version", &wasm_api_versions.end().to_string()),
-                ("ids", &extension_ids),
-            ],
-            cx,
-        );
-        cx.spawn(move |this, mut cx| async move {
-            let extensions = task.await?;
-            this.update(&mut cx, |this, _cx| {
-                extensions
-                    .into_iter()
-                    .filter(|extension| {
-                        this.extension_index.extensions.get(&extension.id).map_or(
-                            true,
-                            |installed_extension| {
-                                installed_extension.manifest.version != extension.manifest.version
-                            },
-                        )
-                    })
-                    .collect()
-            })
+            .get_key_value(extension_id)?;
+        let extension_dir = self.installed_dir.join(extension.installed_dir_name());
+        Some(ExtensionReport {
+            languages: self
+                .extension_index
+                .languages
+                .iter()
+                .filter(|(_, language)| language.extension == *extension_id)
+                .map(|(name, language)| (name.clone(), extension_dir.join(&language.path)))
+                .collect(),
+            themes: self
+                .extension_index
+                .themes
+                .iter()
+                .filter(|(_, theme)| theme.extension == *extension_id)
+                .map(|(name, theme)| (name.clone(), extension_dir.join(&theme.path)))
+                .collect(),
+            grammars: extension
+                .manifest
+                .grammars
+                .keys()
+                .map(|name| (name.clone(), self.grammar_path(extension, name)))
+                .collect(),
+            language_servers: extension
+                .manifest
+                .language_servers
+                .iter()
+                .map(|(name, config)| (name.clone(), config.languages().into_iter().collect()))
+                .collect(),
         })
     }
 
-    pub fn fetch_extension_versions(
-        &self,
-        extension_id: &str,
-        cx: &mut ModelContext<Self>,
-    ) -> Task<Result<Vec<ExtensionMetadata>>> {
-        self.fetch_extensions_from_api(&format!("/extensions/{extension_id}"), &[], cx)
+    /// Returns the ID of the installed extension that provides the given grammar,
+    /// either in its manifest or by producing it while running.
+    pub fn extension_for_grammar(&self, grammar_name: &str) -> Option<Arc<str>> {
+        self.extension_index
+            .extensions
+            .iter()
+            .find(|(_, entry)| entry.manifest.grammars.contains_key(grammar_name))
+            .map(|(extension_id, _)| extension_id.clone())
+            .or_else(|| {
+                self.produced_grammars
+                    .iter()
+                    .find(|(_, grammars)| grammars.iter().any(|name| name.as_ref() == grammar_name))
+                    .map(|(extension_id, _)| extension_id.clone())
+            })
     }
 
-    /// Installs any extensions that should be included with Zed by default.
+    /// Returns the grammar to use for a file of the given language with the
+    /// given content, preferring the first of the language's grammar variants
+    /// whose pattern matches the content.
     ///
-    /// This can be used to make certain functionality provided by extensions
-    /// available out-of-the-box.
-    pub fn auto_install_extensions(&mut self, cx: &mut ModelContext<Self>) {
-        let extension_settings = ExtensionSettings::get_global(cx);
+    /// The language registry makes the same choice when a buffer is opened,
+    /// matching the patterns against the buffer's first line.
+    pub fn grammar_for_content(&self, language_name: &str, content: &str) -> Option<Arc<str>> {
+        let language = self.extension_index.languages.get(language_name)?;
+        language
+            .grammar_variants
+            .iter()
+            .find(|variant| variant.content_regex.is_match(content))
+            .map(|variant| variant.grammar.clone())
+            .or_else(|| language.grammar.clone())
+    }
 
-        let extensions_to_install = extension_settings
-            .auto_install_extensions
-            .keys()
-            .filter(|extension_id| extension_settings.should_auto_install(extension_id))
-            .filter(|extension_id| {
-                let is_already_installed = self
-                    .extension_index
-                    .extensions
-                    .contains_key(extension_id.as_ref());
-                !is_already_installed
-            })
-            .cloned()
-            .collect::<Vec<_>>();
+    /// Loads and parses the `config.toml` of the given extension language, for
+    /// inspecting how the language is configured.
+    pub fn language_config(
+        &self,
+        language_name: &str,
+        cx: &AppContext,
+    ) -> Task<Result<LanguageConfig>> {
+        let Some(language) = self.extension_index.languages.get(language_name) else {
+            return Task::ready(Err(anyhow!(
+                "no extension provides language {language_name}"
+            )));
+        };
+
+        let mut config_path = self
+            .extension_index
+            .extension_dir(&self.installed_dir, &language.extension);
+        config_path.extend([language.path.as_path(), Path::new("config.toml")]);
+        let variant = language.variant;
+        let fs = self.fs.clone();
+        cx.background_executor().spawn(async move {
+            let config = fs.load(&config_path).await?;
+            parse_language_config(&config, variant)
+                .with_context(|| format!("failed to parse {}", config_path.display()))
+        })
+    }
+
+    /// Returns the language servers whose version should be resolved again
+    /// because the given worktree file, which they use to determine their
+    /// version, has changed.
+    pub fn language_servers_affected_by_file(&self, path: &Path) -> Vec<LanguageServerName> {
+        let Some(file_name) = path.file_name().and_then(|file_name| file_name.to_str()) else {
+            return Vec::new();
+        };
+
+        self.extension_index
+            .extensions
+            .values()
+            .flat_map(|extension| extension.manifest.language_servers.iter())
+            .filter(|(_, language_server)| {
+                language_server
+                    .version_files
+                    .iter()
+                    .any(|version_file| version_file == file_name)
+            })
+            .map(|(language_server_name, _)| language_server_name.clone())
+            .collect()
+    }
+
+    /// Returns the version of the given language server that the worktree with
+    /// the given root asks for: the first line of the first of the language
+    /// server's version files that exists in the worktree, if any.
+    ///
+    /// The version is cached until [`Self::worktree_file_changed`] reports a
+    /// change to one of the version files.
+    pub fn language_server_version(
+        &mut self,
+        language_server_name: &LanguageServerName,
+        worktree_root: &Path,
+        cx: &mut ModelContext<Self>,
+    ) -> Task<Result<Option<Arc<str>>>> {
+        let key = (language_server_name.clone(), worktree_root.to_path_buf());
+        if let Some(version) = self.language_server_versions.get(&key) {
+            return Task::ready(Ok(version.clone()));
+        }
+
+        let Some(version_files) = self
+            .extension_index
+            .extensions
+            .values()
+            .find_map(|extension| {
+                extension
+                    .manifest
+                    .language_servers
+                    .get(language_server_name)
+            })
+            .map(|language_server| language_server.version_files.clone())
+        else {
+            return Task::ready(Err(anyhow!(
+                "no extension provides language server {}",
+                language_server_name.0
+            )));
+        };
+
+        let fs = self.fs.clone();
+        cx.spawn(|this, mut cx| async move {
+            let mut version = None;
+            for version_file in version_files {
+                if let Ok(contents) = fs.load(&key.1.join(version_file)).await {
+                    version = contents
+                        .lines()
+                        .next()
+                        .map(str::trim)
+                        .filter(|line| !line.is_empty())
+                        .map(Arc::from);
+                    break;
+                }
+            }
+            this.update(&mut cx, |this, _| {
+                this.language_server_versions.insert(key, version.clone());
+            })?;
+            Ok(version)
+        })
+    }
+
+    /// Forgets the cached versions of the language servers that use the given
+    /// worktree file to determine their version, in the worktrees that contain
+    /// it, so that the next call to [`Self::language_server_version`] resolves
+    /// them again. Returns the affected language servers.
+    pub fn worktree_file_changed(&mut self, path: &Path) -> Vec<LanguageServerName> {
+        let language_server_names = self.language_servers_affected_by_file(path);
+        self.language_server_versions
+            .retain(|(language_server_name, worktree_root), _| {
+                !(language_server_names.contains(language_server_name)
+                    && path.starts_with(worktree_root))
+            });
+        language_server_names
+    }
+
+    /// Returns the ID of the installed extension that provides the given language server.
+    pub fn find_extension_by_language_server(
+        &self,
+        language_server_name: &LanguageServerName,
+    ) -> Option<Arc<str>> {
+        self.extension_index
+            .extensions
+            .iter()
+            .find_map(|(extension_id, extension)| {
+                extension
+                    .manifest
+                    .language_servers
+                    .contains_key(language_server_name)
+                    .then(|| extension_id.clone())
+            })
+    }
+
+    /// Sets the index of the extensions that are available from the extension
+    /// registry, which is used to suggest extensions for unsupported files.
+    pub fn set_remote_extension_index(&mut self, remote_extension_index: ExtensionIndex) {
+        self.remote_extension_index = remote_extension_index;
+    }
+
+    /// Returns the available extension whose language claims the given path,
+    /// if no installed extension provides that language.
+    pub fn suggest_extension_for_path(&self, path: &Path) -> Option<ExtensionIndexEntry> {
+        let file_name = path.file_name().and_then(|file_name| file_name.to_str());
+        let extension = path.extension().and_then(|extension| extension.to_str());
+        let path_suffixes = [extension, file_name];
+
+        let (language_name, language) =
+            self.remote_extension_index
+                .languages
+                .iter()
+                .find(|(_, language)| {
+                    language
+                        .matcher
+                        .path_suffixes
+                        .iter()
+                        .any(|suffix| path_suffixes.contains(&Some(suffix.as_str())))
+                })?;
+        if self.extension_index.languages.contains_key(language_name)
+            || self
+                .extension_index
+                .extensions
+                .contains_key(&language.extension)
+        {
+            return None;
+        }
+
+        self.remote_extension_index
+            .extensions
+            .get(&language.extension)
+            .cloned()
+    }
+
+    /// Installs the latest version of the extension suggested for the given path
+    /// by [`Self::suggest_extension_for_path`], returning its ID.
+    pub fn on_demand_install_for_language(
+        &mut self,
+        path: &Path,
+        cx: &mut ModelContext<Self>,
+    ) -> Option<Arc<str>> {
+        let extension_id = self.suggest_extension_for_path(path)?.manifest.id.clone();
+        self.install_latest_extension(extension_id.clone(), cx);
+        Some(extension_id)
+    }
+
+    /// Parses every theme file of the installed extensions and reports the
+    /// issues found in them, without registering any themes.
+    pub fn validate_all_themes(&self, cx: &AppContext) -> Task<Vec<ThemeValidationResult>> {
+        let fs = self.fs.clone();
+        let fallback_appearance = ExtensionSettings::get_global(cx).fallback_theme_appearance();
+        let extension_dirs = self
+            .extension_index
+            .extensions
+            .iter()
+            .map(|(extension_id, extension)| {
+                (
+                    extension_id.clone(),
+                    self.installed_dir.join(extension.installed_dir_name()),
+                )
+            })
+            .collect::<Vec<_>>();
+        cx.background_executor().spawn(async move {
+            let mut results = Vec::new();
+            for (extension_id, extension_dir) in extension_dirs {
+                let Ok(mut theme_paths) = fs.read_dir(&extension_dir.join("themes")).await else {
+                    continue;
+                };
+                let mut extension_results = Vec::new();
+                while let Some(theme_path) = theme_paths.next().await {
+                    let Ok(theme_path) = theme_path else {
+                        continue;
+                    };
+                    let Ok(relative_path) = theme_path.strip_prefix(&extension_dir) else {
+                        continue;
+                    };
+
+                    let mut result = ThemeValidationResult {
+                        extension_id: extension_id.clone(),
+                        path: relative_path.to_path_buf(),
+                        theme_names: Vec::new(),
+                        issues: Vec::new(),
+                    };
+                    match ThemeRegistry::read_user_theme_with_fallback_appearance(
+                        &theme_path,
+                        fs.clone(),
+                        fallback_appearance,
+                    )
+                    .await
+                    {
+                        Ok(theme_family) => {
+                            for theme in &theme_family.themes {
+                                result.theme_names.push(theme.name.clone());
+                                result
+                                    .issues
+                                    .extend(missing_syntax_tokens_warning(&extension_id, theme));
+                                result
+                                    .issues
+                                    .extend(invalid_theme_colors_warning(&extension_id, theme));
+                            }
+                        }
+                        Err(error) => result.issues.push(ExtensionLoadError {
+                            extension_id: extension_id.clone(),
+                            severity: LoadErrorSeverity::Error,
+                            message: format!("failed to parse theme: {error}"),
+                        }),
+                    }
+                    extension_results.push(result);
+                }
+                extension_results.sort_by(|a, b| a.path.cmp(&b.path));
+                results.extend(extension_results);
+            }
+            results
+        })
+    }
+
+    /// Returns the file suffixes claimed by the languages of the installed
+    /// extensions, mapped to the names of the languages that claim them.
+    ///
+    /// A suffix that is claimed by more than one language is a conflict.
+    pub fn claimed_suffixes(&self) -> BTreeMap<String, Vec<Arc<str>>> {
+        let mut claimed_suffixes = BTreeMap::<String, Vec<Arc<str>>>::default();
+        for (language_name, language) in &self.extension_index.languages {
+            for suffix in &language.matcher.path_suffixes {
+                claimed_suffixes
+                    .entry(suffix.clone())
+                    .or_default()
+                    .push(language_name.clone());
+            }
+        }
+        claimed_suffixes
+    }
+
+    pub fn fetch_extensions(
+        &self,
+        search: Option<&str>,
+        cx: &mut ModelContext<Self>,
+    ) -> Task<Result<Vec<ExtensionMetadata>>> {
+        if let Some(index_path) = ExtensionSettings::get_global(cx)
+            .extension_index_path
+            .clone()
+        {
+            let release_channel = ReleaseChannel::global(cx);
+            let search = search.map(str::to_lowercase);
+            let matches_name = {
+                let search = search.clone();
+                move |extension: &ExtensionMetadata| {
+                    search.as_ref().map_or(true, |search| {
+                        extension.id.to_lowercase().contains(search)
+                            || extension.manifest.name.to_lowercase().contains(search)
+                    })
+                }
+            };
+            let extensions = self.fetch_extensions_from_local_index(
+                index_path,
+                {
+                    let matches_name = matches_name.clone();
+                    move |extension| {
+                        is_version_compatible(release_channel, extension)
+                            && (matches_name(extension)
+                                || search.as_ref().map_or(false, |search| {
+                                    extension
+                                        .manifest
+                                        .tags
+                                        .iter()
+                                        .any(|tag| tag.to_lowercase().contains(search))
+                                }))
+                    }
+                },
+                cx,
+            );
+            // As when searching the installed extensions, extensions that
+            // match by name or ID come before those that only match by tag.
+            return cx.background_executor().spawn(async move {
+                let mut extensions = extensions.await?;
+                extensions.sort_by_key(|extension| !matches_name(extension));
+                Ok(extensions)
+            });
+        }
+
+        let version = CURRENT_SCHEMA_VERSION.to_string();
+        let mut query = vec![("max_schema_version", version.as_str())];
+        if let Some(search) = search {
+            query.push(("filter", search));
+        }
+
+        self.fetch_extensions_from_api("/extensions", &query, cx)
+    }
+
+    pub fn fetch_extensions_with_update_available(
+        &mut self,
+        cx: &mut ModelContext<Self>,
+    ) -> Task<Result<Vec<ExtensionMetadata>>> {
+        let schema_versions = schema_version_range();
+        let wasm_api_versions = wasm_api_version_range(ReleaseChannel::global(cx));
+        let extension_settings = ExtensionSettings::get_global(cx);
+        let extension_ids = self
+            .extension_index
+            .extensions
+            .iter()
+            .filter(|(id, entry)| !entry.dev && extension_settings.should_auto_update(id))
+            .map(|(id, _)| id.as_ref())
+            .collect::<Vec<_>>();
+        let task = if let Some(index_path) = extension_settings.extension_index_path.clone() {
+            let release_channel = ReleaseChannel::global(cx);
+            let extension_ids = extension_ids
+                .into_iter()
+                .map(Arc::<str>::from)
+                .collect::<HashSet<_>>();
+            self.fetch_extensions_from_local_index(
+                index_path,
+                move |extension| {
+                    extension_ids.contains(&extension.id)
+                        && is_version_compatible(release_channel, extension)
+                },
+                cx,
+            )
+        } else {
+            self.fetch_extensions_from_api(
+                "/extensions/updates",
+                &[
+                    ("min_schema_version", &schema_versions.start().to_string()),
+                    ("max_schema_version", &schema_versions.end().to_string()),
+                    (
+                        "min_wasm_api_version",
+                        &wasm_api_versions.start().to_string(),
+                    ),
+                    ("max_wasm_api_version", &wasm_api_versions.end().to_string()),
+                    ("ids", &extension_ids.join(",")),
+                ],
+                cx,
+            )
+        };
+        cx.spawn(move |this, mut cx| async move {
+            let extensions = task.await?;
+            this.update(&mut cx, |this, _cx| {
+                extensions
+                    .into_iter()
+                    .filter(|extension| {
+                        this.extension_index.extensions.get(&extension.id).map_or(
+                            true,
+                            |installed_extension| {
+                                installed_extension.manifest.version != extension.manifest.version
+                            },
+                        )
+                    })
+                    .collect()
+            })
+        })
+    }
+
+    pub fn fetch_extension_versions(
+        &self,
+        extension_id: &str,
+        cx: &mut ModelContext<Self>,
+    ) -> Task<Result<Vec<ExtensionMetadata>>> {
+        if let Some(index_path) = ExtensionSettings::get_global(cx)
+            .extension_index_path
+            .clone()
+        {
+            let extension_id = Arc::<str>::from(extension_id);
+            return self.fetch_extensions_from_local_index(
+                index_path,
+                move |extension| extension.id == extension_id,
+                cx,
+            );
+        }
+
+        self.fetch_extensions_from_api(&format!("/extensions/{extension_id}"), &[], cx)
+    }
+
+    /// Installs any extensions that should be included with Zed by default.
+    ///
+    /// This can be used to make certain functionality provided by extensions
+    /// available out-of-the-box.
+    pub fn auto_install_extensions(&mut self, cx: &mut ModelContext<Self>) {
+        let extension_settings = ExtensionSettings::get_global(cx);
+
+        let extensions_to_install = extension_settings
+            .auto_install_extensions
+            .keys()
+            .filter(|extension_id| extension_settings.should_auto_install(extension_id))
+            .filter(|extension_id| {
+                let is_already_installed = self
+                    .extension_index
+                    .extensions
+                    .contains_key(extension_id.as_ref());
+                !is_already_installed
+            })
+            .cloned()
+            .collect::<Vec<_>>();
 
         cx.spawn(move |this, mut cx| async move {
             for extension_id in extensions_to_install {
@@ -541,11 +2594,41 @@ impl ExtensionStore {
     pub fn check_for_updates(&mut self, cx: &mut ModelContext<Self>) {
         let task = self.fetch_extensions_with_update_available(cx);
         cx.spawn(move |this, mut cx| async move {
-            Self::upgrade_extensions(this, task.await?, &mut cx).await
+            let extensions = task.await.inspect_err(|error| {
+                if is_registry_unreachable(error) {
+                    this.update(&mut cx, |this, _| {
+                        this.defer_operation(DeferredOperation::CheckForUpdates)
+                    })
+                    .ok();
+                }
+            })?;
+            Self::upgrade_extensions(this, extensions, &mut cx).await
         })
         .detach();
     }
 
+    fn defer_operation(&mut self, operation: DeferredOperation) {
+        log::info!("deferring {operation:?} until the network is available");
+        if !self.deferred_operations.contains(&operation) {
+            self.deferred_operations.push(operation);
+        }
+    }
+
+    /// Retries the operations that failed because the extension registry could
+    /// not be reached. This should be called when the network becomes available.
+    pub fn notify_network_available(&mut self, cx: &mut ModelContext<Self>) {
+        for operation in mem::take(&mut self.deferred_operations) {
+            match operation {
+                DeferredOperation::CheckForUpdates => self.check_for_updates(cx),
+                DeferredOperation::InstallLatest(extension_id) => {
+                    if !self.extension_index.extensions.contains_key(&extension_id) {
+                        self.install_latest_extension(extension_id, cx);
+                    }
+                }
+            }
+        }
+    }
+
     async fn upgrade_extensions(
         this: WeakModel<Self>,
         extensions: Vec<ExtensionMetadata>,
@@ -584,10 +2667,12 @@ impl ExtensionStore {
     ) -> Task<Result<Vec<ExtensionMetadata>>> {
         let url = self.http_client.build_zed_api_url(path, &query);
         let http_client = self.http_client.clone();
-        cx.spawn(move |_, _| async move {
-            let mut response = http_client
-                .get(&url?.as_ref(), AsyncBody::empty(), true)
-                .await?;
+        let auth_token = url
+            .as_ref()
+            .ok()
+            .and_then(|url| registry_auth_token(url, cx));
+        cx.spawn(move |this, mut cx| async move {
+            let mut response = get_from_registry(http_client.as_ref(), &url?, auth_token).await?;
 
             let mut body = Vec::new();
             response
@@ -605,10 +2690,51 @@ impl ExtensionStore {
             }
 
             let response: GetExtensionsResponse = serde_json::from_slice(&body)?;
+            this.update(&mut cx, |this, _| {
+                this.record_declared_archive_sizes(&response.data)
+            })
+            .ok();
             Ok(response.data)
         })
     }
 
+    /// Reads the extensions that match the given filter from a local file in
+    /// the format of the registry's `/extensions` response.
+    fn fetch_extensions_from_local_index(
+        &self,
+        index_path: PathBuf,
+        filter: impl Fn(&ExtensionMetadata) -> bool + Send + 'static,
+        cx: &mut ModelContext<'_, ExtensionStore>,
+    ) -> Task<Result<Vec<ExtensionMetadata>>> {
+        let fs = self.fs.clone();
+        cx.spawn(move |this, mut cx| async move {
+            let content = fs
+                .load(&index_path)
+                .await
+                .with_context(|| format!("error reading extension index {index_path:?}"))?;
+            let response: GetExtensionsResponse = serde_json::from_str(&content)
+                .with_context(|| format!("invalid extension index {index_path:?}"))?;
+            let extensions = response
+                .data
+                .into_iter()
+                .filter(|extension| filter(extension))
+                .collect::<Vec<_>>();
+            this.update(&mut cx, |this, _| {
+                this.record_declared_archive_sizes(&extensions)
+            })
+            .ok();
+            Ok(extensions)
+        })
+    }
+
+    fn record_declared_archive_sizes(&mut self, extensions: &[ExtensionMetadata]) {
+        self.declared_archive_sizes
+            .extend(extensions.iter().filter_map(|extension| {
+                let key = (extension.id.clone(), extension.manifest.version.clone());
+                Some((key, extension.archive_size?))
+            }));
+    }
+
     pub fn install_extension(
         &mut self,
         extension_id: Arc<str>,
@@ -623,11 +2749,24 @@ impl ExtensionStore {
         &mut self,
         extension_id: Arc<str>,
         url: Url,
+        declared_size: Option<u64>,
         operation: ExtensionOperation,
         cx: &mut ModelContext<Self>,
     ) -> Task<Result<()>> {
         let extension_dir = self.installed_dir.join(extension_id.as_ref());
+        let staging_dir = self.staging_dir(&extension_id);
+        let replaced_dir = self.replaced_dir(&extension_id);
+        let preserved_data_dir = self.preserved_data_dir(&extension_id);
+        let grammar_store_dir = self.grammar_store_dir.clone();
+        let previous_grammar_hashes = self
+            .extension_index
+            .extensions
+            .get(&extension_id)
+            .map(|extension| extension.grammar_hashes.clone())
+            .unwrap_or_default();
         let http_client = self.http_client.clone();
+        let retry_policy = self.retry_policy;
+        let auth_token = registry_auth_token(&url, cx);
         let fs = self.fs.clone();
 
         match self.outstanding_operations.entry(extension_id.clone()) {
@@ -650,48 +2789,122 @@ impl ExtensionStore {
                 }
             });
 
-            let mut response = http_client
-                .get(&url.as_ref(), Default::default(), true)
+            let executor = cx.background_executor().clone();
+            let mut response = retry_policy
+                .send(&executor, || {
+                    get_from_registry(http_client.as_ref(), &url, auth_token.clone())
+                })
                 .await
-                .map_err(|err| anyhow!("error downloading extension: {}", err))?;
+                .context("error downloading extension")?;
 
-            fs.remove_dir(
-                &extension_dir,
-                RemoveOptions {
-                    recursive: true,
-                    ignore_if_not_exists: true,
-                },
-            )
-            .await?;
+            // Reinstalling or upgrading an extension keeps the time at which
+            // it was first installed.
+            let installed_at = match read_installed_at(fs.as_ref(), &extension_dir).await {
+                Some(installed_at) => installed_at,
+                None => SystemTime::now(),
+            };
+
+            // The new version is prepared in a staging directory, and only
+            // replaces the installed version once it is complete, so that a
+            // failed download leaves the installed version intact.
+            for dir in [&staging_dir, &replaced_dir] {
+                fs.remove_dir(
+                    dir,
+                    RemoveOptions {
+                        recursive: true,
+                        ignore_if_not_exists: true,
+                    },
+                )
+                .await?;
+            }
 
             let content_length = response
                 .headers()
                 .get(isahc::http::header::CONTENT_LENGTH)
                 .and_then(|value| value.to_str().ok()?.parse::<usize>().ok());
+            let provenance = response
+                .headers()
+                .get(SIGNED_BY_HEADER)
+                .and_then(|value| value.to_str().ok())
+                .map(|signed_by| ExtensionProvenance {
+                    signed_by: signed_by.to_string(),
+                });
 
             let mut body = BufReader::new(response.body_mut());
-            let mut tar_gz_bytes = Vec::new();
-            body.read_to_end(&mut tar_gz_bytes).await?;
+            let mut archive_bytes = Vec::new();
+            body.read_to_end(&mut archive_bytes).await?;
 
             if let Some(content_length) = content_length {
-                let actual_len = tar_gz_bytes.len();
+                let actual_len = archive_bytes.len();
                 if content_length != actual_len {
                     bail!("downloaded extension size {actual_len} does not match content length {content_length}");
                 }
             }
-            let decompressed_bytes = GzipDecoder::new(BufReader::new(tar_gz_bytes.as_slice()));
-            let archive = Archive::new(decompressed_bytes);
-            archive.unpack(extension_dir).await?;
-            this.update(&mut cx, |this, cx| {
-                this.reload(Some(extension_id.clone()), cx)
-            })?
-            .await;
-
-            match operation {
-                ExtensionOperation::Install => {
-                    this.update(&mut cx, |_, cx| {
-                        cx.emit(Event::ExtensionInstalled(extension_id));
-                    })
+            verify_download_before_extract(&archive_bytes, declared_size)?;
+            if archive_bytes.starts_with(ZIP_MAGIC) {
+                extract_zip(&staging_dir, archive_bytes.as_slice()).await?;
+            } else {
+                let decompressed_bytes =
+                    GzipDecoder::new(BufReader::new(archive_bytes.as_slice()));
+                let archive = Archive::new(decompressed_bytes);
+                archive.unpack(&staging_dir).await?;
+            }
+            ExtensionManifest::load(fs.clone(), &staging_dir)
+                .await
+                .context("downloaded extension is invalid")?;
+            move_grammars_to_store(fs.as_ref(), &staging_dir, &grammar_store_dir)
+                .await
+                .context("failed to move extension grammars to the grammar store")?;
+            if let Some(provenance) = provenance {
+                fs.atomic_write(
+                    staging_dir.join(PROVENANCE_FILE_NAME),
+                    serde_json::to_string(&provenance)?,
+                )
+                .await
+                .context("failed to record extension provenance")?;
+            }
+            fs.atomic_write(
+                staging_dir.join(INSTALLED_AT_FILE_NAME),
+                installed_at.duration_since(UNIX_EPOCH)?.as_secs().to_string(),
+            )
+            .await
+            .context("failed to record when the extension was installed")?;
+            if fs.is_dir(&preserved_data_dir).await {
+                fs.rename(
+                    &preserved_data_dir,
+                    &staging_dir.join(EXTENSION_DATA_DIR_NAME),
+                    RenameOptions {
+                        overwrite: true,
+                        ignore_if_exists: false,
+                    },
+                )
+                .await
+                .context("failed to restore extension data")?;
+            }
+            replace_dir(fs.as_ref(), &staging_dir, &extension_dir, &replaced_dir)
+                .await
+                .context("failed to replace the installed extension")?;
+            this.update(&mut cx, |this, cx| {
+                this.reload(Some(extension_id.clone()), cx)
+            })?
+            .await;
+            this.update(&mut cx, |this, cx| this.update_lockfile(&extension_id, cx))?;
+
+            // Remove the grammars of the replaced version from the grammar
+            // store, unless the new version or another extension still ships
+            // them.
+            let unused_grammar_paths = this.update(&mut cx, |this, _| {
+                this.unreferenced_grammar_store_paths(previous_grammar_hashes.values())
+            })?;
+            remove_files(fs.as_ref(), unused_grammar_paths)
+                .await
+                .context("failed to remove unused grammars from the grammar store")?;
+
+            match operation {
+                ExtensionOperation::Install => {
+                    this.update(&mut cx, |_, cx| {
+                        cx.emit(Event::ExtensionInstalled(extension_id));
+                    })
                     .ok();
                 }
                 _ => {}
@@ -706,6 +2919,17 @@ impl ExtensionStore {
         extension_id: Arc<str>,
         cx: &mut ModelContext<Self>,
     ) {
+        // When installing reproducibly, the locked version is installed instead
+        // of the latest one.
+        let locked_version = self
+            .lockfile_path
+            .as_ref()
+            .and_then(|_| self.lockfile.lock().extensions.get(&extension_id).cloned());
+        if let Some(version) = locked_version {
+            self.install_extension(extension_id, version, cx);
+            return;
+        }
+
         log::info!("installing extension {extension_id} latest version");
 
         let schema_versions = schema_version_range();
@@ -730,12 +2954,23 @@ impl ExtensionStore {
             return;
         };
 
-        self.install_or_upgrade_extension_at_endpoint(
-            extension_id,
+        let install = self.install_or_upgrade_extension_at_endpoint(
+            extension_id.clone(),
             url,
+            None,
             ExtensionOperation::Install,
             cx,
-        )
+        );
+        cx.spawn(move |this, mut cx| async move {
+            install.await.inspect_err(|error| {
+                if is_registry_unreachable(error) {
+                    this.update(&mut cx, |this, _| {
+                        this.defer_operation(DeferredOperation::InstallLatest(extension_id))
+                    })
+                    .ok();
+                }
+            })
+        })
         .detach_and_log_err(cx);
     }
 
@@ -767,17 +3002,57 @@ impl ExtensionStore {
             return Task::ready(Ok(()));
         };
 
-        self.install_or_upgrade_extension_at_endpoint(extension_id, url, operation, cx)
+        let declared_size = self
+            .declared_archive_sizes
+            .get(&(extension_id.clone(), version))
+            .copied();
+        self.install_or_upgrade_extension_at_endpoint(
+            extension_id,
+            url,
+            declared_size,
+            operation,
+            cx,
+        )
     }
 
-    pub fn uninstall_extension(&mut self, extension_id: Arc<str>, cx: &mut ModelContext<Self>) {
-        let extension_dir = self.installed_dir.join(extension_id.as_ref());
+    /// Uninstalls the given extension, returning what is removed.
+    ///
+    /// A dry run fails if the extension isn't installed, since there is
+    /// nothing to report.
+    pub fn uninstall_extension(
+        &mut self,
+        extension_id: Arc<str>,
+        options: UninstallOptions,
+        cx: &mut ModelContext<Self>,
+    ) -> Result<UninstallReport> {
+        let UninstallOptions { keep_data, dry_run } = options;
+        if dry_run {
+            if !self.extension_index.extensions.contains_key(&extension_id) {
+                bail!("extension {extension_id} is not installed");
+            }
+            return Ok(self.uninstall_report(&extension_id));
+        }
+
+        let report = self.uninstall_report(&extension_id);
+
+        let extension_dir = self
+            .extension_index
+            .extension_dir(&self.installed_dir, &extension_id);
+        let preserved_data_dir = self.preserved_data_dir(&extension_id);
+        let installed_dir = self.installed_dir.clone();
         let fs = self.fs.clone();
+        let grammar_hashes = self
+            .extension_index
+            .extensions
+            .get(&extension_id)
+            .map(|extension| extension.grammar_hashes.clone())
+            .unwrap_or_default();
 
         match self.outstanding_operations.entry(extension_id.clone()) {
-            btree_map::Entry::Occupied(_) => return,
+            btree_map::Entry::Occupied(_) => return Ok(report),
             btree_map::Entry::Vacant(e) => e.insert(ExtensionOperation::Remove),
         };
+        self.dev_extension_watchers.remove(&extension_id);
 
         cx.spawn(move |this, mut cx| async move {
             let _finish = util::defer({
@@ -793,20 +3068,153 @@ impl ExtensionStore {
                 }
             });
 
-            fs.remove_dir(
-                &extension_dir,
-                RemoveOptions {
-                    recursive: true,
-                    ignore_if_not_exists: true,
-                },
-            )
-            .await?;
+            let data_dir = extension_dir.join(EXTENSION_DATA_DIR_NAME);
+            if keep_data && fs.is_dir(&data_dir).await {
+                if let Some(parent) = preserved_data_dir.parent() {
+                    fs.create_dir(parent).await?;
+                }
+                fs.rename(
+                    &data_dir,
+                    &preserved_data_dir,
+                    RenameOptions {
+                        overwrite: true,
+                        ignore_if_exists: false,
+                    },
+                )
+                .await
+                .context("failed to preserve extension data")?;
+            }
+
+            // Every installed version of the extension is removed, rather than
+            // only the one that is loaded.
+            for version_dir in
+                extension_version_dirs(fs.as_ref(), &installed_dir, &extension_id).await
+            {
+                fs.remove_dir(
+                    &version_dir,
+                    RemoveOptions {
+                        recursive: true,
+                        ignore_if_not_exists: true,
+                    },
+                )
+                .await?;
+            }
 
             this.update(&mut cx, |this, cx| this.reload(None, cx))?
                 .await;
-            anyhow::Ok(())
+            this.update(&mut cx, |this, cx| {
+                this.update_lockfile(&extension_id, cx);
+                cx.emit(Event::ExtensionUninstalled(extension_id.clone()))
+            })?;
+
+            // Remove the extension's grammars from the grammar store, unless
+            // another extension ships the same grammar.
+            let unused_grammar_paths = this.update(&mut cx, |this, _| {
+                this.unreferenced_grammar_store_paths(grammar_hashes.values())
+            })?;
+            remove_files(fs.as_ref(), unused_grammar_paths).await
         })
-        .detach_and_log_err(cx)
+        .detach_and_log_err(cx);
+
+        Ok(report)
+    }
+
+    /// Returns the paths in the grammar store of the grammars with the given
+    /// hashes that no installed extension refers to.
+    fn unreferenced_grammar_store_paths<'a>(
+        &self,
+        hashes: impl IntoIterator<Item = &'a Arc<str>>,
+    ) -> Vec<PathBuf> {
+        let used_hashes = self
+            .extension_index
+            .extensions
+            .values()
+            .flat_map(|extension| extension.grammar_hashes.values())
+            .collect::<HashSet<_>>();
+        hashes
+            .into_iter()
+            .filter(|hash| !used_hashes.contains(hash))
+            .filter_map(|hash| grammar_store_path(&self.grammar_store_dir, hash))
+            .collect()
+    }
+
+    /// Returns what uninstalling the given extension would remove.
+    fn uninstall_report(&self, extension_id: &Arc<str>) -> UninstallReport {
+        let index = &self.extension_index;
+        let other_extensions = || {
+            index
+                .extensions
+                .iter()
+                .filter(|(id, _)| *id != extension_id)
+                .map(|(_, extension)| extension)
+        };
+
+        let shared_grammars = other_extensions()
+            .flat_map(|extension| extension.manifest.grammars.keys())
+            .collect::<HashSet<_>>();
+        let shared_hashes = other_extensions()
+            .flat_map(|extension| extension.grammar_hashes.values())
+            .collect::<HashSet<_>>();
+
+        let mut report = UninstallReport {
+            extension_dir: index.extension_dir(&self.installed_dir, extension_id),
+            ..Default::default()
+        };
+        report.artifacts.languages = index
+            .languages
+            .iter()
+            .filter(|(_, language)| language.extension == *extension_id)
+            .map(|(name, _)| name.clone())
+            .collect();
+        report.artifacts.themes = index
+            .themes
+            .iter()
+            .filter(|(_, theme)| theme.extension == *extension_id)
+            .map(|(name, _)| name.clone())
+            .collect();
+        if let Some(extension) = index.extensions.get(extension_id) {
+            report.artifacts.grammars = extension
+                .manifest
+                .grammars
+                .keys()
+                .filter(|grammar| !shared_grammars.contains(grammar))
+                .cloned()
+                .collect();
+            report.grammar_store_paths = extension
+                .grammar_hashes
+                .values()
+                .filter(|hash| !shared_hashes.contains(hash))
+                .filter_map(|hash| grammar_store_path(&self.grammar_store_dir, hash))
+                .collect();
+        }
+        report
+    }
+
+    /// Returns the directory where a version of an extension is prepared
+    /// before it is installed.
+    fn staging_dir(&self, extension_id: &str) -> PathBuf {
+        self.wasm_host
+            .work_dir
+            .join(STAGING_DIR_NAME)
+            .join(extension_id)
+    }
+
+    /// Returns the directory where the installed version of an extension is
+    /// kept while it is being replaced by another version.
+    fn replaced_dir(&self, extension_id: &str) -> PathBuf {
+        self.wasm_host
+            .work_dir
+            .join(REPLACED_DIR_NAME)
+            .join(extension_id)
+    }
+
+    /// Returns the directory where the data of an uninstalled extension is kept
+    /// until it is installed again.
+    fn preserved_data_dir(&self, extension_id: &str) -> PathBuf {
+        self.wasm_host
+            .work_dir
+            .join(".preserved-data")
+            .join(extension_id)
     }
 
     pub fn install_dev_extension(
@@ -814,6 +3222,10 @@ impl ExtensionStore {
         extension_source_path: PathBuf,
         cx: &mut ModelContext<Self>,
     ) -> Task<Result<()>> {
+        if is_extension_bundle(&extension_source_path) {
+            return self.install_extension_bundle(extension_source_path, cx);
+        }
+
         let extensions_dir = self.extensions_dir();
         let fs = self.fs.clone();
         let builder = self.builder.clone();
@@ -887,6 +3299,173 @@ impl ExtensionStore {
         })
     }
 
+    /// Installs the extension contained in the given extension bundle,
+    /// replacing any installed version of it.
+    pub fn install_extension_bundle(
+        &mut self,
+        bundle_path: PathBuf,
+        cx: &mut ModelContext<Self>,
+    ) -> Task<Result<()>> {
+        let extensions_dir = self.extensions_dir();
+        // The extension's ID is only known once the bundle is extracted, so each
+        // bundle is extracted into a staging directory of its own.
+        let staging_dir = self.staging_dir(&format!(".bundle-{}", self.bundle_install_count));
+        self.bundle_install_count += 1;
+        let fs = self.fs.clone();
+
+        cx.spawn(move |this, mut cx| async move {
+            let remove_options = RemoveOptions {
+                recursive: true,
+                ignore_if_not_exists: true,
+            };
+            let result = async {
+                fs.remove_dir(&staging_dir, remove_options).await?;
+                fs.create_dir(&staging_dir).await?;
+
+                let bundle_bytes = fs
+                    .load_bytes(&bundle_path)
+                    .await
+                    .with_context(|| format!("failed to read extension bundle {bundle_path:?}"))?;
+                extract_zip(&staging_dir, bundle_bytes.as_slice())
+                    .await
+                    .with_context(|| {
+                        format!("failed to extract extension bundle {bundle_path:?}")
+                    })?;
+
+                let extension_manifest = ExtensionManifest::load(fs.clone(), &staging_dir).await?;
+                validate_extension_id(&extension_manifest.id)
+                    .with_context(|| format!("invalid extension bundle {bundle_path:?}"))?;
+                let extension_id = extension_manifest.id.clone();
+
+                let replaced_dir = this.update(&mut cx, |this, cx| {
+                    match this.outstanding_operations.entry(extension_id.clone()) {
+                        btree_map::Entry::Occupied(_) => {
+                            bail!("extension {extension_id} is already being modified")
+                        }
+                        btree_map::Entry::Vacant(e) => e.insert(ExtensionOperation::Install),
+                    };
+                    cx.notify();
+                    anyhow::Ok(this.replaced_dir(&extension_id))
+                })??;
+
+                let _finish = util::defer({
+                    let this = this.clone();
+                    let mut cx = cx.clone();
+                    let extension_id = extension_id.clone();
+                    move || {
+                        this.update(&mut cx, |this, cx| {
+                            this.outstanding_operations.remove(extension_id.as_ref());
+                            cx.notify();
+                        })
+                        .ok();
+                    }
+                });
+
+                let extension_dir = extensions_dir.join(extension_id.as_ref());
+                replace_dir(fs.as_ref(), &staging_dir, &extension_dir, &replaced_dir)
+                    .await
+                    .context("failed to replace the installed extension")?;
+                remove_other_version_dirs(fs.as_ref(), &extensions_dir, &extension_dir).await?;
+
+                this.update(&mut cx, |this, cx| this.reload(Some(extension_id), cx))?
+                    .await;
+                anyhow::Ok(())
+            }
+            .await;
+
+            // The staging directory is only left behind if the install failed.
+            fs.remove_dir(&staging_dir, remove_options).await.log_err();
+            result
+        })
+    }
+
+    /// Installs the extension in the given local directory by copying it into
+    /// the installed extensions directory, replacing any installed version of it.
+    ///
+    /// Unlike [`Self::install_dev_extension`], the extension is not compiled, and
+    /// later changes to the directory only take effect once it is installed again.
+    pub fn install_extension_from_path(
+        &mut self,
+        source_path: PathBuf,
+        cx: &mut ModelContext<Self>,
+    ) -> Task<Result<()>> {
+        let extensions_dir = self.extensions_dir();
+        let staging_dir = self.wasm_host.work_dir.join(".local-install");
+        let fs = self.fs.clone();
+
+        cx.spawn(move |this, mut cx| async move {
+            if !fs.is_dir(&source_path).await {
+                bail!("{source_path:?} is not a directory");
+            }
+            if !fs.is_file(&source_path.join("extension.json")).await
+                && !fs.is_file(&source_path.join("extension.toml")).await
+            {
+                bail!("{source_path:?} is not an extension, as it has no extension.json or extension.toml");
+            }
+            let extension_manifest = ExtensionManifest::load(fs.clone(), &source_path)
+                .await
+                .with_context(|| format!("failed to install extension from {source_path:?}"))?;
+            validate_extension_id(&extension_manifest.id)
+                .with_context(|| format!("failed to install extension from {source_path:?}"))?;
+            let extension_id = extension_manifest.id.clone();
+
+            this.update(&mut cx, |this, cx| {
+                match this.outstanding_operations.entry(extension_id.clone()) {
+                    btree_map::Entry::Occupied(_) => {
+                        bail!("extension {extension_id} is already being modified")
+                    }
+                    btree_map::Entry::Vacant(e) => e.insert(ExtensionOperation::Install),
+                };
+                cx.notify();
+                anyhow::Ok(())
+            })??;
+
+            let _finish = util::defer({
+                let this = this.clone();
+                let mut cx = cx.clone();
+                let extension_id = extension_id.clone();
+                move || {
+                    this.update(&mut cx, |this, cx| {
+                        this.outstanding_operations.remove(extension_id.as_ref());
+                        cx.notify();
+                    })
+                    .ok();
+                }
+            });
+
+            let remove_options = RemoveOptions {
+                recursive: true,
+                ignore_if_not_exists: true,
+            };
+            fs.remove_dir(&staging_dir, remove_options).await?;
+            copy_recursive(
+                fs.as_ref(),
+                &source_path,
+                &staging_dir,
+                CopyOptions::default(),
+            )
+            .await
+            .with_context(|| format!("failed to copy extension from {source_path:?}"))?;
+
+            // Installing from the same directory again replaces the installed copy.
+            let extension_dir = extensions_dir.join(extension_id.as_ref());
+            if let Some(metadata) = fs.metadata(&extension_dir).await? {
+                if metadata.is_symlink {
+                    fs.remove_file(&extension_dir, remove_options).await?;
+                } else {
+                    fs.remove_dir(&extension_dir, remove_options).await?;
+                }
+            }
+            fs.rename(&staging_dir, &extension_dir, Default::default())
+                .await?;
+            remove_other_version_dirs(fs.as_ref(), &extensions_dir, &extension_dir).await?;
+
+            this.update(&mut cx, |this, cx| this.reload(Some(extension_id), cx))?
+                .await;
+            Ok(())
+        })
+    }
+
     pub fn rebuild_dev_extension(&mut self, extension_id: Arc<str>, cx: &mut ModelContext<Self>) {
         let path = self.installed_dir.join(extension_id.as_ref());
         let builder = self.builder.clone();
@@ -929,74 +3508,54 @@ impl ExtensionStore {
 
     /// Updates the set of installed extensions.
     ///
-    /// First, this unloads any themes, languages, or grammars that are
-    /// no longer in the manifest, or whose files have changed on disk.
-    /// Then it loads any themes, languages, or grammars that are newly
-    /// added to the manifest, or whose files have changed on disk.
+    /// This happens in phases:
+    /// 1. [`Self::diff_extensions`] finds the extensions that were added,
+    ///    removed, or modified since the current index.
+    /// 2. [`Self::filter_extensions`] decides which of them are loaded, and
+    ///    which other extensions must be reloaded because of them.
+    /// 3. [`Self::unregister_extensions`] removes the themes, languages, and
+    ///    grammars of the extensions that are unloaded.
+    /// 4. [`Self::register_extensions`] registers the grammars and languages of
+    ///    the extensions that are loaded, removing those whose names are taken
+    ///    from the index.
+    /// 5. [`Self::load_extension_files`] reads their themes, snippets, default
+    ///    settings, and wasm modules in the background.
+    ///
+    /// The new index is written to disk by [`Self::rebuild_extension_index`].
     fn extensions_updated(
         &mut self,
-        new_index: ExtensionIndex,
+        mut new_index: ExtensionIndex,
         cx: &mut ModelContext<Self>,
     ) -> Task<()> {
-        let old_index = &self.extension_index;
-
-        // Determine which extensions need to be loaded and unloaded, based
-        // on the changes to the manifest and the extensions that we know have been
-        // modified.
-        let mut extensions_to_unload = Vec::default();
-        let mut extensions_to_load = Vec::default();
-        {
-            let mut old_keys = old_index.extensions.iter().peekable();
-            let mut new_keys = new_index.extensions.iter().peekable();
-            loop {
-                match (old_keys.peek(), new_keys.peek()) {
-                    (None, None) => break,
-                    (None, Some(_)) => {
-                        extensions_to_load.push(new_keys.next().unwrap().0.clone());
-                    }
-                    (Some(_), None) => {
-                        extensions_to_unload.push(old_keys.next().unwrap().0.clone());
-                    }
-                    (Some((old_key, _)), Some((new_key, _))) => match old_key.cmp(&new_key) {
-                        Ordering::Equal => {
-                            let (old_key, old_value) = old_keys.next().unwrap();
-                            let (new_key, new_value) = new_keys.next().unwrap();
-                            if old_value != new_value || self.modified_extensions.contains(old_key)
-                            {
-                                extensions_to_unload.push(old_key.clone());
-                                extensions_to_load.push(new_key.clone());
-                            }
-                        }
-                        Ordering::Less => {
-                            extensions_to_unload.push(old_keys.next().unwrap().0.clone());
-                        }
-                        Ordering::Greater => {
-                            extensions_to_load.push(new_keys.next().unwrap().0.clone());
-                        }
-                    },
-                }
-            }
-            self.modified_extensions.clear();
+        self.update_dev_extension_watchers(&new_index, cx);
+
+        let mut changes = self.diff_extensions(&new_index, cx);
+        self.filter_extensions(&new_index, &mut changes, cx);
+        // The names that were rejected are tried again when their extensions
+        // are reloaded.
+        self.rejected_artifacts
+            .retain(|extension_id, _| !changes.to_unload.contains(extension_id));
+        new_index.remove_rejected(&self.rejected_artifacts);
+        self.theme_changes = ThemeChanges::between(&self.extension_index, &new_index);
+        if let Some(pending_theme_changes) = self.pending_theme_changes.take() {
+            self.theme_changes =
+                pending_theme_changes.followed_by(mem::take(&mut self.theme_changes));
         }
-
-        if extensions_to_load.is_empty() && extensions_to_unload.is_empty() {
+        if changes.is_empty() {
             return Task::ready(());
         }
 
-        let reload_count = extensions_to_unload
-            .iter()
-            .filter(|id| extensions_to_load.contains(id))
-            .count();
-
+        let reloaded_extensions = changes.reloaded_extensions();
+        let reload_count = reloaded_extensions.len();
         log::info!(
             "extensions updated. loading {}, reloading {}, unloading {}",
-            extensions_to_load.len() - reload_count,
+            changes.to_load.len() - reload_count,
             reload_count,
-            extensions_to_unload.len() - reload_count
+            changes.to_unload.len() - reload_count
         );
 
         if let Some(telemetry) = &self.telemetry {
-            for extension_id in &extensions_to_load {
+            for extension_id in &changes.to_load {
                 if let Some(extension) = new_index.extensions.get(extension_id) {
                     telemetry.report_extension_event(
                         extension_id.clone(),
@@ -1006,134 +3565,643 @@ impl ExtensionStore {
             }
         }
 
-        let themes_to_remove = old_index
-            .themes
-            .iter()
-            .filter_map(|(name, entry)| {
-                if extensions_to_unload.contains(&entry.extension) {
-                    Some(name.clone().into())
-                } else {
-                    None
+        let reregistered_languages = self.reregistered_languages(&new_index, &changes);
+        let previous_theme_hashes =
+            self.unregister_extensions(&new_index, &changes, &reregistered_languages, cx);
+        self.record_collision_warnings(&new_index, &changes.to_load);
+        let files = self.register_extensions(&mut new_index, &changes, &reregistered_languages, cx);
+        let theme_renames = new_index.theme_renames(&changes.to_load);
+        // Themes that were rejected are not reported as added.
+        for rejected_theme in &files.rejected_themes {
+            self.theme_changes.added.remove(rejected_theme);
+        }
+
+        self.extension_index = new_index;
+        cx.notify();
+        cx.emit(Event::ExtensionsUpdated);
+        for extension_id in reloaded_extensions {
+            cx.emit(Event::ExtensionReloaded(extension_id));
+        }
+
+        self.load_extension_files(files, previous_theme_hashes, theme_renames, cx)
+    }
+
+    /// Determines which extensions need to be loaded and unloaded, based on
+    /// the differences between the current index and the given one, and the
+    /// extensions that we know have been modified.
+    ///
+    /// Emits [`Event::ExtensionUpgraded`] for the extensions whose version
+    /// increased.
+    fn diff_extensions(
+        &mut self,
+        new_index: &ExtensionIndex,
+        cx: &mut ModelContext<Self>,
+    ) -> ExtensionChanges {
+        let old_index = &self.extension_index;
+        let mut changes = ExtensionChanges::default();
+        let mut old_keys = old_index.extensions.iter().peekable();
+        let mut new_keys = new_index.extensions.iter().peekable();
+        loop {
+            match (old_keys.peek(), new_keys.peek()) {
+                (None, None) => break,
+                (None, Some(_)) => {
+                    changes.to_load.push(new_keys.next().unwrap().0.clone());
                 }
-            })
-            .collect::<Vec<_>>();
-        let languages_to_remove = old_index
-            .languages
-            .iter()
-            .filter_map(|(name, entry)| {
-                if extensions_to_unload.contains(&entry.extension) {
-                    Some(name.clone())
-                } else {
-                    None
+                (Some(_), None) => {
+                    changes.to_unload.push(old_keys.next().unwrap().0.clone());
                 }
-            })
-            .collect::<Vec<_>>();
-        let mut grammars_to_remove = Vec::new();
-        for extension_id in &extensions_to_unload {
-            let Some(extension) = old_index.extensions.get(extension_id) else {
+                (Some((old_key, _)), Some((new_key, _))) => match old_key.cmp(&new_key) {
+                    Ordering::Equal => {
+                        let (old_key, old_value) = old_keys.next().unwrap();
+                        let (new_key, new_value) = new_keys.next().unwrap();
+                        if old_value != new_value || self.modified_extensions.contains(old_key) {
+                            changes.to_unload.push(old_key.clone());
+                            changes.to_load.push(new_key.clone());
+                        }
+                    }
+                    Ordering::Less => {
+                        changes.to_unload.push(old_keys.next().unwrap().0.clone());
+                    }
+                    Ordering::Greater => {
+                        changes.to_load.push(new_keys.next().unwrap().0.clone());
+                    }
+                },
+            }
+        }
+        self.modified_extensions.clear();
+
+        for extension_id in &changes.to_load {
+            let (Some(old_extension), Some(new_extension)) = (
+                old_index.extensions.get(extension_id),
+                new_index.extensions.get(extension_id),
+            ) else {
                 continue;
             };
-            grammars_to_remove.extend(extension.manifest.grammars.keys().cloned());
-            for (language_server_name, config) in extension.manifest.language_servers.iter() {
-                for language in config.languages() {
-                    self.language_registry
-                        .remove_lsp_adapter(&language, language_server_name);
-                }
+            let from = &old_extension.manifest.version;
+            let to = &new_extension.manifest.version;
+            if from == to {
+                continue;
+            }
+            // Versions that cannot be parsed are lower than any valid version.
+            let old_version =
+                parse_extension_version(extension_id, from, &mut self.malformed_versions);
+            let new_version =
+                parse_extension_version(extension_id, to, &mut self.malformed_versions);
+            if new_version > old_version {
+                cx.emit(Event::ExtensionUpgraded {
+                    extension_id: extension_id.clone(),
+                    from: from.clone(),
+                    to: to.clone(),
+                });
             }
         }
 
-        self.wasm_extensions
-            .retain(|(extension, _)| !extensions_to_unload.contains(&extension.id));
-        self.theme_registry.remove_user_themes(&themes_to_remove);
-        self.language_registry
-            .remove_languages(&languages_to_remove, &grammars_to_remove);
+        changes
+    }
 
-        let languages_to_add = new_index
+    /// Removes the extensions that should not be registered from the ones to
+    /// load, and adds the extensions that must be reloaded because of changes
+    /// to other extensions.
+    ///
+    /// Updates the incompatible and conflicting extensions as a side effect.
+    fn filter_extensions(
+        &mut self,
+        new_index: &ExtensionIndex,
+        changes: &mut ExtensionChanges,
+        cx: &AppContext,
+    ) {
+        let old_index = &self.extension_index;
+        let app_version = AppVersion::global(cx);
+        let extension_settings = ExtensionSettings::get_global(cx);
+
+        // Compatibility is checked again on every reload, since the version of
+        // Zed may have changed. Each change is only logged once.
+        let mut incompatible_extensions = BTreeMap::default();
+        let mut loaded_incompatible_extensions = BTreeSet::default();
+        for (extension_id, extension) in &new_index.extensions {
+            let Some(reason) = incompatibility_reason(&extension.manifest, app_version) else {
+                continue;
+            };
+            if extension_settings.should_load_incompatible(extension_id) {
+                if !self.loaded_incompatible_extensions.contains(extension_id) {
+                    log::warn!(
+                        "loading extension {extension_id} even though it {reason}, because it is listed in load_incompatible_extensions"
+                    );
+                }
+                loaded_incompatible_extensions.insert(extension_id.clone());
+            } else {
+                if self.incompatible_extensions.get(extension_id) != Some(&reason) {
+                    log::error!("not loading extension {extension_id}: it {reason}");
+                }
+                incompatible_extensions.insert(extension_id.clone(), reason);
+            }
+        }
+
+        // Extensions whose compatibility has changed are loaded or unloaded,
+        // even if they have not changed themselves.
+        for extension_id in incompatible_extensions.keys() {
+            if !self.incompatible_extensions.contains_key(extension_id)
+                && old_index.extensions.contains_key(extension_id)
+                && !changes.to_unload.contains(extension_id)
+            {
+                changes.to_unload.push(extension_id.clone());
+            }
+        }
+        for extension_id in self.incompatible_extensions.keys() {
+            if !incompatible_extensions.contains_key(extension_id)
+                && new_index.extensions.contains_key(extension_id)
+                && !changes.to_load.contains(extension_id)
+            {
+                changes.to_load.push(extension_id.clone());
+            }
+        }
+        self.incompatible_extensions = incompatible_extensions;
+        self.loaded_incompatible_extensions = loaded_incompatible_extensions;
+
+        // Extensions whose conflicts have changed are loaded or unloaded, even
+        // if they have not changed themselves.
+        let conflicting_extensions = find_conflicting_extensions(new_index);
+        for extension_id in conflicting_extensions.keys() {
+            if !self.conflicting_extensions.contains_key(extension_id)
+                && old_index.extensions.contains_key(extension_id)
+                && !changes.to_unload.contains(extension_id)
+            {
+                changes.to_unload.push(extension_id.clone());
+            }
+        }
+        for extension_id in self.conflicting_extensions.keys() {
+            if !conflicting_extensions.contains_key(extension_id)
+                && new_index.extensions.contains_key(extension_id)
+                && !changes.to_load.contains(extension_id)
+            {
+                changes.to_load.push(extension_id.clone());
+            }
+        }
+
+        // Extensions whose themes are renamed, or whose languages or grammars are
+        // shadowed, because of other extensions are reloaded when that changes,
+        // so that they are registered under their new names, or at all.
+        let renamed_themes = |index: &ExtensionIndex| {
+            index
+                .themes
+                .iter()
+                .filter(|(_, theme)| theme.original_name.is_some())
+                .map(|(theme_name, theme)| (theme.extension.clone(), theme_name.clone()))
+                .collect::<HashSet<_>>()
+        };
+        let shadowed_languages = |index: &ExtensionIndex| {
+            index
+                .shadowed_languages
+                .iter()
+                .flat_map(|(language_name, languages)| {
+                    languages
+                        .iter()
+                        .map(|language| (language.extension.clone(), language_name.clone()))
+                })
+                .collect::<HashSet<_>>()
+        };
+        let old_renamed_themes = renamed_themes(old_index);
+        let new_renamed_themes = renamed_themes(new_index);
+        let old_shadowed_languages = shadowed_languages(old_index);
+        let new_shadowed_languages = shadowed_languages(new_index);
+        let old_shadowed_grammars = old_index.shadowed_grammars();
+        let new_shadowed_grammars = new_index.shadowed_grammars();
+        let changed_extensions = old_renamed_themes
+            .symmetric_difference(&new_renamed_themes)
+            .chain(old_shadowed_languages.symmetric_difference(&new_shadowed_languages))
+            .chain(old_shadowed_grammars.symmetric_difference(&new_shadowed_grammars))
+            .map(|(extension_id, _)| extension_id.clone())
+            .collect::<BTreeSet<_>>();
+        for extension_id in &changed_extensions {
+            if old_index.extensions.contains_key(extension_id)
+                && new_index.extensions.contains_key(extension_id)
+                && !changes.to_load.contains(extension_id)
+                && !changes.to_unload.contains(extension_id)
+            {
+                changes.to_unload.push(extension_id.clone());
+                changes.to_load.push(extension_id.clone());
+            }
+        }
+
+        changes.to_load.retain(|extension_id| {
+            if let Some(reason) = conflicting_extensions.get(extension_id) {
+                log::warn!("not loading extension {extension_id}: it {reason}");
+                return false;
+            }
+            new_index
+                .extensions
+                .get(extension_id)
+                .map_or(true, |extension| {
+                    should_register_extension(extension, app_version, extension_settings)
+                })
+        });
+        self.conflicting_extensions = conflicting_extensions;
+    }
+
+    /// Returns the languages that are registered again because the extensions
+    /// that add queries to them are loaded or unloaded, so that they use the
+    /// new queries.
+    fn reregistered_languages(
+        &self,
+        new_index: &ExtensionIndex,
+        changes: &ExtensionChanges,
+    ) -> HashSet<Arc<str>> {
+        changes
+            .to_unload
+            .iter()
+            .filter_map(|extension_id| self.extension_index.extensions.get(extension_id))
+            .chain(
+                changes
+                    .to_load
+                    .iter()
+                    .filter_map(|extension_id| new_index.extensions.get(extension_id)),
+            )
+            .flat_map(|extension| extension.manifest.language_queries.keys())
+            .filter(|language_name| self.registered.languages.contains(*language_name))
+            .cloned()
+            .collect()
+    }
+
+    /// Removes the themes, languages, grammars, language servers, and default
+    /// settings of the extensions that are unloaded, along with their load errors.
+    ///
+    /// Returns the content hashes of the registered themes from before they
+    /// were removed.
+    fn unregister_extensions(
+        &mut self,
+        new_index: &ExtensionIndex,
+        changes: &ExtensionChanges,
+        reregistered_languages: &HashSet<Arc<str>>,
+        cx: &mut AppContext,
+    ) -> HashMap<Arc<str>, u64> {
+        let old_index = &self.extension_index;
+        let extensions_to_unload = &changes.to_unload;
+        let extensions_to_load = &changes.to_load;
+
+        // Themes that are provided by the same file after a reload are kept
+        // registered, and are only replaced if their contents have changed.
+        let themes_to_remove = old_index
+            .themes
+            .iter()
+            .filter_map(|(name, entry)| {
+                if !extensions_to_unload.contains(&entry.extension) {
+                    return None;
+                }
+                let is_reloaded_from_same_file = extensions_to_load.contains(&entry.extension)
+                    && new_index.themes.get(name) == Some(entry);
+                if is_reloaded_from_same_file {
+                    None
+                } else {
+                    Some(name.clone().into())
+                }
+            })
+            .collect::<Vec<_>>();
+        let languages_to_remove = old_index
+            .languages
+            .iter()
+            .filter_map(|(name, entry)| {
+                if extensions_to_unload.contains(&entry.extension)
+                    || reregistered_languages.contains(name)
+                {
+                    Some(name.clone())
+                } else {
+                    None
+                }
+            })
+            .collect::<Vec<_>>();
+        let old_shadowed_grammars = old_index.shadowed_grammars();
+        let mut grammars_to_remove = Vec::new();
+        for extension_id in extensions_to_unload {
+            let Some(extension) = old_index.extensions.get(extension_id) else {
+                continue;
+            };
+            // A shadowed grammar was never registered, and its name refers to
+            // the grammar of the extension that takes precedence.
+            grammars_to_remove.extend(
+                extension
+                    .manifest
+                    .grammars
+                    .keys()
+                    .filter(|grammar_name| {
+                        !old_shadowed_grammars
+                            .contains(&(extension_id.clone(), (*grammar_name).clone()))
+                    })
+                    .cloned(),
+            );
+            grammars_to_remove.extend(
+                self.produced_grammars
+                    .remove(extension_id)
+                    .into_iter()
+                    .flatten(),
+            );
+            for (language_server_name, config) in extension.manifest.language_servers.iter() {
+                for language in config.languages() {
+                    self.language_registry
+                        .remove_lsp_adapter(&language, language_server_name);
+                }
+            }
+        }
+
+        self.wasm_extensions
+            .retain(|(extension, _)| !extensions_to_unload.contains(&extension.id));
+        self.load_errors
+            .retain(|error| !extensions_to_unload.contains(&error.extension_id));
+        SettingsStore::update_global(cx, |store, cx| {
+            for extension_id in extensions_to_unload {
+                store
+                    .remove_extension_default_settings(extension_id, cx)
+                    .log_err();
+            }
+        });
+
+        // The hashes are copied before those of the removed themes are dropped,
+        // so that a theme that is removed and added again with different
+        // contents is reported as changed.
+        let previous_theme_hashes = self.theme_content_hashes.clone();
+        self.theme_registry.remove_user_themes(&themes_to_remove);
+        self.language_registry
+            .remove_languages(&languages_to_remove, &grammars_to_remove);
+        for theme in &themes_to_remove {
+            self.registered.themes.remove(theme.as_ref());
+            self.theme_content_hashes.remove(theme.as_ref());
+        }
+        for language in &languages_to_remove {
+            self.registered.languages.remove(language);
+        }
+        for grammar in &grammars_to_remove {
+            self.registered.grammars.remove(grammar);
+        }
+
+        previous_theme_hashes
+    }
+
+    /// Records a warning for each theme, language, and grammar of the given
+    /// extensions that is renamed or shadowed because another extension
+    /// provides one with the same name.
+    fn record_collision_warnings(
+        &mut self,
+        new_index: &ExtensionIndex,
+        extensions_to_load: &[Arc<str>],
+    ) {
+        for (theme_name, theme) in &new_index.themes {
+            let Some(original_name) = &theme.original_name else {
+                continue;
+            };
+            if !extensions_to_load.contains(&theme.extension) {
+                continue;
+            }
+            let other_extension = new_index
+                .themes
+                .get(original_name)
+                .map_or("another extension", |other| other.extension.as_ref());
+            self.load_errors.push(ExtensionLoadError {
+                extension_id: theme.extension.clone(),
+                severity: LoadErrorSeverity::Warning,
+                message: format!(
+                    "theme {original_name:?} is registered as {theme_name:?}, because extension {other_extension} also provides a theme with that name"
+                ),
+            });
+        }
+        for (language_name, languages) in &new_index.shadowed_languages {
+            let other_extension = new_index
+                .languages
+                .get(language_name)
+                .map_or("another extension", |other| other.extension.as_ref());
+            for language in languages {
+                if extensions_to_load.contains(&language.extension) {
+                    self.load_errors.push(ExtensionLoadError {
+                        extension_id: language.extension.clone(),
+                        severity: LoadErrorSeverity::Warning,
+                        message: format!(
+                            "language {language_name:?} is not registered, because extension {other_extension} also provides a language with that name"
+                        ),
+                    });
+                }
+            }
+        }
+        for (extension_id, grammar_name) in &new_index.shadowed_grammars() {
+            if !extensions_to_load.contains(extension_id) {
+                continue;
+            }
+            let other_extension = new_index
+                .extensions
+                .iter()
+                .find(|(_, other)| other.manifest.grammars.contains_key(grammar_name))
+                .map_or("another extension", |(other_extension, _)| {
+                    other_extension.as_ref()
+                });
+            self.load_errors.push(ExtensionLoadError {
+                extension_id: extension_id.clone(),
+                severity: LoadErrorSeverity::Warning,
+                message: format!(
+                    "grammar {grammar_name:?} is not registered, because extension {other_extension} also provides a grammar with that name"
+                ),
+            });
+        }
+    }
+
+    /// Registers the grammars and languages of the extensions that are loaded,
+    /// and returns their files that are read in the background.
+    ///
+    /// The languages and themes whose names are already taken by languages and
+    /// themes that this store did not register are removed from the index, and
+    /// recorded as load errors.
+    fn register_extensions(
+        &mut self,
+        new_index: &mut ExtensionIndex,
+        changes: &ExtensionChanges,
+        reregistered_languages: &HashSet<Arc<str>>,
+        cx: &mut ModelContext<Self>,
+    ) -> ExtensionFiles {
+        let extensions_to_load = &changes.to_load;
+        let new_shadowed_grammars = new_index.shadowed_grammars();
+        let languages_to_add = new_index
             .languages
             .iter()
-            .filter(|(_, entry)| extensions_to_load.contains(&entry.extension))
+            .filter(|(name, entry)| {
+                extensions_to_load.contains(&entry.extension)
+                    || reregistered_languages.contains(*name)
+            })
             .collect::<Vec<_>>();
         let mut grammars_to_add = Vec::new();
-        let mut themes_to_add = Vec::new();
-        let mut snippets_to_add = Vec::new();
-        for extension_id in &extensions_to_load {
+        let mut files = ExtensionFiles::default();
+        for extension_id in extensions_to_load {
             let Some(extension) = new_index.extensions.get(extension_id) else {
                 continue;
             };
 
-            grammars_to_add.extend(extension.manifest.grammars.keys().map(|grammar_name| {
-                let mut grammar_path = self.installed_dir.clone();
-                grammar_path.extend([extension_id.as_ref(), "grammars"]);
-                grammar_path.push(grammar_name.as_ref());
-                grammar_path.set_extension("wasm");
-                (grammar_name.clone(), grammar_path)
-            }));
-            themes_to_add.extend(extension.manifest.themes.iter().map(|theme_path| {
-                let mut path = self.installed_dir.clone();
-                path.extend([Path::new(extension_id.as_ref()), theme_path.as_path()]);
-                path
-            }));
-            snippets_to_add.extend(extension.manifest.snippets.iter().map(|snippets_path| {
-                let mut path = self.installed_dir.clone();
-                path.extend([Path::new(extension_id.as_ref()), snippets_path.as_path()]);
-                path
-            }));
+            files.default_settings.push((
+                extension_id.clone(),
+                self.installed_dir
+                    .join(extension.installed_dir_name())
+                    .join(DEFAULT_SETTINGS_FILE_NAME),
+            ));
+
+            grammars_to_add.extend(
+                extension
+                    .manifest
+                    .grammars
+                    .keys()
+                    .filter(|grammar_name| {
+                        !new_shadowed_grammars
+                            .contains(&(extension_id.clone(), (*grammar_name).clone()))
+                    })
+                    .map(|grammar_name| {
+                        (
+                            grammar_name.clone(),
+                            self.grammar_path(extension, grammar_name),
+                        )
+                    }),
+            );
+            let extension_dir = self.installed_dir.join(extension.installed_dir_name());
+            files.themes.extend(
+                extension
+                    .manifest
+                    .themes
+                    .iter()
+                    .map(|theme_path| (extension_id.clone(), extension_dir.join(theme_path))),
+            );
+            files.snippets.extend(
+                extension
+                    .manifest
+                    .snippets
+                    .iter()
+                    .map(|snippets_path| extension_dir.join(snippets_path)),
+            );
+            files.extensions.push(extension.clone());
         }
 
+        if !grammars_to_add.is_empty() {
+            cx.emit(Event::ReloadProgress(ReloadPhase::RegisteringGrammars {
+                grammar_count: grammars_to_add.len(),
+            }));
+        }
+        self.registered
+            .grammars
+            .extend(grammars_to_add.iter().map(|(name, _)| name.clone()));
         self.language_registry
             .register_wasm_grammars(grammars_to_add);
 
+        let mut rejected_languages = Vec::new();
         for (language_name, language) in languages_to_add {
-            let mut language_path = self.installed_dir.clone();
-            language_path.extend([
-                Path::new(language.extension.as_ref()),
-                language.path.as_path(),
-            ]);
-            self.language_registry.register_language(
-                language_name.clone(),
-                language.grammar.clone(),
-                language.matcher.clone(),
-                move || {
-                    let config = std::fs::read_to_string(language_path.join("config.toml"))?;
-                    let config: LanguageConfig = ::toml::from_str(&config)?;
-                    let queries = load_plugin_queries(&language_path);
-                    let tasks = std::fs::read_to_string(language_path.join("tasks.json"))
-                        .ok()
-                        .and_then(|contents| {
-                            let definitions = serde_json_lenient::from_str(&contents).log_err()?;
-                            Some(Arc::new(ContextProviderWithTasks::new(definitions)) as Arc<_>)
-                        });
-
-                    Ok((config, queries, tasks))
-                },
-            );
+            match self.register_language(language_name, language, new_index) {
+                Ok(()) => {
+                    self.registered.languages.insert(language_name.clone());
+                }
+                Err(error) => {
+                    rejected_languages.push((
+                        language.extension.clone(),
+                        language_name.clone(),
+                        format!("{error:#}"),
+                    ));
+                }
+            }
+        }
+        let rejected_themes = new_index
+            .themes
+            .iter()
+            .filter(|(theme_name, theme)| {
+                extensions_to_load.contains(&theme.extension)
+                    && !self.registered.themes.contains(*theme_name)
+                    && self.theme_registry.get(theme_name).is_ok()
+            })
+            .map(|(theme_name, theme)| {
+                (
+                    theme.extension.clone(),
+                    theme_name.clone(),
+                    format!(
+                        "failed to register theme {theme_name:?}: a theme with that name is already registered"
+                    ),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        for (extension_id, language_name, message) in rejected_languages {
+            log::error!("{message}");
+            new_index.languages.remove(&language_name);
+            self.rejected_artifacts
+                .entry(extension_id.clone())
+                .or_default()
+                .languages
+                .insert(language_name);
+            self.load_errors.push(ExtensionLoadError {
+                extension_id,
+                severity: LoadErrorSeverity::Error,
+                message,
+            });
+        }
+        for (extension_id, theme_name, message) in rejected_themes {
+            log::error!("{message}");
+            new_index.themes.remove(&theme_name);
+            files.rejected_themes.insert(theme_name.clone());
+            self.rejected_artifacts
+                .entry(extension_id.clone())
+                .or_default()
+                .themes
+                .insert(theme_name);
+            self.load_errors.push(ExtensionLoadError {
+                extension_id,
+                severity: LoadErrorSeverity::Error,
+                message,
+            });
         }
+        self.registered.themes.extend(
+            new_index
+                .themes
+                .iter()
+                .filter(|(_, entry)| extensions_to_load.contains(&entry.extension))
+                .map(|(name, _)| name.clone()),
+        );
+
+        files
+    }
 
+    /// Reads the themes, snippets, and default settings of the loaded
+    /// extensions, and loads their wasm modules, registering the language
+    /// servers, slash commands, and docs providers that they provide.
+    fn load_extension_files(
+        &self,
+        files: ExtensionFiles,
+        previous_theme_hashes: HashMap<Arc<str>, u64>,
+        theme_renames: HashMap<Arc<str>, HashMap<Arc<str>, Arc<str>>>,
+        cx: &mut ModelContext<Self>,
+    ) -> Task<()> {
         let fs = self.fs.clone();
         let wasm_host = self.wasm_host.clone();
         let root_dir = self.installed_dir.clone();
         let theme_registry = self.theme_registry.clone();
         let snippet_registry = self.snippet_registry.clone();
-        let extension_entries = extensions_to_load
-            .iter()
-            .filter_map(|name| new_index.extensions.get(name).cloned())
-            .collect::<Vec<_>>();
-
-        self.extension_index = new_index;
-        cx.notify();
-        cx.emit(Event::ExtensionsUpdated);
+        let fallback_appearance = ExtensionSettings::get_global(cx).fallback_theme_appearance();
+        let ExtensionFiles {
+            themes: themes_to_add,
+            rejected_themes,
+            snippets: snippets_to_add,
+            default_settings: default_settings_to_add,
+            extensions: extension_entries,
+        } = files;
+
+        if !themes_to_add.is_empty() {
+            cx.emit(Event::ReloadProgress(ReloadPhase::RegisteringThemes {
+                theme_count: themes_to_add.len(),
+            }));
+        }
 
         cx.spawn(|this, mut cx| async move {
-            cx.background_executor()
+            let (loaded_themes, default_settings) = cx
+                .background_executor()
                 .spawn({
                     let fs = fs.clone();
                     async move {
-                        for theme_path in &themes_to_add {
-                            theme_registry
-                                .load_user_theme(&theme_path, fs.clone())
-                                .await
-                                .log_err();
-                        }
+                        let loaded_themes = load_theme_files(
+                            fs.clone(),
+                            &theme_registry,
+                            &themes_to_add,
+                            &theme_renames,
+                            &rejected_themes,
+                            &previous_theme_hashes,
+                            fallback_appearance,
+                        )
+                        .await;
 
                         for snippets_path in &snippets_to_add {
                             if let Some(snippets_contents) = fs.load(snippets_path).await.log_err()
@@ -1143,19 +4211,66 @@ impl ExtensionStore {
                                     .log_err();
                             }
                         }
+
+                        let mut default_settings = Vec::new();
+                        for (extension_id, settings_path) in default_settings_to_add {
+                            if fs.is_file(&settings_path).await {
+                                if let Some(content) = fs.load(&settings_path).await.log_err() {
+                                    default_settings.push((extension_id, content));
+                                }
+                            }
+                        }
+
+                        (loaded_themes, default_settings)
                     }
                 })
                 .await;
-
+            this.update(&mut cx, |this, cx| {
+                this.load_errors.extend(loaded_themes.warnings);
+                this.theme_content_hashes.extend(loaded_themes.hashes);
+                this.theme_changes.changed.extend(loaded_themes.changed);
+                SettingsStore::update_global(cx, |store, cx| {
+                    for (extension_id, content) in default_settings {
+                        store
+                            .set_extension_default_settings(extension_id, &content, cx)
+                            .log_err();
+                    }
+                });
+            })
+            .ok();
             let mut wasm_extensions = Vec::new();
-            for extension in extension_entries {
-                if extension.manifest.lib.kind.is_none() {
-                    continue;
-                };
+            let mut wasm_extension_entries = extension_entries
+                .into_iter()
+                .filter(|extension| extension.manifest.lib.kind.is_some())
+                .collect::<Vec<_>>();
+            if !wasm_host.can_load_extensions() && !wasm_extension_entries.is_empty() {
+                // The language servers, slash commands, and docs providers of
+                // these extensions are not registered, but their languages and
+                // themes still are.
+                log::error!(
+                    "cannot load wasm extensions, because the release channel has not been \
+                    initialized. Was `release_channel::init` called?"
+                );
+                this.update(&mut cx, |this, _| {
+                    this.load_errors
+                        .extend(wasm_extension_entries.iter().map(|extension| {
+                            ExtensionLoadError {
+                                extension_id: extension.manifest.id.clone(),
+                                severity: LoadErrorSeverity::Error,
+                                message: "the extension could not be loaded, because the \
+                                    release channel has not been initialized"
+                                    .into(),
+                            }
+                        }));
+                })
+                .ok();
+                wasm_extension_entries.clear();
+            }
 
+            for extension in wasm_extension_entries {
                 let wasm_extension = maybe!(async {
                     let mut path = root_dir.clone();
-                    path.extend([extension.manifest.clone().id.as_ref(), "extension.wasm"]);
+                    path.extend([extension.installed_dir_name(), "extension.wasm"]);
                     let mut wasm_file = fs
                         .open_sync(&path)
                         .await
@@ -1179,112 +4294,1033 @@ impl ExtensionStore {
                 })
                 .await;
 
-                if let Some(wasm_extension) = wasm_extension.log_err() {
-                    wasm_extensions.push((extension.manifest.clone(), wasm_extension));
-                } else {
+                match wasm_extension {
+                    Ok(wasm_extension) => {
+                        wasm_extensions.push((extension.manifest.clone(), wasm_extension));
+                    }
+                    Err(error) => {
+                        log::error!("{error:?}");
+                        this.update(&mut cx, |this, cx| {
+                            this.load_errors.push(ExtensionLoadError {
+                                extension_id: extension.manifest.id.clone(),
+                                severity: LoadErrorSeverity::Error,
+                                message: format!("{error:#}"),
+                            });
+                            cx.emit(Event::ExtensionFailedToLoad(extension.manifest.id.clone()))
+                        })
+                        .ok();
+                    }
+                }
+            }
+
+            this.update(&mut cx, |this, cx| {
+                let extension_settings = ExtensionSettings::get_global(cx);
+                for (manifest, wasm_extension) in &wasm_extensions {
+                    this.register_language_servers(manifest, wasm_extension, extension_settings);
+
+                    for (slash_command_name, slash_command) in &manifest.slash_commands {
+                        this.slash_command_registry.register_command(
+                            ExtensionSlashCommand {
+                                command: crate::wit::SlashCommand {
+                                    name: slash_command_name.to_string(),
+                                    description: slash_command.description.to_string(),
+                                    tooltip_text: slash_command.tooltip_text.to_string(),
+                                    requires_argument: slash_command.requires_argument,
+                                },
+                                extension: wasm_extension.clone(),
+                                host: this.wasm_host.clone(),
+                            },
+                            false,
+                        );
+                    }
+
+                    for (provider_id, _provider) in &manifest.indexed_docs_providers {
+                        this.indexed_docs_registry.register_provider(Box::new(
+                            ExtensionIndexedDocsProvider {
+                                extension: wasm_extension.clone(),
+                                host: this.wasm_host.clone(),
+                                id: ProviderId(provider_id.clone()),
+                            },
+                        ));
+                    }
+                }
+
+                this.wasm_extensions.extend(wasm_extensions);
+                ThemeSettings::reload_current_theme(cx)
+            })
+            .ok();
+        })
+    }
+
+    /// Registers the language servers of the given loaded extension that are
+    /// enabled by the settings.
+    fn register_language_servers(
+        &self,
+        manifest: &ExtensionManifest,
+        wasm_extension: &WasmExtension,
+        extension_settings: &ExtensionSettings,
+    ) {
+        for (language_server_id, language_server_config) in
+            manifest.enabled_language_servers(extension_settings)
+        {
+            for language in language_server_config.languages() {
+                self.language_registry.register_lsp_adapter(
+                    language.clone(),
+                    Arc::new(ExtensionLspAdapter {
+                        extension: wasm_extension.clone(),
+                        host: self.wasm_host.clone(),
+                        language_server_id: language_server_id.clone(),
+                        config: wit::LanguageServerConfig {
+                            name: language_server_id.0.to_string(),
+                            language_name: language.to_string(),
+                        },
+                    }),
+                );
+            }
+        }
+    }
+
+    /// Registers the language servers of the loaded extensions again when the
+    /// features that enable them have changed, so that only the enabled ones
+    /// are registered.
+    fn extension_settings_changed(&mut self, cx: &mut ModelContext<Self>) {
+        let extension_settings = ExtensionSettings::get_global(cx);
+        if extension_settings.extension_features == self.extension_features {
+            return;
+        }
+        let previous_features = mem::replace(
+            &mut self.extension_features,
+            extension_settings.extension_features.clone(),
+        );
+
+        for (manifest, wasm_extension) in &self.wasm_extensions {
+            if previous_features.get(&manifest.id)
+                == extension_settings.extension_features.get(&manifest.id)
+            {
+                continue;
+            }
+            for (language_server_name, config) in &manifest.language_servers {
+                for language in config.languages() {
+                    self.language_registry
+                        .remove_lsp_adapter(&language, language_server_name);
+                }
+            }
+            self.register_language_servers(manifest, wasm_extension, extension_settings);
+        }
+    }
+
+    /// Loads all of the grammars registered by extensions, instead of waiting
+    /// for the languages that use them to be opened.
+    ///
+    /// The grammars are loaded concurrently, bounded by the
+    /// `max_concurrent_grammar_loads` setting. Those that fail to load are
+    /// recorded in [`Self::load_errors`].
+    pub fn preload_grammars(&self, cx: &ModelContext<Self>) -> Task<()> {
+        let grammar_names = self.registered.grammars.iter().cloned().collect();
+        let parallelism = ExtensionSettings::get_global(cx).max_concurrent_grammar_loads();
+        cx.background_executor().spawn(
+            self.language_registry
+                .load_wasm_grammars(grammar_names, parallelism),
+        )
+    }
+
+    /// Removes the grammars that no language in the extension index refers to:
+    /// unregisters them, and deletes the WASM files of those that installed
+    /// extensions provide. Returns the names of the grammars that were
+    /// registered or had files to delete.
+    ///
+    /// A grammar is kept as long as some language refers to it, whichever
+    /// extension provides that language. The files of dev extensions are left
+    /// in place.
+    pub fn prune_orphaned_grammars(
+        &mut self,
+        cx: &mut ModelContext<Self>,
+    ) -> Task<Result<Vec<Arc<str>>>> {
+        let referenced_grammars = self
+            .extension_index
+            .languages
+            .values()
+            .flat_map(|language| {
+                language.grammar.iter().chain(
+                    language
+                        .grammar_variants
+                        .iter()
+                        .map(|variant| &variant.grammar),
+                )
+            })
+            .cloned()
+            .collect::<HashSet<_>>();
+
+        // The files of each orphaned grammar, including its blob in the
+        // grammar store unless a grammar that is still in use shares it.
+        let used_hashes = self
+            .extension_index
+            .extensions
+            .values()
+            .flat_map(|extension| {
+                extension
+                    .grammar_hashes
+                    .iter()
+                    .filter(|(grammar, _)| referenced_grammars.contains(*grammar))
+                    .map(|(_, hash)| hash)
+            })
+            .collect::<HashSet<_>>();
+        let mut orphaned_files = Vec::<(Arc<str>, PathBuf)>::new();
+        for extension in self.extension_index.extensions.values() {
+            if extension.dev {
+                continue;
+            }
+            let grammars_dir = self
+                .installed_dir
+                .join(extension.installed_dir_name())
+                .join("grammars");
+            for grammar in extension.manifest.grammars.keys() {
+                if referenced_grammars.contains(grammar) {
+                    continue;
+                }
+                let grammar_path = grammars_dir.join(grammar.as_ref());
+                orphaned_files.push((grammar.clone(), grammar_path.with_extension("wasm")));
+                orphaned_files.push((
+                    grammar.clone(),
+                    grammar_path.with_extension(GRAMMAR_HASH_FILE_EXTENSION),
+                ));
+                let stored_grammar_path = extension
+                    .grammar_hashes
+                    .get(grammar)
+                    .filter(|hash| !used_hashes.contains(hash))
+                    .and_then(|hash| grammar_store_path(&self.grammar_store_dir, hash));
+                if let Some(stored_grammar_path) = stored_grammar_path {
+                    orphaned_files.push((grammar.clone(), stored_grammar_path));
+                }
+            }
+        }
+
+        let mut orphaned_grammars = self
+            .registered
+            .grammars
+            .iter()
+            .filter(|grammar| !referenced_grammars.contains(*grammar))
+            .cloned()
+            .collect::<BTreeSet<_>>();
+        self.language_registry
+            .remove_languages(&[], &orphaned_grammars.iter().cloned().collect::<Vec<_>>());
+        for grammar in &orphaned_grammars {
+            self.registered.grammars.remove(grammar);
+        }
+
+        let fs = self.fs.clone();
+        cx.background_executor().spawn(async move {
+            for (grammar, path) in orphaned_files {
+                if fs.metadata(&path).await?.is_none() {
+                    continue;
+                }
+                fs.remove_file(
+                    &path,
+                    RemoveOptions {
+                        recursive: false,
+                        ignore_if_not_exists: true,
+                    },
+                )
+                .await?;
+                orphaned_grammars.insert(grammar);
+            }
+            Ok(orphaned_grammars.into_iter().collect())
+        })
+    }
+
+    /// Removes everything that the installed extensions contributed to the
+    /// shared registries: their languages, grammars, themes, language servers,
+    /// and default settings. This lets the registries be reused once the store
+    /// is shut down, e.g. between tests.
+    ///
+    /// The extension index is kept, so a later reload registers the
+    /// extensions again.
+    pub fn unregister_all(&mut self, cx: &mut ModelContext<Self>) {
+        for extension in self.extension_index.extensions.values() {
+            for (language_server_name, config) in extension.manifest.language_servers.iter() {
+                for language in config.languages() {
+                    self.language_registry
+                        .remove_lsp_adapter(&language, language_server_name);
+                }
+            }
+        }
+        SettingsStore::update_global(cx, |store, cx| {
+            for extension_id in self.extension_index.extensions.keys() {
+                store
+                    .remove_extension_default_settings(extension_id, cx)
+                    .log_err();
+            }
+        });
+
+        let registered = mem::take(&mut self.registered);
+        self.produced_grammars.clear();
+        self.theme_content_hashes.clear();
+        self.wasm_extensions.clear();
+        self.theme_registry.remove_user_themes(
+            &registered
+                .themes
+                .into_iter()
+                .map(SharedString::from)
+                .collect::<Vec<_>>(),
+        );
+        self.language_registry.remove_languages(
+            &registered.languages.into_iter().collect::<Vec<_>>(),
+            &registered.grammars.into_iter().collect::<Vec<_>>(),
+        );
+        cx.notify();
+    }
+
+    /// Removes the extensions whose directories no longer exist, e.g. because
+    /// they were deleted while Zed was not running, from the extension index,
+    /// and rewrites the index on disk.
+    ///
+    /// Returns the IDs of the removed extensions.
+    pub fn compact(&mut self, cx: &mut ModelContext<Self>) -> Task<Result<Vec<Arc<str>>>> {
+        let fs = self.fs.clone();
+        let extension_dirs = self
+            .extension_index
+            .extensions
+            .keys()
+            .map(|extension_id| {
+                let extension_dir = self
+                    .extension_index
+                    .extension_dir(&self.installed_dir, extension_id);
+                (extension_id.clone(), extension_dir)
+            })
+            .collect::<Vec<_>>();
+
+        cx.spawn(|this, mut cx| async move {
+            let mut dangling_extensions = Vec::new();
+            for (extension_id, extension_dir) in extension_dirs {
+                if !fs.is_dir(&extension_dir).await {
+                    dangling_extensions.push(extension_id);
+                }
+            }
+
+            let write = this.update(&mut cx, |this, cx| {
+                this.extension_index.remove_extensions(&dangling_extensions);
+                if !dangling_extensions.is_empty() {
+                    this.reconcile(cx).detach();
+                    cx.notify();
+                }
+
+                let index_json = serde_json::to_string_pretty(&this.extension_index)?;
+                this.index_write_task = None;
+                this.pending_index_json = Some(index_json);
+                anyhow::Ok(this.write_pending_index(cx))
+            })??;
+            write.await?;
+
+            Ok(dangling_extensions)
+        })
+    }
+
+    /// Makes the language, theme, and grammar registries exactly match the
+    /// extension index.
+    ///
+    /// Anything in the index that is missing from the registries is registered,
+    /// and anything this store registered that is no longer in the index is
+    /// removed. Extensions that are disabled, incompatible, or conflicting are
+    /// left unregistered, as they are on reload. Returns the changes that were
+    /// made.
+    pub fn reconcile(&mut self, cx: &mut ModelContext<Self>) -> Task<ReconcileReport> {
+        let mut report = ReconcileReport::default();
+
+        let registered_languages = self
+            .language_registry
+            .language_names()
+            .into_iter()
+            .map(Arc::<str>::from)
+            .collect::<HashSet<_>>();
+        let registered_grammars = self
+            .language_registry
+            .grammar_names()
+            .into_iter()
+            .collect::<HashSet<_>>();
+        let registered_themes = self
+            .theme_registry
+            .list_names(false)
+            .into_iter()
+            .map(|name| Arc::<str>::from(name.as_ref()))
+            .collect::<HashSet<_>>();
+
+        let registered_extensions = self.registered_extension_ids(cx);
+        let shadowed_grammars = self.extension_index.shadowed_grammars();
+        let mut expected_grammars = BTreeMap::default();
+        for (extension_id, extension) in &self.extension_index.extensions {
+            if !registered_extensions.contains(extension_id) {
+                continue;
+            }
+            for grammar_name in extension.manifest.grammars.keys() {
+                if shadowed_grammars.contains(&(extension_id.clone(), grammar_name.clone())) {
+                    continue;
+                }
+                expected_grammars.insert(
+                    grammar_name.clone(),
+                    self.grammar_path(extension, grammar_name),
+                );
+            }
+        }
+        let expected_languages = self
+            .extension_index
+            .languages
+            .iter()
+            .filter(|(_, language)| registered_extensions.contains(&language.extension))
+            .collect::<BTreeMap<_, _>>();
+        let expected_themes = self
+            .extension_index
+            .themes
+            .iter()
+            .filter(|(_, theme)| registered_extensions.contains(&theme.extension))
+            .collect::<BTreeMap<_, _>>();
+
+        report.removed.languages = self
+            .registered
+            .languages
+            .iter()
+            .filter(|name| !expected_languages.contains_key(*name))
+            .filter(|name| registered_languages.contains(*name))
+            .cloned()
+            .collect();
+        report.removed.grammars = self
+            .registered
+            .grammars
+            .iter()
+            .filter(|name| !expected_grammars.contains_key(*name))
+            .filter(|name| registered_grammars.contains(*name))
+            .cloned()
+            .collect();
+        report.removed.themes = self
+            .registered
+            .themes
+            .iter()
+            .filter(|name| !expected_themes.contains_key(*name))
+            .filter(|name| registered_themes.contains(*name))
+            .cloned()
+            .collect();
+
+        self.theme_registry.remove_user_themes(
+            &report
+                .removed
+                .themes
+                .iter()
+                .map(|name| SharedString::from(name.clone()))
+                .collect::<Vec<_>>(),
+        );
+        self.language_registry.remove_languages(
+            &report.removed.languages.iter().cloned().collect::<Vec<_>>(),
+            &report.removed.grammars.iter().cloned().collect::<Vec<_>>(),
+        );
+
+        let expected_grammar_names = expected_grammars.keys().cloned().collect();
+        let grammars_to_add = expected_grammars
+            .into_iter()
+            .filter(|(name, _)| !registered_grammars.contains(name))
+            .collect::<Vec<_>>();
+        report.added.grammars = grammars_to_add
+            .iter()
+            .map(|(name, _)| name.clone())
+            .collect();
+        self.language_registry
+            .register_wasm_grammars(grammars_to_add);
+
+        for (language_name, language) in &expected_languages {
+            if !registered_languages.contains(*language_name)
+                && self
+                    .register_language(language_name, language, &self.extension_index)
+                    .log_err()
+                    .is_some()
+            {
+                report.added.languages.insert((*language_name).clone());
+            }
+        }
+
+        let mut theme_paths_to_load = BTreeSet::default();
+        for (theme_name, theme) in &expected_themes {
+            if !registered_themes.contains(*theme_name) {
+                let path = self
+                    .extension_index
+                    .extension_dir(&self.installed_dir, &theme.extension)
+                    .join(&theme.path);
+                theme_paths_to_load.insert((theme.extension.clone(), path));
+                report.added.themes.insert((*theme_name).clone());
+            }
+        }
+        let theme_renames = self.extension_index.theme_renames(
+            &theme_paths_to_load
+                .iter()
+                .map(|(extension_id, _)| extension_id.clone())
+                .collect::<Vec<_>>(),
+        );
+
+        self.registered = ExtensionArtifacts {
+            languages: expected_languages.into_keys().cloned().collect(),
+            themes: expected_themes.into_keys().cloned().collect(),
+            grammars: expected_grammar_names,
+        };
+
+        let fs = self.fs.clone();
+        let theme_registry = self.theme_registry.clone();
+        let previous_theme_hashes = self.theme_content_hashes.clone();
+        let rejected_themes = self
+            .rejected_artifacts
+            .values()
+            .flat_map(|artifacts| artifacts.themes.iter().cloned())
+            .collect::<HashSet<_>>();
+        let fallback_appearance = ExtensionSettings::get_global(cx).fallback_theme_appearance();
+        cx.spawn(|this, mut cx| async move {
+            if !theme_paths_to_load.is_empty() {
+                let theme_paths_to_load = theme_paths_to_load.into_iter().collect::<Vec<_>>();
+                let loaded_themes = cx
+                    .background_executor()
+                    .spawn(async move {
+                        load_theme_files(
+                            fs,
+                            &theme_registry,
+                            &theme_paths_to_load,
+                            &theme_renames,
+                            &rejected_themes,
+                            &previous_theme_hashes,
+                            fallback_appearance,
+                        )
+                        .await
+                    })
+                    .await;
+                this.update(&mut cx, |this, cx| {
+                    this.theme_content_hashes.extend(loaded_themes.hashes);
+                    ThemeSettings::reload_current_theme(cx)
+                })
+                .ok();
+            }
+
+            report
+        })
+    }
+
+    /// Returns the IDs of the extensions in the index whose themes, languages,
+    /// and grammars are registered, which are those that are enabled,
+    /// compatible, and not conflicting with another extension.
+    fn registered_extension_ids(&self, cx: &AppContext) -> HashSet<Arc<str>> {
+        let app_version = AppVersion::global(cx);
+        let extension_settings = ExtensionSettings::get_global(cx);
+        self.extension_index
+            .extensions
+            .iter()
+            .filter(|(extension_id, _)| !self.conflicting_extensions.contains_key(*extension_id))
+            .filter(|(_, extension)| {
+                should_register_extension(extension, app_version, extension_settings)
+            })
+            .map(|(extension_id, _)| extension_id.clone())
+            .collect()
+    }
+
+    fn grammar_path(&self, extension: &ExtensionIndexEntry, grammar_name: &Arc<str>) -> PathBuf {
+        let stored_grammar_path = extension
+            .grammar_hashes
+            .get(grammar_name)
+            .and_then(|hash| grammar_store_path(&self.grammar_store_dir, hash));
+        if let Some(stored_grammar_path) = stored_grammar_path {
+            return stored_grammar_path;
+        }
+
+        let mut grammar_path = self.installed_dir.clone();
+        grammar_path.extend([extension.installed_dir_name(), "grammars"]);
+        grammar_path.push(grammar_name.as_ref());
+        grammar_path.set_extension("wasm");
+        grammar_path
+    }
+
+    fn record_call_error(&mut self, error: ExtensionLoadError, cx: &mut ModelContext<Self>) {
+        log::error!(
+            "extension {} encountered an error: {}",
+            error.extension_id,
+            error.message
+        );
+        // Calls that fail are often retried, so each error is only recorded once.
+        if !self.load_errors.contains(&error) {
+            self.load_errors.push(error);
+            cx.notify();
+        }
+    }
+
+    fn record_resolved_version(&mut self, resolved: ResolvedVersion, cx: &mut ModelContext<Self>) {
+        if self.lockfile_path.is_none() {
+            return;
+        }
+        self.lockfile.lock().record(resolved);
+        self.write_lockfile(cx).detach_and_log_err(cx);
+    }
+
+    /// Records the installed version of the given extension in the lockfile,
+    /// or removes the extension from it if it is no longer installed.
+    ///
+    /// The versions that the extension resolved are forgotten when its version
+    /// changes, so that the new version resolves its own.
+    fn update_lockfile(&mut self, extension_id: &Arc<str>, cx: &mut ModelContext<Self>) {
+        if self.lockfile_path.is_none() {
+            return;
+        }
+
+        let version = match self.extension_index.extensions.get(extension_id) {
+            Some(extension) if extension.dev => return,
+            Some(extension) => Some(extension.manifest.version.clone()),
+            None => None,
+        };
+        {
+            let mut lockfile = self.lockfile.lock();
+            if lockfile.extensions.get(extension_id) == version.as_ref() {
+                return;
+            }
+            lockfile.resolved_versions.remove(extension_id);
+            match version {
+                Some(version) => lockfile.extensions.insert(extension_id.clone(), version),
+                None => lockfile.extensions.remove(extension_id),
+            };
+        }
+        self.write_lockfile(cx).detach_and_log_err(cx);
+    }
+
+    fn write_lockfile(&self, cx: &mut ModelContext<Self>) -> Task<Result<()>> {
+        let Some(lockfile_path) = self.lockfile_path.clone() else {
+            return Task::ready(Ok(()));
+        };
+
+        let fs = self.fs.clone();
+        let lockfile = self.lockfile.clone();
+        let lockfile_write_lock = self.lockfile_write_lock.clone();
+        cx.background_executor().spawn(async move {
+            let _guard = lockfile_write_lock.lock().await;
+            // The lockfile is read once the lock is held, so that the last write
+            // always has the most recent versions.
+            let lockfile = lockfile.lock().clone();
+            lockfile.save(fs.as_ref(), &lockfile_path).await
+        })
+    }
+
+    /// Replaces the manifest errors found by the previous reload, along with
+    /// the load errors that were reported for them.
+    fn set_manifest_errors(
+        &mut self,
+        manifest_errors: Vec<ManifestError>,
+        cx: &mut ModelContext<Self>,
+    ) {
+        if manifest_errors == self.manifest_errors {
+            return;
+        }
+
+        let old_load_errors = self
+            .manifest_errors
+            .iter()
+            .map(manifest_load_error)
+            .collect::<Vec<_>>();
+        self.load_errors
+            .retain(|error| !old_load_errors.contains(error));
+        self.load_errors
+            .extend(manifest_errors.iter().map(manifest_load_error));
+        self.manifest_errors = manifest_errors;
+        cx.notify();
+    }
+
+    fn record_grammar_load_failure(
+        &mut self,
+        failure: GrammarLoadFailure,
+        cx: &mut ModelContext<Self>,
+    ) {
+        let Some(extension_id) = self.extension_for_grammar(&failure.grammar_name) else {
+            return;
+        };
+        let error = ExtensionLoadError {
+            extension_id: extension_id.clone(),
+            severity: LoadErrorSeverity::Error,
+            message: format!(
+                "failed to load grammar {}: {:?}",
+                failure.grammar_name, failure.error
+            ),
+        };
+        if !self.load_errors.contains(&error) {
+            self.load_errors.push(error);
+        }
+        cx.emit(Event::GrammarFailedToLoad {
+            extension_id,
+            grammar_name: failure.grammar_name,
+        });
+        cx.notify();
+    }
+
+    fn register_produced_grammar(&mut self, grammar: ProducedGrammar) {
+        log::info!(
+            "registering grammar {} produced by extension {}",
+            grammar.grammar_name,
+            grammar.extension_id
+        );
+        self.language_registry
+            .register_wasm_grammars([(grammar.grammar_name.clone(), grammar.path)]);
+        self.registered
+            .grammars
+            .insert(grammar.grammar_name.clone());
+        self.produced_grammars
+            .entry(grammar.extension_id)
+            .or_default()
+            .push(grammar.grammar_name);
+    }
+
+    /// Registers the given language, along with the queries that the extensions
+    /// in the given index add to it.
+    fn register_language(
+        &self,
+        language_name: &Arc<str>,
+        language: &ExtensionIndexLanguageEntry,
+        index: &ExtensionIndex,
+    ) -> Result<()> {
+        let language_path = index
+            .extension_dir(&self.installed_dir, &language.extension)
+            .join(&language.path);
+        let query_paths = self.language_query_paths(language_name, language, index);
+        let variant = language.variant;
+        let load = move || -> Result<(
+            LanguageConfig,
+            LanguageQueries,
+            Option<Arc<dyn ContextProvider>>,
+        )> {
+            let config = std::fs::read_to_string(language_path.join("config.toml"))?;
+            let config = parse_language_config(&config, variant)?;
+            let mut queries = LanguageQueries::default();
+            for query_path in &query_paths {
+                append_plugin_queries(&mut queries, query_path);
+            }
+            let tasks = std::fs::read_to_string(language_path.join("tasks.json"))
+                .ok()
+                .and_then(|contents| {
+                    let definitions = serde_json_lenient::from_str(&contents).log_err()?;
+                    Some(Arc::new(ContextProviderWithTasks::new(definitions)) as Arc<_>)
+                });
+
+            Ok((config, queries, tasks))
+        };
+
+        // Only the languages that this store registered itself are replaced.
+        if self.registered.languages.contains(language_name) {
+            self.language_registry.register_language(
+                language_name.clone(),
+                language.grammar.clone(),
+                language.matcher.clone(),
+                load,
+            );
+        } else {
+            self.language_registry
+                .try_register_language(
+                    language_name.clone(),
+                    language.grammar.clone(),
+                    language.matcher.clone(),
+                    load,
+                )
+                .with_context(|| format!("failed to register language {language_name:?}"))?;
+        }
+        self.language_registry.set_grammar_variants(
+            language_name.clone(),
+            language
+                .grammar_variants
+                .iter()
+                .map(|variant| (variant.content_regex.clone(), variant.grammar.clone()))
+                .collect(),
+        );
+        Ok(())
+    }
+
+    /// Returns the directories of the given language's queries, in the order in
+    /// which they are merged: the language's own directory, followed by those
+    /// of the queries that the extensions in the given index add to it.
+    fn language_query_paths(
+        &self,
+        language_name: &str,
+        language: &ExtensionIndexLanguageEntry,
+        index: &ExtensionIndex,
+    ) -> Vec<PathBuf> {
+        let language_path = index
+            .extension_dir(&self.installed_dir, &language.extension)
+            .join(&language.path);
+
+        let mut entries = index
+            .extensions
+            .iter()
+            .filter_map(|(extension_id, extension)| {
+                let entry = extension.manifest.language_queries.get(language_name)?;
+                Some((extension, entry))
+            })
+            .collect::<Vec<_>>();
+        // The extensions are ordered by ID, which the stable sort preserves
+        // between extensions with the same priority.
+        entries.sort_by_key(|(_, entry)| entry.priority);
+        iter::once(language_path)
+            .chain(entries.into_iter().map(|(extension, entry)| {
+                self.installed_dir
+                    .join(extension.installed_dir_name())
+                    .join(&entry.path)
+            }))
+            .collect()
+    }
+
+    /// Loads the extensions in the given index that are not yet in the extension
+    /// index, so that they are available before the rest of the index has been
+    /// rebuilt.
+    ///
+    /// They are loaded like any other change to the index, so that they are not
+    /// loaded again once the rest of the index is rebuilt. Returns the IDs of the
+    /// extensions that were added.
+    fn register_new_extensions(
+        &mut self,
+        extension_index: &ExtensionIndex,
+        cx: &mut ModelContext<Self>,
+    ) -> (Vec<Arc<str>>, Task<()>) {
+        let mut new_index = self.extension_index.clone();
+        let mut new_extension_ids = Vec::new();
+        for (extension_id, extension) in &extension_index.extensions {
+            if !self.extension_index.extensions.contains_key(extension_id) {
+                new_index.merge(extension_index.extension_subset(extension_id, extension));
+                new_extension_ids.push(extension_id.clone());
+            }
+        }
+        if new_extension_ids.is_empty() {
+            return (Vec::new(), Task::ready(()));
+        }
+
+        // The modified extensions are reloaded along with the rest of the
+        // index, once it has been rebuilt, and the theme changes are reported
+        // along with its own.
+        let modified_extensions = mem::take(&mut self.modified_extensions);
+        let task = self.extensions_updated(new_index, cx);
+        self.modified_extensions = modified_extensions;
+        let theme_changes = mem::take(&mut self.theme_changes);
+        self.pending_theme_changes = Some(match self.pending_theme_changes.take() {
+            Some(pending_theme_changes) => pending_theme_changes.followed_by(theme_changes),
+            None => theme_changes,
+        });
+
+        (new_extension_ids, task)
+    }
+
+    /// Builds an index of the extensions in the given installed extensions directory,
+    /// blocking the current thread until it is done, along with the extensions that
+    /// were skipped due to an invalid manifest.
+    ///
+    /// Unlike [`Self::reload`], this doesn't require a running app, so it can be used
+    /// by command-line tools that validate extensions. The extensions are not loaded.
+    pub fn reload_blocking(
+        fs: Arc<dyn Fs>,
+        installed_dir: &Path,
+    ) -> (ExtensionIndex, Vec<ManifestError>) {
+        futures::executor::block_on(async {
+            let mut index = ExtensionIndex::default();
+            let mut manifest_errors = Vec::new();
+            let extension_dirs =
+                installed_extension_dirs(fs.as_ref(), installed_dir, &HashMap::default()).await;
+            for extension_dir in extension_dirs {
+                let (extension_index, manifest_error) = Self::index_extension_dir(
+                    fs.clone(),
+                    extension_dir,
+                    theme::DEFAULT_FALLBACK_APPEARANCE,
+                )
+                .await;
+                manifest_errors.extend(manifest_error);
+                index.extensions.extend(extension_index.extensions);
+                index.languages.extend(extension_index.languages);
+                index.themes.extend(extension_index.themes);
+                index.theme_families.extend(extension_index.theme_families);
+            }
+            (index, manifest_errors)
+        })
+    }
+
+    /// Indexes the extension in the given directory, returning the error in its
+    /// manifest if it is invalid.
+    async fn index_extension_dir(
+        fs: Arc<dyn Fs>,
+        extension_dir: PathBuf,
+        fallback_appearance: AppearanceContent,
+    ) -> (ExtensionIndex, Option<ManifestError>) {
+        let mut extension_index = ExtensionIndex::default();
+        let result = Self::add_extension_to_index(
+            fs,
+            extension_dir,
+            &mut extension_index,
+            fallback_appearance,
+        )
+        .await;
+        // Invalid manifests are reported to the user, rather than only logged.
+        let manifest_error = result
+            .err()
+            .and_then(|error| error.downcast::<ManifestError>().log_err());
+        if let Some(manifest_error) = &manifest_error {
+            log::error!("{manifest_error}");
+        }
+        (extension_index, manifest_error)
+    }
+
+    /// Rebuilds the extension index from the installed extensions, returning it
+    /// along with the extensions that were skipped due to an invalid manifest.
+    ///
+    /// Unless a full reload was requested, only the extensions that have been
+    /// modified or added are read again, and the others keep their existing entries.
+    fn rebuild_extension_index(
+        &mut self,
+        cx: &mut ModelContext<Self>,
+    ) -> Task<(ExtensionIndex, Vec<ManifestError>)> {
+        let mut unchanged_extensions = HashMap::default();
+        if !mem::take(&mut self.full_reload_requested) {
+            // The unchanged extensions are keyed by the name of their
+            // directory, which may include their version.
+            for (extension_id, extension) in &self.extension_index.extensions {
+                if !self.modified_extensions.contains(extension_id) {
+                    unchanged_extensions.insert(
+                        Arc::<str>::from(extension.installed_dir_name()),
+                        self.extension_index
+                            .extension_subset(extension_id, extension),
+                    );
+                }
+            }
+        }
+
+        let fs = self.fs.clone();
+        let work_dir = self.wasm_host.work_dir.clone();
+        let extensions_dir = self.installed_dir.clone();
+        let pinned_versions = ExtensionSettings::get_global(cx)
+            .pinned_extension_versions
+            .clone();
+        let fallback_appearance = ExtensionSettings::get_global(cx).fallback_theme_appearance();
+        cx.emit(Event::ReloadProgress(ReloadPhase::Scanning));
+        cx.spawn(|this, mut cx| async move {
+            let start_time = Instant::now();
+
+            let extension_dirs = cx
+                .background_executor()
+                .spawn({
+                    let fs = fs.clone();
+                    async move {
+                        fs.create_dir(&work_dir).await.log_err();
+                        fs.create_dir(&extensions_dir).await.log_err();
+                        installed_extension_dirs(fs.as_ref(), &extensions_dir, &pinned_versions)
+                            .await
+                    }
+                })
+                .await;
+
+            this.update(&mut cx, |_, cx| {
+                cx.emit(Event::ReloadProgress(ReloadPhase::ParsingManifests {
+                    extension_count: extension_dirs.len(),
+                }))
+            })
+            .ok();
+
+            let mut index = ExtensionIndex::default();
+            let mut manifest_errors = Vec::new();
+            for extension_dir in extension_dirs {
+                let unchanged_extension = extension_dir
+                    .file_name()
+                    .and_then(|name| name.to_str())
+                    .and_then(|name| unchanged_extensions.remove(name));
+                if let Some(extension_index) = unchanged_extension {
+                    index.merge(extension_index);
+                    continue;
+                }
+
+                let (extension_index, manifest_error) = cx
+                    .background_executor()
+                    .spawn(Self::index_extension_dir(
+                        fs.clone(),
+                        extension_dir,
+                        fallback_appearance,
+                    ))
+                    .await;
+                manifest_errors.extend(manifest_error);
+
+                if let Ok((new_extension_ids, load_new_extensions)) = this
+                    .update(&mut cx, |this, cx| {
+                        this.register_new_extensions(&extension_index, cx)
+                    })
+                {
+                    load_new_extensions.await;
                     this.update(&mut cx, |_, cx| {
-                        cx.emit(Event::ExtensionFailedToLoad(extension.manifest.id.clone()))
+                        for extension_id in new_extension_ids {
+                            cx.emit(Event::ExtensionRegistered(extension_id));
+                        }
                     })
                     .ok();
                 }
+
+                index.merge(extension_index);
             }
 
-            this.update(&mut cx, |this, cx| {
-                this.reload_complete_senders.clear();
+            let (index, index_json) = cx
+                .background_executor()
+                .spawn(async move {
+                    let index_json = serde_json::to_string_pretty(&index).log_err();
+                    log::info!("rebuilt extension index in {:?}", start_time.elapsed());
+                    (index, index_json)
+                })
+                .await;
 
-                for (manifest, wasm_extension) in &wasm_extensions {
-                    for (language_server_id, language_server_config) in &manifest.language_servers {
-                        for language in language_server_config.languages() {
-                            this.language_registry.register_lsp_adapter(
-                                language.clone(),
-                                Arc::new(ExtensionLspAdapter {
-                                    extension: wasm_extension.clone(),
-                                    host: this.wasm_host.clone(),
-                                    language_server_id: language_server_id.clone(),
-                                    config: wit::LanguageServerConfig {
-                                        name: language_server_id.0.to_string(),
-                                        language_name: language.to_string(),
-                                    },
-                                }),
-                            );
-                        }
-                    }
+            if let Some(index_json) = index_json {
+                this.update(&mut cx, |this, cx| {
+                    this.schedule_index_write(index_json, cx)
+                })
+                .ok();
+            }
+            (index, manifest_errors)
+        })
+    }
 
-                    for (slash_command_name, slash_command) in &manifest.slash_commands {
-                        this.slash_command_registry.register_command(
-                            ExtensionSlashCommand {
-                                command: crate::wit::SlashCommand {
-                                    name: slash_command_name.to_string(),
-                                    description: slash_command.description.to_string(),
-                                    tooltip_text: slash_command.tooltip_text.to_string(),
-                                    requires_argument: slash_command.requires_argument,
-                                },
-                                extension: wasm_extension.clone(),
-                                host: this.wasm_host.clone(),
-                            },
-                            false,
-                        );
-                    }
+    /// Queues the given index to be written to disk once the index write delay
+    /// has elapsed, replacing any index that is still waiting to be written.
+    fn schedule_index_write(&mut self, index_json: String, cx: &mut ModelContext<Self>) {
+        self.pending_index_json = Some(index_json);
 
-                    for (provider_id, _provider) in &manifest.indexed_docs_providers {
-                        this.indexed_docs_registry.register_provider(Box::new(
-                            ExtensionIndexedDocsProvider {
-                                extension: wasm_extension.clone(),
-                                host: this.wasm_host.clone(),
-                                id: ProviderId(provider_id.clone()),
-                            },
-                        ));
-                    }
-                }
+        let delay = self.index_write_delay;
+        if delay.is_zero() {
+            self.index_write_task = None;
+            self.write_pending_index(cx).detach_and_log_err(cx);
+            return;
+        }
 
-                this.wasm_extensions.extend(wasm_extensions);
-                ThemeSettings::reload_current_theme(cx)
+        self.index_write_task = Some(cx.spawn(|this, mut cx| async move {
+            cx.background_executor().timer(delay).await;
+            this.update(&mut cx, |this, cx| {
+                this.write_pending_index(cx).detach_and_log_err(cx)
             })
             .ok();
+        }));
+    }
+
+    /// Writes the extension index to disk immediately if a write is pending,
+    /// and waits for any write that is already in progress to complete.
+    ///
+    /// This should be called before shutting down, so that the most recent
+    /// index is not lost to the index write delay.
+    pub fn flush(&mut self, cx: &mut ModelContext<Self>) -> Task<Result<()>> {
+        self.index_write_task = None;
+        if self.pending_index_json.is_some() {
+            return self.write_pending_index(cx);
+        }
+
+        let index_write_lock = self.index_write_lock.clone();
+        cx.background_executor().spawn(async move {
+            drop(index_write_lock.lock().await);
+            Ok(())
         })
     }
 
-    fn rebuild_extension_index(&self, cx: &mut ModelContext<Self>) -> Task<ExtensionIndex> {
+    fn write_pending_index(&mut self, cx: &mut ModelContext<Self>) -> Task<Result<()>> {
+        let Some(index_json) = self.pending_index_json.take() else {
+            return Task::ready(Ok(()));
+        };
+
         let fs = self.fs.clone();
-        let work_dir = self.wasm_host.work_dir.clone();
-        let extensions_dir = self.installed_dir.clone();
         let index_path = self.index_path.clone();
+        let index_write_lock = self.index_write_lock.clone();
         cx.background_executor().spawn(async move {
-            let start_time = Instant::now();
-            let mut index = ExtensionIndex::default();
-
-            fs.create_dir(&work_dir).await.log_err();
-            fs.create_dir(&extensions_dir).await.log_err();
-
-            let extension_paths = fs.read_dir(&extensions_dir).await;
-            if let Ok(mut extension_paths) = extension_paths {
-                while let Some(extension_dir) = extension_paths.next().await {
-                    let Ok(extension_dir) = extension_dir else {
-                        continue;
-                    };
-
-                    if extension_dir
-                        .file_name()
-                        .map_or(false, |file_name| file_name == ".DS_Store")
-                    {
-                        continue;
-                    }
-
-                    Self::add_extension_to_index(fs.clone(), extension_dir, &mut index)
-                        .await
-                        .log_err();
-                }
-            }
-
-            if let Ok(index_json) = serde_json::to_string_pretty(&index) {
-                fs.save(&index_path, &index_json.as_str().into(), Default::default())
-                    .await
-                    .context("failed to save extension index")
-                    .log_err();
-            }
-
-            log::info!("rebuilt extension index in {:?}", start_time.elapsed());
-            index
+            let _guard = index_write_lock.lock().await;
+            fs.save(&index_path, &index_json.as_str().into(), Default::default())
+                .await
+                .context("failed to save extension index")
         })
     }
 
@@ -1292,6 +5328,7 @@ impl ExtensionStore {
         fs: Arc<dyn Fs>,
         extension_dir: PathBuf,
         index: &mut ExtensionIndex,
+        fallback_appearance: AppearanceContent,
     ) -> Result<()> {
         let mut extension_manifest = ExtensionManifest::load(fs.clone(), &extension_dir).await?;
         let extension_id = extension_manifest.id.clone();
@@ -1304,6 +5341,26 @@ impl ExtensionStore {
             .ok_or_else(|| anyhow!("directory does not exist"))?
             .is_symlink;
 
+        let provenance = fs
+            .load(&extension_dir.join(PROVENANCE_FILE_NAME))
+            .await
+            .ok()
+            .and_then(|provenance| serde_json::from_str(&provenance).log_err());
+
+        let mut grammar_hashes = BTreeMap::default();
+        for grammar_name in extension_manifest.grammars.keys() {
+            let mut hash_path = extension_dir.join("grammars").join(grammar_name.as_ref());
+            hash_path.set_extension(GRAMMAR_HASH_FILE_EXTENSION);
+            if let Ok(hash) = fs.load(&hash_path).await {
+                let hash = hash.trim();
+                if is_valid_grammar_hash(hash) {
+                    grammar_hashes.insert(grammar_name.clone(), hash.into());
+                } else {
+                    log::error!("invalid grammar hash {hash:?} in {hash_path:?}");
+                }
+            }
+        }
+
         if let Ok(mut language_paths) = fs.read_dir(&extension_dir.join("languages")).await {
             while let Some(language_path) = language_paths.next().await {
                 let language_path = language_path?;
@@ -1317,22 +5374,38 @@ impl ExtensionStore {
                     continue;
                 }
                 let config = fs.load(&language_path.join("config.toml")).await?;
-                let config = ::toml::from_str::<LanguageConfig>(&config)?;
+                let tables = language_config_tables(&config)
+                    .with_context(|| format!("invalid config.toml in {:?}", language_path))?;
 
                 let relative_path = relative_path.to_path_buf();
                 if !extension_manifest.languages.contains(&relative_path) {
                     extension_manifest.languages.push(relative_path.clone());
                 }
 
-                index.languages.insert(
-                    config.name.clone(),
-                    ExtensionIndexLanguageEntry {
-                        extension: extension_id.clone(),
-                        path: relative_path,
-                        matcher: config.matcher,
-                        grammar: config.grammar,
-                    },
-                );
+                let has_variants = tables.len() > 1;
+                for (ix, table) in tables.into_iter().enumerate() {
+                    let table = ::toml::Value::Table(table);
+                    let grammar_variants = table
+                        .clone()
+                        .try_into::<ExtensionLanguageConfig>()
+                        .with_context(|| {
+                            format!("invalid grammar_variants in {:?}", language_path)
+                        })?
+                        .grammar_variants;
+                    let config = table.try_into::<LanguageConfig>()?;
+
+                    index.languages.insert(
+                        config.name.clone(),
+                        ExtensionIndexLanguageEntry {
+                            extension: extension_id.clone(),
+                            path: relative_path.clone(),
+                            matcher: config.matcher,
+                            grammar: config.grammar,
+                            grammar_variants,
+                            variant: has_variants.then_some(ix),
+                        },
+                    );
+                }
             }
         }
 
@@ -1343,10 +5416,13 @@ impl ExtensionStore {
                     continue;
                 };
 
-                let Some(theme_family) = ThemeRegistry::read_user_theme(&theme_path, fs.clone())
-                    .await
-                    .log_err()
-                else {
+                let Some(theme_family) = ThemeRegistry::read_user_theme_with_fallback_appearance(
+                    &theme_path,
+                    fs.clone(),
+                    fallback_appearance,
+                )
+                .await
+                .log_err() else {
                     continue;
                 };
 
@@ -1355,15 +5431,29 @@ impl ExtensionStore {
                     extension_manifest.themes.push(relative_path.clone());
                 }
 
+                let mut family_themes = Vec::new();
                 for theme in theme_family.themes {
+                    let theme_name: Arc<str> = theme.name.into();
+                    family_themes.push(theme_name.clone());
                     index.themes.insert(
-                        theme.name.into(),
+                        theme_name,
                         ExtensionIndexThemeEntry {
                             extension: extension_id.clone(),
                             path: relative_path.clone(),
+                            appearance: Some(theme.appearance),
+                            original_name: None,
                         },
                     );
                 }
+                index.theme_families.insert(
+                    theme_family.name.into(),
+                    ExtensionIndexThemeFamilyEntry {
+                        extension: extension_id.clone(),
+                        path: relative_path,
+                        author: theme_family.author.into(),
+                        themes: family_themes,
+                    },
+                );
             }
         }
 
@@ -1380,6 +5470,15 @@ impl ExtensionStore {
             ExtensionIndexEntry {
                 dev: is_dev,
                 manifest: Arc::new(extension_manifest),
+                provenance,
+                grammar_hashes,
+                enabled: !fs.is_file(&extension_dir.join(DISABLED_FILE_NAME)).await,
+                dir_name: extension_dir
+                    .file_name()
+                    .and_then(|dir_name| dir_name.to_str())
+                    .filter(|dir_name| *dir_name != extension_id.as_ref())
+                    .map(Arc::from),
+                installed_at: read_installed_at(fs.as_ref(), &extension_dir).await,
             },
         );
 
@@ -1387,8 +5486,505 @@ impl ExtensionStore {
     }
 }
 
-fn load_plugin_queries(root_path: &Path) -> LanguageQueries {
-    let mut result = LanguageQueries::default();
+/// Replaces the directory at `destination`, if any, with the one at `source`.
+///
+/// The replaced directory is moved to `replaced_dir` first, and moved back if
+/// `source` can't be moved into its place, so that `destination` is never left
+/// partially written.
+async fn replace_dir(
+    fs: &dyn Fs,
+    source: &Path,
+    destination: &Path,
+    replaced_dir: &Path,
+) -> Result<()> {
+    let has_destination = fs.is_dir(destination).await;
+    if has_destination {
+        if let Some(parent) = replaced_dir.parent() {
+            fs.create_dir(parent).await?;
+        }
+        fs.rename(destination, replaced_dir, RenameOptions::default())
+            .await?;
+    }
+    if let Err(error) = fs
+        .rename(source, destination, RenameOptions::default())
+        .await
+    {
+        if has_destination {
+            fs.rename(replaced_dir, destination, RenameOptions::default())
+                .await
+                .log_err();
+        }
+        return Err(error);
+    }
+    if has_destination {
+        fs.remove_dir(
+            replaced_dir,
+            RemoveOptions {
+                recursive: true,
+                ignore_if_not_exists: true,
+            },
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+/// Cleans up after the installs that were interrupted, e.g. because Zed crashed
+/// while it was extracting or replacing an extension.
+///
+/// A partially extracted extension is removed. An installed version that was
+/// moved aside to be replaced is restored if its replacement was not moved into
+/// place, and is removed otherwise.
+async fn clean_up_interrupted_installs(
+    fs: &dyn Fs,
+    installed_dir: &Path,
+    work_dir: &Path,
+) -> Result<()> {
+    let remove_options = RemoveOptions {
+        recursive: true,
+        ignore_if_not_exists: true,
+    };
+
+    let staging_dir = work_dir.join(STAGING_DIR_NAME);
+    if fs.is_dir(&staging_dir).await {
+        log::info!("removing the extensions of interrupted installs in {staging_dir:?}");
+        fs.remove_dir(&staging_dir, remove_options).await?;
+    }
+
+    let replaced_dir = work_dir.join(REPLACED_DIR_NAME);
+    let Ok(mut replaced_paths) = fs.read_dir(&replaced_dir).await else {
+        return Ok(());
+    };
+    while let Some(replaced_path) = replaced_paths.next().await {
+        let replaced_path = replaced_path?;
+        let Some(dir_name) = replaced_path.file_name() else {
+            continue;
+        };
+        let extension_dir = installed_dir.join(dir_name);
+        if fs.is_dir(&extension_dir).await {
+            fs.remove_dir(&replaced_path, remove_options).await?;
+        } else {
+            log::info!("restoring {extension_dir:?}, whose replacement was interrupted");
+            fs.create_dir(installed_dir).await?;
+            fs.rename(&replaced_path, &extension_dir, RenameOptions::default())
+                .await?;
+        }
+    }
+    fs.remove_dir(&replaced_dir, remove_options).await?;
+    Ok(())
+}
+
+/// Returns whether the given string is the hex-encoded SHA-256 hash of a
+/// grammar, which is what the grammar is named after in the grammar store.
+fn is_valid_grammar_hash(hash: &str) -> bool {
+    hash.len() == 64 && hash.bytes().all(|byte| byte.is_ascii_hexdigit())
+}
+
+/// Returns the path of the grammar with the given hash in the grammar store,
+/// unless the hash is invalid.
+fn grammar_store_path(grammar_store_dir: &Path, hash: &str) -> Option<PathBuf> {
+    is_valid_grammar_hash(hash).then(|| grammar_store_dir.join(format!("{hash}.wasm")))
+}
+
+async fn remove_files(fs: &dyn Fs, paths: Vec<PathBuf>) -> Result<()> {
+    for path in paths {
+        fs.remove_file(
+            &path,
+            RemoveOptions {
+                recursive: false,
+                ignore_if_not_exists: true,
+            },
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+/// Moves the grammars of the given installed extension into the grammar store,
+/// keeping a single copy of each distinct grammar, and replaces each of them
+/// with a file containing its hash.
+async fn move_grammars_to_store(
+    fs: &dyn Fs,
+    extension_dir: &Path,
+    grammar_store_dir: &Path,
+) -> Result<()> {
+    let Ok(mut grammar_paths) = fs.read_dir(&extension_dir.join("grammars")).await else {
+        return Ok(());
+    };
+    while let Some(grammar_path) = grammar_paths.next().await {
+        let grammar_path = grammar_path?;
+        if grammar_path.extension() != Some("wasm".as_ref()) {
+            continue;
+        }
+
+        let hash = format!("{:x}", Sha256::digest(fs.load_bytes(&grammar_path).await?));
+        let stored_grammar_path = grammar_store_dir.join(format!("{hash}.wasm"));
+        if fs.is_file(&stored_grammar_path).await {
+            fs.remove_file(&grammar_path, RemoveOptions::default())
+                .await?;
+        } else {
+            fs.create_dir(grammar_store_dir).await?;
+            fs.rename(
+                &grammar_path,
+                &stored_grammar_path,
+                RenameOptions::default(),
+            )
+            .await?;
+        }
+        fs.atomic_write(
+            grammar_path.with_extension(GRAMMAR_HASH_FILE_EXTENSION),
+            hash,
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+/// Checks that a downloaded extension archive has the size declared by the
+/// extension registry, to catch truncated downloads before they are extracted.
+fn verify_download_before_extract(archive_bytes: &[u8], declared_size: Option<u64>) -> Result<()> {
+    if let Some(declared_size) = declared_size {
+        let actual_size = archive_bytes.len() as u64;
+        if actual_size != declared_size {
+            bail!("downloaded extension size {actual_size} does not match declared size {declared_size}");
+        }
+    }
+    Ok(())
+}
+
+/// Reads the given theme files and registers their themes, under their new names
+/// if they are renamed, except for the rejected themes.
+///
+/// Themes whose contents hash to the same value as before, and that are still
+/// registered, are not inserted again.
+async fn load_theme_files(
+    fs: Arc<dyn Fs>,
+    theme_registry: &ThemeRegistry,
+    theme_paths: &[(Arc<str>, PathBuf)],
+    theme_renames: &HashMap<Arc<str>, HashMap<Arc<str>, Arc<str>>>,
+    rejected_themes: &HashSet<Arc<str>>,
+    previous_theme_hashes: &HashMap<Arc<str>, u64>,
+    fallback_appearance: AppearanceContent,
+) -> LoadedThemes {
+    let mut loaded = LoadedThemes::default();
+    for (extension_id, theme_path) in theme_paths {
+        let Some(mut theme_family) = ThemeRegistry::read_user_theme_with_fallback_appearance(
+            theme_path,
+            fs.clone(),
+            fallback_appearance,
+        )
+        .await
+        .log_err() else {
+            continue;
+        };
+        if let Some(renames) = theme_renames.get(extension_id) {
+            rename_themes(&mut theme_family, renames);
+        }
+        theme_family
+            .themes
+            .retain(|theme| !rejected_themes.contains(theme.name.as_str()));
+        if theme_family.themes.is_empty() {
+            continue;
+        }
+        loaded
+            .warnings
+            .extend(theme_family.themes.iter().flat_map(|theme| {
+                missing_syntax_tokens_warning(extension_id, theme)
+                    .into_iter()
+                    .chain(invalid_theme_colors_warning(extension_id, theme))
+            }));
+
+        theme_registry.register_family(
+            theme_family.name.clone().into(),
+            theme_family
+                .themes
+                .iter()
+                .map(|theme| theme.name.clone().into()),
+        );
+        let mut changed_themes = Vec::new();
+        for theme in theme_family.themes {
+            let Some(hash) = theme_content_hash(&theme) else {
+                changed_themes.push(theme);
+                continue;
+            };
+            let previous_hash = previous_theme_hashes.get(theme.name.as_str());
+            let is_unchanged =
+                previous_hash == Some(&hash) && theme_registry.get(&theme.name).is_ok();
+            let theme_name = Arc::<str>::from(theme.name.as_str());
+            if previous_hash.is_some_and(|previous_hash| *previous_hash != hash) {
+                loaded.changed.push(theme_name.clone());
+            }
+            loaded.hashes.push((theme_name, hash));
+            if !is_unchanged {
+                changed_themes.push(theme);
+            }
+        }
+        theme_registry.insert_user_themes(changed_themes);
+    }
+    loaded
+}
+
+/// Renames the themes of a theme family that are registered under other names,
+/// given their new names keyed by their names in the family's file.
+fn rename_themes(theme_family: &mut ThemeFamilyContent, renames: &HashMap<Arc<str>, Arc<str>>) {
+    for theme in &mut theme_family.themes {
+        if let Some(renamed_name) = renames.get(theme.name.as_str()) {
+            theme.name = renamed_name.to_string();
+        }
+    }
+}
+
+/// Returns a hash of the given theme's contents, used to detect whether a theme
+/// has changed since it was registered.
+fn theme_content_hash(theme: &ThemeContent) -> Option<u64> {
+    let contents = serde_json::to_string(theme).log_err()?;
+    let mut hasher = DefaultHasher::new();
+    contents.hash(&mut hasher);
+    Some(hasher.finish())
+}
+
+/// Returns a warning listing the standard syntax tokens that the theme does
+/// not provide a style for, if any.
+fn missing_syntax_tokens_warning(
+    extension_id: &Arc<str>,
+    theme: &ThemeContent,
+) -> Option<ExtensionLoadError> {
+    let missing_tokens = STANDARD_SYNTAX_TOKENS
+        .iter()
+        .copied()
+        .filter(|token| !theme.style.syntax.contains_key(*token))
+        .collect::<Vec<_>>();
+    if missing_tokens.is_empty() {
+        return None;
+    }
+
+    Some(ExtensionLoadError {
+        extension_id: extension_id.clone(),
+        severity: LoadErrorSeverity::Warning,
+        message: format!(
+            "theme {:?} does not style the syntax tokens: {}",
+            theme.name,
+            missing_tokens.join(", ")
+        ),
+    })
+}
+
+fn invalid_theme_colors_warning(
+    extension_id: &Arc<str>,
+    theme: &ThemeContent,
+) -> Option<ExtensionLoadError> {
+    let invalid_colors = theme.style.invalid_accent_and_role_colors();
+    if invalid_colors.is_empty() {
+        return None;
+    }
+
+    Some(ExtensionLoadError {
+        extension_id: extension_id.clone(),
+        severity: LoadErrorSeverity::Warning,
+        message: format!(
+            "theme {:?} has invalid colors, which will be ignored: {}",
+            theme.name,
+            invalid_colors.join(", ")
+        ),
+    })
+}
+
+/// Returns the directories of the extensions in the given installed extensions
+/// directory.
+///
+/// Of the directories named `<id>-<version>` that hold versions of the same
+/// extension, only the one with the pinned version, or else the highest version,
+/// is returned. A directory named after the extension's ID takes precedence
+/// over all of them.
+async fn installed_extension_dirs(
+    fs: &dyn Fs,
+    installed_dir: &Path,
+    pinned_versions: &HashMap<Arc<str>, String>,
+) -> Vec<PathBuf> {
+    let mut extension_dirs = Vec::new();
+    let mut versioned_dirs = BTreeMap::<String, Vec<(SemanticVersion, PathBuf)>>::default();
+    if let Ok(mut extension_paths) = fs.read_dir(installed_dir).await {
+        while let Some(extension_dir) = extension_paths.next().await {
+            let Ok(extension_dir) = extension_dir else {
+                continue;
+            };
+
+            let Some(dir_name) = extension_dir.file_name().and_then(|name| name.to_str()) else {
+                continue;
+            };
+            if dir_name == ".DS_Store" {
+                continue;
+            }
+
+            if let Some((extension_id, version)) = parse_versioned_dir_name(dir_name) {
+                versioned_dirs
+                    .entry(extension_id.to_string())
+                    .or_default()
+                    .push((version, extension_dir));
+            } else {
+                extension_dirs.push(extension_dir);
+            }
+        }
+    }
+
+    for (extension_id, mut versions) in versioned_dirs {
+        if extension_dirs.iter().any(|dir| {
+            dir.file_name().and_then(|name| name.to_str()) == Some(extension_id.as_str())
+        }) {
+            continue;
+        }
+
+        versions.sort_by_key(|(version, _)| *version);
+        let pinned_version = pinned_versions
+            .get(extension_id.as_str())
+            .and_then(|version| version.parse::<SemanticVersion>().log_err());
+        let position = pinned_version
+            .and_then(|pinned_version| {
+                let position = versions
+                    .iter()
+                    .position(|(version, _)| *version == pinned_version);
+                if position.is_none() {
+                    log::warn!(
+                        "pinned version {pinned_version} of extension {extension_id} is not installed"
+                    );
+                }
+                position
+            })
+            .unwrap_or(versions.len() - 1);
+        extension_dirs.push(versions.swap_remove(position).1);
+    }
+    extension_dirs
+}
+
+/// Returns the directories in the given installed extensions directory that hold
+/// a version of the given extension: the one named after its ID, and those named
+/// `<id>-<version>`.
+async fn extension_version_dirs(
+    fs: &dyn Fs,
+    installed_dir: &Path,
+    extension_id: &str,
+) -> Vec<PathBuf> {
+    let mut version_dirs = Vec::new();
+    if let Ok(mut extension_paths) = fs.read_dir(installed_dir).await {
+        while let Some(extension_dir) = extension_paths.next().await {
+            let Ok(extension_dir) = extension_dir else {
+                continue;
+            };
+            let Some(dir_name) = extension_dir.file_name().and_then(|name| name.to_str()) else {
+                continue;
+            };
+            let dir_extension_id = parse_versioned_dir_name(dir_name)
+                .map_or(dir_name, |(extension_id, _)| extension_id);
+            if dir_extension_id == extension_id {
+                version_dirs.push(extension_dir);
+            }
+        }
+    }
+    version_dirs
+}
+
+/// Removes the other installed versions of the extension that was just
+/// installed into the given directory, which would otherwise be left behind
+/// once they are no longer loaded.
+async fn remove_other_version_dirs(
+    fs: &dyn Fs,
+    installed_dir: &Path,
+    extension_dir: &Path,
+) -> Result<()> {
+    let Some(extension_id) = extension_dir.file_name().and_then(|name| name.to_str()) else {
+        return Ok(());
+    };
+    for version_dir in extension_version_dirs(fs, installed_dir, extension_id).await {
+        if version_dir != extension_dir {
+            fs.remove_dir(
+                &version_dir,
+                RemoveOptions {
+                    recursive: true,
+                    ignore_if_not_exists: true,
+                },
+            )
+            .await?;
+        }
+    }
+    Ok(())
+}
+
+/// Reports an invalid manifest as a load error of the extension in its directory.
+fn manifest_load_error(manifest_error: &ManifestError) -> ExtensionLoadError {
+    let dir_name = manifest_error
+        .extension_dir
+        .file_name()
+        .map(|name| name.to_string_lossy())
+        .unwrap_or_default();
+    let extension_id = parse_versioned_dir_name(&dir_name)
+        .map_or(dir_name.as_ref(), |(extension_id, _)| extension_id)
+        .into();
+    ExtensionLoadError {
+        extension_id,
+        severity: LoadErrorSeverity::Error,
+        message: manifest_error.to_string(),
+    }
+}
+
+/// Returns whether the path refers to an extension bundle rather than an
+/// extension directory.
+fn is_extension_bundle(path: &Path) -> bool {
+    path.extension()
+        .map_or(false, |extension| extension == EXTENSION_BUNDLE_EXTENSION)
+}
+
+/// Returns the bearer token configured for the registry that serves the given URL.
+fn registry_auth_token(url: &Url, cx: &AppContext) -> Option<String> {
+    ExtensionSettings::get_global(cx).registry_token(url.host_str()?)
+}
+
+/// Reads the time at which the extension in the given directory was installed.
+async fn read_installed_at(fs: &dyn Fs, extension_dir: &Path) -> Option<SystemTime> {
+    let installed_at = fs
+        .load(&extension_dir.join(INSTALLED_AT_FILE_NAME))
+        .await
+        .ok()?;
+    let seconds = installed_at.trim().parse().log_err()?;
+    Some(UNIX_EPOCH + Duration::from_secs(seconds))
+}
+
+/// Sends a GET request to an extension registry, authenticating with the
+/// given bearer token, if any.
+async fn get_from_registry(
+    http_client: &dyn HttpClient,
+    url: &Url,
+    auth_token: Option<String>,
+) -> Result<Response<AsyncBody>> {
+    let mut request = Request::builder()
+        .redirect_policy(RedirectPolicy::Follow)
+        .method(Method::GET)
+        .uri(url.as_str());
+    if let Some(auth_token) = auth_token {
+        request = request.header("Authorization", format!("Bearer {auth_token}"));
+    }
+    http_client
+        .send(request.body(AsyncBody::empty())?)
+        .await
+        .context(RegistryUnreachable)
+}
+
+/// The context of the errors of requests to the extension registry that could
+/// not be sent, e.g. because the network is unavailable.
+#[derive(Debug)]
+struct RegistryUnreachable;
+
+impl std::fmt::Display for RegistryUnreachable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "the extension registry could not be reached")
+    }
+}
+
+fn is_registry_unreachable(error: &anyhow::Error) -> bool {
+    error.downcast_ref::<RegistryUnreachable>().is_some()
+}
+
+/// Appends the queries in the given directory to the given queries.
+fn append_plugin_queries(result: &mut LanguageQueries, root_path: &Path) {
     if let Some(entries) = std::fs::read_dir(root_path).log_err() {
         for entry in entries {
             let Some(entry) = entry.log_err() else {
@@ -1402,8 +5998,8 @@ fn load_plugin_queries(root_path: &Path) -> LanguageQueries {
                 for (name, query) in QUERY_FILENAME_PREFIXES {
                     if remainder.starts_with(name) {
                         if let Some(contents) = std::fs::read_to_string(&path).log_err() {
-                            match query(&mut result) {
-                                None => *query(&mut result) = Some(contents.into()),
+                            match query(result) {
+                                None => *query(result) = Some(contents.into()),
                                 Some(r) => r.to_mut().push_str(contents.as_ref()),
                             }
                         }
@@ -1413,5 +6009,4 @@ fn load_plugin_queries(root_path: &Path) -> LanguageQueries {
             }
         }
     }
-    result
 }
@@ -1,31 +1,53 @@
+use crate::append_plugin_queries;
+use crate::archive::{extract_archive, ArchiveFormat};
+use crate::extension_builder::{CompileExtensionOptions, ExtensionBuilder};
+use crate::extension_indexed_docs_provider::INDEX_DOCS_TIMEOUT;
 use crate::extension_manifest::SchemaVersion;
-use crate::extension_settings::ExtensionSettings;
+use crate::extension_settings::{ExtensionSettings, RegistryAuth};
+use crate::wasm_host::{
+    verify_download_checksum, wit::ExtensionImports as _, with_timeout, CallLimiter, WasmExtension,
+    WasmState, DEFAULT_CALL_TIMEOUT,
+};
 use crate::{
-    Event, ExtensionIndex, ExtensionIndexEntry, ExtensionIndexLanguageEntry,
-    ExtensionIndexThemeEntry, ExtensionManifest, ExtensionStore, GrammarManifestEntry,
-    RELOAD_DEBOUNCE_DURATION,
+    DeferredOperation, Event, ExtensionArtifacts, ExtensionIndex, ExtensionIndexEntry,
+    ExtensionIndexLanguageEntry, ExtensionIndexThemeEntry, ExtensionIndexThemeFamilyEntry,
+    ExtensionInfo, ExtensionLoadError, ExtensionLoadStatus, ExtensionLockfile, ExtensionManifest,
+    ExtensionOperation, ExtensionProvenance, ExtensionStore, GrammarManifestEntry,
+    LoadErrorSeverity, ReconcileReport, ReloadPhase, RetryPolicy, ThemeChanges, UninstallOptions,
+    UninstallReport, CURRENT_INDEX_SCHEMA_VERSION, RELOAD_DEBOUNCE_DURATION,
 };
+use anyhow::anyhow;
 use assistant_slash_command::SlashCommandRegistry;
-use async_compression::futures::bufread::GzipEncoder;
-use collections::BTreeMap;
-use fs::{FakeFs, Fs, RealFs};
-use futures::{io::BufReader, AsyncReadExt, StreamExt};
-use gpui::{Context, SemanticVersion, TestAppContext};
-use http_client::{FakeHttpClient, Response};
+use async_compression::futures::bufread::{GzipEncoder, XzEncoder};
+use async_zip::{base::write::ZipFileWriter, Compression, ZipEntryBuilder};
+use client::ExtensionMetadata;
+use collections::{BTreeMap, BTreeSet};
+use fs::{FakeFs, Fs, RealFs, RemoveOptions};
+use futures::{io::BufReader, AsyncReadExt, Future, FutureExt as _, StreamExt};
+use gpui::{Context, Model, SemanticVersion, SharedString, TestAppContext};
+use http_client::{FakeHttpClient, HttpClient as _, HttpClientWithUrl, Response};
 use indexed_docs::IndexedDocsRegistry;
-use language::{LanguageMatcher, LanguageRegistry, LanguageServerBinaryStatus, LanguageServerName};
+use language::{
+    LanguageConfig, LanguageMatcher, LanguageQueries, LanguageRegistry, LanguageServerBinaryStatus,
+    LanguageServerName, Rope, TestFile,
+};
 use node_runtime::FakeNodeRuntime;
 use parking_lot::Mutex;
 use project::{Project, DEFAULT_COMPLETION_CONTEXT};
 use serde_json::json;
 use settings::{Settings as _, SettingsStore};
+use sha2::Digest as _;
 use snippet_provider::SnippetRegistry;
 use std::{
     ffi::OsString,
     path::{Path, PathBuf},
-    sync::Arc,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering::SeqCst},
+        Arc,
+    },
+    time::{Duration, SystemTime},
 };
-use theme::ThemeRegistry;
+use theme::{Appearance, AppearanceContent, AutoTheme, ThemeRegistry};
 use util::test::temp_tree;
 
 #[cfg(test)]
@@ -137,6 +159,7 @@ async fn test_extension_store(cx: &mut TestAppContext) {
     .await;
 
     let mut expected_index = ExtensionIndex {
+        schema_version: CURRENT_INDEX_SCHEMA_VERSION,
         extensions: [
             (
                 "zed-ruby".into(),
@@ -146,8 +169,11 @@ async fn test_extension_store(cx: &mut TestAppContext) {
                         name: "Zed Ruby".into(),
                         version: "1.0.0".into(),
                         schema_version: SchemaVersion::ZERO,
+                        min_zed_version: None,
                         description: None,
                         authors: Vec::new(),
+                        categories: Vec::new(),
+                        tags: Vec::new(),
                         repository: None,
                         themes: Default::default(),
                         lib: Default::default(),
@@ -158,12 +184,21 @@ async fn test_extension_store(cx: &mut TestAppContext) {
                         ]
                         .into_iter()
                         .collect(),
+                        language_queries: BTreeMap::default(),
                         language_servers: BTreeMap::default(),
                         slash_commands: BTreeMap::default(),
                         indexed_docs_providers: BTreeMap::default(),
                         snippets: None,
+                        features: BTreeMap::default(),
+                        conflicts: Vec::new(),
+                        priority: 0,
                     }),
                     dev: false,
+                    provenance: None,
+                    grammar_hashes: BTreeMap::default(),
+                    enabled: true,
+                    dir_name: None,
+                    installed_at: None,
                 },
             ),
             (
@@ -174,8 +209,11 @@ async fn test_extension_store(cx: &mut TestAppContext) {
                         name: "Zed Monokai".into(),
                         version: "2.0.0".into(),
                         schema_version: SchemaVersion::ZERO,
+                        min_zed_version: None,
                         description: None,
                         authors: vec![],
+                        categories: Vec::new(),
+                        tags: Vec::new(),
                         repository: None,
                         themes: vec![
                             "themes/monokai-pro.json".into(),
@@ -184,12 +222,21 @@ async fn test_extension_store(cx: &mut TestAppContext) {
                         lib: Default::default(),
                         languages: Default::default(),
                         grammars: BTreeMap::default(),
+                        language_queries: BTreeMap::default(),
                         language_servers: BTreeMap::default(),
                         slash_commands: BTreeMap::default(),
                         indexed_docs_providers: BTreeMap::default(),
                         snippets: None,
+                        features: BTreeMap::default(),
+                        conflicts: Vec::new(),
+                        priority: 0,
                     }),
                     dev: false,
+                    provenance: None,
+                    grammar_hashes: BTreeMap::default(),
+                    enabled: true,
+                    dir_name: None,
+                    installed_at: None,
                 },
             ),
         ]
@@ -205,7 +252,10 @@ async fn test_extension_store(cx: &mut TestAppContext) {
                     matcher: LanguageMatcher {
                         path_suffixes: vec!["erb".into()],
                         first_line_pattern: None,
+                        path_globs: Vec::new(),
                     },
+                    grammar_variants: Vec::new(),
+                    variant: None,
                 },
             ),
             (
@@ -217,7 +267,10 @@ async fn test_extension_store(cx: &mut TestAppContext) {
                     matcher: LanguageMatcher {
                         path_suffixes: vec!["rb".into()],
                         first_line_pattern: None,
+                        path_globs: Vec::new(),
                     },
+                    grammar_variants: Vec::new(),
+                    variant: None,
                 },
             ),
         ]
@@ -229,6 +282,8 @@ async fn test_extension_store(cx: &mut TestAppContext) {
                 ExtensionIndexThemeEntry {
                     extension: "zed-monokai".into(),
                     path: "themes/monokai.json".into(),
+                    appearance: Some(AppearanceContent::Dark),
+                    original_name: None,
                 },
             ),
             (
@@ -236,6 +291,8 @@ async fn test_extension_store(cx: &mut TestAppContext) {
                 ExtensionIndexThemeEntry {
                     extension: "zed-monokai".into(),
                     path: "themes/monokai.json".into(),
+                    appearance: Some(AppearanceContent::Light),
+                    original_name: None,
                 },
             ),
             (
@@ -243,6 +300,8 @@ async fn test_extension_store(cx: &mut TestAppContext) {
                 ExtensionIndexThemeEntry {
                     extension: "zed-monokai".into(),
                     path: "themes/monokai-pro.json".into(),
+                    appearance: Some(AppearanceContent::Dark),
+                    original_name: None,
                 },
             ),
             (
@@ -250,11 +309,36 @@ async fn test_extension_store(cx: &mut TestAppContext) {
                 ExtensionIndexThemeEntry {
                     extension: "zed-monokai".into(),
                     path: "themes/monokai-pro.json".into(),
+                    appearance: Some(AppearanceContent::Light),
+                    original_name: None,
+                },
+            ),
+        ]
+        .into_iter()
+        .collect(),
+        theme_families: [
+            (
+                "Monokai".into(),
+                ExtensionIndexThemeFamilyEntry {
+                    extension: "zed-monokai".into(),
+                    path: "themes/monokai.json".into(),
+                    author: "Someone".into(),
+                    themes: vec!["Monokai Dark".into(), "Monokai Light".into()],
+                },
+            ),
+            (
+                "Monokai Pro".into(),
+                ExtensionIndexThemeFamilyEntry {
+                    extension: "zed-monokai".into(),
+                    path: "themes/monokai-pro.json".into(),
+                    author: "Someone".into(),
+                    themes: vec!["Monokai Pro Dark".into(), "Monokai Pro Light".into()],
                 },
             ),
         ]
         .into_iter()
         .collect(),
+        shadowed_languages: BTreeMap::default(),
     };
 
     let language_registry = Arc::new(LanguageRegistry::test(cx.executor()));
@@ -270,6 +354,7 @@ async fn test_extension_store(cx: &mut TestAppContext) {
             None,
             fs.clone(),
             http_client.clone(),
+            RetryPolicy::default(),
             None,
             node_runtime.clone(),
             language_registry.clone(),
@@ -287,6 +372,7 @@ async fn test_extension_store(cx: &mut TestAppContext) {
         assert_eq!(index.extensions, expected_index.extensions);
         assert_eq!(index.languages, expected_index.languages);
         assert_eq!(index.themes, expected_index.themes);
+        assert_eq!(index.theme_families, expected_index.theme_families);
 
         assert_eq!(
             language_registry.language_names(),
@@ -340,19 +426,31 @@ async fn test_extension_store(cx: &mut TestAppContext) {
                 name: "Zed Gruvbox".into(),
                 version: "1.0.0".into(),
                 schema_version: SchemaVersion::ZERO,
+                min_zed_version: None,
                 description: None,
                 authors: vec![],
+                categories: Vec::new(),
+                tags: Vec::new(),
                 repository: None,
                 themes: vec!["themes/gruvbox.json".into()],
                 lib: Default::default(),
                 languages: Default::default(),
                 grammars: BTreeMap::default(),
+                language_queries: BTreeMap::default(),
                 language_servers: BTreeMap::default(),
                 slash_commands: BTreeMap::default(),
                 indexed_docs_providers: BTreeMap::default(),
                 snippets: None,
+                features: BTreeMap::default(),
+                conflicts: Vec::new(),
+                priority: 0,
             }),
             dev: false,
+            provenance: None,
+            grammar_hashes: BTreeMap::default(),
+            enabled: true,
+            dir_name: None,
+            installed_at: None,
         },
     );
     expected_index.themes.insert(
@@ -360,18 +458,28 @@ async fn test_extension_store(cx: &mut TestAppContext) {
         ExtensionIndexThemeEntry {
             extension: "zed-gruvbox".into(),
             path: "themes/gruvbox.json".into(),
+            appearance: Some(AppearanceContent::Dark),
+            original_name: None,
+        },
+    );
+    expected_index.theme_families.insert(
+        "Gruvbox".into(),
+        ExtensionIndexThemeFamilyEntry {
+            extension: "zed-gruvbox".into(),
+            path: "themes/gruvbox.json".into(),
+            author: "Someone Else".into(),
+            themes: vec!["Gruvbox".into()],
         },
     );
 
-    #[allow(clippy::let_underscore_future)]
-    let _ = store.update(cx, |store, cx| store.reload(None, cx));
-
-    cx.executor().advance_clock(RELOAD_DEBOUNCE_DURATION);
+    let reload = store.update(cx, |store, cx| store.reload(None, cx));
+    finish_reload(reload, cx);
     store.read_with(cx, |store, _| {
         let index = &store.extension_index;
         assert_eq!(index.extensions, expected_index.extensions);
         assert_eq!(index.languages, expected_index.languages);
         assert_eq!(index.themes, expected_index.themes);
+        assert_eq!(index.theme_families, expected_index.theme_families);
 
         assert_eq!(
             theme_registry.list_names(false),
@@ -390,6 +498,7 @@ async fn test_extension_store(cx: &mut TestAppContext) {
     let prev_fs_read_dir_call_count = fs.read_dir_call_count();
 
     // Create new extension store, as if Zed were restarting.
+    store.update(cx, |store, cx| store.flush(cx)).await.unwrap();
     drop(store);
     let store = cx.new_model(|cx| {
         ExtensionStore::new(
@@ -397,6 +506,7 @@ async fn test_extension_store(cx: &mut TestAppContext) {
             None,
             fs.clone(),
             http_client.clone(),
+            RetryPolicy::default(),
             None,
             node_runtime.clone(),
             language_registry.clone(),
@@ -437,8 +547,11 @@ async fn test_extension_store(cx: &mut TestAppContext) {
         assert_eq!(fs.metadata_call_count(), prev_fs_metadata_call_count + 2);
     });
 
+    let mut events = cx.events(&store);
     store.update(cx, |store, cx| {
-        store.uninstall_extension("zed-ruby".into(), cx)
+        store
+            .uninstall_extension("zed-ruby".into(), UninstallOptions::default(), cx)
+            .unwrap()
     });
 
     cx.executor().advance_clock(RELOAD_DEBOUNCE_DURATION);
@@ -451,6 +564,232 @@ async fn test_extension_store(cx: &mut TestAppContext) {
         assert_eq!(language_registry.language_names(), ["Plain Text"]);
         assert_eq!(language_registry.grammar_names(), []);
     });
+
+    cx.executor().run_until_parked();
+    let uninstalled = std::iter::from_fn(|| events.try_next().ok().flatten())
+        .filter_map(|event| match event {
+            Event::ExtensionUninstalled(extension_id) => Some(extension_id),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(uninstalled, [Arc::from("zed-ruby")]);
+}
+
+#[gpui::test]
+async fn test_uninstall_extension_keeping_data(cx: &mut TestAppContext) {
+    init_test(cx);
+
+    let fs = FakeFs::new(cx.executor());
+    let mut ruby = ruby_extension();
+    ruby["data"] = json!({ "snippets.json": "{}" });
+    let mut monokai = monokai_extension();
+    monokai["data"] = json!({ "snippets.json": "{}" });
+    fs.insert_tree(
+        "/the-extension-dir",
+        json!({
+            "installed": {
+                "zed-ruby": ruby,
+                "zed-monokai": monokai,
+            }
+        }),
+    )
+    .await;
+
+    let store = new_test_extension_store(
+        fs.clone(),
+        FakeHttpClient::with_200_response(),
+        Arc::new(LanguageRegistry::test(cx.executor())),
+        Arc::new(ThemeRegistry::new(Box::new(()))),
+        cx,
+    );
+    cx.executor().advance_clock(RELOAD_DEBOUNCE_DURATION);
+    cx.executor().run_until_parked();
+
+    store.update(cx, |store, cx| {
+        store
+            .uninstall_extension(
+                "zed-ruby".into(),
+                UninstallOptions {
+                    keep_data: true,
+                    ..Default::default()
+                },
+                cx,
+            )
+            .unwrap();
+        store
+            .uninstall_extension("zed-monokai".into(), UninstallOptions::default(), cx)
+            .unwrap();
+    });
+    cx.executor().advance_clock(RELOAD_DEBOUNCE_DURATION);
+    cx.executor().run_until_parked();
+
+    store.read_with(cx, |store, _| {
+        assert!(store.installed_extension_entries().is_empty());
+    });
+    assert!(
+        !fs.is_dir("/the-extension-dir/installed/zed-ruby".as_ref())
+            .await
+    );
+    assert_eq!(
+        fs.load("/the-extension-dir/work/.preserved-data/zed-ruby/snippets.json".as_ref())
+            .await
+            .unwrap(),
+        "{}"
+    );
+    assert!(
+        !fs.is_dir("/the-extension-dir/work/.preserved-data/zed-monokai".as_ref())
+            .await
+    );
+}
+
+#[gpui::test]
+async fn test_uninstall_extension_dry_run(cx: &mut TestAppContext) {
+    init_test(cx);
+
+    let TestExtensionStore {
+        fs,
+        store,
+        language_registry,
+        theme_registry,
+    } = init_test_extension_store(
+        json!({
+            "installed": {
+                "zed-ruby": ruby_extension(),
+                "zed-monokai": monokai_extension(),
+            }
+        }),
+        cx,
+    )
+    .await;
+
+    let index_before = store.read_with(cx, |store, _| store.extension_index.clone());
+    let dry_run = UninstallOptions {
+        dry_run: true,
+        ..Default::default()
+    };
+    let report = store
+        .update(cx, |store, cx| {
+            store.uninstall_extension("zed-ruby".into(), dry_run, cx)
+        })
+        .unwrap();
+    assert_eq!(
+        report,
+        UninstallReport {
+            artifacts: ExtensionArtifacts {
+                languages: [Arc::from("ERB"), Arc::from("Ruby")].into_iter().collect(),
+                themes: BTreeSet::new(),
+                grammars: [Arc::from("embedded_template"), Arc::from("ruby")]
+                    .into_iter()
+                    .collect(),
+            },
+            extension_dir: PathBuf::from("/the-extension-dir/installed/zed-ruby"),
+            grammar_store_paths: Vec::new(),
+        }
+    );
+
+    // There is nothing to report for an extension that isn't installed.
+    let error = store
+        .update(cx, |store, cx| {
+            store.uninstall_extension("zed-unknown".into(), dry_run, cx)
+        })
+        .unwrap_err();
+    assert_eq!(error.to_string(), "extension zed-unknown is not installed");
+
+    cx.executor().advance_clock(RELOAD_DEBOUNCE_DURATION);
+    cx.executor().run_until_parked();
+
+    store.read_with(cx, |store, _| {
+        assert_eq!(store.extension_index, index_before);
+        assert!(store.outstanding_operations().is_empty());
+    });
+    assert_eq!(
+        language_registry.language_names(),
+        ["ERB", "Plain Text", "Ruby"]
+    );
+    assert_eq!(
+        language_registry.grammar_names(),
+        ["embedded_template".into(), "ruby".into()]
+    );
+    assert!(theme_registry
+        .list_names(false)
+        .contains(&"Monokai Dark".into()));
+    assert!(
+        fs.is_dir("/the-extension-dir/installed/zed-ruby".as_ref())
+            .await
+    );
+}
+
+#[gpui::test]
+async fn test_set_extensions_dir(cx: &mut TestAppContext) {
+    init_test(cx);
+
+    let TestExtensionStore {
+        fs,
+        store,
+        language_registry,
+        theme_registry,
+    } = init_test_extension_store(
+        json!({
+            "installed": {
+                "zed-ruby": ruby_extension(),
+                "zed-monokai": monokai_extension(),
+            },
+            "work": {
+                "zed-ruby": {
+                    "cache.txt": "cached",
+                }
+            }
+        }),
+        cx,
+    )
+    .await;
+
+    let installed_before =
+        store.read_with(cx, |store, _| store.installed_extension_entries().clone());
+
+    let task = store.update(cx, |store, cx| {
+        store.set_extensions_dir("/new-extensions-dir".into(), cx)
+    });
+    cx.executor().advance_clock(RELOAD_DEBOUNCE_DURATION);
+    cx.executor().run_until_parked();
+    task.await.unwrap();
+
+    assert!(
+        fs.is_dir("/new-extensions-dir/installed/zed-ruby".as_ref())
+            .await
+    );
+    assert!(
+        fs.is_file("/new-extensions-dir/work/zed-ruby/cache.txt".as_ref())
+            .await
+    );
+    assert!(
+        !fs.is_dir("/the-extension-dir/installed/zed-ruby".as_ref())
+            .await
+    );
+    assert!(!fs.is_dir("/the-extension-dir/work".as_ref()).await);
+
+    store.read_with(cx, |store, _| {
+        assert_eq!(store.installed_extension_entries(), &installed_before);
+        assert_eq!(
+            store.extensions_dir(),
+            PathBuf::from("/new-extensions-dir/installed")
+        );
+        assert_eq!(
+            store.wasm_host.work_dir,
+            PathBuf::from("/new-extensions-dir/work")
+        );
+    });
+    assert_eq!(
+        language_registry.language_names(),
+        ["ERB", "Plain Text", "Ruby"]
+    );
+    assert_eq!(
+        language_registry.grammar_names(),
+        ["embedded_template".into(), "ruby".into()]
+    );
+    assert!(theme_registry
+        .list_names(false)
+        .contains(&"Monokai Dark".into()));
 }
 
 #[gpui::test]
@@ -571,6 +910,7 @@ async fn test_extension_store_with_test_extension(cx: &mut TestAppContext) {
             Some(cache_dir),
             fs.clone(),
             http_client.clone(),
+            RetryPolicy::default(),
             None,
             node_runtime,
             language_registry.clone(),
@@ -751,6 +1091,7445 @@ async fn test_extension_store_with_test_extension(cx: &mut TestAppContext) {
     assert!(fs.metadata(&expected_server_path).await.unwrap().is_none());
 }
 
+#[gpui::test]
+async fn test_install_extension_bundle(cx: &mut TestAppContext) {
+    init_test(cx);
+    cx.executor().allow_parking();
+
+    let extensions_dir = temp_tree(json!({
+        "installed": {},
+        "work": {}
+    }));
+    let extensions_dir = extensions_dir.path().canonicalize().unwrap();
+    let bundle_dir = temp_tree(json!({}));
+    let bundle_path = bundle_dir.path().join("zed-gruvbox.zed-extension");
+
+    let gruvbox = gruvbox_extension();
+    let mut writer = ZipFileWriter::new(Vec::new());
+    for (path, contents) in [
+        ("extension.json", &gruvbox["extension.json"]),
+        ("themes/gruvbox.json", &gruvbox["themes"]["gruvbox.json"]),
+    ] {
+        writer
+            .write_entry_whole(
+                ZipEntryBuilder::new(path.to_string().into(), Compression::Deflate),
+                contents.as_str().unwrap().as_bytes(),
+            )
+            .await
+            .unwrap();
+    }
+    std::fs::write(&bundle_path, writer.close().await.unwrap()).unwrap();
+
+    let theme_registry = Arc::new(ThemeRegistry::new(Box::new(())));
+    let extension_store = cx.new_model(|cx| {
+        ExtensionStore::new(
+            extensions_dir.clone(),
+            None,
+            Arc::new(RealFs::default()),
+            FakeHttpClient::with_200_response(),
+            RetryPolicy::default(),
+            None,
+            FakeNodeRuntime::new(),
+            Arc::new(LanguageRegistry::test(cx.background_executor().clone())),
+            theme_registry.clone(),
+            SlashCommandRegistry::new(),
+            Arc::new(IndexedDocsRegistry::new(cx.background_executor().clone())),
+            Arc::new(SnippetRegistry::new()),
+            cx,
+        )
+    });
+
+    // Ensure that debounces fire.
+    let mut events = cx.events(&extension_store);
+    let executor = cx.executor();
+    let _task = cx.executor().spawn(async move {
+        while let Some(event) = events.next().await {
+            if let Event::StartedReloading = event {
+                executor.advance_clock(RELOAD_DEBOUNCE_DURATION);
+            }
+        }
+    });
+
+    extension_store
+        .update(cx, |store, cx| {
+            store.install_dev_extension(bundle_path.clone(), cx)
+        })
+        .await
+        .unwrap();
+
+    assert!(extensions_dir
+        .join("installed/zed-gruvbox/themes/gruvbox.json")
+        .exists());
+    extension_store.read_with(cx, |store, _| {
+        assert!(store
+            .installed_extension_entries()
+            .contains_key("zed-gruvbox"));
+        assert!(store.outstanding_operations().is_empty());
+    });
+    assert_eq!(theme_registry.list_names(false), ["Gruvbox", "One Dark"]);
+
+    // Installing the bundle again replaces the installed extension, and leaves
+    // nothing behind in the work directory.
+    std::fs::write(
+        extensions_dir.join("installed/zed-gruvbox/stale.txt"),
+        "stale",
+    )
+    .unwrap();
+    extension_store
+        .update(cx, |store, cx| {
+            store.install_extension_bundle(bundle_path, cx)
+        })
+        .await
+        .unwrap();
+    assert!(extensions_dir
+        .join("installed/zed-gruvbox/themes/gruvbox.json")
+        .exists());
+    assert!(!extensions_dir
+        .join("installed/zed-gruvbox/stale.txt")
+        .exists());
+    assert_eq!(
+        std::fs::read_dir(extensions_dir.join("work/.staging"))
+            .unwrap()
+            .count(),
+        0
+    );
+    assert!(!extensions_dir.join("work/.replaced/zed-gruvbox").exists());
+}
+
+#[gpui::test]
+async fn test_install_extension_bundle_with_invalid_id(cx: &mut TestAppContext) {
+    init_test(cx);
+    cx.executor().allow_parking();
+
+    let root_dir = temp_tree(json!({
+        "extensions": {
+            "installed": {},
+            "work": {}
+        },
+        "bundles": {}
+    }));
+    let root_dir = root_dir.path().canonicalize().unwrap();
+    let extensions_dir = root_dir.join("extensions");
+
+    let extension_store = cx.new_model(|cx| {
+        ExtensionStore::new(
+            extensions_dir.clone(),
+            None,
+            Arc::new(RealFs::default()),
+            FakeHttpClient::with_200_response(),
+            RetryPolicy::default(),
+            None,
+            FakeNodeRuntime::new(),
+            Arc::new(LanguageRegistry::test(cx.background_executor().clone())),
+            Arc::new(ThemeRegistry::new(Box::new(()))),
+            SlashCommandRegistry::new(),
+            Arc::new(IndexedDocsRegistry::new(cx.background_executor().clone())),
+            Arc::new(SnippetRegistry::new()),
+            cx,
+        )
+    });
+
+    for extension_id in ["../..", "..", "a/b", "a\\b", ""] {
+        let bundle_path = root_dir.join("bundles/hostile.zed-extension");
+        let mut writer = ZipFileWriter::new(Vec::new());
+        writer
+            .write_entry_whole(
+                ZipEntryBuilder::new("extension.toml".to_string().into(), Compression::Deflate),
+                format!(
+                    "id = {extension_id:?}\nname = \"Hostile\"\nversion = \"1.0.0\"\nschema_version = 1\n"
+                )
+                .as_bytes(),
+            )
+            .await
+            .unwrap();
+        std::fs::write(&bundle_path, writer.close().await.unwrap()).unwrap();
+
+        let error = extension_store
+            .update(cx, |store, cx| {
+                store.install_extension_bundle(bundle_path.clone(), cx)
+            })
+            .await
+            .unwrap_err();
+        assert_eq!(
+            format!("{:#}", error),
+            format!(
+                "invalid extension bundle {bundle_path:?}: invalid extension id {extension_id:?}"
+            )
+        );
+
+        // Nothing outside of the work directory was touched, and the bundle's
+        // staging directory was removed.
+        assert_eq!(
+            std::fs::read_dir(extensions_dir.join("work/.staging"))
+                .unwrap()
+                .count(),
+            0
+        );
+        assert!(bundle_path.exists());
+        assert!(extensions_dir.join("installed").is_dir());
+        assert!(root_dir.join("bundles").is_dir());
+        assert_eq!(
+            std::fs::read_dir(extensions_dir.join("installed"))
+                .unwrap()
+                .count(),
+            0
+        );
+    }
+}
+
+#[gpui::test]
+async fn test_extension_language_completion_query_characters(cx: &mut TestAppContext) {
+    init_test(cx);
+    cx.executor().allow_parking();
+
+    let extensions_dir = temp_tree(json!({
+        "installed": {
+            "zed-cpp": {
+                "extension.json": r#"{
+                    "id": "zed-cpp",
+                    "name": "Zed C++",
+                    "version": "1.0.0"
+                }"#,
+                "languages": {
+                    "cpp": {
+                        "config.toml": r#"
+                            name = "C++"
+                            path_suffixes = ["cpp"]
+                            completion_query_characters = [".", "::", "->"]
+                        "#,
+                    }
+                },
+            }
+        },
+        "work": {}
+    }));
+    let extensions_dir = extensions_dir.path().canonicalize().unwrap();
+
+    let language_registry = Arc::new(LanguageRegistry::test(cx.executor()));
+    let extension_store = cx.new_model(|cx| {
+        ExtensionStore::new(
+            extensions_dir.clone(),
+            None,
+            Arc::new(RealFs::default()),
+            FakeHttpClient::with_200_response(),
+            RetryPolicy::default(),
+            None,
+            FakeNodeRuntime::new(),
+            language_registry.clone(),
+            Arc::new(ThemeRegistry::new(Box::new(()))),
+            SlashCommandRegistry::new(),
+            Arc::new(IndexedDocsRegistry::new(cx.background_executor().clone())),
+            Arc::new(SnippetRegistry::new()),
+            cx,
+        )
+    });
+
+    // Ensure that debounces fire.
+    let mut events = cx.events(&extension_store);
+    let executor = cx.executor();
+    let _task = cx.executor().spawn(async move {
+        while let Some(event) = events.next().await {
+            if let Event::StartedReloading = event {
+                executor.advance_clock(RELOAD_DEBOUNCE_DURATION);
+            }
+        }
+    });
+
+    extension_store
+        .update(cx, |store, cx| store.reload(None, cx))
+        .await;
+
+    let language = language_registry.language_for_name("C++").await.unwrap();
+    let expected_characters: &[Arc<str>] = &[".".into(), "::".into(), "->".into()];
+    assert_eq!(
+        language.default_scope().completion_query_characters(),
+        expected_characters
+    );
+}
+
+#[gpui::test]
+async fn test_grammar_load_failures_are_recorded(cx: &mut TestAppContext) {
+    init_test(cx);
+    cx.executor().allow_parking();
+
+    // The Ruby extension's grammar files are empty, so they fail to load when
+    // the Ruby language is first used.
+    let extensions_dir = temp_tree(json!({
+        "installed": {
+            "zed-ruby": ruby_extension(),
+        },
+        "work": {}
+    }));
+    let extensions_dir = extensions_dir.path().canonicalize().unwrap();
+
+    let language_registry = Arc::new(LanguageRegistry::test(cx.executor()));
+    let extension_store = cx.new_model(|cx| {
+        ExtensionStore::new(
+            extensions_dir.clone(),
+            None,
+            Arc::new(RealFs::default()),
+            FakeHttpClient::with_200_response(),
+            RetryPolicy::default(),
+            None,
+            FakeNodeRuntime::new(),
+            language_registry.clone(),
+            Arc::new(ThemeRegistry::new(Box::new(()))),
+            SlashCommandRegistry::new(),
+            Arc::new(IndexedDocsRegistry::new(cx.background_executor().clone())),
+            Arc::new(SnippetRegistry::new()),
+            cx,
+        )
+    });
+
+    // Ensure that debounces fire.
+    let mut events = cx.events(&extension_store);
+    let grammar_failures = Arc::new(Mutex::new(Vec::new()));
+    let executor = cx.executor();
+    let _task = cx.executor().spawn({
+        let grammar_failures = grammar_failures.clone();
+        async move {
+            while let Some(event) = events.next().await {
+                match event {
+                    Event::StartedReloading => executor.advance_clock(RELOAD_DEBOUNCE_DURATION),
+                    Event::GrammarFailedToLoad {
+                        extension_id,
+                        grammar_name,
+                    } => grammar_failures.lock().push((extension_id, grammar_name)),
+                    _ => {}
+                }
+            }
+        }
+    });
+
+    extension_store
+        .update(cx, |store, cx| store.reload(None, cx))
+        .await;
+    extension_store.read_with(cx, |store, _| assert!(store.load_errors().is_empty()));
+
+    assert!(language_registry.language_for_name("Ruby").await.is_err());
+    cx.executor().run_until_parked();
+
+    assert_eq!(
+        *grammar_failures.lock(),
+        [(Arc::from("zed-ruby"), Arc::from("ruby"))]
+    );
+    extension_store.read_with(cx, |store, _| {
+        let load_errors = store.load_errors();
+        assert_eq!(load_errors.len(), 1);
+        assert_eq!(load_errors[0].extension_id.as_ref(), "zed-ruby");
+        assert_eq!(load_errors[0].severity, LoadErrorSeverity::Error);
+        assert!(load_errors[0]
+            .message
+            .starts_with("failed to load grammar ruby"));
+    });
+
+    // The failure is remembered, so using the language again doesn't report it again.
+    assert!(language_registry.language_for_name("Ruby").await.is_err());
+    cx.executor().run_until_parked();
+    assert_eq!(grammar_failures.lock().len(), 1);
+}
+
+#[gpui::test]
+async fn test_preload_grammars(cx: &mut TestAppContext) {
+    init_test(cx);
+    cx.executor().allow_parking();
+
+    // The Ruby extension's grammar files are empty, so both of them fail to load.
+    let extensions_dir = temp_tree(json!({
+        "installed": {
+            "zed-ruby": ruby_extension(),
+        },
+        "work": {}
+    }));
+    let extensions_dir = extensions_dir.path().canonicalize().unwrap();
+
+    cx.update(|cx| {
+        SettingsStore::update_global(cx, |store, cx| {
+            store.update_user_settings::<ExtensionSettings>(cx, |settings| {
+                settings.max_concurrent_grammar_loads = Some(1);
+            });
+        });
+    });
+
+    let language_registry = Arc::new(LanguageRegistry::test(cx.executor()));
+    let extension_store = cx.new_model(|cx| {
+        ExtensionStore::new(
+            extensions_dir.clone(),
+            None,
+            Arc::new(RealFs::default()),
+            FakeHttpClient::with_200_response(),
+            RetryPolicy::default(),
+            None,
+            FakeNodeRuntime::new(),
+            language_registry.clone(),
+            Arc::new(ThemeRegistry::new(Box::new(()))),
+            SlashCommandRegistry::new(),
+            Arc::new(IndexedDocsRegistry::new(cx.background_executor().clone())),
+            Arc::new(SnippetRegistry::new()),
+            cx,
+        )
+    });
+
+    // Ensure that debounces fire.
+    let mut events = cx.events(&extension_store);
+    let executor = cx.executor();
+    let _task = cx.executor().spawn(async move {
+        while let Some(event) = events.next().await {
+            if let Event::StartedReloading = event {
+                executor.advance_clock(RELOAD_DEBOUNCE_DURATION);
+            }
+        }
+    });
+
+    extension_store
+        .update(cx, |store, cx| store.reload(None, cx))
+        .await;
+    extension_store.read_with(cx, |store, _| assert!(store.load_errors().is_empty()));
+
+    extension_store
+        .update(cx, |store, cx| store.preload_grammars(cx))
+        .await;
+    cx.executor().run_until_parked();
+
+    extension_store.read_with(cx, |store, _| {
+        let mut messages = store
+            .load_errors()
+            .iter()
+            .map(|error| {
+                assert_eq!(error.extension_id.as_ref(), "zed-ruby");
+                error.message.split(':').next().unwrap().to_string()
+            })
+            .collect::<Vec<_>>();
+        messages.sort();
+        assert_eq!(
+            messages,
+            [
+                "failed to load grammar embedded_template",
+                "failed to load grammar ruby"
+            ]
+        );
+    });
+    assert_eq!(
+        language_registry.grammar_names(),
+        [Arc::from("embedded_template"), Arc::from("ruby")]
+    );
+}
+
+#[gpui::test]
+async fn test_extension_language_path_globs(cx: &mut TestAppContext) {
+    init_test(cx);
+    cx.executor().allow_parking();
+
+    let extensions_dir = temp_tree(json!({
+        "installed": {
+            "zed-javascript": {
+                "extension.json": r#"{
+                    "id": "zed-javascript",
+                    "name": "Zed JavaScript",
+                    "version": "1.0.0"
+                }"#,
+                "languages": {
+                    "javascript": {
+                        "config.toml": r#"
+                            name = "JavaScript"
+                            path_suffixes = ["js"]
+                        "#,
+                    },
+                    "javascript-config": {
+                        "config.toml": r#"
+                            name = "JavaScript Config"
+                            path_globs = ["*.config.js"]
+                        "#,
+                    },
+                    "dockerfile": {
+                        "config.toml": r#"
+                            name = "Dockerfile"
+                            path_suffixes = ["Dockerfile"]
+                            path_globs = ["Dockerfile.*", "*.conf.d/*"]
+                        "#,
+                    }
+                },
+            }
+        },
+        "work": {}
+    }));
+    let extensions_dir = extensions_dir.path().canonicalize().unwrap();
+
+    let language_registry = Arc::new(LanguageRegistry::test(cx.executor()));
+    let extension_store = cx.new_model(|cx| {
+        ExtensionStore::new(
+            extensions_dir.clone(),
+            None,
+            Arc::new(RealFs::default()),
+            FakeHttpClient::with_200_response(),
+            RetryPolicy::default(),
+            None,
+            FakeNodeRuntime::new(),
+            language_registry.clone(),
+            Arc::new(ThemeRegistry::new(Box::new(()))),
+            SlashCommandRegistry::new(),
+            Arc::new(IndexedDocsRegistry::new(cx.background_executor().clone())),
+            Arc::new(SnippetRegistry::new()),
+            cx,
+        )
+    });
+
+    // Ensure that debounces fire.
+    let mut events = cx.events(&extension_store);
+    let executor = cx.executor();
+    let _task = cx.executor().spawn(async move {
+        while let Some(event) = events.next().await {
+            if let Event::StartedReloading = event {
+                executor.advance_clock(RELOAD_DEBOUNCE_DURATION);
+            }
+        }
+    });
+
+    extension_store
+        .update(cx, |store, cx| store.reload(None, cx))
+        .await;
+
+    for (path, expected_language) in [
+        ("/project/webpack.config.js", Some("JavaScript Config")),
+        ("/project/src/vite.config.js", Some("JavaScript Config")),
+        ("/project/src/config.js", Some("JavaScript")),
+        ("/project/webpack.config.json", None),
+        ("/project/Dockerfile", Some("Dockerfile")),
+        ("/project/docker/Dockerfile.dev", Some("Dockerfile")),
+        ("/etc/nginx.conf.d/default", Some("Dockerfile")),
+        ("/project/nginx.conf", None),
+    ] {
+        let language = language_registry
+            .language_for_file_path(Path::new(path))
+            .await
+            .ok();
+        assert_eq!(
+            language.as_ref().map(|language| language.name()),
+            expected_language.map(Arc::from),
+            "unexpected language for {path}"
+        );
+    }
+}
+
+#[gpui::test]
+async fn test_extension_language_first_line_pattern(cx: &mut TestAppContext) {
+    init_test(cx);
+    cx.executor().allow_parking();
+
+    let extensions_dir = temp_tree(json!({
+        "installed": {
+            "zed-python": {
+                "extension.json": r#"{
+                    "id": "zed-python",
+                    "name": "Zed Python",
+                    "version": "1.0.0"
+                }"#,
+                "languages": {
+                    "python": {
+                        "config.toml": r#"
+                            name = "Python"
+                            path_suffixes = ["py"]
+                            first_line_pattern = "^#!.*\\bpython3?\\b"
+                        "#,
+                    }
+                },
+            }
+        },
+        "work": {}
+    }));
+    let extensions_dir = extensions_dir.path().canonicalize().unwrap();
+
+    let language_registry = Arc::new(LanguageRegistry::test(cx.executor()));
+    let extension_store = cx.new_model(|cx| {
+        ExtensionStore::new(
+            extensions_dir.clone(),
+            None,
+            Arc::new(RealFs::default()),
+            FakeHttpClient::with_200_response(),
+            RetryPolicy::default(),
+            None,
+            FakeNodeRuntime::new(),
+            language_registry.clone(),
+            Arc::new(ThemeRegistry::new(Box::new(()))),
+            SlashCommandRegistry::new(),
+            Arc::new(IndexedDocsRegistry::new(cx.background_executor().clone())),
+            Arc::new(SnippetRegistry::new()),
+            cx,
+        )
+    });
+
+    // Ensure that debounces fire.
+    let mut events = cx.events(&extension_store);
+    let executor = cx.executor();
+    let _task = cx.executor().spawn(async move {
+        while let Some(event) = events.next().await {
+            if let Event::StartedReloading = event {
+                executor.advance_clock(RELOAD_DEBOUNCE_DURATION);
+            }
+        }
+    });
+
+    extension_store
+        .update(cx, |store, cx| store.reload(None, cx))
+        .await;
+
+    let pattern = extension_store.read_with(cx, |store, _| {
+        store.extension_index.languages["Python"]
+            .matcher
+            .first_line_pattern
+            .as_ref()
+            .map(|pattern| pattern.as_str().to_string())
+    });
+    assert_eq!(pattern.as_deref(), Some(r"^#!.*\bpython3?\b"));
+
+    let file: Arc<dyn language::File> = Arc::new(TestFile {
+        path: Path::new("scripts/run.txt").into(),
+        root_name: "project".into(),
+    });
+    for (content, expected_language) in [
+        ("#!/usr/bin/env python3\nprint(1)\n", Some("Python")),
+        ("#!/usr/bin/python\n", Some("Python")),
+        ("#!/bin/sh\n", None),
+        ("print(1)\n", None),
+    ] {
+        let content = Rope::from(content);
+        let language = cx
+            .read(|cx| language_registry.language_for_file(&file, Some(&content), cx))
+            .await
+            .ok();
+        assert_eq!(
+            language.as_ref().map(|language| language.name()),
+            expected_language.map(Arc::from),
+            "unexpected language for {content:?}"
+        );
+    }
+}
+
+#[gpui::test]
+async fn test_extension_language_queries_precedence(cx: &mut TestAppContext) {
+    init_test(cx);
+    cx.executor().allow_parking();
+
+    let extensions_dir = temp_tree(json!({
+        "installed": {
+            "zed-ruby": {
+                "extension.json": r#"{
+                    "id": "zed-ruby",
+                    "name": "Zed Ruby",
+                    "version": "1.0.0"
+                }"#,
+                "languages": {
+                    "ruby": {
+                        "config.toml": r#"
+                            name = "Ruby"
+                            path_suffixes = ["rb"]
+                        "#,
+                        "highlights.scm": "(comment) @comment\n",
+                    }
+                },
+            },
+            "zed-ruby-a": {
+                "extension.toml": r#"
+                    id = "zed-ruby-a"
+                    name = "zed-ruby-a"
+                    version = "1.0.0"
+                    schema_version = 1
+
+                    [language_queries.Ruby]
+                    path = "queries/ruby"
+                    priority = 10
+                "#,
+                "queries": {
+                    "ruby": { "highlights.scm": "(a) @a\n" }
+                },
+            },
+            "zed-ruby-b": {
+                "extension.toml": r#"
+                    id = "zed-ruby-b"
+                    name = "zed-ruby-b"
+                    version = "1.0.0"
+                    schema_version = 1
+
+                    [language_queries.Ruby]
+                    path = "queries/ruby"
+                "#,
+                "queries": {
+                    "ruby": { "highlights.scm": "(b) @b\n" }
+                },
+            },
+            "zed-ruby-c": {
+                "extension.toml": r#"
+                    id = "zed-ruby-c"
+                    name = "zed-ruby-c"
+                    version = "1.0.0"
+                    schema_version = 1
+
+                    [language_queries.Ruby]
+                    path = "queries/ruby"
+                "#,
+                "queries": {
+                    "ruby": { "highlights.scm": "(c) @c\n" }
+                },
+            },
+        },
+        "work": {}
+    }));
+    let extensions_dir = extensions_dir.path().canonicalize().unwrap();
+
+    let extension_store = cx.new_model(|cx| {
+        ExtensionStore::new(
+            extensions_dir.clone(),
+            None,
+            Arc::new(RealFs::default()),
+            FakeHttpClient::with_200_response(),
+            RetryPolicy::default(),
+            None,
+            FakeNodeRuntime::new(),
+            Arc::new(LanguageRegistry::test(cx.executor())),
+            Arc::new(ThemeRegistry::new(Box::new(()))),
+            SlashCommandRegistry::new(),
+            Arc::new(IndexedDocsRegistry::new(cx.background_executor().clone())),
+            Arc::new(SnippetRegistry::new()),
+            cx,
+        )
+    });
+
+    // Ensure that debounces fire.
+    let mut events = cx.events(&extension_store);
+    let executor = cx.executor();
+    let _task = cx.executor().spawn(async move {
+        while let Some(event) = events.next().await {
+            if let Event::StartedReloading = event {
+                executor.advance_clock(RELOAD_DEBOUNCE_DURATION);
+            }
+        }
+    });
+
+    extension_store
+        .update(cx, |store, cx| store.reload(None, cx))
+        .await;
+
+    // The language's own queries come first, followed by those of the other
+    // extensions by ascending priority, and then by extension ID.
+    let highlights = extension_store.read_with(cx, |store, _| {
+        let index = &store.extension_index;
+        let mut queries = LanguageQueries::default();
+        for query_path in store.language_query_paths("Ruby", &index.languages["Ruby"], index) {
+            append_plugin_queries(&mut queries, &query_path);
+        }
+        queries.highlights.unwrap().into_owned()
+    });
+    assert_eq!(highlights, "(comment) @comment\n(b) @b\n(c) @c\n(a) @a\n");
+}
+
+#[gpui::test]
+async fn test_extension_provenance(cx: &mut TestAppContext) {
+    init_test(cx);
+    cx.executor().allow_parking();
+
+    let extensions_dir = temp_tree(json!({
+        "installed": {},
+        "work": {}
+    }));
+    let extensions_dir = extensions_dir.path().canonicalize().unwrap();
+
+    let http_client = FakeHttpClient::create(|request| async move {
+        let extension_id = match request.uri().path() {
+            "/extensions/zed-signed/1.0.0/download" => "zed-signed",
+            "/extensions/zed-unsigned/1.0.0/download" => "zed-unsigned",
+            _ => return Ok(Response::builder().status(404).body("not found".into())?),
+        };
+
+        let manifest =
+            format!(r#"{{"id": "{extension_id}", "name": "{extension_id}", "version": "1.0.0"}}"#);
+        let mut bytes = Vec::<u8>::new();
+        let mut archive = async_tar::Builder::new(&mut bytes);
+        let mut header = async_tar::Header::new_gnu();
+        header.set_size(manifest.len() as u64);
+        header.set_mode(0o644);
+        archive
+            .append_data(&mut header, "extension.json", manifest.as_bytes())
+            .await
+            .unwrap();
+        archive.into_inner().await.unwrap();
+        let mut gzipped_bytes = Vec::new();
+        let mut encoder = GzipEncoder::new(BufReader::new(bytes.as_slice()));
+        encoder.read_to_end(&mut gzipped_bytes).await.unwrap();
+
+        let mut response = Response::builder();
+        if extension_id == "zed-signed" {
+            response = response.header("x-zed-extension-signed-by", "Zed Industries");
+        }
+        Ok(response.body(gzipped_bytes.into())?)
+    });
+
+    let extension_store = cx.new_model(|cx| {
+        ExtensionStore::new(
+            extensions_dir.clone(),
+            None,
+            Arc::new(RealFs::default()),
+            http_client,
+            RetryPolicy::default(),
+            None,
+            FakeNodeRuntime::new(),
+            Arc::new(LanguageRegistry::test(cx.background_executor().clone())),
+            Arc::new(ThemeRegistry::new(Box::new(()))),
+            SlashCommandRegistry::new(),
+            Arc::new(IndexedDocsRegistry::new(cx.background_executor().clone())),
+            Arc::new(SnippetRegistry::new()),
+            cx,
+        )
+    });
+
+    // Ensure that debounces fire.
+    let mut events = cx.events(&extension_store);
+    let executor = cx.executor();
+    let _task = cx.executor().spawn(async move {
+        while let Some(event) = events.next().await {
+            if let Event::StartedReloading = event {
+                executor.advance_clock(RELOAD_DEBOUNCE_DURATION);
+            }
+        }
+    });
+
+    for extension_id in ["zed-signed", "zed-unsigned"] {
+        extension_store
+            .update(cx, |store, cx| {
+                store.install_or_upgrade_extension(
+                    extension_id.into(),
+                    "1.0.0".into(),
+                    ExtensionOperation::Install,
+                    cx,
+                )
+            })
+            .await
+            .unwrap();
+    }
+
+    extension_store.read_with(cx, |store, _| {
+        assert!(store
+            .installed_extension_entries()
+            .contains_key("zed-signed"));
+        assert!(store
+            .installed_extension_entries()
+            .contains_key("zed-unsigned"));
+        assert_eq!(
+            store.extension_provenance("zed-signed"),
+            Some(&ExtensionProvenance {
+                signed_by: "Zed Industries".into()
+            })
+        );
+        assert_eq!(store.extension_provenance("zed-unsigned"), None);
+    });
+}
+
+#[gpui::test]
+async fn test_extension_installed_at(cx: &mut TestAppContext) {
+    init_test(cx);
+    cx.executor().allow_parking();
+
+    let extensions_dir = temp_tree(json!({
+        "installed": {},
+        "work": {}
+    }));
+    let extensions_dir = extensions_dir.path().canonicalize().unwrap();
+
+    let http_client = FakeHttpClient::create(|request| async move {
+        let version = match request.uri().path() {
+            "/extensions/zed-ruby/1.0.0/download" => "1.0.0",
+            "/extensions/zed-ruby/1.1.0/download" => "1.1.0",
+            _ => return Ok(Response::builder().status(404).body("not found".into())?),
+        };
+
+        let manifest =
+            format!(r#"{{"id": "zed-ruby", "name": "Zed Ruby", "version": "{version}"}}"#);
+        let mut bytes = Vec::<u8>::new();
+        let mut archive = async_tar::Builder::new(&mut bytes);
+        let mut header = async_tar::Header::new_gnu();
+        header.set_size(manifest.len() as u64);
+        header.set_mode(0o644);
+        archive
+            .append_data(&mut header, "extension.json", manifest.as_bytes())
+            .await
+            .unwrap();
+        archive.into_inner().await.unwrap();
+        let mut gzipped_bytes = Vec::new();
+        let mut encoder = GzipEncoder::new(BufReader::new(bytes.as_slice()));
+        encoder.read_to_end(&mut gzipped_bytes).await.unwrap();
+        Ok(Response::builder().body(gzipped_bytes.into())?)
+    });
+
+    let extension_store = cx.new_model(|cx| {
+        ExtensionStore::new(
+            extensions_dir.clone(),
+            None,
+            Arc::new(RealFs::default()),
+            http_client,
+            RetryPolicy::default(),
+            None,
+            FakeNodeRuntime::new(),
+            Arc::new(LanguageRegistry::test(cx.background_executor().clone())),
+            Arc::new(ThemeRegistry::new(Box::new(()))),
+            SlashCommandRegistry::new(),
+            Arc::new(IndexedDocsRegistry::new(cx.background_executor().clone())),
+            Arc::new(SnippetRegistry::new()),
+            cx,
+        )
+    });
+
+    // Ensure that debounces fire.
+    let mut events = cx.events(&extension_store);
+    let executor = cx.executor();
+    let _task = cx.executor().spawn(async move {
+        while let Some(event) = events.next().await {
+            if let Event::StartedReloading = event {
+                executor.advance_clock(RELOAD_DEBOUNCE_DURATION);
+            }
+        }
+    });
+
+    let installed_at = |cx: &mut TestAppContext| {
+        extension_store.read_with(cx, |store, _| {
+            store.installed_extension_entries()["zed-ruby"].installed_at
+        })
+    };
+
+    let before_install = SystemTime::now() - Duration::from_secs(1);
+    extension_store
+        .update(cx, |store, cx| {
+            store.install_or_upgrade_extension(
+                "zed-ruby".into(),
+                "1.0.0".into(),
+                ExtensionOperation::Install,
+                cx,
+            )
+        })
+        .await
+        .unwrap();
+    let first_installed_at = installed_at(cx).expect("install time should be recorded");
+    assert!(first_installed_at >= before_install);
+    assert!(first_installed_at <= SystemTime::now());
+
+    // The install time is read back from disk when the index is rebuilt.
+    extension_store
+        .update(cx, |store, cx| store.reload(None, cx))
+        .await;
+    assert_eq!(installed_at(cx), Some(first_installed_at));
+
+    // Upgrading an extension keeps the time at which it was first installed.
+    let installed_at_path = extensions_dir.join("installed/zed-ruby/installed_at");
+    std::fs::write(&installed_at_path, "1000").unwrap();
+    extension_store
+        .update(cx, |store, cx| {
+            store.install_or_upgrade_extension(
+                "zed-ruby".into(),
+                "1.1.0".into(),
+                ExtensionOperation::Upgrade,
+                cx,
+            )
+        })
+        .await
+        .unwrap();
+    extension_store.read_with(cx, |store, _| {
+        assert_eq!(
+            store.installed_extension_entries()["zed-ruby"]
+                .manifest
+                .version
+                .as_ref(),
+            "1.1.0"
+        );
+    });
+    assert_eq!(
+        installed_at(cx),
+        Some(SystemTime::UNIX_EPOCH + Duration::from_secs(1000))
+    );
+}
+
+#[gpui::test]
+async fn test_failed_upgrade_keeps_installed_version(cx: &mut TestAppContext) {
+    init_test(cx);
+    cx.executor().allow_parking();
+
+    let extensions_dir = temp_tree(json!({
+        "installed": {},
+        "work": {}
+    }));
+    let extensions_dir = extensions_dir.path().canonicalize().unwrap();
+
+    let http_client = FakeHttpClient::create(|request| async move {
+        let manifest = |version: &str| {
+            format!(r#"{{"id": "zed-ruby", "name": "Zed Ruby", "version": "{version}"}}"#)
+        };
+        let (file_name, content) = match request.uri().path() {
+            "/extensions/zed-ruby/1.0.0/download" => ("extension.json", manifest("1.0.0")),
+            "/extensions/zed-ruby/1.1.0/download" => ("extension.json", manifest("1.1.0")),
+            // An archive without a manifest.
+            "/extensions/zed-ruby/2.0.0/download" => ("README.md", "# Zed Ruby".to_string()),
+            // An archive that can't be extracted.
+            "/extensions/zed-ruby/3.0.0/download" => {
+                return Ok(Response::builder().body(b"\x1f\x8bnot gzip".to_vec().into())?)
+            }
+            _ => return Ok(Response::builder().status(404).body("not found".into())?),
+        };
+
+        let mut bytes = Vec::<u8>::new();
+        let mut archive = async_tar::Builder::new(&mut bytes);
+        let mut header = async_tar::Header::new_gnu();
+        header.set_size(content.len() as u64);
+        header.set_mode(0o644);
+        archive
+            .append_data(&mut header, file_name, content.as_bytes())
+            .await
+            .unwrap();
+        archive.into_inner().await.unwrap();
+        let mut gzipped_bytes = Vec::new();
+        let mut encoder = GzipEncoder::new(BufReader::new(bytes.as_slice()));
+        encoder.read_to_end(&mut gzipped_bytes).await.unwrap();
+        Ok(Response::builder().body(gzipped_bytes.into())?)
+    });
+
+    let extension_store = cx.new_model(|cx| {
+        ExtensionStore::new(
+            extensions_dir.clone(),
+            None,
+            Arc::new(RealFs::default()),
+            http_client,
+            RetryPolicy::default(),
+            None,
+            FakeNodeRuntime::new(),
+            Arc::new(LanguageRegistry::test(cx.background_executor().clone())),
+            Arc::new(ThemeRegistry::new(Box::new(()))),
+            SlashCommandRegistry::new(),
+            Arc::new(IndexedDocsRegistry::new(cx.background_executor().clone())),
+            Arc::new(SnippetRegistry::new()),
+            cx,
+        )
+    });
+
+    // Ensure that debounces fire.
+    let upgrades = Arc::new(Mutex::new(Vec::new()));
+    let mut events = cx.events(&extension_store);
+    let executor = cx.executor();
+    let _task = cx.executor().spawn({
+        let upgrades = upgrades.clone();
+        async move {
+            while let Some(event) = events.next().await {
+                match event {
+                    Event::StartedReloading => executor.advance_clock(RELOAD_DEBOUNCE_DURATION),
+                    Event::ExtensionUpgraded { from, to, .. } => upgrades.lock().push((from, to)),
+                    _ => {}
+                }
+            }
+        }
+    });
+
+    let install = |version: &'static str, operation, cx: &mut TestAppContext| {
+        extension_store.update(cx, |store, cx| {
+            store.install_or_upgrade_extension("zed-ruby".into(), version.into(), operation, cx)
+        })
+    };
+    let installed_version = |cx: &mut TestAppContext| {
+        let manifest =
+            std::fs::read_to_string(extensions_dir.join("installed/zed-ruby/extension.json"))
+                .unwrap();
+        let index_version = extension_store.read_with(cx, |store, _| {
+            store.installed_extension_entries()["zed-ruby"]
+                .manifest
+                .version
+                .clone()
+        });
+        (manifest, index_version)
+    };
+
+    install("1.0.0", ExtensionOperation::Install, cx)
+        .await
+        .unwrap();
+    let (manifest, version) = installed_version(cx);
+    assert!(manifest.contains(r#""version": "1.0.0""#));
+    assert_eq!(version.as_ref(), "1.0.0");
+
+    // Upgrades that fail to download or validate leave the installed version as it was.
+    for version in ["2.0.0", "3.0.0"] {
+        install(version, ExtensionOperation::Upgrade, cx)
+            .await
+            .unwrap_err();
+        let (manifest, installed_version) = installed_version(cx);
+        assert!(
+            manifest.contains(r#""version": "1.0.0""#),
+            "after failing to upgrade to {version}"
+        );
+        assert_eq!(installed_version.as_ref(), "1.0.0");
+    }
+    assert!(upgrades.lock().is_empty());
+
+    install("1.1.0", ExtensionOperation::Upgrade, cx)
+        .await
+        .unwrap();
+    let (manifest, version) = installed_version(cx);
+    assert!(manifest.contains(r#""version": "1.1.0""#));
+    assert_eq!(version.as_ref(), "1.1.0");
+    assert_eq!(
+        *upgrades.lock(),
+        [(Arc::<str>::from("1.0.0"), Arc::<str>::from("1.1.0"))]
+    );
+    assert!(!extensions_dir.join("work/.staging/zed-ruby").exists());
+    assert!(!extensions_dir.join("work/.replaced/zed-ruby").exists());
+}
+
+#[gpui::test]
+async fn test_lockfile_records_resolved_versions(cx: &mut TestAppContext) {
+    init_test(cx);
+    cx.executor().allow_parking();
+
+    let extensions_dir = temp_tree(json!({
+        "installed": {},
+        "work": {}
+    }));
+    let extensions_dir = extensions_dir.path().canonicalize().unwrap();
+    let lockfile_path = extensions_dir.join("extensions.lock");
+    cx.update(|cx| {
+        SettingsStore::update_global(cx, |store, cx| {
+            store.update_user_settings::<ExtensionSettings>(cx, |settings| {
+                settings.extension_lockfile_path = Some(lockfile_path.clone());
+            });
+        });
+    });
+
+    let http_client = FakeHttpClient::create(|request| async move {
+        let version = match request.uri().path() {
+            "/extensions/zed-ruby/1.0.0/download" => "1.0.0",
+            "/extensions/zed-ruby/1.1.0/download" => "1.1.0",
+            _ => return Ok(Response::builder().status(404).body("not found".into())?),
+        };
+        let content =
+            format!(r#"{{"id": "zed-ruby", "name": "Zed Ruby", "version": "{version}"}}"#);
+
+        let mut bytes = Vec::<u8>::new();
+        let mut archive = async_tar::Builder::new(&mut bytes);
+        let mut header = async_tar::Header::new_gnu();
+        header.set_size(content.len() as u64);
+        header.set_mode(0o644);
+        archive
+            .append_data(&mut header, "extension.json", content.as_bytes())
+            .await
+            .unwrap();
+        archive.into_inner().await.unwrap();
+        let mut gzipped_bytes = Vec::new();
+        let mut encoder = GzipEncoder::new(BufReader::new(bytes.as_slice()));
+        encoder.read_to_end(&mut gzipped_bytes).await.unwrap();
+        Ok(Response::builder().body(gzipped_bytes.into())?)
+    });
+
+    let new_store = |cx: &mut TestAppContext| {
+        let extension_store = cx.new_model(|cx| {
+            ExtensionStore::new(
+                extensions_dir.clone(),
+                None,
+                Arc::new(RealFs::default()),
+                http_client.clone(),
+                RetryPolicy::default(),
+                None,
+                FakeNodeRuntime::new(),
+                Arc::new(LanguageRegistry::test(cx.background_executor().clone())),
+                Arc::new(ThemeRegistry::new(Box::new(()))),
+                SlashCommandRegistry::new(),
+                Arc::new(IndexedDocsRegistry::new(cx.background_executor().clone())),
+                Arc::new(SnippetRegistry::new()),
+                cx,
+            )
+        });
+
+        // Ensure that debounces fire.
+        let mut events = cx.events(&extension_store);
+        let executor = cx.executor();
+        cx.executor()
+            .spawn(async move {
+                while let Some(event) = events.next().await {
+                    if let Event::StartedReloading = event {
+                        executor.advance_clock(RELOAD_DEBOUNCE_DURATION);
+                    }
+                }
+            })
+            .detach();
+        extension_store
+    };
+    let read_lockfile = |extension_store: &Model<ExtensionStore>, cx: &mut TestAppContext| {
+        let write = extension_store.update(cx, |store, cx| store.write_lockfile(cx));
+        let lockfile_path = lockfile_path.clone();
+        async move {
+            write.await.unwrap();
+            let content = std::fs::read_to_string(&lockfile_path).unwrap();
+            serde_json::from_str::<ExtensionLockfile>(&content).unwrap()
+        }
+    };
+
+    let extension_store = new_store(cx);
+    extension_store
+        .update(cx, |store, cx| {
+            store.install_or_upgrade_extension(
+                "zed-ruby".into(),
+                "1.0.0".into(),
+                ExtensionOperation::Install,
+                cx,
+            )
+        })
+        .await
+        .unwrap();
+
+    // Resolve the language server's versions as the extension would when it
+    // starts the server.
+    let wasm_host = extension_store.read_with(cx, |store, _| store.wasm_host.clone());
+    let extension_id = Arc::<str>::from("zed-ruby");
+    wasm_host.record_resolved_version(&extension_id, "npm:ruby-lsp".into(), "0.17.4".into());
+    wasm_host.record_resolved_version(
+        &extension_id,
+        "github:rubocop/rubocop".into(),
+        "v1.65.0".into(),
+    );
+    cx.executor().run_until_parked();
+
+    let lockfile = read_lockfile(&extension_store, cx).await;
+    assert_eq!(
+        lockfile.extensions,
+        [(extension_id.clone(), Arc::<str>::from("1.0.0"))]
+            .into_iter()
+            .collect()
+    );
+    assert_eq!(
+        lockfile.resolved_version("zed-ruby", "npm:ruby-lsp"),
+        Some("0.17.4")
+    );
+    assert_eq!(
+        lockfile.resolved_version("zed-ruby", "github:rubocop/rubocop"),
+        Some("v1.65.0")
+    );
+
+    // Another store with the same lockfile uses the locked versions.
+    let other_store = new_store(cx);
+    let other_wasm_host = other_store.read_with(cx, |store, _| store.wasm_host.clone());
+    assert_eq!(
+        other_wasm_host.locked_version("zed-ruby", "npm:ruby-lsp"),
+        Some("0.17.4".to_string())
+    );
+    assert_eq!(
+        other_wasm_host.locked_version("zed-ruby", "npm:other"),
+        None
+    );
+    drop(other_store);
+
+    // Upgrading the extension forgets the versions that it resolved.
+    extension_store
+        .update(cx, |store, cx| {
+            store.upgrade_extension("zed-ruby".into(), "1.1.0".into(), cx)
+        })
+        .await
+        .unwrap();
+    let lockfile = read_lockfile(&extension_store, cx).await;
+    assert_eq!(
+        lockfile.extensions,
+        [(extension_id.clone(), Arc::<str>::from("1.1.0"))]
+            .into_iter()
+            .collect()
+    );
+    assert!(lockfile.resolved_versions.is_empty());
+    assert_eq!(wasm_host.locked_version("zed-ruby", "npm:ruby-lsp"), None);
+}
+
+#[gpui::test]
+async fn test_interrupted_installs_are_cleaned_up(cx: &mut TestAppContext) {
+    init_test(cx);
+
+    let mut old_gruvbox = gruvbox_extension();
+    old_gruvbox["extension.json"] =
+        r#"{"id": "zed-gruvbox", "name": "Zed Gruvbox", "version": "0.9.0"}"#.into();
+
+    let TestExtensionStore {
+        fs,
+        store,
+        theme_registry,
+        ..
+    } = init_test_extension_store(
+        json!({
+            "installed": {
+                "zed-gruvbox": gruvbox_extension(),
+            },
+            "work": {
+                // An install that was interrupted while it was extracting.
+                ".staging": {
+                    "zed-ruby": {
+                        "extension.json": r#"{"id": "zed-ru"#,
+                    },
+                },
+                ".replaced": {
+                    // An upgrade that was interrupted after the new version was
+                    // moved into place.
+                    "zed-gruvbox": old_gruvbox,
+                    // An upgrade that was interrupted before the new version was
+                    // moved into place.
+                    "zed-monokai": monokai_extension(),
+                },
+            },
+        }),
+        cx,
+    )
+    .await;
+
+    store.read_with(cx, |store, _| {
+        let installed = store.installed_extension_entries();
+        assert_eq!(
+            installed.keys().map(AsRef::as_ref).collect::<Vec<_>>(),
+            ["zed-gruvbox", "zed-monokai"]
+        );
+        assert_eq!(installed["zed-gruvbox"].manifest.version.as_ref(), "1.0.0");
+    });
+    assert_eq!(
+        theme_registry.list_names(false),
+        ["Gruvbox", "Monokai Dark", "Monokai Light", "One Dark"]
+    );
+    assert!(
+        !fs.is_dir(Path::new("/the-extension-dir/work/.staging"))
+            .await
+    );
+    assert!(
+        !fs.is_dir(Path::new("/the-extension-dir/work/.replaced"))
+            .await
+    );
+}
+
+#[gpui::test]
+async fn test_install_retries_transient_failures(cx: &mut TestAppContext) {
+    init_test(cx);
+    cx.executor().allow_parking();
+
+    let extensions_dir = temp_tree(json!({
+        "installed": {},
+        "work": {}
+    }));
+    let extensions_dir = extensions_dir.path().canonicalize().unwrap();
+
+    let flaky_attempts = Arc::new(AtomicUsize::new(0));
+    let http_client = FakeHttpClient::create({
+        let flaky_attempts = flaky_attempts.clone();
+        move |request| {
+            let flaky_attempts = flaky_attempts.clone();
+            async move {
+                if request.uri().path() != "/extensions/zed-flaky/1.0.0/download" {
+                    return Ok(Response::builder().status(404).body("not found".into())?);
+                }
+                if flaky_attempts.fetch_add(1, SeqCst) < 2 {
+                    return Ok(Response::builder().status(503).body("unavailable".into())?);
+                }
+
+                let manifest = r#"{"id": "zed-flaky", "name": "Zed Flaky", "version": "1.0.0"}"#;
+                let mut bytes = Vec::<u8>::new();
+                let mut archive = async_tar::Builder::new(&mut bytes);
+                let mut header = async_tar::Header::new_gnu();
+                header.set_size(manifest.len() as u64);
+                header.set_mode(0o644);
+                archive
+                    .append_data(&mut header, "extension.json", manifest.as_bytes())
+                    .await
+                    .unwrap();
+                archive.into_inner().await.unwrap();
+                let mut gzipped_bytes = Vec::new();
+                let mut encoder = GzipEncoder::new(BufReader::new(bytes.as_slice()));
+                encoder.read_to_end(&mut gzipped_bytes).await.unwrap();
+                Ok(Response::builder().body(gzipped_bytes.into())?)
+            }
+        }
+    });
+
+    let retry_policy = RetryPolicy {
+        max_retries: 3,
+        initial_delay: Duration::ZERO,
+        max_delay: Duration::ZERO,
+    };
+    let extension_store = cx.new_model(|cx| {
+        ExtensionStore::new(
+            extensions_dir.clone(),
+            None,
+            Arc::new(RealFs::default()),
+            http_client,
+            retry_policy,
+            None,
+            FakeNodeRuntime::new(),
+            Arc::new(LanguageRegistry::test(cx.background_executor().clone())),
+            Arc::new(ThemeRegistry::new(Box::new(()))),
+            SlashCommandRegistry::new(),
+            Arc::new(IndexedDocsRegistry::new(cx.background_executor().clone())),
+            Arc::new(SnippetRegistry::new()),
+            cx,
+        )
+    });
+
+    // Ensure that debounces fire.
+    let mut events = cx.events(&extension_store);
+    let executor = cx.executor();
+    let _task = cx.executor().spawn(async move {
+        while let Some(event) = events.next().await {
+            if let Event::StartedReloading = event {
+                executor.advance_clock(RELOAD_DEBOUNCE_DURATION);
+            }
+        }
+    });
+
+    // The download is retried after the server is unavailable.
+    extension_store
+        .update(cx, |store, cx| {
+            store.install_or_upgrade_extension(
+                "zed-flaky".into(),
+                "1.0.0".into(),
+                ExtensionOperation::Install,
+                cx,
+            )
+        })
+        .await
+        .unwrap();
+    assert_eq!(flaky_attempts.load(SeqCst), 3);
+    extension_store.read_with(cx, |store, _| {
+        assert!(store
+            .installed_extension_entries()
+            .contains_key("zed-flaky"));
+        assert_eq!(store.http_request_count(), 3);
+    });
+
+    // Client errors are not retried.
+    let result = extension_store
+        .update(cx, |store, cx| {
+            store.install_or_upgrade_extension(
+                "zed-missing".into(),
+                "1.0.0".into(),
+                ExtensionOperation::Install,
+                cx,
+            )
+        })
+        .await;
+    assert!(result.is_err());
+    extension_store.read_with(cx, |store, _| {
+        assert!(!store
+            .installed_extension_entries()
+            .contains_key("zed-missing"));
+        assert_eq!(store.http_request_count(), 4);
+    });
+}
+
+#[gpui::test]
+async fn test_installed_extensions_share_identical_grammars(cx: &mut TestAppContext) {
+    init_test(cx);
+    cx.executor().allow_parking();
+
+    let extensions_dir = temp_tree(json!({
+        "installed": {},
+        "work": {}
+    }));
+    let extensions_dir = extensions_dir.path().canonicalize().unwrap();
+
+    const GRAMMAR_WASM: &[u8] = b"\0asm\x01\0\0\0";
+    let http_client = FakeHttpClient::create(|request| async move {
+        let (extension_id, grammar_name) = match request.uri().path() {
+            "/extensions/zed-ruby/1.0.0/download" => ("zed-ruby", "ruby"),
+            "/extensions/zed-crystal/1.0.0/download" => ("zed-crystal", "crystal"),
+            _ => return Ok(Response::builder().status(404).body("not found".into())?),
+        };
+
+        let manifest = format!(
+            r#"
+                id = "{extension_id}"
+                name = "{extension_id}"
+                version = "1.0.0"
+                schema_version = 1
+
+                [grammars.{grammar_name}]
+                repository = "https://github.com/tree-sitter/tree-sitter-{grammar_name}"
+                rev = "main"
+            "#
+        );
+        let mut bytes = Vec::<u8>::new();
+        let mut archive = async_tar::Builder::new(&mut bytes);
+        for (path, content) in [
+            ("extension.toml".to_string(), manifest.as_bytes()),
+            (format!("grammars/{grammar_name}.wasm"), GRAMMAR_WASM),
+        ] {
+            let mut header = async_tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            archive
+                .append_data(&mut header, path, content)
+                .await
+                .unwrap();
+        }
+        archive.into_inner().await.unwrap();
+        let mut gzipped_bytes = Vec::new();
+        let mut encoder = GzipEncoder::new(BufReader::new(bytes.as_slice()));
+        encoder.read_to_end(&mut gzipped_bytes).await.unwrap();
+
+        Ok(Response::builder().body(gzipped_bytes.into())?)
+    });
+
+    let language_registry = Arc::new(LanguageRegistry::test(cx.executor()));
+    let extension_store = cx.new_model(|cx| {
+        ExtensionStore::new(
+            extensions_dir.clone(),
+            None,
+            Arc::new(RealFs::default()),
+            http_client,
+            RetryPolicy::default(),
+            None,
+            FakeNodeRuntime::new(),
+            language_registry.clone(),
+            Arc::new(ThemeRegistry::new(Box::new(()))),
+            SlashCommandRegistry::new(),
+            Arc::new(IndexedDocsRegistry::new(cx.background_executor().clone())),
+            Arc::new(SnippetRegistry::new()),
+            cx,
+        )
+    });
+
+    // Ensure that debounces fire.
+    let mut events = cx.events(&extension_store);
+    let executor = cx.executor();
+    let _task = cx.executor().spawn(async move {
+        while let Some(event) = events.next().await {
+            if let Event::StartedReloading = event {
+                executor.advance_clock(RELOAD_DEBOUNCE_DURATION);
+            }
+        }
+    });
+
+    for extension_id in ["zed-ruby", "zed-crystal"] {
+        extension_store
+            .update(cx, |store, cx| {
+                store.install_or_upgrade_extension(
+                    extension_id.into(),
+                    "1.0.0".into(),
+                    ExtensionOperation::Install,
+                    cx,
+                )
+            })
+            .await
+            .unwrap();
+    }
+
+    // Only a single copy of the grammar is kept on disk.
+    let stored_grammars = std::fs::read_dir(extensions_dir.join("grammars"))
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect::<Vec<_>>();
+    assert_eq!(stored_grammars.len(), 1);
+    assert_eq!(std::fs::read(&stored_grammars[0]).unwrap(), GRAMMAR_WASM);
+    for (extension_id, grammar_name) in [("zed-ruby", "ruby"), ("zed-crystal", "crystal")] {
+        let grammars_dir = extensions_dir
+            .join("installed")
+            .join(extension_id)
+            .join("grammars");
+        assert!(!grammars_dir.join(format!("{grammar_name}.wasm")).exists());
+    }
+
+    extension_store.read_with(cx, |store, _| {
+        let extensions = store.installed_extension_entries();
+        let ruby_hash = &extensions["zed-ruby"].grammar_hashes["ruby"];
+        let crystal_hash = &extensions["zed-crystal"].grammar_hashes["crystal"];
+        assert_eq!(ruby_hash, crystal_hash);
+        assert_eq!(
+            stored_grammars[0],
+            extensions_dir
+                .join("grammars")
+                .join(format!("{ruby_hash}.wasm"))
+        );
+    });
+    assert_eq!(
+        language_registry.grammar_names(),
+        ["crystal".into(), "ruby".into()]
+    );
+
+    // Both grammars are read from the grammar store. The stored module doesn't
+    // define a language, so loading it fails after it has been read.
+    let mut grammar_load_failures = language_registry.grammar_load_failures();
+    language_registry
+        .load_wasm_grammars(vec!["crystal".into(), "ruby".into()], 1)
+        .await;
+    for _ in 0..2 {
+        let failure = grammar_load_failures.next().await.unwrap();
+        assert_eq!(failure.wasm_path, stored_grammars[0]);
+        assert!(failure.error.downcast_ref::<std::io::Error>().is_none());
+    }
+}
+
+#[gpui::test]
+async fn test_invalid_grammar_hashes_are_ignored(cx: &mut TestAppContext) {
+    init_test(cx);
+
+    let TestExtensionStore { store, .. } = init_test_extension_store(
+        json!({
+            "installed": {
+                "zed-ruby": {
+                    "extension.toml": r#"
+                        id = "zed-ruby"
+                        name = "Zed Ruby"
+                        version = "1.0.0"
+                        schema_version = 1
+
+                        [grammars.ruby]
+                        repository = "https://github.com/tree-sitter/tree-sitter-ruby"
+                        rev = "main"
+                    "#,
+                    "grammars": {
+                        "ruby.sha256": "../../../etc/passwd",
+                        "ruby.wasm": "",
+                    },
+                },
+            },
+        }),
+        cx,
+    )
+    .await;
+
+    // The grammar is loaded from the extension's directory instead of the
+    // grammar store.
+    store.read_with(cx, |store, _| {
+        let extension = &store.installed_extension_entries()["zed-ruby"];
+        assert!(extension.grammar_hashes.is_empty());
+        assert_eq!(
+            store.grammar_path(extension, &"ruby".into()),
+            PathBuf::from("/the-extension-dir/installed/zed-ruby/grammars/ruby.wasm")
+        );
+    });
+}
+
+#[gpui::test]
+async fn test_upgrading_extension_removes_unreferenced_grammars(cx: &mut TestAppContext) {
+    init_test(cx);
+    cx.executor().allow_parking();
+
+    let extensions_dir = temp_tree(json!({
+        "installed": {},
+        "work": {}
+    }));
+    let extensions_dir = extensions_dir.path().canonicalize().unwrap();
+
+    let http_client = FakeHttpClient::create(|request| async move {
+        // Each version of the Ruby extension ships a different grammar, the
+        // first of which is identical to the Crystal extension's grammar.
+        let (extension_id, grammar_name, version, grammar_wasm) = match request.uri().path() {
+            "/extensions/zed-ruby/1.0.0/download" => ("zed-ruby", "ruby", "1.0.0", b"\0asm\x01"),
+            "/extensions/zed-ruby/2.0.0/download" => ("zed-ruby", "ruby", "2.0.0", b"\0asm\x02"),
+            "/extensions/zed-ruby/3.0.0/download" => ("zed-ruby", "ruby", "3.0.0", b"\0asm\x03"),
+            "/extensions/zed-crystal/1.0.0/download" => {
+                ("zed-crystal", "crystal", "1.0.0", b"\0asm\x01")
+            }
+            _ => return Ok(Response::builder().status(404).body("not found".into())?),
+        };
+
+        let manifest = format!(
+            r#"
+                id = "{extension_id}"
+                name = "{extension_id}"
+                version = "{version}"
+                schema_version = 1
+
+                [grammars.{grammar_name}]
+                repository = "https://github.com/tree-sitter/tree-sitter-{grammar_name}"
+                rev = "main"
+            "#
+        );
+        let mut bytes = Vec::<u8>::new();
+        let mut archive = async_tar::Builder::new(&mut bytes);
+        for (path, content) in [
+            ("extension.toml".to_string(), manifest.as_bytes()),
+            (
+                format!("grammars/{grammar_name}.wasm"),
+                grammar_wasm.as_slice(),
+            ),
+        ] {
+            let mut header = async_tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            archive
+                .append_data(&mut header, path, content)
+                .await
+                .unwrap();
+        }
+        archive.into_inner().await.unwrap();
+        let mut gzipped_bytes = Vec::new();
+        let mut encoder = GzipEncoder::new(BufReader::new(bytes.as_slice()));
+        encoder.read_to_end(&mut gzipped_bytes).await.unwrap();
+
+        Ok(Response::builder().body(gzipped_bytes.into())?)
+    });
+
+    let extension_store = cx.new_model(|cx| {
+        ExtensionStore::new(
+            extensions_dir.clone(),
+            None,
+            Arc::new(RealFs::default()),
+            http_client,
+            RetryPolicy::default(),
+            None,
+            FakeNodeRuntime::new(),
+            Arc::new(LanguageRegistry::test(cx.executor())),
+            Arc::new(ThemeRegistry::new(Box::new(()))),
+            SlashCommandRegistry::new(),
+            Arc::new(IndexedDocsRegistry::new(cx.background_executor().clone())),
+            Arc::new(SnippetRegistry::new()),
+            cx,
+        )
+    });
+
+    // Ensure that debounces fire.
+    let mut events = cx.events(&extension_store);
+    let executor = cx.executor();
+    let _task = cx.executor().spawn(async move {
+        while let Some(event) = events.next().await {
+            if let Event::StartedReloading = event {
+                executor.advance_clock(RELOAD_DEBOUNCE_DURATION);
+            }
+        }
+    });
+
+    let stored_grammars = || {
+        let mut paths = std::fs::read_dir(extensions_dir.join("grammars"))
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect::<Vec<_>>();
+        paths.sort();
+        paths
+    };
+    let ruby_grammar = |cx: &mut TestAppContext| {
+        extension_store.read_with(cx, |store, _| {
+            let hash = &store.installed_extension_entries()["zed-ruby"].grammar_hashes["ruby"];
+            extensions_dir.join("grammars").join(format!("{hash}.wasm"))
+        })
+    };
+
+    for (extension_id, version, operation) in [
+        ("zed-ruby", "1.0.0", ExtensionOperation::Install),
+        ("zed-crystal", "1.0.0", ExtensionOperation::Install),
+    ] {
+        extension_store
+            .update(cx, |store, cx| {
+                store.install_or_upgrade_extension(
+                    extension_id.into(),
+                    version.into(),
+                    operation,
+                    cx,
+                )
+            })
+            .await
+            .unwrap();
+    }
+    let shared_grammar = ruby_grammar(cx);
+    assert_eq!(stored_grammars(), [shared_grammar.clone()]);
+
+    let upgrade_ruby = |version: &'static str, cx: &mut TestAppContext| {
+        extension_store.update(cx, |store, cx| {
+            store.install_or_upgrade_extension(
+                "zed-ruby".into(),
+                version.into(),
+                ExtensionOperation::Upgrade,
+                cx,
+            )
+        })
+    };
+
+    // The replaced grammar is kept while another extension ships it.
+    upgrade_ruby("2.0.0", cx).await.unwrap();
+    let replaced_grammar = ruby_grammar(cx);
+    let mut expected_grammars = vec![shared_grammar.clone(), replaced_grammar.clone()];
+    expected_grammars.sort();
+    assert_eq!(stored_grammars(), expected_grammars);
+
+    // Once no extension ships it, it is removed from the grammar store.
+    upgrade_ruby("3.0.0", cx).await.unwrap();
+    let mut expected_grammars = vec![shared_grammar, ruby_grammar(cx)];
+    expected_grammars.sort();
+    assert_eq!(stored_grammars(), expected_grammars);
+    assert!(!replaced_grammar.exists());
+}
+
+#[gpui::test]
+async fn test_uninstall_extension_removes_unshared_grammars(cx: &mut TestAppContext) {
+    init_test(cx);
+
+    let TestExtensionStore { fs, store, .. } = init_test_extension_store(
+        json!({
+            "installed": {
+                "zed-ruby": {
+                    "extension.toml": r#"
+                        id = "zed-ruby"
+                        name = "Zed Ruby"
+                        version = "1.0.0"
+                        schema_version = 1
+
+                        [grammars.ruby]
+                        repository = "https://github.com/tree-sitter/tree-sitter-ruby"
+                        rev = "main"
+
+                        [grammars.embedded_template]
+                        repository = "https://github.com/tree-sitter/tree-sitter-embedded-template"
+                        rev = "main"
+                    "#,
+                    "grammars": {
+                        "ruby.sha256": "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa",
+                        "embedded_template.sha256": "bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb",
+                    },
+                },
+                "zed-crystal": {
+                    "extension.toml": r#"
+                        id = "zed-crystal"
+                        name = "Zed Crystal"
+                        version = "1.0.0"
+                        schema_version = 1
+
+                        [grammars.crystal]
+                        repository = "https://github.com/tree-sitter/tree-sitter-crystal"
+                        rev = "main"
+                    "#,
+                    "grammars": {
+                        "crystal.sha256": "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa",
+                    },
+                },
+            },
+            "grammars": {
+                "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa.wasm": "",
+                "bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb.wasm": "",
+            },
+        }),
+        cx,
+    )
+    .await;
+
+    let stored_grammars = || {
+        fs.files()
+            .into_iter()
+            .filter(|path| path.starts_with("/the-extension-dir/grammars"))
+            .collect::<Vec<_>>()
+    };
+    store.read_with(cx, |store, _| {
+        let grammar_hashes = &store.installed_extension_entries()["zed-ruby"].grammar_hashes;
+        assert_eq!(
+            grammar_hashes["ruby"].as_ref(),
+            "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa"
+        );
+        assert_eq!(
+            grammar_hashes["embedded_template"].as_ref(),
+            "bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb"
+        );
+    });
+
+    store.update(cx, |store, cx| {
+        store
+            .uninstall_extension("zed-ruby".into(), UninstallOptions::default(), cx)
+            .unwrap()
+    });
+    cx.executor().advance_clock(RELOAD_DEBOUNCE_DURATION);
+    cx.executor().run_until_parked();
+
+    // The grammar that is still used by another extension is kept.
+    assert_eq!(
+        stored_grammars(),
+        [PathBuf::from("/the-extension-dir/grammars/aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa.wasm")]
+    );
+
+    store.update(cx, |store, cx| {
+        store
+            .uninstall_extension("zed-crystal".into(), UninstallOptions::default(), cx)
+            .unwrap()
+    });
+    cx.executor().advance_clock(RELOAD_DEBOUNCE_DURATION);
+    cx.executor().run_until_parked();
+
+    assert!(stored_grammars().is_empty());
+}
+
+#[gpui::test]
+async fn test_extension_grammar_from_oci_registry(cx: &mut TestAppContext) {
+    init_test(cx);
+    cx.executor().allow_parking();
+
+    let extensions_dir = temp_tree(json!({
+        "installed": {
+            "zed-foo": {
+                "extension.toml": r#"
+                    id = "zed-foo"
+                    name = "Zed Foo"
+                    version = "1.0.0"
+                    schema_version = 1
+
+                    [grammars.foo]
+                    oci = "ghcr.io/zed-extensions/tree-sitter-foo:1.0.0"
+                "#,
+            }
+        },
+        "work": {}
+    }));
+    let extensions_dir = extensions_dir.path().canonicalize().unwrap();
+    let extension_dir = extensions_dir.join("installed").join("zed-foo");
+
+    let wasm_bytes = grammar_wasm("foo", true);
+    let layer_digest = format!("sha256:{:x}", sha2::Sha256::digest(&wasm_bytes));
+    let http_client = FakeHttpClient::create({
+        let wasm_bytes = wasm_bytes.clone();
+        move |request| {
+            let layer_digest = layer_digest.clone();
+            let wasm_bytes = wasm_bytes.clone();
+            async move {
+                let authorized = request
+                    .headers()
+                    .get("authorization")
+                    .map_or(false, |value| value == "Bearer the-token");
+                let path = request.uri().path();
+                let body = if path == "/token" {
+                    json!({ "token": "the-token" }).to_string().into_bytes()
+                } else if !authorized {
+                    return Ok(Response::builder()
+                    .status(401)
+                    .header(
+                        "www-authenticate",
+                        r#"Bearer realm="https://ghcr.io/token",service="ghcr.io",scope="repository:zed-extensions/tree-sitter-foo:pull""#,
+                    )
+                    .body(Default::default())?);
+                } else if path == "/v2/zed-extensions/tree-sitter-foo/manifests/1.0.0" {
+                    json!({
+                        "schemaVersion": 2,
+                        "mediaType": "application/vnd.oci.image.manifest.v1+json",
+                        "layers": [
+                            {
+                                "mediaType": "application/wasm",
+                                "digest": layer_digest,
+                                "size": wasm_bytes.len(),
+                            }
+                        ]
+                    })
+                    .to_string()
+                    .into_bytes()
+                } else if path == format!("/v2/zed-extensions/tree-sitter-foo/blobs/{layer_digest}")
+                {
+                    wasm_bytes
+                } else {
+                    return Ok(Response::builder().status(404).body("not found".into())?);
+                };
+                Ok(Response::builder().body(body.into())?)
+            }
+        }
+    });
+
+    let fs = Arc::new(RealFs::default());
+    let builder = ExtensionBuilder::new(http_client.clone(), extensions_dir.join("build"));
+    let mut manifest = ExtensionManifest::load(fs.clone(), &extension_dir)
+        .await
+        .unwrap();
+    builder
+        .compile_extension(
+            &extension_dir,
+            &mut manifest,
+            CompileExtensionOptions { release: false },
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        std::fs::read(extension_dir.join("grammars").join("foo.wasm")).unwrap(),
+        wasm_bytes
+    );
+
+    let language_registry = Arc::new(LanguageRegistry::test(cx.executor()));
+    let extension_store = cx.new_model(|cx| {
+        ExtensionStore::new(
+            extensions_dir.clone(),
+            None,
+            fs,
+            http_client,
+            RetryPolicy::default(),
+            None,
+            FakeNodeRuntime::new(),
+            language_registry.clone(),
+            Arc::new(ThemeRegistry::new(Box::new(()))),
+            SlashCommandRegistry::new(),
+            Arc::new(IndexedDocsRegistry::new(cx.background_executor().clone())),
+            Arc::new(SnippetRegistry::new()),
+            cx,
+        )
+    });
+
+    // Ensure that debounces fire.
+    let mut events = cx.events(&extension_store);
+    let executor = cx.executor();
+    let _task = cx.executor().spawn(async move {
+        while let Some(event) = events.next().await {
+            if let Event::StartedReloading = event {
+                executor.advance_clock(RELOAD_DEBOUNCE_DURATION);
+            }
+        }
+    });
+
+    extension_store
+        .update(cx, |store, cx| store.reload(None, cx))
+        .await;
+
+    assert_eq!(language_registry.grammar_names(), ["foo".into()]);
+}
+
+#[gpui::test]
+async fn test_extension_grammar_checksum(cx: &mut TestAppContext) {
+    init_test(cx);
+    cx.executor().allow_parking();
+
+    let wasm_bytes = grammar_wasm("foo", false);
+    let wasm_sha256 = format!("{:x}", sha2::Sha256::digest(&wasm_bytes));
+    let extension_toml = |sha256: &str| {
+        format!(
+            r#"
+                id = "zed-foo"
+                name = "Zed Foo"
+                version = "1.0.0"
+                schema_version = 1
+
+                [grammars.foo]
+                oci = "ghcr.io/zed-extensions/tree-sitter-foo:1.0.0"
+                sha256 = "{sha256}"
+            "#
+        )
+    };
+    let extensions_dir = temp_tree(json!({
+        "installed": {
+            "zed-foo": {
+                "extension.toml": extension_toml(&wasm_sha256),
+            }
+        },
+    }));
+    let extensions_dir = extensions_dir.path().canonicalize().unwrap();
+    let extension_dir = extensions_dir.join("installed").join("zed-foo");
+    let grammar_path = extension_dir.join("grammars").join("foo.wasm");
+
+    let blob_request_count = Arc::new(AtomicUsize::new(0));
+    let http_client = FakeHttpClient::create({
+        let wasm_bytes = wasm_bytes.clone();
+        let blob_request_count = blob_request_count.clone();
+        move |request| {
+            let wasm_bytes = wasm_bytes.clone();
+            let blob_request_count = blob_request_count.clone();
+            let layer_digest = format!("sha256:{:x}", sha2::Sha256::digest(&wasm_bytes));
+            async move {
+                let path = request.uri().path();
+                let body = if path == "/v2/zed-extensions/tree-sitter-foo/manifests/1.0.0" {
+                    json!({
+                        "layers": [{ "mediaType": "application/wasm", "digest": layer_digest }]
+                    })
+                    .to_string()
+                    .into_bytes()
+                } else if path == format!("/v2/zed-extensions/tree-sitter-foo/blobs/{layer_digest}")
+                {
+                    blob_request_count.fetch_add(1, SeqCst);
+                    wasm_bytes
+                } else {
+                    return Ok(Response::builder().status(404).body("not found".into())?);
+                };
+                Ok(Response::builder().body(body.into())?)
+            }
+        }
+    });
+
+    let fs = Arc::new(RealFs::default());
+    let builder = ExtensionBuilder::new(http_client, extensions_dir.join("build"));
+    let compile = || {
+        let fs = fs.clone();
+        let builder = &builder;
+        let extension_dir = &extension_dir;
+        async move {
+            let mut manifest = ExtensionManifest::load(fs.clone(), &extension_dir)
+                .await
+                .unwrap();
+            builder
+                .compile_extension(
+                    &extension_dir,
+                    &mut manifest,
+                    CompileExtensionOptions { release: false },
+                )
+                .await
+        }
+    };
+
+    compile().await.unwrap();
+    assert_eq!(blob_request_count.load(SeqCst), 1);
+    assert_eq!(std::fs::read(&grammar_path).unwrap(), wasm_bytes);
+
+    // A grammar that matches its checksum is not fetched again.
+    compile().await.unwrap();
+    assert_eq!(blob_request_count.load(SeqCst), 1);
+
+    // A corrupted grammar is fetched again.
+    std::fs::write(&grammar_path, b"corrupted").unwrap();
+    compile().await.unwrap();
+    assert_eq!(blob_request_count.load(SeqCst), 2);
+    assert_eq!(std::fs::read(&grammar_path).unwrap(), wasm_bytes);
+
+    // When the fetched grammar doesn't match the checksum, it is fetched once
+    // more before failing.
+    std::fs::write(
+        extension_dir.join("extension.toml"),
+        extension_toml(&"0".repeat(64)),
+    )
+    .unwrap();
+    let error = compile().await.unwrap_err();
+    assert!(
+        format!("{error:?}").contains("checksum mismatch"),
+        "unexpected error: {error:?}"
+    );
+    assert_eq!(blob_request_count.load(SeqCst), 4);
+}
+
+#[test]
+fn test_reload_blocking() {
+    let extensions_dir = temp_tree(json!({
+        "installed": {
+            "zed-monokai": monokai_extension(),
+            "zed-ruby": ruby_extension(),
+            "not-json": {
+                "extension.json": "{ this is not json",
+            },
+        }
+    }));
+    let installed_dir = extensions_dir
+        .path()
+        .canonicalize()
+        .unwrap()
+        .join("installed");
+
+    let (index, manifest_errors) =
+        ExtensionStore::reload_blocking(Arc::new(RealFs::default()), &installed_dir);
+
+    assert_eq!(
+        index
+            .extensions
+            .keys()
+            .map(AsRef::as_ref)
+            .collect::<Vec<_>>(),
+        ["zed-monokai", "zed-ruby"]
+    );
+    let ruby_manifest = &index.extensions["zed-ruby"].manifest;
+    assert_eq!(ruby_manifest.name, "Zed Ruby");
+    assert_eq!(ruby_manifest.version.as_ref(), "1.0.0");
+    assert_eq!(
+        ruby_manifest
+            .grammars
+            .keys()
+            .map(AsRef::as_ref)
+            .collect::<Vec<_>>(),
+        ["embedded_template", "ruby"]
+    );
+    assert_eq!(
+        index
+            .languages
+            .keys()
+            .map(AsRef::as_ref)
+            .collect::<Vec<_>>(),
+        ["ERB", "Ruby"]
+    );
+    assert_eq!(index.themes.len(), 4);
+
+    assert_eq!(manifest_errors.len(), 1);
+    assert_eq!(
+        manifest_errors[0].extension_dir,
+        installed_dir.join("not-json")
+    );
+    assert_eq!(manifest_errors[0].field, None);
+}
+
+#[gpui::test]
+async fn test_extension_call_timeout(cx: &mut TestAppContext) {
+    init_test(cx);
+
+    let result = Arc::new(Mutex::new(None));
+    let executor = cx.executor();
+    cx.executor()
+        .spawn({
+            let result = result.clone();
+            async move {
+                let call_result = with_timeout(
+                    &executor,
+                    DEFAULT_CALL_TIMEOUT,
+                    futures::future::pending::<anyhow::Result<()>>(),
+                )
+                .await;
+                *result.lock() = Some(call_result);
+            }
+        })
+        .detach();
+
+    cx.executor().run_until_parked();
+    cx.executor()
+        .advance_clock(DEFAULT_CALL_TIMEOUT - Duration::from_secs(1));
+    assert!(result.lock().is_none());
+
+    cx.executor().advance_clock(Duration::from_secs(1));
+    let error = result.lock().take().unwrap().unwrap_err();
+    assert_eq!(error.to_string(), "extension call timed out after 30s");
+
+    // Calls that complete in time are unaffected, and each call can have its
+    // own timeout.
+    let result = Arc::new(Mutex::new(None));
+    let executor = cx.executor();
+    cx.executor()
+        .spawn({
+            let result = result.clone();
+            async move {
+                let call_result = with_timeout(&executor, Duration::from_secs(1), async {
+                    executor.timer(Duration::from_millis(500)).await;
+                    anyhow::Ok(42)
+                })
+                .await;
+                *result.lock() = Some(call_result);
+            }
+        })
+        .detach();
+
+    cx.executor().run_until_parked();
+    cx.executor().advance_clock(Duration::from_secs(2));
+    assert_eq!(result.lock().take().unwrap().unwrap(), 42);
+}
+
+#[gpui::test]
+async fn test_index_docs_not_cancelled_after_default_timeout(cx: &mut TestAppContext) {
+    init_test(cx);
+
+    let result = Arc::new(Mutex::new(None));
+    let executor = cx.executor();
+    cx.executor()
+        .spawn({
+            let result = result.clone();
+            async move {
+                let call_result = with_timeout(&executor, INDEX_DOCS_TIMEOUT, async {
+                    executor.timer(DEFAULT_CALL_TIMEOUT * 2).await;
+                    anyhow::Ok(())
+                })
+                .await;
+                *result.lock() = Some(call_result);
+            }
+        })
+        .detach();
+
+    cx.executor().run_until_parked();
+    cx.executor()
+        .advance_clock(DEFAULT_CALL_TIMEOUT + Duration::from_secs(1));
+    assert!(result.lock().is_none());
+
+    cx.executor().advance_clock(DEFAULT_CALL_TIMEOUT);
+    result.lock().take().unwrap().unwrap();
+}
+
+#[gpui::test]
+async fn test_call_limiter_bounds_concurrent_calls(cx: &mut TestAppContext) {
+    init_test(cx);
+
+    let max_concurrent_calls = cx.update(|cx| {
+        SettingsStore::update_global(cx, |store, cx| {
+            store.update_user_settings::<ExtensionSettings>(cx, |settings| {
+                settings.max_concurrent_extension_calls = Some(2);
+            });
+        });
+        ExtensionSettings::get_global(cx).max_concurrent_calls()
+    });
+    assert_eq!(max_concurrent_calls, 2);
+
+    const CALL_DURATION: Duration = Duration::from_millis(50);
+    let limiter = CallLimiter::new(max_concurrent_calls);
+    let running = Arc::new(AtomicUsize::new(0));
+    let max_running = Arc::new(AtomicUsize::new(0));
+    let completed = Arc::new(AtomicUsize::new(0));
+    for _ in 0..5 {
+        let limiter = limiter.clone();
+        let running = running.clone();
+        let max_running = max_running.clone();
+        let completed = completed.clone();
+        let executor = cx.executor();
+        cx.executor()
+            .spawn(async move {
+                limiter
+                    .run(async {
+                        let now_running = running.fetch_add(1, SeqCst) + 1;
+                        max_running.fetch_max(now_running, SeqCst);
+                        executor.timer(CALL_DURATION).await;
+                        running.fetch_sub(1, SeqCst);
+                    })
+                    .await;
+                completed.fetch_add(1, SeqCst);
+            })
+            .detach();
+    }
+
+    cx.executor().run_until_parked();
+    assert_eq!(running.load(SeqCst), 2);
+    assert_eq!(completed.load(SeqCst), 0);
+
+    for _ in 0..3 {
+        cx.executor().advance_clock(CALL_DURATION);
+        cx.executor().run_until_parked();
+    }
+    assert_eq!(completed.load(SeqCst), 5);
+    assert_eq!(max_running.load(SeqCst), 2);
+}
+
+#[gpui::test]
+async fn test_extension_store_without_release_channel(cx: &mut TestAppContext) {
+    // Like `init_test`, but without initializing the release channel, which
+    // wasm extensions require.
+    cx.update(|cx| {
+        let store = SettingsStore::test(cx);
+        cx.set_global(store);
+        theme::init(theme::LoadThemes::JustBase, cx);
+        Project::init_settings(cx);
+        ExtensionSettings::register(cx);
+        language::init(cx);
+    });
+
+    let TestExtensionStore {
+        store,
+        language_registry,
+        ..
+    } = init_test_extension_store(
+        json!({
+            "installed": {
+                "zed-ruby": ruby_extension(),
+                "zed-ruby-lsp": {
+                    "extension.toml": r#"
+                        id = "zed-ruby-lsp"
+                        name = "Zed Ruby LSP"
+                        version = "1.0.0"
+                        schema_version = 1
+
+                        [language_servers.ruby-lsp]
+                        languages = ["Ruby"]
+                    "#,
+                    "extension.wasm": "",
+                },
+            }
+        }),
+        cx,
+    )
+    .await;
+
+    // The languages are still registered, even though the wasm extension
+    // could not be loaded.
+    assert_eq!(
+        language_registry.language_names(),
+        ["ERB", "Plain Text", "Ruby"]
+    );
+    store.read_with(cx, |store, _| {
+        assert!(store
+            .installed_extension_entries()
+            .contains_key("zed-ruby-lsp"));
+        assert_eq!(
+            store.load_errors(),
+            [ExtensionLoadError {
+                extension_id: "zed-ruby-lsp".into(),
+                severity: LoadErrorSeverity::Error,
+                message: "the extension could not be loaded, because the release channel has \
+                    not been initialized"
+                    .into(),
+            }]
+        );
+    });
+}
+
+#[gpui::test]
+async fn test_extension_call_errors_are_recorded(cx: &mut TestAppContext) {
+    init_test(cx);
+
+    let TestExtensionStore { store, .. } = init_test_extension_store(
+        json!({
+            "installed": {
+                "zed-ruby": ruby_extension(),
+            }
+        }),
+        cx,
+    )
+    .await;
+
+    // An error thrown by the extension's code, as it reaches the host.
+    let error = anyhow!("wasm trap: wasm `unreachable` instruction executed")
+        .context("error while executing at wasm backtrace:\n    0: 0x1f2e - zed_ruby!language_server_command")
+        .context("failed to get the command for language server ruby-lsp");
+    store.read_with(cx, |store, _| {
+        // Calls that are retried do not record the same error again.
+        store
+            .wasm_host
+            .report_call_error(&"zed-ruby".into(), &error);
+        store
+            .wasm_host
+            .report_call_error(&"zed-ruby".into(), &error);
+    });
+    cx.executor().run_until_parked();
+
+    store.read_with(cx, |store, _| {
+        assert_eq!(
+            store.load_errors(),
+            [ExtensionLoadError {
+                extension_id: "zed-ruby".into(),
+                severity: LoadErrorSeverity::Error,
+                message: format!("{error:?}"),
+            }]
+        );
+        let message = &store.load_errors()[0].message;
+        assert!(message.contains("failed to get the command for language server ruby-lsp"));
+        assert!(message.contains("0: 0x1f2e - zed_ruby!language_server_command"));
+        assert!(message.contains("wasm `unreachable` instruction executed"));
+    });
+}
+
+#[gpui::test]
+async fn test_extension_store_reload_progress(cx: &mut TestAppContext) {
+    init_test(cx);
+
+    let fs = FakeFs::new(cx.executor());
+    fs.insert_tree(
+        "/the-extension-dir",
+        json!({
+            "installed": {
+                "zed-monokai": monokai_extension(),
+                "zed-ruby": ruby_extension(),
+            }
+        }),
+    )
+    .await;
+
+    let language_registry = Arc::new(LanguageRegistry::test(cx.executor()));
+    let theme_registry = Arc::new(ThemeRegistry::new(Box::new(())));
+    let store = new_test_extension_store(
+        fs.clone(),
+        FakeHttpClient::with_200_response(),
+        language_registry.clone(),
+        theme_registry.clone(),
+        cx,
+    );
+
+    let mut events = cx.events(&store);
+    cx.executor().advance_clock(RELOAD_DEBOUNCE_DURATION);
+    cx.executor().run_until_parked();
+
+    let phases = std::iter::from_fn(|| events.try_next().ok().flatten())
+        .filter_map(|event| match event {
+            Event::ReloadProgress(phase) => Some(phase),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        phases,
+        [
+            ReloadPhase::Scanning,
+            ReloadPhase::ParsingManifests { extension_count: 2 },
+            // The extensions are registered one at a time, as they are parsed.
+            ReloadPhase::RegisteringThemes { theme_count: 2 },
+            ReloadPhase::RegisteringGrammars { grammar_count: 2 },
+            ReloadPhase::Done,
+        ]
+    );
+}
+
+#[gpui::test]
+async fn test_extension_store_last_reload_duration(cx: &mut TestAppContext) {
+    init_test(cx);
+
+    let fs = FakeFs::new(cx.executor());
+    fs.insert_tree(
+        "/the-extension-dir",
+        json!({
+            "installed": {
+                "zed-monokai": monokai_extension(),
+            }
+        }),
+    )
+    .await;
+
+    let store = new_test_extension_store(
+        fs.clone(),
+        FakeHttpClient::with_200_response(),
+        Arc::new(LanguageRegistry::test(cx.executor())),
+        Arc::new(ThemeRegistry::new(Box::new(()))),
+        cx,
+    );
+    cx.executor().run_until_parked();
+    store.read_with(cx, |store, _| {
+        assert_eq!(store.last_reload_duration(), None);
+    });
+
+    cx.executor().advance_clock(RELOAD_DEBOUNCE_DURATION);
+    cx.executor().run_until_parked();
+    store.read_with(cx, |store, _| {
+        assert!(store.last_reload_duration().is_some());
+    });
+}
+
+#[gpui::test]
+async fn test_extension_store_registers_extensions_incrementally(cx: &mut TestAppContext) {
+    init_test(cx);
+
+    let fs = FakeFs::new(cx.executor());
+    fs.insert_tree(
+        "/the-extension-dir",
+        json!({
+            "installed": {
+                "zed-gruvbox": gruvbox_extension(),
+                "zed-monokai": monokai_extension(),
+            }
+        }),
+    )
+    .await;
+
+    let theme_registry = Arc::new(ThemeRegistry::new(Box::new(())));
+    let store = new_test_extension_store(
+        fs.clone(),
+        FakeHttpClient::with_200_response(),
+        Arc::new(LanguageRegistry::test(cx.executor())),
+        theme_registry.clone(),
+        cx,
+    );
+
+    let registrations = Arc::new(Mutex::new(Vec::new()));
+    cx.update(|cx| {
+        let registrations = registrations.clone();
+        let theme_registry = theme_registry.clone();
+        cx.subscribe(&store, move |_, event, _| {
+            if let Event::ExtensionRegistered(extension_id) = event {
+                registrations
+                    .lock()
+                    .push((extension_id.clone(), theme_registry.list_names(false)));
+            }
+        })
+        .detach();
+    });
+
+    let mut events = cx.events(&store);
+    cx.executor().advance_clock(RELOAD_DEBOUNCE_DURATION);
+    cx.executor().run_until_parked();
+
+    assert_eq!(
+        *registrations.lock(),
+        [
+            (
+                "zed-gruvbox".into(),
+                vec!["Gruvbox".into(), "One Dark".into()]
+            ),
+            (
+                "zed-monokai".into(),
+                vec![
+                    "Gruvbox".into(),
+                    "Monokai Dark".into(),
+                    "Monokai Light".into(),
+                    "Monokai Pro Dark".into(),
+                    "Monokai Pro Light".into(),
+                    "One Dark".into(),
+                ]
+            ),
+        ] as [(Arc<str>, Vec<SharedString>); 2]
+    );
+
+    // Each theme file is registered once, when its extension is registered,
+    // and not again once the whole index has been rebuilt. It is read once to
+    // index it, and once to register it.
+    let registered_theme_counts = std::iter::from_fn(|| events.try_next().ok().flatten())
+        .filter_map(|event| match event {
+            Event::ReloadProgress(ReloadPhase::RegisteringThemes { theme_count }) => {
+                Some(theme_count)
+            }
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(registered_theme_counts, [1, 2]);
+    for theme_path in [
+        "/the-extension-dir/installed/zed-gruvbox/themes/gruvbox.json",
+        "/the-extension-dir/installed/zed-monokai/themes/monokai.json",
+        "/the-extension-dir/installed/zed-monokai/themes/monokai-pro.json",
+    ] {
+        assert_eq!(fs.read_call_count(theme_path), 2, "{theme_path}");
+    }
+}
+
+#[gpui::test]
+async fn test_grammar_for_content(cx: &mut TestAppContext) {
+    init_test(cx);
+    cx.executor().allow_parking();
+
+    let extensions_dir = temp_tree(json!({
+        "installed": {
+            "zed-ruby": {
+                "extension.toml": r#"
+                    id = "zed-ruby"
+                    name = "Zed Ruby"
+                    version = "1.0.0"
+                    schema_version = 1
+
+                    [grammars.ruby]
+                    repository = "https://github.com/tree-sitter/tree-sitter-ruby"
+                    rev = "abc"
+
+                    [grammars.ruby_rbs]
+                    repository = "https://github.com/joker1007/tree-sitter-rbs"
+                    rev = "def"
+                "#,
+                "languages": {
+                    "ruby": {
+                        "config.toml": r#"
+                            name = "Ruby"
+                            grammar = "ruby"
+                            path_suffixes = ["rb"]
+
+                            [[grammar_variants]]
+                            content_regex = "^# rbs_inline: enabled"
+                            grammar = "ruby_rbs"
+                        "#,
+                    },
+                },
+            },
+        },
+        "work": {}
+    }));
+    let extensions_dir = extensions_dir.path().canonicalize().unwrap();
+
+    let language_registry = Arc::new(LanguageRegistry::test(cx.executor()));
+    let extension_store = cx.new_model(|cx| {
+        ExtensionStore::new(
+            extensions_dir.clone(),
+            None,
+            Arc::new(RealFs::default()),
+            FakeHttpClient::with_200_response(),
+            RetryPolicy::default(),
+            None,
+            FakeNodeRuntime::new(),
+            language_registry.clone(),
+            Arc::new(ThemeRegistry::new(Box::new(()))),
+            SlashCommandRegistry::new(),
+            Arc::new(IndexedDocsRegistry::new(cx.background_executor().clone())),
+            Arc::new(SnippetRegistry::new()),
+            cx,
+        )
+    });
+
+    // Ensure that debounces fire.
+    let mut events = cx.events(&extension_store);
+    let executor = cx.executor();
+    let _task = cx.executor().spawn(async move {
+        while let Some(event) = events.next().await {
+            if let Event::StartedReloading = event {
+                executor.advance_clock(RELOAD_DEBOUNCE_DURATION);
+            }
+        }
+    });
+
+    extension_store
+        .update(cx, |store, cx| store.reload(None, cx))
+        .await;
+
+    extension_store.read_with(cx, |store, _| {
+        assert_eq!(
+            store.extension_index.languages["Ruby"].grammar_variants[0]
+                .content_regex
+                .as_str(),
+            "^# rbs_inline: enabled"
+        );
+        assert_eq!(
+            store
+                .grammar_for_content("Ruby", "# rbs_inline: enabled\nclass Foo; end")
+                .as_deref(),
+            Some("ruby_rbs")
+        );
+        assert_eq!(
+            store
+                .grammar_for_content("Ruby", "class Foo; end")
+                .as_deref(),
+            Some("ruby")
+        );
+        assert_eq!(store.grammar_for_content("Python", "class Foo: pass"), None);
+    });
+
+    // Opening a file loads the language with the grammar that its content
+    // selects. Neither grammar is built, so loading fails, naming the grammar.
+    let file: Arc<dyn language::File> = Arc::new(TestFile {
+        path: Path::new("lib/foo.rb").into(),
+        root_name: "project".into(),
+    });
+    for (content, expected_grammar) in [
+        ("# rbs_inline: enabled\nclass Foo; end\n", "ruby_rbs"),
+        ("class Foo; end\n", "ruby"),
+    ] {
+        let content = Rope::from(content);
+        let error = cx
+            .read(|cx| language_registry.language_for_file(&file, Some(&content), cx))
+            .await
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            format!("failed to load language Ruby: no such grammar {expected_grammar}"),
+            "unexpected grammar for {content:?}"
+        );
+    }
+}
+
+#[gpui::test]
+async fn test_language_config(cx: &mut TestAppContext) {
+    init_test(cx);
+
+    let TestExtensionStore { store, .. } = init_test_extension_store(
+        json!({
+            "installed": {
+                "zed-ruby": ruby_extension(),
+            }
+        }),
+        cx,
+    )
+    .await;
+
+    let config = store
+        .update(cx, |store, cx| store.language_config("Ruby", cx))
+        .await
+        .unwrap();
+    assert_eq!(config.name.as_ref(), "Ruby");
+    assert_eq!(config.grammar.as_deref(), Some("ruby"));
+    assert_eq!(config.matcher.path_suffixes, ["rb"]);
+
+    assert!(store
+        .update(cx, |store, cx| store.language_config("Python", cx))
+        .await
+        .is_err());
+}
+
+#[gpui::test]
+async fn test_language_config_brackets(cx: &mut TestAppContext) {
+    init_test(cx);
+
+    let TestExtensionStore { store, .. } = init_test_extension_store(
+        json!({
+            "installed": {
+                "zed-ruby": {
+                    "extension.json": r#"{ "name": "Zed Ruby", "version": "1.0.0" }"#,
+                    "languages": {
+                        "ruby": {
+                            "config.toml": r#"
+                                name = "Ruby"
+                                path_suffixes = ["rb"]
+                                brackets = [
+                                    { start = "{", end = "}", close = true, newline = true },
+                                    { start = "\"", end = "\"", close = true, not_in = ["string", "comment"] },
+                                ]
+                            "#,
+                        },
+                    },
+                },
+            }
+        }),
+        cx,
+    )
+    .await;
+
+    let config = store
+        .update(cx, |store, cx| store.language_config("Ruby", cx))
+        .await
+        .unwrap();
+    let brackets = &config.brackets;
+    assert_eq!(
+        brackets
+            .pairs
+            .iter()
+            .map(|pair| (pair.start.as_str(), pair.close, pair.newline))
+            .collect::<Vec<_>>(),
+        [("{", true, true), ("\"", true, false)]
+    );
+    assert_eq!(
+        brackets.disabled_scopes_by_bracket_ix,
+        [
+            Vec::new(),
+            vec!["string".to_string(), "comment".to_string()]
+        ]
+    );
+}
+
+#[gpui::test]
+async fn test_extension_store_registers_produced_grammars(cx: &mut TestAppContext) {
+    init_test(cx);
+
+    let TestExtensionStore {
+        fs,
+        store,
+        language_registry,
+        ..
+    } = init_test_extension_store(
+        json!({
+            "installed": {
+                "zed-ruby": ruby_extension(),
+            }
+        }),
+        cx,
+    )
+    .await;
+    assert!(!language_registry.grammar_names().contains(&"rbs".into()));
+
+    // Simulate the extension building a grammar while installing its language server.
+    fs.insert_file(
+        "/the-extension-dir/work/zed-ruby/grammars/rbs.wasm",
+        Vec::new(),
+    )
+    .await;
+    let mut state = store.read_with(cx, |store, _| {
+        WasmState::fake(
+            store.extension_index.extensions["zed-ruby"]
+                .manifest
+                .clone(),
+            store.wasm_host.clone(),
+        )
+    });
+    state
+        .register_grammar("rbs".into(), "grammars/rbs.wasm".into())
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(state
+        .register_grammar("escaped".into(), "../zed-other/escaped.wasm".into())
+        .await
+        .unwrap()
+        .is_err());
+    assert!(state
+        .register_grammar("rbs".into(), "grammars/rbs.so".into())
+        .await
+        .unwrap()
+        .is_err());
+    cx.executor().run_until_parked();
+
+    assert!(language_registry.grammar_names().contains(&"rbs".into()));
+    store.read_with(cx, |store, _| {
+        assert!(store.registered.grammars.contains("rbs"));
+    });
+
+    // Grammars produced by an extension are removed when it is uninstalled.
+    store.update(cx, |store, cx| {
+        store
+            .uninstall_extension("zed-ruby".into(), UninstallOptions::default(), cx)
+            .unwrap()
+    });
+    cx.executor().advance_clock(RELOAD_DEBOUNCE_DURATION);
+    cx.executor().run_until_parked();
+    assert!(!language_registry.grammar_names().contains(&"rbs".into()));
+}
+
+#[gpui::test]
+async fn test_download_cache(cx: &mut TestAppContext) {
+    init_test(cx);
+
+    let server = b"the-language-server".to_vec();
+    let server_sha256 = format!("{:x}", sha2::Sha256::digest(&server));
+
+    let fs = FakeFs::new(cx.executor());
+    fs.insert_tree(
+        "/the-extension-dir",
+        json!({
+            "installed": {},
+            "work": {
+                "the-lsp-extension": {}
+            }
+        }),
+    )
+    .await;
+    fs.insert_file(
+        "/the-extension-dir/work/the-lsp-extension/server",
+        server.clone(),
+    )
+    .await;
+
+    let store = new_test_extension_store(
+        fs.clone(),
+        FakeHttpClient::with_200_response(),
+        Arc::new(LanguageRegistry::test(cx.executor())),
+        Arc::new(ThemeRegistry::new(Box::new(()))),
+        cx,
+    );
+    cx.executor().advance_clock(RELOAD_DEBOUNCE_DURATION);
+    cx.executor().run_until_parked();
+    let wasm_host = store.read_with(cx, |store, _| store.wasm_host.clone());
+
+    let manifest = |language_server: &str| -> ExtensionManifest {
+        toml::from_str(&format!(
+            r#"
+                id = "the-lsp-extension"
+                name = "The LSP Extension"
+                version = "0.1.0"
+                schema_version = 1
+
+                [language_servers.the-server]
+                language = "Ruby"
+                {language_server}
+            "#
+        ))
+        .unwrap()
+    };
+    let with_checksum = manifest(&format!(
+        r#"sha256 = {{ "server-1.0.0" = "{server_sha256}", "server-2.0.0" = "{}" }}"#,
+        "0".repeat(64)
+    ));
+
+    // Downloads land in the extension's work directory, and can't escape it.
+    let extension_id: Arc<str> = "the-lsp-extension".into();
+    let destination = wasm_host
+        .writeable_path_from_extension(&extension_id, Path::new("server"))
+        .unwrap();
+    assert_eq!(
+        destination,
+        PathBuf::from("/the-extension-dir/work/the-lsp-extension/server")
+    );
+    assert!(wasm_host
+        .writeable_path_from_extension(&extension_id, Path::new("../other/server"))
+        .is_err());
+
+    // An existing file is reused when it has the expected checksum.
+    assert!(
+        wasm_host
+            .is_download_cached(
+                &with_checksum,
+                "https://example.com/server-1.0.0",
+                &destination
+            )
+            .await
+    );
+    assert!(
+        !wasm_host
+            .is_download_cached(
+                &with_checksum,
+                "https://example.com/server-2.0.0",
+                &destination
+            )
+            .await
+    );
+    assert!(
+        !wasm_host
+            .is_download_cached(
+                &with_checksum,
+                "https://example.com/server-1.0.0",
+                Path::new("/the-extension-dir/work/the-lsp-extension/missing")
+            )
+            .await
+    );
+
+    // Without a checksum, files are always downloaded again.
+    assert!(
+        !wasm_host
+            .is_download_cached(
+                &manifest(""),
+                "https://example.com/server-1.0.0",
+                &destination
+            )
+            .await
+    );
+}
+
+#[gpui::test]
+async fn test_prune_orphaned_grammars(cx: &mut TestAppContext) {
+    init_test(cx);
+
+    let TestExtensionStore {
+        fs,
+        store,
+        language_registry,
+        ..
+    } = init_test_extension_store(
+        json!({
+            "installed": {
+                "zed-ruby": ruby_extension(),
+                "zed-orphan": {
+                    "extension.toml": r#"
+                        id = "zed-orphan"
+                        name = "Zed Orphan"
+                        version = "1.0.0"
+                        schema_version = 1
+
+                        [grammars.orphan]
+                        repository = "https://github.com/someone/tree-sitter-orphan"
+                        rev = "abc"
+
+                        [grammars.shared]
+                        repository = "https://github.com/someone/tree-sitter-shared"
+                        rev = "abc"
+                    "#,
+                    "grammars": {
+                        "orphan.wasm": "",
+                        "shared.wasm": "",
+                    },
+                },
+                // Refers to a grammar that another extension provides.
+                "zed-shared": {
+                    "extension.toml": r#"
+                        id = "zed-shared"
+                        name = "Zed Shared"
+                        version = "1.0.0"
+                        schema_version = 1
+                    "#,
+                    "languages": {
+                        "shared": {
+                            "config.toml": r#"
+                                name = "Shared"
+                                grammar = "shared"
+                                path_suffixes = ["shared"]
+                            "#,
+                        },
+                    },
+                },
+            }
+        }),
+        cx,
+    )
+    .await;
+
+    assert_eq!(
+        language_registry.grammar_names(),
+        [
+            "embedded_template".into(),
+            "orphan".into(),
+            "ruby".into(),
+            "shared".into()
+        ] as [Arc<str>; 4]
+    );
+
+    let pruned = store
+        .update(cx, |store, cx| store.prune_orphaned_grammars(cx))
+        .await
+        .unwrap();
+    assert_eq!(pruned, ["orphan".into()] as [Arc<str>; 1]);
+    assert_eq!(
+        language_registry.grammar_names(),
+        ["embedded_template".into(), "ruby".into(), "shared".into()] as [Arc<str>; 3]
+    );
+    assert!(
+        !fs.is_file(Path::new(
+            "/the-extension-dir/installed/zed-orphan/grammars/orphan.wasm"
+        ))
+        .await
+    );
+    for grammar_path in [
+        "/the-extension-dir/installed/zed-orphan/grammars/shared.wasm",
+        "/the-extension-dir/installed/zed-ruby/grammars/ruby.wasm",
+        "/the-extension-dir/installed/zed-ruby/grammars/embedded_template.wasm",
+    ] {
+        assert!(fs.is_file(Path::new(grammar_path)).await, "{grammar_path}");
+    }
+
+    let pruned = store
+        .update(cx, |store, cx| store.prune_orphaned_grammars(cx))
+        .await
+        .unwrap();
+    assert!(pruned.is_empty());
+}
+
+#[gpui::test]
+async fn test_incompatible_extensions(cx: &mut TestAppContext) {
+    init_test(cx);
+
+    let TestExtensionStore { store, .. } = init_test_extension_store(
+        json!({
+            "installed": {
+                "zed-future": {
+                    "extension.toml": r#"
+                        id = "zed-future"
+                        name = "Zed Future"
+                        version = "1.0.0"
+                        schema_version = 1
+                        min_zed_version = "99.0.0"
+                    "#,
+                },
+                "zed-monokai": monokai_extension(),
+            }
+        }),
+        cx,
+    )
+    .await;
+
+    store.read_with(cx, |store, _| {
+        assert_eq!(
+            store.incompatible_extensions(),
+            [(
+                Arc::from("zed-future"),
+                "requires Zed 99.0.0 or later (running 0.0.0)".to_string()
+            )]
+        );
+    });
+
+    // Extensions that the user has chosen to load regardless are not listed.
+    cx.update(|cx| {
+        SettingsStore::update_global(cx, |store, cx| {
+            store.update_user_settings::<ExtensionSettings>(cx, |settings| {
+                settings
+                    .load_incompatible_extensions
+                    .insert("zed-future".into(), true);
+            });
+        });
+    });
+    let reload = store.update(cx, |store, cx| store.reload(Some("zed-future".into()), cx));
+    finish_reload(reload, cx);
+
+    store.read_with(cx, |store, _| {
+        assert!(store.incompatible_extensions().is_empty());
+    });
+}
+
+#[gpui::test]
+async fn test_compatibility_is_checked_again(cx: &mut TestAppContext) {
+    init_test(cx);
+
+    let manifest = |schema_version: i32| {
+        format!(
+            r#"
+                id = "zed-future"
+                name = "Zed Future"
+                version = "1.0.0"
+                schema_version = {schema_version}
+                min_zed_version = "99.0.0"
+            "#
+        )
+    };
+    let TestExtensionStore {
+        fs,
+        store,
+        theme_registry,
+        ..
+    } = init_test_extension_store(
+        json!({
+            "installed": {
+                "zed-future": {
+                    "extension.toml": manifest(1),
+                    "themes": {
+                        "future.json": r#"{
+                            "name": "Future",
+                            "author": "Someone",
+                            "themes": [
+                                { "name": "Future Dark", "appearance": "dark", "style": {} }
+                            ]
+                        }"#,
+                    },
+                },
+            }
+        }),
+        cx,
+    )
+    .await;
+    assert_eq!(theme_registry.list_names(false), ["One Dark"]);
+
+    // Once Zed is updated to a version that the extension supports, it is
+    // loaded without having changed itself.
+    cx.update(|cx| release_channel::init(SemanticVersion::new(99, 0, 0), cx));
+    let reload = store.update(cx, |store, cx| store.recheck_compatibility(cx));
+    finish_reload(reload, cx);
+    store.read_with(cx, |store, _| {
+        assert!(store.incompatible_extensions().is_empty());
+    });
+    assert_eq!(
+        theme_registry.list_names(false),
+        ["Future Dark", "One Dark"]
+    );
+
+    // The extension is unloaded once it requires a newer schema version.
+    fs.save(
+        "/the-extension-dir/installed/zed-future/extension.toml".as_ref(),
+        &manifest(2).as_str().into(),
+        Default::default(),
+    )
+    .await
+    .unwrap();
+    let reload = store.update(cx, |store, cx| store.reload(Some("zed-future".into()), cx));
+    finish_reload(reload, cx);
+    store.read_with(cx, |store, _| {
+        assert_eq!(
+            store.incompatible_extensions(),
+            [(
+                Arc::from("zed-future"),
+                "uses schema version 2 (Zed supports up to 1)".to_string()
+            )]
+        );
+    });
+    assert_eq!(theme_registry.list_names(false), ["One Dark"]);
+}
+
+#[gpui::test]
+async fn test_conflicting_extensions(cx: &mut TestAppContext) {
+    init_test(cx);
+
+    let TestExtensionStore {
+        fs,
+        store,
+        theme_registry,
+        ..
+    } = init_test_extension_store(
+        json!({
+            "installed": {
+                "pack-a": {
+                    "extension.toml": r#"
+                        id = "pack-a"
+                        name = "Pack A"
+                        version = "1.0.0"
+                        schema_version = 1
+                        conflicts = ["pack-b"]
+                    "#,
+                    "themes": {
+                        "theme.json": r#"{
+                            "name": "Pack A",
+                            "author": "Someone",
+                            "themes": [
+                                { "name": "Pack A Dark", "appearance": "dark", "style": {} }
+                            ]
+                        }"#,
+                    },
+                },
+                "pack-b": {
+                    "extension.toml": r#"
+                        id = "pack-b"
+                        name = "Pack B"
+                        version = "1.0.0"
+                        schema_version = 1
+                        priority = 1
+                    "#,
+                    "themes": {
+                        "theme.json": r#"{
+                            "name": "Pack B",
+                            "author": "Someone",
+                            "themes": [
+                                { "name": "Pack B Dark", "appearance": "dark", "style": {} }
+                            ]
+                        }"#,
+                    },
+                },
+            }
+        }),
+        cx,
+    )
+    .await;
+
+    // Both extensions are installed, but only the one with the higher
+    // priority is loaded.
+    store.read_with(cx, |store, _| {
+        assert!(store.installed_extension_entries().contains_key("pack-a"));
+        assert!(store.installed_extension_entries().contains_key("pack-b"));
+        assert_eq!(
+            store.conflicting_extensions(),
+            [(
+                Arc::from("pack-a"),
+                "conflicts with extension pack-b, which takes precedence".to_string()
+            )]
+        );
+    });
+    assert_eq!(
+        theme_registry.list_names(false),
+        ["One Dark", "Pack B Dark"]
+    );
+
+    // Once the extension that takes precedence is removed, the other is loaded.
+    fs.remove_dir(
+        "/the-extension-dir/installed/pack-b".as_ref(),
+        RemoveOptions {
+            recursive: true,
+            ignore_if_not_exists: false,
+        },
+    )
+    .await
+    .unwrap();
+    let reload = store.update(cx, |store, cx| store.reload(Some("pack-b".into()), cx));
+    finish_reload(reload, cx);
+
+    store.read_with(cx, |store, _| {
+        assert!(store.conflicting_extensions().is_empty());
+    });
+    assert_eq!(
+        theme_registry.list_names(false),
+        ["One Dark", "Pack A Dark"]
+    );
+}
+
+#[gpui::test]
+async fn test_install_extension_from_path(cx: &mut TestAppContext) {
+    init_test(cx);
+
+    let theme_json = |name: &str| {
+        format!(
+            r#"{{
+                "name": "Local",
+                "author": "Someone",
+                "themes": [{{ "name": "{name}", "appearance": "dark", "style": {{}} }}]
+            }}"#
+        )
+    };
+
+    let TestExtensionStore {
+        fs,
+        store,
+        theme_registry,
+        ..
+    } = init_test_extension_store(
+        json!({
+            "installed": {
+                "zed-monokai": monokai_extension(),
+            }
+        }),
+        cx,
+    )
+    .await;
+    fs.insert_tree(
+        "/projects",
+        json!({
+            "local-theme": {
+                "extension.toml": r#"
+                    id = "local-theme"
+                    name = "Local Theme"
+                    version = "1.0.0"
+                    schema_version = 1
+                "#,
+                "themes": {
+                    "local.json": theme_json("Local Dark"),
+                },
+            },
+            "not-an-extension": {
+                "README.md": "hello",
+            },
+            "hostile": {
+                "extension.toml": r#"
+                    id = "../escaped"
+                    name = "Hostile"
+                    version = "1.0.0"
+                    schema_version = 1
+                "#,
+            },
+        }),
+    )
+    .await;
+
+    let install = store.update(cx, |store, cx| {
+        store.install_extension_from_path("/projects/local-theme".into(), cx)
+    });
+    cx.executor().advance_clock(RELOAD_DEBOUNCE_DURATION);
+    cx.executor().run_until_parked();
+    install.await.unwrap();
+
+    assert!(
+        fs.is_file("/the-extension-dir/installed/local-theme/themes/local.json".as_ref())
+            .await
+    );
+    assert!(theme_registry
+        .list_names(false)
+        .contains(&"Local Dark".into()));
+
+    // Installing from the same directory again updates the installed copy.
+    fs.save(
+        "/projects/local-theme/themes/local.json".as_ref(),
+        &theme_json("Local Darker").as_str().into(),
+        Default::default(),
+    )
+    .await
+    .unwrap();
+    let install = store.update(cx, |store, cx| {
+        store.install_extension_from_path("/projects/local-theme".into(), cx)
+    });
+    cx.executor().advance_clock(RELOAD_DEBOUNCE_DURATION);
+    cx.executor().run_until_parked();
+    install.await.unwrap();
+
+    store.read_with(cx, |store, _| {
+        assert_eq!(
+            store
+                .installed_extension_entries()
+                .keys()
+                .collect::<Vec<_>>(),
+            [&Arc::from("local-theme"), &Arc::from("zed-monokai")]
+        );
+    });
+    let theme_names = theme_registry.list_names(false);
+    assert!(theme_names.contains(&"Local Darker".into()));
+    assert!(!theme_names.contains(&"Local Dark".into()));
+
+    // Directories without an extension manifest are rejected.
+    let install = store.update(cx, |store, cx| {
+        store.install_extension_from_path("/projects/not-an-extension".into(), cx)
+    });
+    cx.executor().run_until_parked();
+    let error = install.await.unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "\"/projects/not-an-extension\" is not an extension, as it has no extension.json or extension.toml"
+    );
+    assert!(
+        !fs.is_dir("/the-extension-dir/installed/not-an-extension".as_ref())
+            .await
+    );
+
+    // Extensions whose ID would place them outside of the installed extensions
+    // directory are rejected.
+    let install = store.update(cx, |store, cx| {
+        store.install_extension_from_path("/projects/hostile".into(), cx)
+    });
+    cx.executor().run_until_parked();
+    let error = install.await.unwrap_err();
+    assert_eq!(
+        format!("{error:#}"),
+        "failed to install extension from \"/projects/hostile\": invalid extension id \"../escaped\""
+    );
+    assert!(!fs.is_dir("/the-extension-dir/escaped".as_ref()).await);
+}
+
+#[gpui::test]
+async fn test_dev_extensions_reload_on_change(cx: &mut TestAppContext) {
+    init_test(cx);
+
+    let manifest = |description: &str| {
+        format!(
+            r#"
+                id = "my-theme"
+                name = "My Theme"
+                version = "1.0.0"
+                schema_version = 1
+                description = "{description}"
+            "#
+        )
+    };
+    let theme_json = |name: &str| {
+        format!(
+            r#"{{
+                "name": "Mine",
+                "author": "Someone",
+                "themes": [{{ "name": "{name}", "appearance": "dark", "style": {{}} }}]
+            }}"#
+        )
+    };
+
+    let fs = FakeFs::new(cx.executor());
+    fs.insert_tree(
+        "/projects",
+        json!({
+            "my-theme": {
+                "extension.toml": manifest("A theme."),
+                "themes": {
+                    "mine.json": theme_json("Mine Dark"),
+                },
+            },
+        }),
+    )
+    .await;
+    fs.insert_tree("/the-extension-dir", json!({ "installed": {} }))
+        .await;
+    fs.insert_symlink(
+        "/the-extension-dir/installed/my-theme",
+        "/projects/my-theme".into(),
+    )
+    .await;
+
+    let theme_registry = Arc::new(ThemeRegistry::new(Box::new(())));
+    let store = new_test_extension_store(
+        fs.clone(),
+        FakeHttpClient::with_200_response(),
+        Arc::new(LanguageRegistry::test(cx.executor())),
+        theme_registry.clone(),
+        cx,
+    );
+    cx.executor().advance_clock(RELOAD_DEBOUNCE_DURATION);
+    cx.executor().run_until_parked();
+
+    store.read_with(cx, |store, _| {
+        assert!(store.installed_extension_entries()["my-theme"].dev);
+        assert!(store.dev_extension_watchers.contains_key("my-theme"));
+    });
+    assert!(theme_registry
+        .list_names(false)
+        .contains(&"Mine Dark".into()));
+
+    // Saving several files in quick succession reloads the extension once.
+    let mut events = cx.events(&store);
+    fs.save(
+        "/projects/my-theme/themes/mine.json".as_ref(),
+        &theme_json("Mine Darker").as_str().into(),
+        Default::default(),
+    )
+    .await
+    .unwrap();
+    fs.save(
+        "/projects/my-theme/extension.toml".as_ref(),
+        &manifest("A darker theme.").as_str().into(),
+        Default::default(),
+    )
+    .await
+    .unwrap();
+    cx.executor().run_until_parked();
+    cx.executor().advance_clock(RELOAD_DEBOUNCE_DURATION);
+    cx.executor().run_until_parked();
+
+    let reload_count = std::iter::from_fn(|| events.try_next().ok().flatten())
+        .filter(|event| matches!(event, Event::ReloadProgress(ReloadPhase::Done)))
+        .count();
+    assert_eq!(reload_count, 1);
+    let theme_names = theme_registry.list_names(false);
+    assert!(theme_names.contains(&"Mine Darker".into()));
+    assert!(!theme_names.contains(&"Mine Dark".into()));
+    store.read_with(cx, |store, _| {
+        assert_eq!(
+            store.installed_extension_entries()["my-theme"]
+                .manifest
+                .description
+                .as_deref(),
+            Some("A darker theme.")
+        );
+    });
+
+    // Uninstalling the extension stops watching its directory.
+    store.update(cx, |store, cx| {
+        store
+            .uninstall_extension("my-theme".into(), UninstallOptions::default(), cx)
+            .unwrap();
+        assert!(!store.dev_extension_watchers.contains_key("my-theme"));
+    });
+}
+
+#[gpui::test]
+async fn test_dev_extension_query_changes_reload_only_languages(cx: &mut TestAppContext) {
+    init_test(cx);
+
+    let fs = FakeFs::new(cx.executor());
+    fs.insert_tree("/projects", json!({ "zed-ruby": ruby_extension() }))
+        .await;
+    fs.insert_tree("/the-extension-dir", json!({ "installed": {} }))
+        .await;
+    fs.insert_symlink(
+        "/the-extension-dir/installed/zed-ruby",
+        "/projects/zed-ruby".into(),
+    )
+    .await;
+
+    let language_registry = Arc::new(LanguageRegistry::test(cx.executor()));
+    let store = new_test_extension_store(
+        fs.clone(),
+        FakeHttpClient::with_200_response(),
+        language_registry.clone(),
+        Arc::new(ThemeRegistry::new(Box::new(()))),
+        cx,
+    );
+    cx.executor().advance_clock(RELOAD_DEBOUNCE_DURATION);
+    cx.executor().run_until_parked();
+    store.read_with(cx, |store, _| {
+        assert!(store.installed_extension_entries()["zed-ruby"].dev);
+    });
+
+    // Editing a query reloads the language, but not its grammar or the rest
+    // of the extension.
+    let mut events = cx.events(&store);
+    let reload_count = language_registry.reload_count();
+    fs.save(
+        "/projects/zed-ruby/languages/ruby/highlights.scm".as_ref(),
+        &"(identifier) @variable".into(),
+        Default::default(),
+    )
+    .await
+    .unwrap();
+    cx.executor().run_until_parked();
+    cx.executor().advance_clock(RELOAD_DEBOUNCE_DURATION);
+    cx.executor().run_until_parked();
+
+    let extension_reload_count = std::iter::from_fn(|| events.try_next().ok().flatten())
+        .filter(|event| matches!(event, Event::ReloadProgress(ReloadPhase::Done)))
+        .count();
+    assert_eq!(extension_reload_count, 0);
+    assert_eq!(language_registry.reload_count(), reload_count + 1);
+    assert_eq!(
+        language_registry.language_names(),
+        ["ERB", "Plain Text", "Ruby"]
+    );
+    assert_eq!(
+        language_registry.grammar_names(),
+        ["embedded_template".into(), "ruby".into()]
+    );
+
+    // Other changes reload the whole extension.
+    fs.save(
+        "/projects/zed-ruby/languages/ruby/config.toml".as_ref(),
+        &r#"
+            name = "Ruby"
+            grammar = "ruby"
+            path_suffixes = ["rb", "rake"]
+        "#
+        .into(),
+        Default::default(),
+    )
+    .await
+    .unwrap();
+    cx.executor().run_until_parked();
+    cx.executor().advance_clock(RELOAD_DEBOUNCE_DURATION);
+    cx.executor().run_until_parked();
+
+    let extension_reload_count = std::iter::from_fn(|| events.try_next().ok().flatten())
+        .filter(|event| matches!(event, Event::ReloadProgress(ReloadPhase::Done)))
+        .count();
+    assert_eq!(extension_reload_count, 1);
+}
+
+#[gpui::test]
+async fn test_notify_network_available(cx: &mut TestAppContext) {
+    init_test(cx);
+
+    let fs = FakeFs::new(cx.executor());
+    fs.insert_tree(
+        "/the-extension-dir",
+        json!({
+            "installed": {
+                "zed-monokai": monokai_extension(),
+            }
+        }),
+    )
+    .await;
+
+    let online = Arc::new(AtomicBool::new(false));
+    let requested_paths = Arc::new(Mutex::new(Vec::new()));
+    let http_client = FakeHttpClient::create({
+        let online = online.clone();
+        let requested_paths = requested_paths.clone();
+        move |request| {
+            let online = online.clone();
+            let requested_paths = requested_paths.clone();
+            async move {
+                if !online.load(SeqCst) {
+                    return Err(anyhow!("network is unreachable"));
+                }
+                requested_paths
+                    .lock()
+                    .push(request.uri().path().to_string());
+                Ok(Response::builder()
+                    .status(200)
+                    .body(r#"{"data": []}"#.into())?)
+            }
+        }
+    });
+
+    // The update check on startup fails while offline, and is deferred.
+    let store = new_test_extension_store(
+        fs.clone(),
+        http_client,
+        Arc::new(LanguageRegistry::test(cx.executor())),
+        Arc::new(ThemeRegistry::new(Box::new(()))),
+        cx,
+    );
+    cx.executor().advance_clock(RELOAD_DEBOUNCE_DURATION);
+    cx.executor().run_until_parked();
+    store.update(cx, |store, cx| store.check_for_updates(cx));
+    cx.executor().run_until_parked();
+
+    store.read_with(cx, |store, _| {
+        let check_count = store
+            .deferred_operations
+            .iter()
+            .filter(|operation| **operation == DeferredOperation::CheckForUpdates)
+            .count();
+        assert_eq!(check_count, 1);
+    });
+    assert!(requested_paths.lock().is_empty());
+
+    online.store(true, SeqCst);
+    store.update(cx, |store, cx| store.notify_network_available(cx));
+    cx.executor().run_until_parked();
+
+    assert!(requested_paths
+        .lock()
+        .contains(&"/extensions/updates".to_string()));
+    store.read_with(cx, |store, _| {
+        assert!(store.deferred_operations.is_empty());
+    });
+}
+
+#[gpui::test]
+async fn test_extension_upgraded_event(cx: &mut TestAppContext) {
+    init_test(cx);
+
+    let manifest = |version: &str| {
+        format!(
+            r#"
+                id = "zed-upgradable"
+                name = "Zed Upgradable"
+                version = "{version}"
+                schema_version = 1
+            "#
+        )
+    };
+
+    let TestExtensionStore { fs, store, .. } = init_test_extension_store(
+        json!({
+            "installed": {
+                "zed-upgradable": {
+                    "extension.toml": manifest("1.0.0"),
+                },
+            }
+        }),
+        cx,
+    )
+    .await;
+
+    // Each version is compared with the previous one. Invalid versions are
+    // lower than any valid version.
+    let mut events = cx.events(&store);
+    for (version, expected_upgrade_from) in [
+        ("1.10.0", Some("1.0.0")),
+        ("2.0.0-beta.1", Some("1.10.0")),
+        ("2.0.0", Some("2.0.0-beta.1")),
+        ("1.2.0", None),
+        ("latest", None),
+        ("0.1.0", Some("latest")),
+    ] {
+        fs.save(
+            "/the-extension-dir/installed/zed-upgradable/extension.toml".as_ref(),
+            &manifest(version).as_str().into(),
+            Default::default(),
+        )
+        .await
+        .unwrap();
+        let reload = store.update(cx, |store, cx| {
+            store.reload(Some("zed-upgradable".into()), cx)
+        });
+        finish_reload(reload, cx);
+
+        let upgrades = std::iter::from_fn(|| events.try_next().ok().flatten())
+            .filter_map(|event| match event {
+                Event::ExtensionUpgraded {
+                    extension_id,
+                    from,
+                    to,
+                } => Some((extension_id, from, to)),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        let expected_upgrades = expected_upgrade_from
+            .map(|from| {
+                (
+                    Arc::from("zed-upgradable"),
+                    Arc::from(from),
+                    Arc::from(version),
+                )
+            })
+            .into_iter()
+            .collect::<Vec<_>>();
+        assert_eq!(upgrades, expected_upgrades, "reloading version {version}");
+    }
+}
+
+#[gpui::test]
+async fn test_extension_store_counts(cx: &mut TestAppContext) {
+    init_test(cx);
+
+    let TestExtensionStore { fs, store, .. } = init_test_extension_store(
+        json!({
+            "installed": {
+                "zed-monokai": monokai_extension(),
+                "zed-ruby": ruby_extension(),
+            }
+        }),
+        cx,
+    )
+    .await;
+
+    store.read_with(cx, |store, _| {
+        assert_eq!(store.theme_count(), 4);
+        assert_eq!(store.language_count(), 2);
+        assert_eq!(store.grammar_count(), 2);
+    });
+
+    fs.insert_tree(
+        "/the-extension-dir/installed/zed-gruvbox",
+        gruvbox_extension(),
+    )
+    .await;
+    let reload = store.update(cx, |store, cx| store.reload(None, cx));
+    finish_reload(reload, cx);
+
+    store.read_with(cx, |store, _| {
+        assert_eq!(store.theme_count(), 5);
+        assert_eq!(store.language_count(), 2);
+        assert_eq!(store.grammar_count(), 2);
+    });
+}
+
+#[gpui::test]
+async fn test_incremental_reload(cx: &mut TestAppContext) {
+    init_test(cx);
+
+    let theme_extension = |ix: usize| {
+        json!({
+            "extension.json": format!(r#"{{ "name": "Theme {ix}", "version": "1.0.0" }}"#),
+            "themes": {
+                "theme.json": format!(
+                    r#"{{
+                        "name": "Theme {ix}",
+                        "author": "Someone",
+                        "themes": [{{ "name": "Theme {ix}", "appearance": "dark", "style": {{}} }}]
+                    }}"#
+                ),
+            }
+        })
+    };
+
+    let fs = FakeFs::new(cx.executor());
+    fs.insert_tree("/the-extension-dir", json!({ "installed": {} }))
+        .await;
+    for ix in 0..10 {
+        fs.insert_tree(
+            format!("/the-extension-dir/installed/theme-{ix}"),
+            theme_extension(ix),
+        )
+        .await;
+    }
+
+    let store = new_test_extension_store(
+        fs.clone(),
+        FakeHttpClient::with_200_response(),
+        Arc::new(LanguageRegistry::test(cx.executor())),
+        Arc::new(ThemeRegistry::new(Box::new(()))),
+        cx,
+    );
+    cx.executor().advance_clock(RELOAD_DEBOUNCE_DURATION);
+    cx.executor().run_until_parked();
+
+    let manifests = |store: &ExtensionStore| {
+        store
+            .extension_index
+            .extensions
+            .iter()
+            .map(|(extension_id, extension)| (extension_id.clone(), extension.manifest.clone()))
+            .collect::<BTreeMap<_, _>>()
+    };
+    let old_manifests = store.read_with(cx, |store, _| manifests(store));
+    assert_eq!(old_manifests.len(), 10);
+
+    // Adding an extension only reads the new extension's directory.
+    let prev_fs_metadata_call_count = fs.metadata_call_count();
+    fs.insert_tree("/the-extension-dir/installed/theme-10", theme_extension(10))
+        .await;
+    cx.executor().advance_clock(RELOAD_DEBOUNCE_DURATION);
+    cx.executor().run_until_parked();
+
+    let new_manifests = store.read_with(cx, |store, _| {
+        assert_eq!(store.extension_themes("theme-10").count(), 1);
+        manifests(store)
+    });
+    assert_eq!(fs.metadata_call_count(), prev_fs_metadata_call_count + 1);
+    assert_eq!(new_manifests.len(), 11);
+    for (extension_id, manifest) in &old_manifests {
+        assert!(Arc::ptr_eq(manifest, &new_manifests[extension_id]));
+    }
+
+    // Only the given extension is read again when it is reloaded on its own.
+    let reload = store.update(cx, |store, cx| store.reload_extension("theme-3".into(), cx));
+    finish_reload(reload, cx);
+
+    let reloaded_manifests = store.read_with(cx, |store, _| manifests(store));
+    assert_eq!(reloaded_manifests, new_manifests);
+    for (extension_id, manifest) in &new_manifests {
+        assert_eq!(
+            Arc::ptr_eq(manifest, &reloaded_manifests[extension_id]),
+            extension_id.as_ref() != "theme-3",
+            "unexpected reload of {extension_id}"
+        );
+    }
+
+    // A full reload reads every extension again.
+    let reload = store.update(cx, |store, cx| store.reload(None, cx));
+    finish_reload(reload, cx);
+
+    let fully_reloaded_manifests = store.read_with(cx, |store, _| manifests(store));
+    for (extension_id, manifest) in &reloaded_manifests {
+        assert!(!Arc::ptr_eq(
+            manifest,
+            &fully_reloaded_manifests[extension_id]
+        ));
+    }
+}
+
+#[gpui::test]
+async fn test_invalid_extension_manifests(cx: &mut TestAppContext) {
+    init_test(cx);
+
+    let TestExtensionStore { fs, store, .. } = init_test_extension_store(
+        json!({
+            "installed": {
+                "zed-monokai": monokai_extension(),
+                "no-name": {
+                    "extension.json": r#"{ "id": "no-name", "version": "1.0.0" }"#,
+                },
+                "bad-version": {
+                    "extension.json": r#"{ "name": "Bad Version", "version": 1 }"#,
+                },
+                "bad-id": {
+                    "extension.json": r#"{ "id": ["bad-id"], "name": "Bad ID" }"#,
+                },
+                "not-json": {
+                    "extension.json": "{ this is not json",
+                },
+                "zed-broken-1.2.0": {
+                    "extension.json": r#"{ "id": "zed-broken", "version": "1.2.0" }"#,
+                },
+            }
+        }),
+        cx,
+    )
+    .await;
+
+    let installed_dir = PathBuf::from("/the-extension-dir/installed");
+    store.read_with(cx, |store, _| {
+        assert_eq!(
+            store.extension_index.extensions.keys().collect::<Vec<_>>(),
+            [&Arc::from("zed-monokai")]
+        );
+
+        let mut manifest_errors = store
+            .manifest_errors()
+            .iter()
+            .map(|error| {
+                (
+                    error.extension_dir.clone(),
+                    error.field.as_deref().map(str::to_string),
+                )
+            })
+            .collect::<Vec<_>>();
+        manifest_errors.sort();
+        assert_eq!(
+            manifest_errors,
+            [
+                (installed_dir.join("bad-id"), Some("id".to_string())),
+                (
+                    installed_dir.join("bad-version"),
+                    Some("version".to_string())
+                ),
+                (installed_dir.join("no-name"), Some("name".to_string())),
+                (installed_dir.join("not-json"), None),
+                (
+                    installed_dir.join("zed-broken-1.2.0"),
+                    Some("name".to_string())
+                ),
+            ]
+        );
+
+        let mut load_error_ids = store
+            .load_errors()
+            .iter()
+            .filter(|error| error.severity == LoadErrorSeverity::Error)
+            .map(|error| error.extension_id.clone())
+            .collect::<Vec<_>>();
+        load_error_ids.sort();
+        assert_eq!(
+            load_error_ids,
+            [
+                Arc::from("bad-id"),
+                Arc::from("bad-version"),
+                Arc::from("no-name"),
+                Arc::from("not-json"),
+                // The errors of a versioned directory are reported for the
+                // extension's ID, rather than the directory's name.
+                Arc::from("zed-broken"),
+            ]
+        );
+    });
+
+    let not_json_error = store.read_with(cx, |store, _| {
+        store
+            .manifest_errors()
+            .iter()
+            .find(|error| error.field.is_none())
+            .cloned()
+            .unwrap()
+    });
+    assert!(not_json_error
+        .to_string()
+        .contains("/the-extension-dir/installed/not-json/extension.json"));
+
+    // Fixing a manifest removes its error and loads the extension.
+    fs.insert_file(
+        "/the-extension-dir/installed/bad-version/extension.json",
+        r#"{ "name": "Bad Version", "version": "1.0.0" }"#.into(),
+    )
+    .await;
+    let reload = store.update(cx, |store, cx| store.reload(None, cx));
+    finish_reload(reload, cx);
+
+    store.read_with(cx, |store, _| {
+        assert!(store.extension_index.extensions.contains_key("bad-version"));
+        assert_eq!(store.manifest_errors().len(), 4);
+        assert!(store
+            .manifest_errors()
+            .iter()
+            .all(|error| !error.extension_dir.ends_with("bad-version")));
+        assert!(store
+            .load_errors()
+            .iter()
+            .all(|error| error.extension_id.as_ref() != "bad-version"));
+    });
+}
+
+#[gpui::test]
+async fn test_extension_language_variants(cx: &mut TestAppContext) {
+    init_test(cx);
+
+    let TestExtensionStore {
+        store,
+        language_registry,
+        ..
+    } = init_test_extension_store(
+        json!({
+            "installed": {
+                "zed-sql": {
+                    "extension.toml": r#"
+                        id = "zed-sql"
+                        name = "Zed SQL"
+                        version = "1.0.0"
+                        schema_version = 1
+                    "#,
+                    "languages": {
+                        "sql": {
+                            "config.toml": r#"
+                                grammar = "sql"
+                                line_comments = ["-- "]
+
+                                [[languages]]
+                                name = "PostgreSQL"
+                                path_suffixes = ["pgsql"]
+
+                                [[languages]]
+                                name = "SQLite"
+                                path_suffixes = ["sqlite"]
+                                line_comments = ["# "]
+                            "#,
+                        }
+                    },
+                },
+            }
+        }),
+        cx,
+    )
+    .await;
+
+    assert_eq!(
+        language_registry.language_names(),
+        ["Plain Text", "PostgreSQL", "SQLite"]
+    );
+    store.read_with(cx, |store, _| {
+        let languages = &store.extension_index.languages;
+        assert_eq!(languages["PostgreSQL"].variant, Some(0));
+        assert_eq!(languages["SQLite"].variant, Some(1));
+        assert_eq!(languages["SQLite"].path, PathBuf::from("languages/sql"));
+        assert_eq!(
+            store.installed_extension_entries()["zed-sql"]
+                .manifest
+                .languages,
+            [PathBuf::from("languages/sql")]
+        );
+    });
+
+    // Each variant has its own configuration, along with the shared one.
+    let postgres = store
+        .read_with(cx, |store, cx| store.language_config("PostgreSQL", cx))
+        .await
+        .unwrap();
+    let sqlite = store
+        .read_with(cx, |store, cx| store.language_config("SQLite", cx))
+        .await
+        .unwrap();
+    assert_eq!(postgres.grammar.as_deref(), Some("sql"));
+    assert_eq!(postgres.matcher.path_suffixes, ["pgsql"]);
+    assert_eq!(postgres.line_comments, [Arc::from("-- ")]);
+    assert_eq!(sqlite.grammar.as_deref(), Some("sql"));
+    assert_eq!(sqlite.matcher.path_suffixes, ["sqlite"]);
+    assert_eq!(sqlite.line_comments, [Arc::from("# ")]);
+}
+
+#[gpui::test]
+async fn test_extension_store_min_zed_version(cx: &mut TestAppContext) {
+    init_test(cx);
+
+    let fs = FakeFs::new(cx.executor());
+    fs.insert_tree(
+        "/the-extension-dir",
+        json!({
+            "installed": {
+                "zed-future": {
+                    "extension.toml": r#"
+                        id = "zed-future"
+                        name = "Zed Future"
+                        version = "1.0.0"
+                        schema_version = 1
+                        min_zed_version = "99.0.0"
+                    "#,
+                    "themes": {
+                        "future.json": r#"{
+                            "name": "Future",
+                            "author": "Someone",
+                            "themes": [
+                                { "name": "Future Dark", "appearance": "dark", "style": {} }
+                            ]
+                        }"#,
+                    },
+                },
+            }
+        }),
+    )
+    .await;
+
+    cx.update(|cx| {
+        SettingsStore::update_global(cx, |store, cx| {
+            store.update_user_settings::<ExtensionSettings>(cx, |settings| {
+                settings
+                    .load_incompatible_extensions
+                    .insert("zed-future".into(), true);
+            });
+        });
+    });
+
+    let theme_registry = Arc::new(ThemeRegistry::new(Box::new(())));
+    let store = new_test_extension_store(
+        fs.clone(),
+        FakeHttpClient::with_200_response(),
+        Arc::new(LanguageRegistry::test(cx.executor())),
+        theme_registry.clone(),
+        cx,
+    );
+    cx.executor().advance_clock(RELOAD_DEBOUNCE_DURATION);
+    cx.executor().run_until_parked();
+
+    assert_eq!(
+        theme_registry.list_names(false),
+        ["Future Dark", "One Dark"]
+    );
+
+    // Without the override, the extension is installed but not loaded.
+    cx.update(|cx| {
+        SettingsStore::update_global(cx, |store, cx| {
+            store.update_user_settings::<ExtensionSettings>(cx, |settings| {
+                settings.load_incompatible_extensions.clear();
+            });
+        });
+    });
+    let reload = store.update(cx, |store, cx| store.reload(Some("zed-future".into()), cx));
+    finish_reload(reload, cx);
+
+    store.read_with(cx, |store, _| {
+        assert!(store
+            .installed_extension_entries()
+            .contains_key("zed-future"));
+    });
+    assert_eq!(theme_registry.list_names(false), ["One Dark"]);
+}
+
+#[gpui::test]
+async fn test_extension_store_reports_missing_syntax_tokens(cx: &mut TestAppContext) {
+    init_test(cx);
+
+    let syntax = [
+        "attribute",
+        "boolean",
+        "constant",
+        "function",
+        "keyword",
+        "number",
+        "operator",
+        "property",
+        "punctuation",
+        "type",
+        "variable",
+    ]
+    .into_iter()
+    .map(|token| (token.to_string(), json!({ "color": "#ffffffff" })))
+    .collect::<serde_json::Map<_, _>>();
+
+    let TestExtensionStore {
+        store,
+        theme_registry,
+        ..
+    } = init_test_extension_store(
+        json!({
+            "installed": {
+                "zed-partial": {
+                    "extension.json": r#"{
+                        "id": "zed-partial",
+                        "name": "Zed Partial",
+                        "version": "1.0.0"
+                    }"#,
+                    "themes": {
+                        "partial.json": json!({
+                            "name": "Partial",
+                            "author": "Someone",
+                            "themes": [
+                                {
+                                    "name": "Partial Dark",
+                                    "appearance": "dark",
+                                    "style": { "syntax": syntax }
+                                }
+                            ]
+                        })
+                        .to_string(),
+                    },
+                },
+            }
+        }),
+        cx,
+    )
+    .await;
+
+    assert_eq!(
+        theme_registry.list_names(false),
+        ["One Dark", "Partial Dark"]
+    );
+    store.read_with(cx, |store, _| {
+        assert_eq!(
+            store.load_errors(),
+            [ExtensionLoadError {
+                extension_id: "zed-partial".into(),
+                severity: LoadErrorSeverity::Warning,
+                message:
+                    r#"theme "Partial Dark" does not style the syntax tokens: comment, string"#
+                        .into(),
+            }]
+        );
+    });
+}
+
+#[gpui::test]
+async fn test_themes_with_appearance(cx: &mut TestAppContext) {
+    init_test(cx);
+
+    let TestExtensionStore { fs, store, .. } = init_test_extension_store(
+        json!({
+            "installed": {
+                "zed-monokai": monokai_extension(),
+                "zed-gruvbox": gruvbox_extension(),
+            }
+        }),
+        cx,
+    )
+    .await;
+
+    store.read_with(cx, |store, _| {
+        assert_eq!(
+            store.themes_with_appearance(Appearance::Dark),
+            [
+                Arc::from("Gruvbox"),
+                Arc::from("Monokai Dark"),
+                Arc::from("Monokai Pro Dark"),
+            ]
+        );
+        assert_eq!(
+            store.themes_with_appearance(Appearance::Light),
+            [Arc::from("Monokai Light"), Arc::from("Monokai Pro Light")]
+        );
+    });
+
+    // An index written before theme appearances were recorded is rebuilt on startup.
+    store.update(cx, |store, cx| store.flush(cx)).await.unwrap();
+    let index_path = Path::new("/the-extension-dir/index.json");
+    let mut index_json: serde_json::Value =
+        serde_json::from_str(&fs.load(index_path).await.unwrap()).unwrap();
+    for theme in index_json["themes"].as_object_mut().unwrap().values_mut() {
+        theme.as_object_mut().unwrap().remove("appearance");
+    }
+    fs.save(
+        index_path,
+        &index_json.to_string().as_str().into(),
+        Default::default(),
+    )
+    .await
+    .unwrap();
+
+    let store = new_test_extension_store(
+        fs.clone(),
+        FakeHttpClient::with_200_response(),
+        Arc::new(LanguageRegistry::test(cx.executor())),
+        Arc::new(ThemeRegistry::new(Box::new(()))),
+        cx,
+    );
+    store.read_with(cx, |store, _| {
+        assert!(store.themes_with_appearance(Appearance::Light).is_empty());
+    });
+    cx.executor().advance_clock(RELOAD_DEBOUNCE_DURATION);
+    cx.executor().run_until_parked();
+
+    store.read_with(cx, |store, _| {
+        assert_eq!(
+            store.themes_with_appearance(Appearance::Light),
+            [Arc::from("Monokai Light"), Arc::from("Monokai Pro Light")]
+        );
+    });
+    let index: ExtensionIndex = serde_json::from_str(&fs.load(index_path).await.unwrap()).unwrap();
+    assert!(index
+        .themes
+        .values()
+        .all(|theme| theme.appearance.is_some()));
+}
+
+#[gpui::test]
+async fn test_auto_theme_for_family(cx: &mut TestAppContext) {
+    init_test(cx);
+
+    let TestExtensionStore {
+        store,
+        theme_registry,
+        ..
+    } = init_test_extension_store(
+        json!({
+            "installed": {
+                "zed-monokai": monokai_extension(),
+                "zed-gruvbox": gruvbox_extension(),
+            }
+        }),
+        cx,
+    )
+    .await;
+
+    assert_eq!(
+        theme_registry.auto_theme_for_family("Monokai"),
+        Some(AutoTheme {
+            light: "Monokai Light".into(),
+            dark: "Monokai Dark".into(),
+        })
+    );
+    // A family with only a dark theme has no pairing.
+    assert_eq!(theme_registry.auto_theme_for_family("Gruvbox"), None);
+    assert_eq!(theme_registry.auto_theme_for_family("Unknown"), None);
+
+    // The pairing goes away along with the extension's themes.
+    store.update(cx, |store, cx| {
+        store
+            .uninstall_extension("zed-monokai".into(), UninstallOptions::default(), cx)
+            .unwrap()
+    });
+    cx.executor().advance_clock(RELOAD_DEBOUNCE_DURATION);
+    cx.executor().run_until_parked();
+
+    assert_eq!(theme_registry.auto_theme_for_family("Monokai"), None);
+}
+
+#[gpui::test]
+async fn test_extension_theme_semantic_roles(cx: &mut TestAppContext) {
+    init_test(cx);
+
+    let syntax = crate::STANDARD_SYNTAX_TOKENS
+        .iter()
+        .map(|token| (token.to_string(), json!({ "color": "#ffffffff" })))
+        .collect::<serde_json::Map<_, _>>();
+
+    let TestExtensionStore {
+        store,
+        theme_registry,
+        ..
+    } = init_test_extension_store(
+        json!({
+            "installed": {
+                "zed-roles": {
+                    "extension.json": r#"{
+                        "id": "zed-roles",
+                        "name": "Zed Roles",
+                        "version": "1.0.0"
+                    }"#,
+                    "themes": {
+                        "roles.json": json!({
+                            "name": "Roles",
+                            "author": "Someone",
+                            "themes": [
+                                {
+                                    "name": "Roles Dark",
+                                    "appearance": "dark",
+                                    "style": {
+                                        "accents": ["#ff0000ff", "#0000ffff"],
+                                        "roles": {
+                                            "primary": "#00ff00ff",
+                                            "warning": "#ffff00ff",
+                                            "danger": "not-a-color"
+                                        },
+                                        "syntax": syntax
+                                    }
+                                }
+                            ]
+                        })
+                        .to_string(),
+                    },
+                },
+            }
+        }),
+        cx,
+    )
+    .await;
+
+    let theme = theme_registry.get("Roles Dark").unwrap();
+    assert_eq!(theme.accents().0.len(), 2);
+    let roles = theme
+        .roles()
+        .0
+        .keys()
+        .map(|role| role.as_ref())
+        .collect::<Vec<_>>();
+    assert_eq!(roles, ["primary", "warning"]);
+    assert!(theme.roles().get("primary").is_some());
+    assert!(theme.roles().get("danger").is_none());
+
+    store.read_with(cx, |store, _| {
+        assert_eq!(
+            store.load_errors(),
+            [ExtensionLoadError {
+                extension_id: "zed-roles".into(),
+                severity: LoadErrorSeverity::Warning,
+                message: r#"theme "Roles Dark" has invalid colors, which will be ignored: roles.danger ("not-a-color")"#
+                    .into(),
+            }]
+        );
+    });
+}
+
+#[gpui::test]
+async fn test_extension_version_from_version_file(cx: &mut TestAppContext) {
+    init_test(cx);
+
+    let TestExtensionStore { store, .. } = init_test_extension_store(
+        json!({
+            "installed": {
+                "zed-versionless": {
+                    "extension.json": r#"{
+                        "id": "zed-versionless",
+                        "name": "Zed Versionless"
+                    }"#,
+                    "VERSION": "1.2.3\n",
+                },
+                "zed-unversioned": {
+                    "extension.toml": r#"
+                        id = "zed-unversioned"
+                        name = "Zed Unversioned"
+                        schema_version = 1
+                    "#,
+                },
+            }
+        }),
+        cx,
+    )
+    .await;
+
+    store.read_with(cx, |store, _| {
+        let installed_extensions = store.installed_extension_entries();
+        assert_eq!(
+            installed_extensions["zed-versionless"]
+                .manifest
+                .version
+                .as_ref(),
+            "1.2.3"
+        );
+        // Extensions without any version are not loaded.
+        assert!(!installed_extensions.contains_key("zed-unversioned"));
+    });
+}
+
+#[gpui::test]
+async fn test_extension_json_capability_hints(cx: &mut TestAppContext) {
+    init_test(cx);
+
+    // Extensions may declare how many of each thing they provide. The hints
+    // are accepted, but don't change how the extension is loaded.
+    let mut hinted_extension = ruby_extension();
+    hinted_extension["extension.json"] = r#"{
+        "id": "zed-ruby",
+        "name": "Zed Ruby",
+        "version": "1.0.0",
+        "grammars": {
+            "ruby": "grammars/ruby.wasm",
+            "embedded_template": "grammars/embedded_template.wasm"
+        },
+        "capabilities": {
+            "languages": 2,
+            "grammars": 2,
+            "themes": 0
+        }
+    }"#
+    .into();
+
+    let mut loaded = Vec::new();
+    for extension in [ruby_extension(), hinted_extension] {
+        let fs = FakeFs::new(cx.executor());
+        fs.insert_tree(
+            "/the-extension-dir",
+            json!({ "installed": { "zed-ruby": extension } }),
+        )
+        .await;
+
+        let language_registry = Arc::new(LanguageRegistry::test(cx.executor()));
+        let store = new_test_extension_store(
+            fs,
+            FakeHttpClient::with_200_response(),
+            language_registry.clone(),
+            Arc::new(ThemeRegistry::new(Box::new(()))),
+            cx,
+        );
+        cx.executor().advance_clock(RELOAD_DEBOUNCE_DURATION);
+        cx.executor().run_until_parked();
+
+        let manifest = store.read_with(cx, |store, _| {
+            assert!(store.load_errors().is_empty());
+            store.installed_extension_entries()["zed-ruby"]
+                .manifest
+                .clone()
+        });
+        assert_eq!(
+            language_registry.language_names(),
+            ["ERB", "Plain Text", "Ruby"]
+        );
+        loaded.push(manifest);
+    }
+    assert_eq!(loaded[0], loaded[1]);
+}
+
+#[gpui::test]
+async fn test_validate_all_themes(cx: &mut TestAppContext) {
+    init_test(cx);
+
+    let TestExtensionStore {
+        fs,
+        store,
+        theme_registry,
+        ..
+    } = init_test_extension_store(
+        json!({
+            "installed": {
+                "zed-gruvbox": gruvbox_extension(),
+            }
+        }),
+        cx,
+    )
+    .await;
+
+    fs.insert_file(
+        "/the-extension-dir/installed/zed-gruvbox/themes/broken.json",
+        r#"{ "name": "Broken", "themes": [ { "name": "Broken Dark" } ] }"#
+            .as_bytes()
+            .to_vec(),
+    )
+    .await;
+
+    let results = store
+        .update(cx, |store, cx| store.validate_all_themes(cx))
+        .await;
+    assert_eq!(
+        results
+            .iter()
+            .map(|result| (result.path.as_path(), result.is_valid()))
+            .collect::<Vec<_>>(),
+        [
+            (Path::new("themes/broken.json"), false),
+            (Path::new("themes/gruvbox.json"), true),
+        ]
+    );
+    assert!(results[0].theme_names.is_empty());
+    assert_eq!(results[1].theme_names, ["Gruvbox"]);
+
+    // Validating themes does not register them.
+    assert_eq!(theme_registry.list_names(false), ["Gruvbox", "One Dark"]);
+}
+
+#[gpui::test]
+async fn test_unregister_all(cx: &mut TestAppContext) {
+    init_test(cx);
+
+    let TestExtensionStore {
+        store,
+        language_registry,
+        theme_registry,
+        ..
+    } = init_test_extension_store(
+        json!({
+            "installed": {
+                "zed-gruvbox": gruvbox_extension(),
+                "zed-ruby": ruby_extension(),
+            }
+        }),
+        cx,
+    )
+    .await;
+    assert_eq!(
+        language_registry.language_names(),
+        ["ERB", "Plain Text", "Ruby"]
+    );
+    assert_eq!(theme_registry.list_names(false), ["Gruvbox", "One Dark"]);
+
+    store.update(cx, |store, cx| store.unregister_all(cx));
+    assert_eq!(language_registry.language_names(), ["Plain Text"]);
+    assert_eq!(language_registry.grammar_names(), []);
+    assert_eq!(theme_registry.list_names(false), ["One Dark"]);
+    store.read_with(cx, |store, _| {
+        assert_eq!(store.registered, ExtensionArtifacts::default());
+        // The extensions remain installed.
+        assert!(store.installed_extension_entries().contains_key("zed-ruby"));
+        assert!(store
+            .installed_extension_entries()
+            .contains_key("zed-gruvbox"));
+    });
+}
+
+#[gpui::test]
+async fn test_theme_name_collisions(cx: &mut TestAppContext) {
+    init_test(cx);
+
+    let TestExtensionStore {
+        fs,
+        store,
+        theme_registry,
+        ..
+    } = init_test_extension_store(
+        json!({
+            "installed": {
+                "zed-retro": {
+                    "extension.json": r#"{
+                        "id": "zed-retro",
+                        "name": "Zed Retro",
+                        "version": "1.0.0"
+                    }"#,
+                    "themes": {
+                        "retro.json": r#"{
+                            "name": "Retro",
+                            "author": "Someone",
+                            "themes": [
+                                { "name": "Gruvbox", "appearance": "light", "style": {} },
+                                { "name": "Retro Dark", "appearance": "dark", "style": {} }
+                            ]
+                        }"#,
+                    }
+                },
+            }
+        }),
+        cx,
+    )
+    .await;
+    assert_eq!(
+        theme_registry.list_names(false),
+        ["Gruvbox", "One Dark", "Retro Dark"]
+    );
+
+    // An extension whose ID sorts first takes the name, even though it is
+    // installed later.
+    fs.insert_tree(
+        "/the-extension-dir/installed",
+        json!({ "zed-gruvbox": gruvbox_extension() }),
+    )
+    .await;
+    let reload = store.update(cx, |store, cx| store.reload(None, cx));
+    finish_reload(reload, cx);
+
+    assert_eq!(
+        theme_registry.list_names(false),
+        ["Gruvbox", "Gruvbox (zed-retro)", "One Dark", "Retro Dark"]
+    );
+    assert_eq!(
+        theme_registry.get("Gruvbox").unwrap().appearance,
+        Appearance::Dark
+    );
+    assert_eq!(
+        theme_registry
+            .get("Gruvbox (zed-retro)")
+            .unwrap()
+            .appearance,
+        Appearance::Light
+    );
+    store.read_with(cx, |store, _| {
+        assert_eq!(
+            store.extension_for_theme("Gruvbox").as_deref(),
+            Some("zed-gruvbox")
+        );
+        assert_eq!(
+            store.extension_for_theme("Gruvbox (zed-retro)").as_deref(),
+            Some("zed-retro")
+        );
+        assert_eq!(
+            store.theme_families()["Retro"].themes,
+            [Arc::from("Gruvbox (zed-retro)"), Arc::from("Retro Dark")]
+        );
+        assert_eq!(
+            store.load_errors(),
+            [ExtensionLoadError {
+                extension_id: "zed-retro".into(),
+                severity: LoadErrorSeverity::Warning,
+                message: r#"theme "Gruvbox" is registered as "Gruvbox (zed-retro)", because extension zed-gruvbox also provides a theme with that name"#.into(),
+            }]
+        );
+    });
+
+    // A full rebuild decides the same names.
+    let other_store = new_test_extension_store(
+        fs.clone(),
+        FakeHttpClient::with_200_response(),
+        Arc::new(LanguageRegistry::test(cx.executor())),
+        Arc::new(ThemeRegistry::new(Box::new(()))),
+        cx,
+    );
+    cx.executor().advance_clock(RELOAD_DEBOUNCE_DURATION);
+    cx.executor().run_until_parked();
+    other_store.read_with(cx, |store, _| {
+        assert_eq!(
+            store.extension_for_theme("Gruvbox").as_deref(),
+            Some("zed-gruvbox")
+        );
+        assert_eq!(
+            store.extension_for_theme("Gruvbox (zed-retro)").as_deref(),
+            Some("zed-retro")
+        );
+    });
+    drop(other_store);
+
+    // Once the other extension is uninstalled, the theme takes its own name.
+    fs.remove_dir(
+        Path::new("/the-extension-dir/installed/zed-gruvbox"),
+        RemoveOptions {
+            recursive: true,
+            ignore_if_not_exists: false,
+        },
+    )
+    .await
+    .unwrap();
+    let reload = store.update(cx, |store, cx| store.reload(None, cx));
+    finish_reload(reload, cx);
+
+    assert_eq!(
+        theme_registry.list_names(false),
+        ["Gruvbox", "One Dark", "Retro Dark"]
+    );
+    assert_eq!(
+        theme_registry.get("Gruvbox").unwrap().appearance,
+        Appearance::Light
+    );
+    store.read_with(cx, |store, _| {
+        assert_eq!(
+            store.extension_for_theme("Gruvbox").as_deref(),
+            Some("zed-retro")
+        );
+        assert!(store.load_errors().is_empty());
+    });
+}
+
+#[gpui::test]
+async fn test_language_name_collisions(cx: &mut TestAppContext) {
+    init_test(cx);
+
+    let TestExtensionStore {
+        fs,
+        store,
+        language_registry,
+        ..
+    } = init_test_extension_store(
+        json!({
+            "installed": {
+                "zed-ruby-next": {
+                    "extension.json": r#"{
+                        "id": "zed-ruby-next",
+                        "name": "Zed Ruby Next",
+                        "version": "1.0.0",
+                        "grammars": {
+                            "ruby": "grammars/ruby.wasm"
+                        }
+                    }"#,
+                    "grammars": {
+                        "ruby.wasm": "",
+                    },
+                    "languages": {
+                        "ruby": {
+                            "config.toml": r#"
+                                name = "Ruby"
+                                grammar = "ruby"
+                                path_suffixes = ["rbx"]
+                            "#,
+                            "highlights.scm": "",
+                        },
+                    },
+                },
+            }
+        }),
+        cx,
+    )
+    .await;
+    store.read_with(cx, |store, _| {
+        assert_eq!(
+            store.extension_for_language("Ruby").as_deref(),
+            Some("zed-ruby-next")
+        );
+    });
+
+    // An extension whose ID sorts first provides the language and the grammar,
+    // even though it is installed later.
+    fs.insert_tree(
+        "/the-extension-dir/installed",
+        json!({ "zed-ruby": ruby_extension() }),
+    )
+    .await;
+    let reload = store.update(cx, |store, cx| store.reload(None, cx));
+    finish_reload(reload, cx);
+
+    assert_eq!(
+        language_registry.language_names(),
+        ["ERB", "Plain Text", "Ruby"]
+    );
+    assert_eq!(
+        language_registry.grammar_names(),
+        [Arc::from("embedded_template"), Arc::from("ruby")]
+    );
+    store.read_with(cx, |store, _| {
+        assert_eq!(
+            store.extension_for_language("Ruby").as_deref(),
+            Some("zed-ruby")
+        );
+        assert_eq!(
+            store.extension_for_grammar("ruby").as_deref(),
+            Some("zed-ruby")
+        );
+        assert_eq!(
+            store.claimed_suffixes().keys().collect::<Vec<_>>(),
+            ["erb", "rb"]
+        );
+        assert_eq!(
+            store.load_errors(),
+            [
+                ExtensionLoadError {
+                    extension_id: "zed-ruby-next".into(),
+                    severity: LoadErrorSeverity::Warning,
+                    message: r#"language "Ruby" is not registered, because extension zed-ruby also provides a language with that name"#.into(),
+                },
+                ExtensionLoadError {
+                    extension_id: "zed-ruby-next".into(),
+                    severity: LoadErrorSeverity::Warning,
+                    message: r#"grammar "ruby" is not registered, because extension zed-ruby also provides a grammar with that name"#.into(),
+                },
+            ]
+        );
+    });
+
+    // Once the other extension is uninstalled, the shadowed language and
+    // grammar are registered in its place.
+    fs.remove_dir(
+        Path::new("/the-extension-dir/installed/zed-ruby"),
+        RemoveOptions {
+            recursive: true,
+            ignore_if_not_exists: false,
+        },
+    )
+    .await
+    .unwrap();
+    let reload = store.update(cx, |store, cx| store.reload(None, cx));
+    finish_reload(reload, cx);
+
+    assert_eq!(language_registry.language_names(), ["Plain Text", "Ruby"]);
+    assert_eq!(language_registry.grammar_names(), [Arc::from("ruby")]);
+    store.read_with(cx, |store, _| {
+        assert_eq!(
+            store.extension_for_language("Ruby").as_deref(),
+            Some("zed-ruby-next")
+        );
+        assert_eq!(store.claimed_suffixes().keys().collect::<Vec<_>>(), ["rbx"]);
+        assert!(store.load_errors().is_empty());
+    });
+}
+
+#[gpui::test]
+async fn test_theme_changes(cx: &mut TestAppContext) {
+    init_test(cx);
+
+    let fs = FakeFs::new(cx.executor());
+    fs.insert_tree("/the-extension-dir", json!({ "installed": {} }))
+        .await;
+
+    let store = new_test_extension_store(
+        fs.clone(),
+        FakeHttpClient::with_200_response(),
+        Arc::new(LanguageRegistry::test(cx.executor())),
+        Arc::new(ThemeRegistry::new(Box::new(()))),
+        cx,
+    );
+    cx.executor().advance_clock(RELOAD_DEBOUNCE_DURATION);
+    cx.executor().run_until_parked();
+    store.read_with(cx, |store, _| {
+        assert_eq!(store.theme_changes(), &ThemeChanges::default());
+    });
+
+    fs.insert_tree(
+        "/the-extension-dir/installed",
+        json!({ "zed-gruvbox": gruvbox_extension() }),
+    )
+    .await;
+    let reload = store.update(cx, |store, cx| store.reload(None, cx));
+    finish_reload(reload, cx);
+    store.read_with(cx, |store, _| {
+        assert_eq!(
+            store.theme_changes(),
+            &ThemeChanges {
+                added: BTreeSet::from_iter([Arc::from("Gruvbox")]),
+                ..Default::default()
+            }
+        );
+    });
+
+    fs.insert_file(
+        "/the-extension-dir/installed/zed-gruvbox/themes/gruvbox.json",
+        r##"{
+            "name": "Gruvbox",
+            "author": "Someone Else",
+            "themes": [
+                { "name": "Gruvbox", "appearance": "dark", "style": { "accents": ["#ff0000ff"] } }
+            ]
+        }"##
+        .into(),
+    )
+    .await;
+    let reload = store.update(cx, |store, cx| store.reload(Some("zed-gruvbox".into()), cx));
+    finish_reload(reload, cx);
+    store.read_with(cx, |store, _| {
+        assert_eq!(
+            store.theme_changes(),
+            &ThemeChanges {
+                changed: BTreeSet::from_iter([Arc::from("Gruvbox")]),
+                ..Default::default()
+            }
+        );
+    });
+
+    fs.remove_dir(
+        Path::new("/the-extension-dir/installed/zed-gruvbox"),
+        RemoveOptions {
+            recursive: true,
+            ignore_if_not_exists: false,
+        },
+    )
+    .await
+    .unwrap();
+    let reload = store.update(cx, |store, cx| store.reload(None, cx));
+    finish_reload(reload, cx);
+    store.read_with(cx, |store, _| {
+        assert_eq!(
+            store.theme_changes(),
+            &ThemeChanges {
+                removed: BTreeSet::from_iter([Arc::from("Gruvbox")]),
+                ..Default::default()
+            }
+        );
+    });
+}
+
+#[gpui::test]
+async fn test_find_extension_by_language_server(cx: &mut TestAppContext) {
+    init_test(cx);
+
+    let TestExtensionStore { store, .. } = init_test_extension_store(
+        json!({
+            "installed": {
+                "zed-ruby": ruby_extension(),
+                "the-lsp-extension": {
+                    "extension.toml": r#"
+                        id = "the-lsp-extension"
+                        name = "The LSP Extension"
+                        version = "0.1.0"
+                        schema_version = 1
+
+                        [language_servers.the-server]
+                        language = "Ruby"
+                    "#,
+                },
+            }
+        }),
+        cx,
+    )
+    .await;
+
+    store.read_with(cx, |store, _| {
+        assert_eq!(
+            store
+                .find_extension_by_language_server(&LanguageServerName("the-server".into()))
+                .as_deref(),
+            Some("the-lsp-extension")
+        );
+        assert_eq!(
+            store.find_extension_by_language_server(&LanguageServerName(
+                "the-unknown-server".into()
+            )),
+            None
+        );
+    });
+}
+
+#[gpui::test]
+async fn test_claimed_suffixes(cx: &mut TestAppContext) {
+    init_test(cx);
+
+    let TestExtensionStore { store, .. } = init_test_extension_store(
+        json!({
+            "installed": {
+                "zed-ruby": ruby_extension(),
+                "zed-crystal": {
+                    "extension.json": r#"{
+                        "id": "zed-crystal",
+                        "name": "Zed Crystal",
+                        "version": "1.0.0"
+                    }"#,
+                    "languages": {
+                        "crystal": {
+                            "config.toml": r#"
+                                name = "Crystal"
+                                path_suffixes = ["cr", "rb"]
+                            "#,
+                        },
+                    },
+                },
+            }
+        }),
+        cx,
+    )
+    .await;
+
+    store.read_with(cx, |store, _| {
+        let claimed_suffixes = store.claimed_suffixes();
+        assert_eq!(
+            claimed_suffixes
+                .keys()
+                .map(String::as_str)
+                .collect::<Vec<_>>(),
+            ["cr", "erb", "rb"]
+        );
+        assert_eq!(
+            claimed_suffixes["rb"],
+            [Arc::from("Crystal"), Arc::from("Ruby")]
+        );
+        assert_eq!(claimed_suffixes["erb"], [Arc::from("ERB")]);
+    });
+}
+
+#[gpui::test]
+async fn test_list_installed_extensions(cx: &mut TestAppContext) {
+    init_test(cx);
+
+    let TestExtensionStore { fs, store, .. } = init_test_extension_store(
+        json!({
+            "installed": {
+                "zed-monokai": monokai_extension(),
+                "zed-ruby": ruby_extension(),
+            }
+        }),
+        cx,
+    )
+    .await;
+
+    let ruby_info = ExtensionInfo {
+        id: "zed-ruby".into(),
+        name: "Zed Ruby".into(),
+        version: "1.0.0".into(),
+        dev: false,
+        provenance: None,
+        installed_at: None,
+        enabled: true,
+        load_status: ExtensionLoadStatus::Loaded,
+        theme_count: 0,
+        language_count: 2,
+        grammar_count: 2,
+        language_server_count: 0,
+    };
+    let monokai_info = ExtensionInfo {
+        id: "zed-monokai".into(),
+        name: "Zed Monokai".into(),
+        version: "2.0.0".into(),
+        dev: false,
+        provenance: None,
+        installed_at: None,
+        enabled: true,
+        load_status: ExtensionLoadStatus::Loaded,
+        theme_count: 4,
+        language_count: 0,
+        grammar_count: 0,
+        language_server_count: 0,
+    };
+    store.read_with(cx, |store, _| {
+        assert_eq!(
+            store.installed_extensions(),
+            [monokai_info.clone(), ruby_info.clone()]
+        );
+    });
+
+    // The list reflects the extensions that are installed after a reload.
+    fs.insert_tree(
+        "/the-extension-dir/installed/zed-gleam",
+        json!({
+            "extension.toml": r#"
+                id = "zed-gleam"
+                name = "Gleam"
+                version = "0.1.0"
+                schema_version = 1
+
+                [language_servers.gleam]
+                language = "Gleam"
+            "#,
+            "installed_at": "1000",
+        }),
+    )
+    .await;
+    let task = store.update(cx, |store, cx| {
+        store.set_extension_enabled("zed-ruby".into(), false, cx)
+    });
+    cx.executor().advance_clock(RELOAD_DEBOUNCE_DURATION);
+    cx.executor().run_until_parked();
+    task.await.unwrap();
+    let reload = store.update(cx, |store, cx| store.reload(None, cx));
+    finish_reload(reload, cx);
+
+    store.read_with(cx, |store, _| {
+        assert_eq!(
+            store.installed_extensions(),
+            [
+                ExtensionInfo {
+                    id: "zed-gleam".into(),
+                    name: "Gleam".into(),
+                    version: "0.1.0".into(),
+                    dev: false,
+                    provenance: None,
+                    installed_at: Some(SystemTime::UNIX_EPOCH + Duration::from_secs(1000)),
+                    enabled: true,
+                    load_status: ExtensionLoadStatus::Loaded,
+                    theme_count: 0,
+                    language_count: 0,
+                    grammar_count: 0,
+                    language_server_count: 1,
+                },
+                monokai_info,
+                ExtensionInfo {
+                    enabled: false,
+                    load_status: ExtensionLoadStatus::Disabled,
+                    ..ruby_info
+                },
+            ]
+        );
+    });
+}
+
+#[gpui::test]
+async fn test_intern_extension_ids(cx: &mut TestAppContext) {
+    init_test(cx);
+
+    let TestExtensionStore { store, .. } = init_test_extension_store(
+        json!({
+            "installed": {
+                "zed-ruby": ruby_extension(),
+            }
+        }),
+        cx,
+    )
+    .await;
+
+    let index_json = store.read_with(cx, |store, _| {
+        serde_json::to_string(&store.extension_index).unwrap()
+    });
+    let mut index = serde_json::from_str::<ExtensionIndex>(&index_json).unwrap();
+    assert!(!Arc::ptr_eq(
+        &index.languages["Ruby"].extension,
+        &index.languages["ERB"].extension
+    ));
+
+    index.intern_extension_ids();
+    let (extension_id, extension) = index.extensions.iter().next().unwrap();
+    assert!(Arc::ptr_eq(
+        &index.languages["Ruby"].extension,
+        &index.languages["ERB"].extension
+    ));
+    assert!(Arc::ptr_eq(
+        &index.languages["Ruby"].extension,
+        extension_id
+    ));
+    assert!(Arc::ptr_eq(&extension.manifest.id, extension_id));
+}
+
+#[gpui::test]
+async fn test_index_with_unknown_schema_version_is_rebuilt(cx: &mut TestAppContext) {
+    init_test(cx);
+
+    let TestExtensionStore { fs, store, .. } = init_test_extension_store(
+        json!({
+            "installed": {
+                "zed-gruvbox": gruvbox_extension(),
+            }
+        }),
+        cx,
+    )
+    .await;
+    store.update(cx, |store, cx| store.flush(cx)).await.unwrap();
+    drop(store);
+
+    // An index written by a newer version of Zed.
+    let index_path = Path::new("/the-extension-dir/index.json");
+    let mut index_json: serde_json::Value =
+        serde_json::from_str(&fs.load(index_path).await.unwrap()).unwrap();
+    assert_eq!(index_json["schema_version"], CURRENT_INDEX_SCHEMA_VERSION);
+    index_json["schema_version"] = (CURRENT_INDEX_SCHEMA_VERSION + 1).into();
+    fs.save(
+        index_path,
+        &index_json.to_string().as_str().into(),
+        Default::default(),
+    )
+    .await
+    .unwrap();
+
+    // The index is ignored, so nothing is loaded until it is rebuilt.
+    let theme_registry = Arc::new(ThemeRegistry::new(Box::new(())));
+    let store = new_test_extension_store(
+        fs.clone(),
+        FakeHttpClient::with_200_response(),
+        Arc::new(LanguageRegistry::test(cx.executor())),
+        theme_registry.clone(),
+        cx,
+    );
+    cx.executor().run_until_parked();
+    store.read_with(cx, |store, _| {
+        assert!(store.installed_extension_entries().is_empty());
+    });
+    assert_eq!(theme_registry.list_names(false), ["One Dark"]);
+
+    cx.executor().advance_clock(RELOAD_DEBOUNCE_DURATION);
+    cx.executor().run_until_parked();
+    store.read_with(cx, |store, _| {
+        assert!(store
+            .installed_extension_entries()
+            .contains_key("zed-gruvbox"));
+    });
+    assert_eq!(theme_registry.list_names(false), ["Gruvbox", "One Dark"]);
+    let index: ExtensionIndex = serde_json::from_str(&fs.load(index_path).await.unwrap()).unwrap();
+    assert_eq!(index.schema_version, CURRENT_INDEX_SCHEMA_VERSION);
+}
+
+#[gpui::test]
+async fn test_extension_for_language_theme_and_grammar(cx: &mut TestAppContext) {
+    init_test(cx);
+
+    let TestExtensionStore { store, .. } = init_test_extension_store(
+        json!({
+            "installed": {
+                "zed-monokai": monokai_extension(),
+                "zed-ruby": ruby_extension(),
+            }
+        }),
+        cx,
+    )
+    .await;
+
+    store.read_with(cx, |store, _| {
+        assert_eq!(
+            store.extension_for_language("Ruby"),
+            Some(Arc::from("zed-ruby"))
+        );
+        assert_eq!(
+            store.extension_for_language("ERB"),
+            Some(Arc::from("zed-ruby"))
+        );
+        assert_eq!(store.extension_for_language("Plain Text"), None);
+        assert_eq!(
+            store.extension_for_theme("Monokai Light"),
+            Some(Arc::from("zed-monokai"))
+        );
+        assert_eq!(store.extension_for_theme("One Dark"), None);
+        assert_eq!(
+            store.extension_for_grammar("embedded_template"),
+            Some(Arc::from("zed-ruby"))
+        );
+        assert_eq!(store.extension_for_grammar("rust"), None);
+    });
+}
+
+#[gpui::test]
+async fn test_describe_extension(cx: &mut TestAppContext) {
+    init_test(cx);
+
+    let TestExtensionStore { store, .. } = init_test_extension_store(
+        json!({
+            "installed": {
+                "zed-monokai": monokai_extension(),
+                "zed-ruby": ruby_extension(),
+            }
+        }),
+        cx,
+    )
+    .await;
+
+    store.read_with(cx, |store, _| {
+        let report = store.describe_extension("zed-ruby").unwrap();
+        let extension_dir = Path::new("/the-extension-dir/installed/zed-ruby");
+        assert_eq!(
+            report.languages,
+            BTreeMap::from_iter([
+                (Arc::from("ERB"), extension_dir.join("languages/erb")),
+                (Arc::from("Ruby"), extension_dir.join("languages/ruby")),
+            ])
+        );
+        assert_eq!(
+            report.grammars,
+            BTreeMap::from_iter([
+                (
+                    Arc::from("embedded_template"),
+                    extension_dir.join("grammars/embedded_template.wasm")
+                ),
+                (Arc::from("ruby"), extension_dir.join("grammars/ruby.wasm")),
+            ])
+        );
+        assert!(report.themes.is_empty());
+        assert!(report.language_servers.is_empty());
+
+        let report = store.describe_extension("zed-monokai").unwrap();
+        assert_eq!(
+            report.themes.get("Monokai Pro Dark"),
+            Some(&PathBuf::from(
+                "/the-extension-dir/installed/zed-monokai/themes/monokai-pro.json"
+            ))
+        );
+        assert!(report.languages.is_empty());
+
+        assert!(store.describe_extension("zed-gruvbox").is_none());
+    });
+}
+
+#[gpui::test]
+async fn test_set_extension_enabled(cx: &mut TestAppContext) {
+    init_test(cx);
+
+    let TestExtensionStore {
+        fs,
+        store,
+        language_registry,
+        theme_registry,
+    } = init_test_extension_store(
+        json!({
+            "installed": {
+                "zed-monokai": monokai_extension(),
+                "zed-ruby": ruby_extension(),
+            }
+        }),
+        cx,
+    )
+    .await;
+    assert_eq!(
+        language_registry.language_names(),
+        ["ERB", "Plain Text", "Ruby"]
+    );
+
+    let task = store.update(cx, |store, cx| {
+        store.set_extension_enabled("zed-ruby".into(), false, cx)
+    });
+    cx.executor().advance_clock(RELOAD_DEBOUNCE_DURATION);
+    cx.executor().run_until_parked();
+    task.await.unwrap();
+
+    // The disabled extension is still installed, but its languages and grammars
+    // are no longer registered.
+    assert_eq!(language_registry.language_names(), ["Plain Text"]);
+    assert!(language_registry.grammar_names().is_empty());
+    store.read_with(cx, |store, _| {
+        let extensions = store.installed_extension_entries();
+        assert!(!extensions["zed-ruby"].enabled);
+        assert!(extensions["zed-monokai"].enabled);
+    });
+    assert!(theme_registry
+        .list_names(false)
+        .contains(&SharedString::from("Monokai Dark")));
+
+    // The extension stays disabled after a restart, even if the index has to be
+    // rebuilt.
+    store.update(cx, |store, cx| store.flush(cx)).await.unwrap();
+    drop(store);
+    fs.remove_file(
+        Path::new("/the-extension-dir/index.json"),
+        RemoveOptions {
+            ignore_if_not_exists: true,
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+    let language_registry = Arc::new(LanguageRegistry::test(cx.executor()));
+    let store = new_test_extension_store(
+        fs.clone(),
+        FakeHttpClient::with_200_response(),
+        language_registry.clone(),
+        Arc::new(ThemeRegistry::new(Box::new(()))),
+        cx,
+    );
+    cx.executor().advance_clock(RELOAD_DEBOUNCE_DURATION);
+    cx.executor().run_until_parked();
+    assert_eq!(language_registry.language_names(), ["Plain Text"]);
+    store.read_with(cx, |store, _| {
+        assert!(!store.installed_extension_entries()["zed-ruby"].enabled);
+    });
+
+    let task = store.update(cx, |store, cx| {
+        store.set_extension_enabled("zed-ruby".into(), true, cx)
+    });
+    cx.executor().advance_clock(RELOAD_DEBOUNCE_DURATION);
+    cx.executor().run_until_parked();
+    task.await.unwrap();
+    assert_eq!(
+        language_registry.language_names(),
+        ["ERB", "Plain Text", "Ruby"]
+    );
+    store.read_with(cx, |store, _| {
+        assert!(store.installed_extension_entries()["zed-ruby"].enabled);
+    });
+
+    let result = store
+        .update(cx, |store, cx| {
+            store.set_extension_enabled("zed-missing".into(), false, cx)
+        })
+        .await;
+    assert!(result.is_err());
+}
+
+#[gpui::test]
+async fn test_versioned_extension_dirs(cx: &mut TestAppContext) {
+    init_test(cx);
+
+    let gruvbox_version = |version: &str, theme_name: &str| {
+        json!({
+            "extension.json": format!(r#"{{
+                "id": "zed-gruvbox",
+                "name": "Zed Gruvbox",
+                "version": "{version}"
+            }}"#),
+            "themes": {
+                "gruvbox.json": format!(r#"{{
+                    "name": "Gruvbox",
+                    "author": "Someone Else",
+                    "themes": [
+                        {{ "name": "{theme_name}", "appearance": "dark", "style": {{}} }}
+                    ]
+                }}"#),
+            }
+        })
+    };
+
+    let TestExtensionStore {
+        fs,
+        store,
+        theme_registry,
+        ..
+    } = init_test_extension_store(
+        json!({
+            "installed": {
+                "zed-gruvbox-1.2.0": gruvbox_version("1.2.0", "Gruvbox Old"),
+                "zed-gruvbox-1.10.0": gruvbox_version("1.10.0", "Gruvbox New"),
+                "zed-monokai": monokai_extension(),
+            }
+        }),
+        cx,
+    )
+    .await;
+
+    // The highest version is used.
+    store.read_with(cx, |store, _| {
+        let extensions = store.installed_extension_entries();
+        assert_eq!(
+            extensions.keys().map(AsRef::as_ref).collect::<Vec<_>>(),
+            ["zed-gruvbox", "zed-monokai"]
+        );
+        assert_eq!(
+            extensions["zed-gruvbox"].manifest.version.as_ref(),
+            "1.10.0"
+        );
+        assert_eq!(
+            extensions["zed-gruvbox"].installed_dir_name(),
+            "zed-gruvbox-1.10.0"
+        );
+        assert_eq!(extensions["zed-monokai"].dir_name, None);
+        assert_eq!(
+            store.describe_extension("zed-gruvbox").unwrap().themes["Gruvbox New"],
+            Path::new("/the-extension-dir/installed/zed-gruvbox-1.10.0/themes/gruvbox.json")
+        );
+    });
+    let theme_names = theme_registry.list_names(false);
+    assert!(theme_names.contains(&SharedString::from("Gruvbox New")));
+    assert!(!theme_names.contains(&SharedString::from("Gruvbox Old")));
+
+    // A pinned version is used instead.
+    cx.update(|cx| {
+        SettingsStore::update_global(cx, |store, cx| {
+            store.update_user_settings::<ExtensionSettings>(cx, |settings| {
+                settings
+                    .pinned_extension_versions
+                    .insert("zed-gruvbox".into(), "1.2.0".into());
+            });
+        });
+    });
+    let reload = store.update(cx, |store, cx| store.reload(None, cx));
+    finish_reload(reload, cx);
+
+    store.read_with(cx, |store, _| {
+        let gruvbox = &store.installed_extension_entries()["zed-gruvbox"];
+        assert_eq!(gruvbox.manifest.version.as_ref(), "1.2.0");
+        assert_eq!(gruvbox.installed_dir_name(), "zed-gruvbox-1.2.0");
+    });
+    let theme_names = theme_registry.list_names(false);
+    assert!(theme_names.contains(&SharedString::from("Gruvbox Old")));
+    assert!(!theme_names.contains(&SharedString::from("Gruvbox New")));
+
+    let installed_dir_names = |fs: Arc<FakeFs>| async move {
+        let mut dir_names = fs
+            .read_dir("/the-extension-dir/installed".as_ref())
+            .await
+            .unwrap()
+            .map(|path| {
+                path.unwrap()
+                    .file_name()
+                    .unwrap()
+                    .to_string_lossy()
+                    .into_owned()
+            })
+            .collect::<Vec<_>>()
+            .await;
+        dir_names.sort();
+        dir_names
+    };
+
+    // Uninstalling the extension removes all of its versions.
+    store.update(cx, |store, cx| {
+        store
+            .uninstall_extension("zed-gruvbox".into(), UninstallOptions::default(), cx)
+            .unwrap();
+    });
+    cx.executor().advance_clock(RELOAD_DEBOUNCE_DURATION);
+    cx.executor().run_until_parked();
+
+    assert_eq!(installed_dir_names(fs.clone()).await, ["zed-monokai"]);
+    store.read_with(cx, |store, _| {
+        assert!(!store
+            .installed_extension_entries()
+            .contains_key("zed-gruvbox"));
+    });
+    let theme_names = theme_registry.list_names(false);
+    assert!(!theme_names.contains(&SharedString::from("Gruvbox Old")));
+    assert!(!theme_names.contains(&SharedString::from("Gruvbox New")));
+
+    // Installing the extension replaces all of its versions.
+    fs.insert_tree(
+        "/the-extension-dir/installed",
+        json!({
+            "zed-gruvbox-1.2.0": gruvbox_version("1.2.0", "Gruvbox Old"),
+            "zed-gruvbox-1.10.0": gruvbox_version("1.10.0", "Gruvbox New"),
+        }),
+    )
+    .await;
+    fs.insert_tree(
+        "/projects/zed-gruvbox",
+        gruvbox_version("2.0.0", "Gruvbox Local"),
+    )
+    .await;
+    let install = store.update(cx, |store, cx| {
+        store.install_extension_from_path("/projects/zed-gruvbox".into(), cx)
+    });
+    cx.executor().advance_clock(RELOAD_DEBOUNCE_DURATION);
+    cx.executor().run_until_parked();
+    install.await.unwrap();
+
+    assert_eq!(
+        installed_dir_names(fs.clone()).await,
+        ["zed-gruvbox", "zed-monokai"]
+    );
+    store.read_with(cx, |store, _| {
+        let gruvbox = &store.installed_extension_entries()["zed-gruvbox"];
+        assert_eq!(gruvbox.manifest.version.as_ref(), "2.0.0");
+        assert_eq!(gruvbox.installed_dir_name(), "zed-gruvbox");
+    });
+}
+
+#[gpui::test]
+async fn test_http_request_count(cx: &mut TestAppContext) {
+    init_test(cx);
+
+    let fs = FakeFs::new(cx.executor());
+    fs.insert_tree(
+        "/the-extension-dir",
+        json!({
+            "installed": {
+                "zed-monokai": monokai_extension(),
+                "zed-ruby": ruby_extension(),
+            }
+        }),
+    )
+    .await;
+
+    let http_client = FakeHttpClient::create(|_| async move {
+        Ok(Response::builder().body(r#"{"data": []}"#.into())?)
+    });
+    let store = new_test_extension_store(
+        fs.clone(),
+        http_client,
+        Arc::new(LanguageRegistry::test(cx.executor())),
+        Arc::new(ThemeRegistry::new(Box::new(()))),
+        cx,
+    );
+    cx.executor().advance_clock(RELOAD_DEBOUNCE_DURATION);
+    cx.executor().run_until_parked();
+
+    // Loading the installed extensions doesn't use the network.
+    store.read_with(cx, |store, _| assert_eq!(store.http_request_count(), 0));
+
+    let updates = store
+        .update(cx, |store, cx| {
+            store.fetch_extensions_with_update_available(cx)
+        })
+        .await
+        .unwrap();
+    assert!(updates.is_empty());
+    store.read_with(cx, |store, _| assert_eq!(store.http_request_count(), 1));
+}
+
+#[gpui::test]
+async fn test_latest_github_release(cx: &mut TestAppContext) {
+    init_test(cx);
+
+    let http_client = FakeHttpClient::create(|request| async move {
+        if request.uri().to_string() != "https://api.github.com/repos/the-owner/the-server/releases"
+        {
+            return Ok(Response::builder().status(404).body("not found".into())?);
+        }
+        let release = |tag_name: &str, pre_release: bool, has_assets: bool| {
+            let assets = if has_assets {
+                json!([{
+                    "name": format!("the-server-{tag_name}.tar.gz"),
+                    "browser_download_url": format!("https://example.com/the-server-{tag_name}.tar.gz"),
+                }])
+            } else {
+                json!([])
+            };
+            json!({
+                "tag_name": tag_name,
+                "prerelease": pre_release,
+                "tarball_url": "",
+                "zipball_url": "",
+                "assets": assets,
+            })
+        };
+        let releases = json!([
+            release("v2.0.0-rc.1", true, true),
+            release("v1.1.0", false, false),
+            release("v1.0.0", false, true),
+        ]);
+        Ok(Response::builder().body(releases.to_string().into())?)
+    });
+
+    let latest_tag = |require_assets: bool, pre_release: bool| {
+        let http_client = http_client.clone();
+        async move {
+            ::http_client::github::latest_github_release(
+                "the-owner/the-server",
+                require_assets,
+                pre_release,
+                http_client,
+            )
+            .await
+            .map(|release| release.tag_name)
+        }
+    };
+
+    assert_eq!(latest_tag(false, false).await.unwrap(), "v1.1.0");
+    assert_eq!(latest_tag(true, false).await.unwrap(), "v1.0.0");
+    // Pre-releases are included, rather than required, with `pre_release`.
+    assert_eq!(latest_tag(false, true).await.unwrap(), "v2.0.0-rc.1");
+
+    let release = ::http_client::github::latest_github_release(
+        "the-owner/the-server",
+        true,
+        false,
+        http_client.clone(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        release
+            .assets
+            .iter()
+            .map(|asset| asset.browser_download_url.as_str())
+            .collect::<Vec<_>>(),
+        &["https://example.com/the-server-v1.0.0.tar.gz"]
+    );
+
+    let error = ::http_client::github::latest_github_release(
+        "the-owner/missing-server",
+        false,
+        false,
+        http_client,
+    )
+    .await
+    .unwrap_err();
+    assert!(error.to_string().contains("404"), "{error}");
+}
+
+#[gpui::test]
+async fn test_verify_download_checksum(cx: &mut TestAppContext) {
+    init_test(cx);
+
+    let archive = b"the-language-server-archive".to_vec();
+    let archive_sha256 = format!("{:x}", sha2::Sha256::digest(&archive));
+
+    let http_client = FakeHttpClient::create({
+        let archive_sha256 = archive_sha256.clone();
+        move |request| {
+            let archive_sha256 = archive_sha256.clone();
+            async move {
+                let body = match request.uri().path() {
+                    "/server-1.0.0.tar.gz.sha256" => {
+                        format!("{archive_sha256}  server-1.0.0.tar.gz\n")
+                    }
+                    "/server-2.0.0.tar.gz.sha256" => {
+                        format!("{}  server-2.0.0.tar.gz\n", "0".repeat(64))
+                    }
+                    _ => return Ok(Response::builder().status(404).body("not found".into())?),
+                };
+                Ok(Response::builder().body(body.into())?)
+            }
+        }
+    });
+
+    let manifest = |language_server: &str| -> ExtensionManifest {
+        toml::from_str(&format!(
+            r#"
+                id = "the-lsp-extension"
+                name = "The LSP Extension"
+                version = "0.1.0"
+                schema_version = 1
+
+                [language_servers.the-server]
+                language = "Ruby"
+                {language_server}
+            "#
+        ))
+        .unwrap()
+    };
+    let verify = |manifest: ExtensionManifest, url: &'static str| {
+        let http_client = http_client.clone();
+        let archive = archive.clone();
+        async move { verify_download_checksum(http_client.as_ref(), &manifest, url, &archive).await }
+    };
+
+    // Without a checksum, downloads are not verified.
+    verify(manifest(""), "https://example.com/server-0.1.0.tar.gz")
+        .await
+        .unwrap();
+    assert_eq!(http_client.as_fake().request_count(), 0);
+
+    // An inline checksum is checked against the contents.
+    let inline = format!(r#"sha256 = {{ "server-1.0.0.tar.gz" = "{archive_sha256}" }}"#);
+    verify(manifest(&inline), "https://example.com/server-1.0.0.tar.gz")
+        .await
+        .unwrap();
+    verify(manifest(&inline), "https://example.com/server-1.0.0.zip")
+        .await
+        .unwrap();
+    let mismatched = format!(
+        r#"sha256 = {{ "server-1.0.0.tar.gz" = "{}" }}"#,
+        "0".repeat(64)
+    );
+    let error = verify(
+        manifest(&mismatched),
+        "https://example.com/server-1.0.0.tar.gz",
+    )
+    .await
+    .unwrap_err();
+    assert!(error.to_string().contains("checksum mismatch"), "{error}");
+    assert_eq!(http_client.as_fake().request_count(), 0);
+
+    // Checksums can also be read from a `.sha256` file next to the download.
+    let assets = "sha256_assets = true";
+    verify(manifest(assets), "https://example.com/server-1.0.0.tar.gz")
+        .await
+        .unwrap();
+    let error = verify(manifest(assets), "https://example.com/server-2.0.0.tar.gz")
+        .await
+        .unwrap_err();
+    assert!(error.to_string().contains("checksum mismatch"), "{error}");
+    assert!(
+        verify(manifest(assets), "https://example.com/server-3.0.0.tar.gz")
+            .await
+            .is_err()
+    );
+    assert_eq!(http_client.as_fake().request_count(), 3);
+}
+
+#[gpui::test]
+async fn test_theme_without_appearance(cx: &mut TestAppContext) {
+    init_test(cx);
+
+    let TestExtensionStore {
+        store,
+        theme_registry,
+        ..
+    } = init_test_extension_store(
+        json!({
+            "installed": {
+                "zed-plain": {
+                    "extension.json": r#"{
+                        "id": "zed-plain",
+                        "name": "Zed Plain",
+                        "version": "1.0.0"
+                    }"#,
+                    "themes": {
+                        "plain.json": r#"{
+                            "name": "Plain",
+                            "author": "Someone",
+                            "themes": [
+                                { "name": "Plain", "style": {} },
+                                { "name": "Plain Light", "appearance": "light", "style": {} }
+                            ]
+                        }"#,
+                    }
+                },
+            }
+        }),
+        cx,
+    )
+    .await;
+
+    // Themes without an appearance default to dark.
+    store.read_with(cx, |store, _| {
+        let themes = &store.extension_index.themes;
+        assert_eq!(themes["Plain"].appearance, Some(AppearanceContent::Dark));
+        assert_eq!(
+            themes["Plain Light"].appearance,
+            Some(AppearanceContent::Light)
+        );
+        assert_eq!(
+            store.themes_with_appearance(Appearance::Dark),
+            vec![Arc::<str>::from("Plain")]
+        );
+    });
+    assert_eq!(
+        theme_registry.get("Plain").unwrap().appearance,
+        Appearance::Dark
+    );
+
+    // The fallback appearance is configurable.
+    cx.update(|cx| {
+        SettingsStore::update_global(cx, |store, cx| {
+            store.update_user_settings::<ExtensionSettings>(cx, |settings| {
+                settings.fallback_theme_appearance = Some(AppearanceContent::Light);
+            });
+        });
+    });
+    let reload = store.update(cx, |store, cx| store.reload(None, cx));
+    finish_reload(reload, cx);
+
+    store.read_with(cx, |store, _| {
+        assert_eq!(
+            store.extension_index.themes["Plain"].appearance,
+            Some(AppearanceContent::Light)
+        );
+    });
+}
+
+#[gpui::test]
+async fn test_compact(cx: &mut TestAppContext) {
+    init_test(cx);
+
+    let TestExtensionStore { fs, store, .. } = init_test_extension_store(
+        json!({
+            "installed": {
+                "zed-monokai": monokai_extension(),
+                "zed-ruby": ruby_extension(),
+            }
+        }),
+        cx,
+    )
+    .await;
+
+    // Add an entry for an extension whose directory was deleted out-of-band.
+    let deleted_id: Arc<str> = "zed-deleted".into();
+    store.update(cx, |store, _| {
+        let index = &mut store.extension_index;
+        let mut extension = index.extensions["zed-ruby"].clone();
+        extension.manifest.id = deleted_id.clone();
+        index.extensions.insert(deleted_id.clone(), extension);
+        let mut language = index.languages["Ruby"].clone();
+        language.extension = deleted_id.clone();
+        index.languages.insert("Deleted Ruby".into(), language);
+    });
+
+    let removed = store
+        .update(cx, |store, cx| store.compact(cx))
+        .await
+        .unwrap();
+    assert_eq!(removed, vec![deleted_id.clone()]);
+
+    store.read_with(cx, |store, _| {
+        let index = &store.extension_index;
+        assert_eq!(
+            index
+                .extensions
+                .keys()
+                .map(|id| id.as_ref())
+                .collect::<Vec<_>>(),
+            &["zed-monokai", "zed-ruby"]
+        );
+        assert!(!index.languages.contains_key("Deleted Ruby"));
+        assert!(index.languages.contains_key("Ruby"));
+    });
+
+    store.update(cx, |store, cx| store.flush(cx)).await.unwrap();
+    let index_json = fs
+        .load("/the-extension-dir/index.json".as_ref())
+        .await
+        .unwrap();
+    let index: ExtensionIndex = serde_json::from_str(&index_json).unwrap();
+    assert!(!index.extensions.contains_key("zed-deleted"));
+    assert!(index.extensions.contains_key("zed-ruby"));
+
+    // Compacting an index without dangling entries changes nothing.
+    let removed = store
+        .update(cx, |store, cx| store.compact(cx))
+        .await
+        .unwrap();
+    assert!(removed.is_empty());
+}
+
+#[gpui::test]
+async fn test_search_installed(cx: &mut TestAppContext) {
+    init_test(cx);
+
+    let TestExtensionStore { store, .. } = init_test_extension_store(
+        json!({
+            "installed": {
+                "zed-gruvbox": gruvbox_extension(),
+                "zed-monokai": monokai_extension(),
+                "zed-ruby": ruby_extension(),
+            }
+        }),
+        cx,
+    )
+    .await;
+
+    let search = |query: &str, cx: &mut TestAppContext| {
+        let results = store.read_with(cx, |store, cx| store.search_installed(query, cx));
+        async move {
+            results
+                .await
+                .into_iter()
+                .map(|manifest| manifest.id.to_string())
+                .collect::<Vec<_>>()
+        }
+    };
+    assert_eq!(search("monk", cx).await, ["zed-monokai"]);
+    assert_eq!(search("ruby", cx).await, ["zed-ruby"]);
+    assert!(search("haskell", cx).await.is_empty());
+}
+
+#[gpui::test]
+async fn test_search_installed_by_tag(cx: &mut TestAppContext) {
+    init_test(cx);
+
+    let TestExtensionStore { store, .. } = init_test_extension_store(
+        json!({
+            "installed": {
+                "zed-gruvbox": {
+                    "extension.json": r#"{
+                        "id": "zed-gruvbox",
+                        "name": "Zed Gruvbox",
+                        "version": "1.0.0",
+                        "tags": ["dark", "retro"]
+                    }"#,
+                },
+                "zed-night": {
+                    "extension.json": r#"{
+                        "id": "zed-night",
+                        "name": "Dark Night",
+                        "version": "1.0.0"
+                    }"#,
+                },
+                "zed-ruby": ruby_extension(),
+            }
+        }),
+        cx,
+    )
+    .await;
+
+    let search = |query: &str, cx: &mut TestAppContext| {
+        let results = store.read_with(cx, |store, cx| store.search_installed(query, cx));
+        async move {
+            results
+                .await
+                .into_iter()
+                .map(|manifest| manifest.id.to_string())
+                .collect::<Vec<_>>()
+        }
+    };
+    // Extensions that match by name rank above those that only match by tag.
+    assert_eq!(search("dark", cx).await, ["zed-night", "zed-gruvbox"]);
+    assert_eq!(search("retro", cx).await, ["zed-gruvbox"]);
+    assert_eq!(search("gruv", cx).await, ["zed-gruvbox"]);
+}
+
+#[gpui::test]
+async fn test_extensions_in_category(cx: &mut TestAppContext) {
+    init_test(cx);
+
+    let TestExtensionStore { store, .. } = init_test_extension_store(
+        json!({
+            "installed": {
+                "zed-ruby": {
+                    "extension.json": r#"{
+                        "id": "zed-ruby",
+                        "name": "Zed Ruby",
+                        "version": "1.0.0",
+                        "categories": ["Languages"]
+                    }"#,
+                },
+                "zed-monokai": {
+                    "extension.json": r#"{
+                        "id": "zed-monokai",
+                        "name": "Zed Monokai",
+                        "version": "2.0.0",
+                        "categories": ["Themes"]
+                    }"#,
+                },
+                "zed-gruvbox": {
+                    "extension.json": r#"{
+                        "id": "zed-gruvbox",
+                        "name": "Zed Gruvbox",
+                        "version": "1.0.0",
+                        "categories": ["Themes", "Icons"]
+                    }"#,
+                },
+            }
+        }),
+        cx,
+    )
+    .await;
+
+    store.read_with(cx, |store, _| {
+        let ids_in_category = |category: &'static str| {
+            store
+                .extensions_in_category(category)
+                .map(|manifest| manifest.id.as_ref())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(ids_in_category("Themes"), ["zed-gruvbox", "zed-monokai"]);
+        assert_eq!(ids_in_category("languages"), ["zed-ruby"]);
+        assert_eq!(ids_in_category("Icons"), ["zed-gruvbox"]);
+        assert!(ids_in_category("Snippets").is_empty());
+    });
+}
+
+#[gpui::test]
+async fn test_extension_default_settings(cx: &mut TestAppContext) {
+    init_test(cx);
+
+    let TestExtensionStore { store, .. } = init_test_extension_store(
+        json!({
+            "installed": {
+                "zed-two-spaces": {
+                    "extension.json": r#"{
+                        "id": "zed-two-spaces",
+                        "name": "Zed Two Spaces",
+                        "version": "1.0.0"
+                    }"#,
+                    "default_settings.json": r#"{
+                        // Indent with two spaces by default.
+                        "tab_size": 2
+                    }"#,
+                },
+            }
+        }),
+        cx,
+    )
+    .await;
+
+    cx.read(|cx| {
+        assert_eq!(
+            language::language_settings::language_settings(None, None, cx)
+                .tab_size
+                .get(),
+            2
+        );
+    });
+
+    store.update(cx, |store, cx| {
+        store
+            .uninstall_extension("zed-two-spaces".into(), UninstallOptions::default(), cx)
+            .unwrap()
+    });
+    cx.executor().advance_clock(RELOAD_DEBOUNCE_DURATION);
+    cx.executor().run_until_parked();
+
+    cx.read(|cx| {
+        assert_eq!(
+            language::language_settings::language_settings(None, None, cx)
+                .tab_size
+                .get(),
+            4
+        );
+    });
+}
+
+#[gpui::test]
+async fn test_suggest_extension_for_path(cx: &mut TestAppContext) {
+    init_test(cx);
+
+    let TestExtensionStore { store, .. } = init_test_extension_store(
+        json!({
+            "installed": {
+                "zed-ruby": ruby_extension(),
+            }
+        }),
+        cx,
+    )
+    .await;
+
+    let remote_extension_index: ExtensionIndex = serde_json::from_value(json!({
+        "extensions": {
+            "zed-elixir": {
+                "manifest": {
+                    "id": "zed-elixir",
+                    "name": "Zed Elixir",
+                    "version": "0.1.0",
+                    "schema_version": 1,
+                    "languages": ["languages/elixir"],
+                },
+                "dev": false,
+            },
+            "zed-ruby": {
+                "manifest": {
+                    "id": "zed-ruby",
+                    "name": "Zed Ruby",
+                    "version": "2.0.0",
+                    "schema_version": 1,
+                    "languages": ["languages/ruby"],
+                },
+                "dev": false,
+            },
+        },
+        "themes": {},
+        "languages": {
+            "Elixir": {
+                "extension": "zed-elixir",
+                "path": "languages/elixir",
+                "matcher": { "path_suffixes": ["ex", "exs"] },
+                "grammar": "elixir",
+            },
+            "Ruby": {
+                "extension": "zed-ruby",
+                "path": "languages/ruby",
+                "matcher": { "path_suffixes": ["rb"] },
+                "grammar": "ruby",
+            },
+        },
+    }))
+    .unwrap();
+
+    store.update(cx, |store, _| {
+        store.set_remote_extension_index(remote_extension_index);
+
+        assert_eq!(
+            store
+                .suggest_extension_for_path(Path::new("lib/foo.ex"))
+                .map(|extension| extension.manifest.id.clone())
+                .as_deref(),
+            Some("zed-elixir")
+        );
+        // Ruby is already provided by an installed extension.
+        assert_eq!(store.suggest_extension_for_path(Path::new("foo.rb")), None);
+        assert_eq!(store.suggest_extension_for_path(Path::new("foo.txt")), None);
+    });
+}
+
+#[gpui::test]
+async fn test_extension_store_skips_rejected_names(cx: &mut TestAppContext) {
+    init_test(cx);
+
+    let fs = FakeFs::new(cx.executor());
+    fs.insert_tree(
+        "/the-extension-dir",
+        json!({
+            "installed": {
+                "zed-monokai": monokai_extension(),
+                "zed-ruby": ruby_extension(),
+                "zed-impostor": {
+                    "extension.json": r#"{
+                        "id": "zed-impostor",
+                        "name": "Impostor",
+                        "version": "1.0.0"
+                    }"#,
+                    "languages": {
+                        "plain-text": {
+                            "config.toml": r#"
+                                name = "Plain Text"
+                                path_suffixes = ["txt"]
+                            "#,
+                        },
+                        "rust": {
+                            "config.toml": r#"
+                                name = "Rust"
+                                path_suffixes = ["rs"]
+                            "#,
+                        },
+                    },
+                    "themes": {
+                        "impostor.json": r#"{
+                            "name": "Impostor",
+                            "author": "Someone",
+                            "themes": [
+                                { "name": "One Dark", "appearance": "light", "style": {} },
+                                { "name": "Impostor Light", "appearance": "light", "style": {} }
+                            ]
+                        }"#,
+                    },
+                },
+            }
+        }),
+    )
+    .await;
+
+    // A language that is registered by something other than the store, like
+    // the built-in languages.
+    let language_registry = Arc::new(LanguageRegistry::test(cx.executor()));
+    language_registry.register_test_language(LanguageConfig {
+        name: "Rust".into(),
+        matcher: LanguageMatcher {
+            path_suffixes: vec!["rs".into()],
+            ..Default::default()
+        },
+        ..Default::default()
+    });
+    let theme_registry = Arc::new(ThemeRegistry::new(Box::new(())));
+    let store = new_test_extension_store(
+        fs.clone(),
+        FakeHttpClient::with_200_response(),
+        language_registry.clone(),
+        theme_registry.clone(),
+        cx,
+    );
+    cx.executor().advance_clock(RELOAD_DEBOUNCE_DURATION);
+    cx.executor().run_until_parked();
+
+    // The themes' warnings about missing syntax tokens are ignored.
+    fn load_errors(store: &ExtensionStore) -> Vec<(&str, &str)> {
+        store
+            .load_errors()
+            .iter()
+            .filter(|error| error.severity == LoadErrorSeverity::Error)
+            .map(|error| (error.extension_id.as_ref(), error.message.as_str()))
+            .collect()
+    }
+
+    // The rejected languages and themes are removed from the index, and
+    // recorded as errors, while the other ones are still registered.
+    store.read_with(cx, |store, _| {
+        assert!(!store.extension_index.languages.contains_key("Plain Text"));
+        assert!(!store.extension_index.languages.contains_key("Rust"));
+        assert!(!store.extension_index.themes.contains_key("One Dark"));
+        assert!(store.extension_index.themes.contains_key("Impostor Light"));
+        assert_eq!(
+            store
+                .registered
+                .languages
+                .iter()
+                .map(|name| name.as_ref())
+                .collect::<Vec<_>>(),
+            ["ERB", "Ruby"]
+        );
+        assert_eq!(
+            load_errors(store),
+            [
+                (
+                    "zed-impostor",
+                    "failed to register language \"Plain Text\": language name \"Plain Text\" is reserved"
+                ),
+                (
+                    "zed-impostor",
+                    "failed to register language \"Rust\": language \"Rust\" is already registered"
+                ),
+                (
+                    "zed-impostor",
+                    "failed to register theme \"One Dark\": a theme with that name is already registered"
+                ),
+            ]
+        );
+    });
+    assert_eq!(
+        language_registry.language_names(),
+        ["ERB", "Plain Text", "Ruby", "Rust"]
+    );
+    assert_eq!(
+        theme_registry.list_names(false),
+        [
+            "Impostor Light",
+            "Monokai Dark",
+            "Monokai Light",
+            "Monokai Pro Dark",
+            "Monokai Pro Light",
+            "One Dark",
+        ]
+    );
+    assert_eq!(
+        theme_registry.get("One Dark").unwrap().appearance(),
+        Appearance::Dark
+    );
+
+    // Reloading the extension doesn't change which names are rejected, and
+    // uninstalling it leaves the languages and themes that it tried to replace.
+    let reload = store.update(cx, |store, cx| {
+        store.reload(Some("zed-impostor".into()), cx)
+    });
+    finish_reload(reload, cx);
+    store.read_with(cx, |store, _| {
+        assert!(!store.extension_index.languages.contains_key("Rust"));
+        assert_eq!(load_errors(store).len(), 3);
+    });
+
+    fs.remove_dir(
+        Path::new("/the-extension-dir/installed/zed-impostor"),
+        RemoveOptions {
+            recursive: true,
+            ignore_if_not_exists: false,
+        },
+    )
+    .await
+    .unwrap();
+    let reload = store.update(cx, |store, cx| store.reload(None, cx));
+    finish_reload(reload, cx);
+
+    store.read_with(cx, |store, _| assert!(load_errors(store).is_empty()));
+    assert_eq!(
+        language_registry.language_names(),
+        ["ERB", "Plain Text", "Ruby", "Rust"]
+    );
+    assert_eq!(
+        theme_registry.list_names(false),
+        [
+            "Monokai Dark",
+            "Monokai Light",
+            "Monokai Pro Dark",
+            "Monokai Pro Light",
+            "One Dark",
+        ]
+    );
+    assert_eq!(
+        theme_registry.get("One Dark").unwrap().appearance(),
+        Appearance::Dark
+    );
+}
+
+#[gpui::test]
+async fn test_registry_auth_token(cx: &mut TestAppContext) {
+    init_test(cx);
+
+    let requests = Arc::new(Mutex::new(Vec::new()));
+    let http_client = FakeHttpClient::create({
+        let requests = requests.clone();
+        move |request| {
+            let requests = requests.clone();
+            async move {
+                requests.lock().push((
+                    request.uri().host().unwrap_or_default().to_string(),
+                    request
+                        .headers()
+                        .get("Authorization")
+                        .and_then(|value| value.to_str().ok())
+                        .map(ToString::to_string),
+                ));
+                Ok(Response::new(json!({ "data": [] }).to_string().into()))
+            }
+        }
+    });
+
+    cx.update(|cx| {
+        SettingsStore::update_global(cx, |store, cx| {
+            store.update_user_settings::<ExtensionSettings>(cx, |settings| {
+                settings.registry_auth.insert(
+                    "test.example".into(),
+                    RegistryAuth {
+                        token: Some("the-token".into()),
+                        token_env: None,
+                    },
+                );
+            });
+        });
+    });
+
+    let fs = FakeFs::new(cx.executor());
+    let store = new_test_extension_store(
+        fs,
+        http_client.clone(),
+        Arc::new(LanguageRegistry::test(cx.executor())),
+        Arc::new(ThemeRegistry::new(Box::new(()))),
+        cx,
+    );
+
+    store
+        .update(cx, |store, cx| store.fetch_extensions(None, cx))
+        .await
+        .unwrap();
+    http_client.set_base_url("http://other.example");
+    store
+        .update(cx, |store, cx| store.fetch_extensions(None, cx))
+        .await
+        .unwrap();
+
+    assert_eq!(
+        *requests.lock(),
+        [
+            (
+                "test.example".to_string(),
+                Some("Bearer the-token".to_string())
+            ),
+            ("other.example".to_string(), None),
+        ]
+    );
+}
+
+#[gpui::test]
+async fn test_fetch_extensions_from_local_index(cx: &mut TestAppContext) {
+    init_test(cx);
+
+    let request_count = Arc::new(AtomicUsize::new(0));
+    let http_client = FakeHttpClient::create({
+        let request_count = request_count.clone();
+        move |_| {
+            request_count.fetch_add(1, SeqCst);
+            async move { Ok(Response::new(json!({ "data": [] }).to_string().into())) }
+        }
+    });
+
+    let extension_metadata = |id: &str, name: &str, schema_version: i32, tags: &[&str]| {
+        json!({
+            "id": id,
+            "name": name,
+            "version": "1.0.0",
+            "description": null,
+            "authors": [],
+            "repository": "https://example.com",
+            "schema_version": schema_version,
+            "wasm_api_version": null,
+            "tags": tags,
+            "published_at": "2024-01-01T00:00:00Z",
+            "download_count": 0,
+        })
+    };
+    let fs = FakeFs::new(cx.executor());
+    fs.insert_tree(
+        "/mirror",
+        json!({
+            "extensions.json": json!({
+                "data": [
+                    extension_metadata("zed-monokai", "Zed Monokai", 1, &["theme", "dark"]),
+                    extension_metadata("zed-ruby", "Zed Ruby", 1, &[]),
+                    extension_metadata("zed-one-dark", "Zed One Dark", 1, &["theme"]),
+                    extension_metadata("zed-future", "Zed Future", 100, &["dark"]),
+                ]
+            })
+            .to_string(),
+        }),
+    )
+    .await;
+
+    cx.update(|cx| {
+        SettingsStore::update_global(cx, |store, cx| {
+            store.update_user_settings::<ExtensionSettings>(cx, |settings| {
+                settings.extension_index_path = Some("/mirror/extensions.json".into());
+            });
+        });
+    });
+
+    let store = new_test_extension_store(
+        fs,
+        http_client,
+        Arc::new(LanguageRegistry::test(cx.executor())),
+        Arc::new(ThemeRegistry::new(Box::new(()))),
+        cx,
+    );
+
+    let extension_ids = |extensions: Vec<ExtensionMetadata>| {
+        extensions
+            .into_iter()
+            .map(|extension| extension.id)
+            .collect::<Vec<_>>()
+    };
+    let extensions = store
+        .update(cx, |store, cx| store.fetch_extensions(None, cx))
+        .await
+        .unwrap();
+    assert_eq!(
+        extension_ids(extensions),
+        [
+            Arc::from("zed-monokai"),
+            Arc::from("zed-ruby"),
+            Arc::from("zed-one-dark")
+        ]
+    );
+
+    let extensions = store
+        .update(cx, |store, cx| store.fetch_extensions(Some("Ruby"), cx))
+        .await
+        .unwrap();
+    assert_eq!(extension_ids(extensions), [Arc::from("zed-ruby")]);
+
+    // Extensions that only match by tag come after those that match by name.
+    let extensions = store
+        .update(cx, |store, cx| store.fetch_extensions(Some("dark"), cx))
+        .await
+        .unwrap();
+    assert_eq!(
+        extension_ids(extensions),
+        [Arc::from("zed-one-dark"), Arc::from("zed-monokai")]
+    );
+
+    assert_eq!(request_count.load(SeqCst), 0);
+}
+
+#[gpui::test]
+async fn test_language_servers_affected_by_file(cx: &mut TestAppContext) {
+    init_test(cx);
+
+    let TestExtensionStore { store, .. } = init_test_extension_store(
+        json!({
+            "installed": {
+                "zed-go": {
+                    "extension.toml": r#"
+                        id = "zed-go"
+                        name = "Zed Go"
+                        version = "1.0.0"
+                        schema_version = 1
+
+                        [language_servers.gopls]
+                        language = "Go"
+                        version_files = [".go-version", "go.mod"]
+
+                        [language_servers.golangci-lint]
+                        language = "Go"
+                    "#,
+                },
+            }
+        }),
+        cx,
+    )
+    .await;
+
+    store.read_with(cx, |store, _| {
+        assert_eq!(
+            store.language_servers_affected_by_file(Path::new("/the-project/.go-version")),
+            [LanguageServerName("gopls".into())]
+        );
+        assert_eq!(
+            store.language_servers_affected_by_file(Path::new("/the-project/sub/go.mod")),
+            [LanguageServerName("gopls".into())]
+        );
+        assert!(store
+            .language_servers_affected_by_file(Path::new("/the-project/main.go"))
+            .is_empty());
+    });
+}
+
+#[gpui::test]
+async fn test_language_server_version_resolved_again(cx: &mut TestAppContext) {
+    init_test(cx);
+
+    let fs = FakeFs::new(cx.executor());
+    fs.insert_tree(
+        "/the-extension-dir",
+        json!({
+            "installed": {
+                "zed-go": {
+                    "extension.toml": r#"
+                        id = "zed-go"
+                        name = "Zed Go"
+                        version = "1.0.0"
+                        schema_version = 1
+
+                        [language_servers.gopls]
+                        language = "Go"
+                        version_files = [".go-version"]
+                    "#,
+                },
+            }
+        }),
+    )
+    .await;
+    fs.insert_tree(
+        "/the-project",
+        json!({ ".go-version": "1.21.0\n", "main.go": "package main" }),
+    )
+    .await;
+
+    let store = new_test_extension_store(
+        fs.clone(),
+        FakeHttpClient::with_200_response(),
+        Arc::new(LanguageRegistry::test(cx.executor())),
+        Arc::new(ThemeRegistry::new(Box::new(()))),
+        cx,
+    );
+    cx.executor().advance_clock(RELOAD_DEBOUNCE_DURATION);
+    cx.executor().run_until_parked();
+
+    let gopls = LanguageServerName("gopls".into());
+    let project = Path::new("/the-project");
+    let version = store
+        .update(cx, |store, cx| {
+            store.language_server_version(&gopls, project, cx)
+        })
+        .await
+        .unwrap();
+    assert_eq!(version.as_deref(), Some("1.21.0"));
+
+    // The version is cached until the version file is reported to change.
+    fs.insert_file("/the-project/.go-version", b"1.22.1\n".to_vec())
+        .await;
+    let version = store
+        .update(cx, |store, cx| {
+            store.language_server_version(&gopls, project, cx)
+        })
+        .await
+        .unwrap();
+    assert_eq!(version.as_deref(), Some("1.21.0"));
+
+    // Changes to other files don't invalidate the version.
+    let affected = store.update(cx, |store, _| {
+        store.worktree_file_changed(Path::new("/the-project/main.go"))
+    });
+    assert!(affected.is_empty());
+
+    let affected = store.update(cx, |store, _| {
+        store.worktree_file_changed(Path::new("/the-project/.go-version"))
+    });
+    assert_eq!(affected, [gopls.clone()]);
+    let version = store
+        .update(cx, |store, cx| {
+            store.language_server_version(&gopls, project, cx)
+        })
+        .await
+        .unwrap();
+    assert_eq!(version.as_deref(), Some("1.22.1"));
+}
+
+#[gpui::test]
+async fn test_install_rejects_truncated_download(cx: &mut TestAppContext) {
+    init_test(cx);
+
+    let http_client = FakeHttpClient::create(|request| async move {
+        match request.uri().path() {
+            "/extensions" => Ok(Response::new(
+                json!({
+                    "data": [{
+                        "id": "zed-ruby",
+                        "name": "Zed Ruby",
+                        "version": "1.0.0",
+                        "description": null,
+                        "authors": [],
+                        "repository": "https://github.com/zed-industries/zed-ruby",
+                        "schema_version": 1,
+                        "wasm_api_version": null,
+                        "published_at": "2024-01-01T00:00:00Z",
+                        "download_count": 0,
+                        "archive_size": 1024,
+                    }]
+                })
+                .to_string()
+                .into(),
+            )),
+            "/extensions/zed-ruby/1.0.0/download" => Ok(Response::new(vec![0; 512].into())),
+            _ => Ok(Response::builder().status(404).body("not found".into())?),
+        }
+    });
+
+    let store = new_test_extension_store(
+        FakeFs::new(cx.executor()),
+        http_client,
+        Arc::new(LanguageRegistry::test(cx.executor())),
+        Arc::new(ThemeRegistry::new(Box::new(()))),
+        cx,
+    );
+
+    let extensions = store
+        .update(cx, |store, cx| store.fetch_extensions(None, cx))
+        .await
+        .unwrap();
+    assert_eq!(extensions[0].archive_size, Some(1024));
+
+    let error = store
+        .update(cx, |store, cx| {
+            store.install_or_upgrade_extension(
+                "zed-ruby".into(),
+                "1.0.0".into(),
+                ExtensionOperation::Install,
+                cx,
+            )
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "downloaded extension size 512 does not match declared size 1024"
+    );
+}
+
+#[gpui::test]
+async fn test_set_http_client(cx: &mut TestAppContext) {
+    init_test(cx);
+
+    let recording_client = |requests: Arc<Mutex<Vec<String>>>| {
+        FakeHttpClient::create(move |request| {
+            let requests = requests.clone();
+            async move {
+                requests.lock().push(request.uri().path().to_string());
+                Ok(Response::new(json!({ "data": [] }).to_string().into()))
+            }
+        })
+    };
+    let old_requests = Arc::new(Mutex::new(Vec::new()));
+    let new_requests = Arc::new(Mutex::new(Vec::new()));
+
+    let store = new_test_extension_store(
+        FakeFs::new(cx.executor()),
+        recording_client(old_requests.clone()),
+        Arc::new(LanguageRegistry::test(cx.executor())),
+        Arc::new(ThemeRegistry::new(Box::new(()))),
+        cx,
+    );
+
+    store
+        .update(cx, |store, cx| store.fetch_extensions(None, cx))
+        .await
+        .unwrap();
+    store.update(cx, |store, _| {
+        store.set_http_client(recording_client(new_requests.clone()))
+    });
+    store
+        .update(cx, |store, cx| store.fetch_extensions(None, cx))
+        .await
+        .unwrap();
+
+    assert_eq!(*old_requests.lock(), ["/extensions"]);
+    assert_eq!(*new_requests.lock(), ["/extensions"]);
+}
+
+#[gpui::test]
+async fn test_extension_store_coalesces_index_writes(cx: &mut TestAppContext) {
+    init_test(cx);
+
+    let fs = FakeFs::new(cx.executor());
+    fs.insert_tree(
+        "/the-extension-dir",
+        json!({
+            "installed": {
+                "zed-monokai": monokai_extension(),
+            }
+        }),
+    )
+    .await;
+
+    let index_write_delay = Duration::from_secs(1);
+    let store = new_test_extension_store(
+        fs.clone(),
+        FakeHttpClient::with_200_response(),
+        Arc::new(LanguageRegistry::test(cx.executor())),
+        Arc::new(ThemeRegistry::new(Box::new(()))),
+        cx,
+    );
+    store.update(cx, |store, _| {
+        store.set_index_write_delay(index_write_delay)
+    });
+
+    cx.executor().advance_clock(RELOAD_DEBOUNCE_DURATION);
+    cx.executor().run_until_parked();
+    cx.executor().advance_clock(index_write_delay);
+    cx.executor().run_until_parked();
+    let save_count = fs.save_call_count();
+
+    for _ in 0..3 {
+        let reload = store.update(cx, |store, cx| store.reload(None, cx));
+        finish_reload(reload, cx);
+    }
+    assert_eq!(fs.save_call_count(), save_count);
+
+    cx.executor().advance_clock(index_write_delay);
+    cx.executor().run_until_parked();
+    assert_eq!(fs.save_call_count(), save_count + 1);
+
+    let index_json = fs
+        .load("/the-extension-dir/index.json".as_ref())
+        .await
+        .unwrap();
+    let index: ExtensionIndex = serde_json::from_str(&index_json).unwrap();
+    store.read_with(cx, |store, _| assert_eq!(index, store.extension_index));
+}
+
+#[gpui::test]
+async fn test_extension_store_flush(cx: &mut TestAppContext) {
+    init_test(cx);
+
+    let fs = FakeFs::new(cx.executor());
+    fs.insert_tree(
+        "/the-extension-dir",
+        json!({
+            "installed": {
+                "zed-monokai": monokai_extension(),
+            }
+        }),
+    )
+    .await;
+
+    let store = new_test_extension_store(
+        fs.clone(),
+        FakeHttpClient::with_200_response(),
+        Arc::new(LanguageRegistry::test(cx.executor())),
+        Arc::new(ThemeRegistry::new(Box::new(()))),
+        cx,
+    );
+    // The index is not written as soon as the extensions are loaded.
+    cx.executor().advance_clock(RELOAD_DEBOUNCE_DURATION);
+    cx.executor().run_until_parked();
+    assert!(fs
+        .load("/the-extension-dir/index.json".as_ref())
+        .await
+        .is_err());
+
+    store.update(cx, |store, cx| store.flush(cx)).await.unwrap();
+
+    let index_json = fs
+        .load("/the-extension-dir/index.json".as_ref())
+        .await
+        .unwrap();
+    let index: ExtensionIndex = serde_json::from_str(&index_json).unwrap();
+    store.read_with(cx, |store, _| {
+        assert!(store
+            .installed_extension_entries()
+            .contains_key("zed-monokai"));
+        assert_eq!(index, store.extension_index);
+    });
+
+    // Flushing without a pending write leaves the index unchanged.
+    let save_count = fs.save_call_count();
+    store.update(cx, |store, cx| store.flush(cx)).await.unwrap();
+    assert_eq!(fs.save_call_count(), save_count);
+}
+
+#[gpui::test]
+async fn test_extension_store_reloads_only_changed_themes(cx: &mut TestAppContext) {
+    init_test(cx);
+
+    let TestExtensionStore {
+        fs,
+        store,
+        theme_registry,
+        ..
+    } = init_test_extension_store(
+        json!({
+            "installed": {
+                "zed-monokai": monokai_extension(),
+            }
+        }),
+        cx,
+    )
+    .await;
+
+    let theme_names = [
+        "Monokai Dark",
+        "Monokai Light",
+        "Monokai Pro Dark",
+        "Monokai Pro Light",
+    ];
+    let old_themes = theme_names.map(|name| theme_registry.get(name).unwrap());
+
+    fs.insert_file(
+        "/the-extension-dir/installed/zed-monokai/themes/monokai-pro.json",
+        r##"{
+            "name": "Monokai Pro",
+            "author": "Someone",
+            "themes": [
+                {
+                    "name": "Monokai Pro Dark",
+                    "appearance": "dark",
+                    "style": { "editor.background": "#2d2a2e" }
+                },
+                { "name": "Monokai Pro Light", "appearance": "light", "style": {} }
+            ]
+        }"##
+        .as_bytes()
+        .to_vec(),
+    )
+    .await;
+
+    let reload = store.update(cx, |store, cx| store.reload(Some("zed-monokai".into()), cx));
+    finish_reload(reload, cx);
+
+    let new_themes = theme_names.map(|name| theme_registry.get(name).unwrap());
+    assert!(Arc::ptr_eq(&old_themes[0], &new_themes[0]));
+    assert!(Arc::ptr_eq(&old_themes[1], &new_themes[1]));
+    assert!(!Arc::ptr_eq(&old_themes[2], &new_themes[2]));
+    assert!(Arc::ptr_eq(&old_themes[3], &new_themes[3]));
+}
+
+#[gpui::test]
+async fn test_extension_store_reconcile(cx: &mut TestAppContext) {
+    init_test(cx);
+
+    let TestExtensionStore {
+        store,
+        language_registry,
+        theme_registry,
+        ..
+    } = init_test_extension_store(
+        json!({
+            "installed": {
+                "zed-monokai": monokai_extension(),
+                "zed-ruby": ruby_extension(),
+            }
+        }),
+        cx,
+    )
+    .await;
+
+    // Desync the registries from the index: drop a language and a theme from
+    // the registries, and drop a language from the index.
+    language_registry.remove_languages(&["Ruby".into()], &[]);
+    theme_registry.remove_user_themes(&["Monokai Dark".into()]);
+    store.update(cx, |store, _| {
+        store.extension_index.languages.remove("ERB");
+    });
+
+    let report = store.update(cx, |store, cx| store.reconcile(cx)).await;
+    assert_eq!(
+        report,
+        ReconcileReport {
+            added: ExtensionArtifacts {
+                languages: ["Ruby".into()].into_iter().collect(),
+                themes: ["Monokai Dark".into()].into_iter().collect(),
+                grammars: Default::default(),
+            },
+            removed: ExtensionArtifacts {
+                languages: ["ERB".into()].into_iter().collect(),
+                themes: Default::default(),
+                grammars: Default::default(),
+            },
+        }
+    );
+
+    store.read_with(cx, |_, _| {
+        assert_eq!(language_registry.language_names(), ["Plain Text", "Ruby"]);
+        assert_eq!(
+            language_registry.grammar_names(),
+            ["embedded_template".into(), "ruby".into()]
+        );
+        assert_eq!(
+            theme_registry.list_names(false),
+            [
+                "Monokai Dark",
+                "Monokai Light",
+                "Monokai Pro Dark",
+                "Monokai Pro Light",
+                "One Dark",
+            ]
+        );
+    });
+
+    // Reconciling again is a no-op.
+    let report = store.update(cx, |store, cx| store.reconcile(cx)).await;
+    assert_eq!(report, ReconcileReport::default());
+}
+
+#[gpui::test]
+async fn test_extension_store_reconcile_skips_disabled_extensions(cx: &mut TestAppContext) {
+    init_test(cx);
+
+    let fs = FakeFs::new(cx.executor());
+    fs.insert_tree(
+        "/the-extension-dir",
+        json!({
+            "installed": {
+                "zed-monokai": monokai_extension(),
+                "zed-ruby": ruby_extension(),
+            }
+        }),
+    )
+    .await;
+    fs.insert_file(
+        "/the-extension-dir/installed/zed-ruby/.disabled",
+        Vec::new(),
+    )
+    .await;
+
+    let language_registry = Arc::new(LanguageRegistry::test(cx.executor()));
+    let theme_registry = Arc::new(ThemeRegistry::new(Box::new(())));
+    let store = new_test_extension_store(
+        fs.clone(),
+        FakeHttpClient::with_200_response(),
+        language_registry.clone(),
+        theme_registry.clone(),
+        cx,
+    );
+    cx.executor().advance_clock(RELOAD_DEBOUNCE_DURATION);
+    cx.executor().run_until_parked();
+
+    theme_registry.remove_user_themes(&["Monokai Dark".into()]);
+
+    // The disabled extension's languages and grammars are not registered,
+    // even though they are missing from the registries.
+    let report = store.update(cx, |store, cx| store.reconcile(cx)).await;
+    assert_eq!(
+        report,
+        ReconcileReport {
+            added: ExtensionArtifacts {
+                themes: ["Monokai Dark".into()].into_iter().collect(),
+                ..Default::default()
+            },
+            removed: ExtensionArtifacts::default(),
+        }
+    );
+
+    store.read_with(cx, |store, _| {
+        assert!(store.extension_index.extensions.contains_key("zed-ruby"));
+        assert_eq!(language_registry.language_names(), ["Plain Text"]);
+        assert_eq!(language_registry.grammar_names(), Vec::<Arc<str>>::new());
+    });
+}
+
+#[test]
+fn test_load_plugin_queries_with_runnables() {
+    let language_dir = temp_tree(json!({
+        "config.toml": r#"
+            name = "Ruby"
+            grammar = "ruby"
+            path_suffixes = ["rb"]
+        "#,
+        "highlights.scm": "(identifier) @variable",
+        "runnables.scm": "(call method: (identifier) @run (#eq? @run \"describe\"))",
+    }));
+
+    let mut queries = LanguageQueries::default();
+    append_plugin_queries(&mut queries, language_dir.path());
+    assert_eq!(
+        queries.runnables.as_deref(),
+        Some("(call method: (identifier) @run (#eq? @run \"describe\"))")
+    );
+    assert_eq!(
+        queries.highlights.as_deref(),
+        Some("(identifier) @variable")
+    );
+}
+
+#[test]
+fn test_load_plugin_queries_with_outline() {
+    let language_dir = temp_tree(json!({
+        "config.toml": r#"
+            name = "Ruby"
+            grammar = "ruby"
+            path_suffixes = ["rb"]
+        "#,
+        "highlights.scm": "(identifier) @variable",
+        "outline.scm": "(class name: (constant) @name) @item",
+    }));
+
+    let mut queries = LanguageQueries::default();
+    append_plugin_queries(&mut queries, language_dir.path());
+    assert_eq!(
+        queries.outline.as_deref(),
+        Some("(class name: (constant) @name) @item")
+    );
+}
+
+#[gpui::test]
+async fn test_extract_archive(cx: &mut TestAppContext) {
+    init_test(cx);
+    cx.executor().allow_parking();
+
+    assert_eq!(
+        ArchiveFormat::detect("https://example.com/server.tar.zst?raw=1", b""),
+        Some(ArchiveFormat::TarZst)
+    );
+    assert_eq!(
+        ArchiveFormat::detect("https://example.com/server", b"PK\x03\x04"),
+        Some(ArchiveFormat::Zip)
+    );
+    assert_eq!(
+        ArchiveFormat::detect("https://example.com/server", b"#!/bin/sh"),
+        None
+    );
+
+    // A `.tar.xz` archive is detected from its contents, whatever its URL.
+    let mut tar_bytes = Vec::<u8>::new();
+    let mut archive = async_tar::Builder::new(&mut tar_bytes);
+    let mut header = async_tar::Header::new_gnu();
+    header.set_size(b"#!/bin/sh\n".len() as u64);
+    header.set_mode(0o755);
+    archive
+        .append_data(&mut header, "bin/server", b"#!/bin/sh\n".as_slice())
+        .await
+        .unwrap();
+    archive.into_inner().await.unwrap();
+    let mut xz_bytes = Vec::new();
+    XzEncoder::new(BufReader::new(tar_bytes.as_slice()))
+        .read_to_end(&mut xz_bytes)
+        .await
+        .unwrap();
+
+    let format = ArchiveFormat::detect("https://example.com/server.tar.gz", &xz_bytes);
+    assert_eq!(format, Some(ArchiveFormat::TarXz));
+
+    let fs = FakeFs::new(cx.executor());
+    extract_archive(
+        fs.as_ref(),
+        ArchiveFormat::TarXz,
+        xz_bytes.as_slice(),
+        Path::new("/work/server"),
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        fs.load(Path::new("/work/server/bin/server")).await.unwrap(),
+        "#!/bin/sh\n"
+    );
+
+    // A `.zip` archive's files keep their permissions, except for the setuid bit.
+    let mut writer = ZipFileWriter::new(Vec::new());
+    writer
+        .write_entry_whole(
+            ZipEntryBuilder::new("bin/server".to_string().into(), Compression::Deflate)
+                .unix_permissions(0o4755),
+            b"#!/bin/sh\n",
+        )
+        .await
+        .unwrap();
+    let zip_bytes = writer.close().await.unwrap();
+
+    let format = ArchiveFormat::detect("https://example.com/server.zip", &zip_bytes);
+    assert_eq!(format, Some(ArchiveFormat::Zip));
+
+    let destination = temp_tree(json!({}));
+    extract_archive(
+        &RealFs::default(),
+        ArchiveFormat::Zip,
+        zip_bytes.as_slice(),
+        destination.path(),
+    )
+    .await
+    .unwrap();
+    let server_path = destination.path().join("bin/server");
+    assert_eq!(
+        std::fs::read_to_string(&server_path).unwrap(),
+        "#!/bin/sh\n"
+    );
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt as _;
+        let mode = std::fs::metadata(&server_path)
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o7777, 0o755);
+    }
+}
+
+#[test]
+fn test_load_plugin_queries_with_text_objects() {
+    let language_dir = temp_tree(json!({
+        "config.toml": r#"
+            name = "Ruby"
+            grammar = "ruby"
+            path_suffixes = ["rb"]
+        "#,
+        "highlights.scm": "(identifier) @variable",
+        "text_objects.scm": "(method body: (_) @function.inside) @function.around",
+    }));
+
+    let mut queries = LanguageQueries::default();
+    append_plugin_queries(&mut queries, language_dir.path());
+    assert_eq!(
+        queries.text_objects.as_deref(),
+        Some("(method body: (_) @function.inside) @function.around")
+    );
+}
+
+#[gpui::test]
+async fn test_language_servers_reregistered_when_features_change(cx: &mut TestAppContext) {
+    init_test(cx);
+
+    let TestExtensionStore {
+        store,
+        language_registry,
+        ..
+    } = init_test_extension_store(
+        json!({
+            "installed": {
+                "zed-ruby": ruby_extension(),
+            }
+        }),
+        cx,
+    )
+    .await;
+
+    let manifest: Arc<ExtensionManifest> = Arc::new(
+        toml::from_str(
+            r#"
+                id = "the-lsp-extension"
+                name = "The LSP Extension"
+                version = "0.1.0"
+                schema_version = 1
+
+                [features.experimental-server]
+                description = "An experimental language server"
+
+                [language_servers.the-server]
+                language = "Ruby"
+
+                [language_servers.the-experimental-server]
+                language = "Ruby"
+                feature = "experimental-server"
+            "#,
+        )
+        .unwrap(),
+    );
+    store.update(cx, |store, cx| {
+        let wasm_extension =
+            WasmExtension::fake(manifest.clone(), cx.background_executor().clone());
+        store.register_language_servers(
+            &manifest,
+            &wasm_extension,
+            ExtensionSettings::get_global(cx),
+        );
+        store
+            .wasm_extensions
+            .push((manifest.clone(), wasm_extension));
+    });
+
+    let ruby = language_registry.language_for_name("Ruby").await.unwrap();
+    let registered_servers = || {
+        language_registry
+            .lsp_adapters(&ruby)
+            .iter()
+            .map(|adapter| adapter.name.0.to_string())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(registered_servers(), ["the-server"]);
+
+    let set_feature_enabled = |enabled: bool, cx: &mut TestAppContext| {
+        cx.update(|cx| {
+            SettingsStore::update_global(cx, |store, cx| {
+                store.update_user_settings::<ExtensionSettings>(cx, |settings| {
+                    settings.extension_features.insert(
+                        "the-lsp-extension".into(),
+                        [("experimental-server".into(), enabled)]
+                            .into_iter()
+                            .collect(),
+                    );
+                });
+            });
+        });
+        cx.executor().run_until_parked();
+    };
+
+    set_feature_enabled(true, cx);
+    assert_eq!(
+        registered_servers(),
+        ["the-experimental-server", "the-server"]
+    );
+
+    set_feature_enabled(false, cx);
+    assert_eq!(registered_servers(), ["the-server"]);
+
+    // Changing the settings of other extensions leaves the language servers
+    // registered as they are.
+    let reload_count = language_registry.reload_count();
+    cx.update(|cx| {
+        SettingsStore::update_global(cx, |store, cx| {
+            store.update_user_settings::<ExtensionSettings>(cx, |settings| {
+                settings
+                    .extension_features
+                    .insert("zed-ruby".into(), Default::default());
+            });
+        });
+    });
+    cx.executor().run_until_parked();
+    assert_eq!(language_registry.reload_count(), reload_count);
+    assert_eq!(registered_servers(), ["the-server"]);
+}
+
+fn new_test_extension_store(
+    fs: Arc<FakeFs>,
+    http_client: Arc<HttpClientWithUrl>,
+    language_registry: Arc<LanguageRegistry>,
+    theme_registry: Arc<ThemeRegistry>,
+    cx: &mut TestAppContext,
+) -> Model<ExtensionStore> {
+    let indexed_docs_registry = Arc::new(IndexedDocsRegistry::new(cx.executor()));
+    cx.new_model(|cx| {
+        ExtensionStore::new(
+            PathBuf::from("/the-extension-dir"),
+            None,
+            fs,
+            http_client,
+            RetryPolicy::default(),
+            None,
+            FakeNodeRuntime::new(),
+            language_registry,
+            theme_registry,
+            SlashCommandRegistry::new(),
+            indexed_docs_registry,
+            Arc::new(SnippetRegistry::new()),
+            cx,
+        )
+    })
+}
+
+/// An extension store over a fake file system, as created by
+/// [`init_test_extension_store`].
+struct TestExtensionStore {
+    fs: Arc<FakeFs>,
+    store: Model<ExtensionStore>,
+    language_registry: Arc<LanguageRegistry>,
+    theme_registry: Arc<ThemeRegistry>,
+}
+
+/// Creates an extension store whose extensions directory has the given
+/// contents, and waits for it to load the installed extensions.
+async fn init_test_extension_store(
+    extensions_dir: serde_json::Value,
+    cx: &mut TestAppContext,
+) -> TestExtensionStore {
+    let fs = FakeFs::new(cx.executor());
+    fs.insert_tree("/the-extension-dir", extensions_dir).await;
+    let language_registry = Arc::new(LanguageRegistry::test(cx.executor()));
+    let theme_registry = Arc::new(ThemeRegistry::new(Box::new(())));
+    let store = new_test_extension_store(
+        fs.clone(),
+        FakeHttpClient::with_200_response(),
+        language_registry.clone(),
+        theme_registry.clone(),
+        cx,
+    );
+    cx.executor().advance_clock(RELOAD_DEBOUNCE_DURATION);
+    cx.executor().run_until_parked();
+    TestExtensionStore {
+        fs,
+        store,
+        language_registry,
+        theme_registry,
+    }
+}
+
+/// Lets the debounce of the given reload elapse, and checks that the reload
+/// then finishes.
+fn finish_reload(reload: impl Future<Output = ()>, cx: &mut TestAppContext) {
+    cx.executor().advance_clock(RELOAD_DEBOUNCE_DURATION);
+    cx.executor().run_until_parked();
+    assert!(reload.now_or_never().is_some(), "reload did not finish");
+}
+
+fn monokai_extension() -> serde_json::Value {
+    json!({
+        "extension.json": r#"{
+            "id": "zed-monokai",
+            "name": "Zed Monokai",
+            "version": "2.0.0"
+        }"#,
+        "themes": {
+            "monokai.json": r#"{
+                "name": "Monokai",
+                "author": "Someone",
+                "themes": [
+                    { "name": "Monokai Dark", "appearance": "dark", "style": {} },
+                    { "name": "Monokai Light", "appearance": "light", "style": {} }
+                ]
+            }"#,
+            "monokai-pro.json": r#"{
+                "name": "Monokai Pro",
+                "author": "Someone",
+                "themes": [
+                    { "name": "Monokai Pro Dark", "appearance": "dark", "style": {} },
+                    { "name": "Monokai Pro Light", "appearance": "light", "style": {} }
+                ]
+            }"#,
+        }
+    })
+}
+
+fn ruby_extension() -> serde_json::Value {
+    json!({
+        "extension.json": r#"{
+            "id": "zed-ruby",
+            "name": "Zed Ruby",
+            "version": "1.0.0",
+            "grammars": {
+                "ruby": "grammars/ruby.wasm",
+                "embedded_template": "grammars/embedded_template.wasm"
+            }
+        }"#,
+        "grammars": {
+            "ruby.wasm": "",
+            "embedded_template.wasm": "",
+        },
+        "languages": {
+            "ruby": {
+                "config.toml": r#"
+                    name = "Ruby"
+                    grammar = "ruby"
+                    path_suffixes = ["rb"]
+                "#,
+                "highlights.scm": "",
+            },
+            "erb": {
+                "config.toml": r#"
+                    name = "ERB"
+                    grammar = "embedded_template"
+                    path_suffixes = ["erb"]
+                "#,
+                "highlights.scm": "",
+            }
+        },
+    })
+}
+
+/// Returns a WASM module with the exports of a Tree-sitter grammar with the
+/// given name, and optionally those of an external scanner.
+pub(crate) fn grammar_wasm(grammar_name: &str, external_scanner: bool) -> Vec<u8> {
+    use wasm_encoder::{
+        CodeSection, EntityType, ExportKind, ExportSection, Function, FunctionSection,
+        ImportSection, Instruction, Module, TypeSection, ValType,
+    };
+
+    let mut types = TypeSection::new();
+    types.function([], [ValType::I32]);
+    types.function([ValType::I32], [ValType::I32]);
+
+    let mut imports = ImportSection::new();
+    let mut export_names = vec![format!("tree_sitter_{grammar_name}")];
+    if external_scanner {
+        // Scanners commonly use libc's character classification functions.
+        imports.import("env", "iswspace", EntityType::Function(1));
+        export_names.extend(
+            ["create", "destroy", "scan", "serialize", "deserialize"]
+                .map(|function| format!("tree_sitter_{grammar_name}_external_scanner_{function}")),
+        );
+    }
+
+    let mut functions = FunctionSection::new();
+    let mut exports = ExportSection::new();
+    let mut code = CodeSection::new();
+    for (ix, export_name) in export_names.iter().enumerate() {
+        functions.function(0);
+        exports.export(export_name, ExportKind::Func, imports.len() + ix as u32);
+        let mut function = Function::new(Vec::new());
+        function.instruction(&Instruction::I32Const(0));
+        function.instruction(&Instruction::End);
+        code.function(&function);
+    }
+
+    let mut module = Module::new();
+    module
+        .section(&types)
+        .section(&imports)
+        .section(&functions)
+        .section(&exports)
+        .section(&code);
+    module.finish()
+}
+
+fn gruvbox_extension() -> serde_json::Value {
+    json!({
+        "extension.json": r#"{
+            "id": "zed-gruvbox",
+            "name": "Zed Gruvbox",
+            "version": "1.0.0"
+        }"#,
+        "themes": {
+            "gruvbox.json": r#"{
+                "name": "Gruvbox",
+                "author": "Someone Else",
+                "themes": [
+                    { "name": "Gruvbox", "appearance": "dark", "style": {} }
+                ]
+            }"#,
+        }
+    })
+}
+
 fn init_test(cx: &mut TestAppContext) {
     cx.update(|cx| {
         let store = SettingsStore::test(cx);
@@ -0,0 +1,66 @@
+use anyhow::{Context as _, Result};
+use collections::BTreeMap;
+use fs::Fs;
+use serde::{Deserialize, Serialize};
+use std::{path::Path, sync::Arc};
+
+/// The record of a reproducible installation, from which another machine can
+/// install exactly the same versions of the extensions and of the language
+/// servers that they download.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExtensionLockfile {
+    /// The installed version of each extension, keyed by extension ID.
+    #[serde(default)]
+    pub extensions: BTreeMap<Arc<str>, Arc<str>>,
+    /// The versions that each extension resolved for the packages and releases
+    /// it installs, keyed by extension ID and then by `npm:<package>` or
+    /// `github:<owner>/<repo>`.
+    #[serde(default)]
+    pub resolved_versions: BTreeMap<Arc<str>, BTreeMap<String, String>>,
+}
+
+/// A version that an extension resolved while it was running, and that should
+/// be recorded in the lockfile.
+pub(crate) struct ResolvedVersion {
+    pub extension_id: Arc<str>,
+    pub package: String,
+    pub version: String,
+}
+
+impl ExtensionLockfile {
+    /// Loads the lockfile at the given path, which is empty if it doesn't exist yet.
+    pub async fn load(fs: &dyn Fs, path: &Path) -> Result<Self> {
+        if !fs.is_file(path).await {
+            return Ok(Self::default());
+        }
+        let content = fs
+            .load(path)
+            .await
+            .with_context(|| format!("error reading extension lockfile {path:?}"))?;
+        serde_json::from_str(&content)
+            .with_context(|| format!("invalid extension lockfile {path:?}"))
+    }
+
+    pub async fn save(&self, fs: &dyn Fs, path: &Path) -> Result<()> {
+        let content = serde_json::to_string_pretty(self)?;
+        fs.atomic_write(path.to_path_buf(), content)
+            .await
+            .with_context(|| format!("failed to save extension lockfile {path:?}"))
+    }
+
+    /// Returns the version that the given extension resolved for a package or
+    /// release, if it is locked.
+    pub fn resolved_version(&self, extension_id: &str, package: &str) -> Option<&str> {
+        self.resolved_versions
+            .get(extension_id)?
+            .get(package)
+            .map(String::as_str)
+    }
+
+    pub(crate) fn record(&mut self, resolved: ResolvedVersion) {
+        self.resolved_versions
+            .entry(resolved.extension_id)
+            .or_default()
+            .insert(resolved.package, resolved.version);
+    }
+}
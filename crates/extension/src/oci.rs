@@ -0,0 +1,204 @@
+use anyhow::{anyhow, bail, Context as _, Result};
+use futures::{io::BufReader, AsyncReadExt};
+use http_client::{AsyncBody, HttpClient, Request, Response, StatusCode};
+use isahc::config::{Configurable, RedirectPolicy};
+use regex::Regex;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::fmt;
+
+const OCI_MANIFEST_MEDIA_TYPE: &str = "application/vnd.oci.image.manifest.v1+json";
+const DOCKER_MANIFEST_MEDIA_TYPE: &str = "application/vnd.docker.distribution.manifest.v2+json";
+
+/// The media types with which registries label a layer containing a WASM module.
+const WASM_LAYER_MEDIA_TYPES: &[&str] = &[
+    "application/wasm",
+    "application/vnd.wasm.content.layer.v1+wasm",
+];
+
+/// A reference to an artifact in an OCI registry, e.g. `ghcr.io/owner/tree-sitter-foo:1.0.0`.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct OciReference {
+    pub registry: String,
+    pub repository: String,
+    /// A tag or a digest.
+    pub reference: String,
+}
+
+impl OciReference {
+    pub fn parse(text: &str) -> Result<Self> {
+        let (registry, rest) = text
+            .split_once('/')
+            .ok_or_else(|| anyhow!("OCI reference '{text}' has no registry"))?;
+        if !(registry.contains('.') || registry.contains(':') || registry == "localhost") {
+            bail!("OCI reference '{text}' has no registry");
+        }
+
+        let (repository, reference) = if let Some((repository, digest)) = rest.split_once('@') {
+            (repository, digest)
+        } else {
+            match rest.rsplit_once(':') {
+                Some((repository, tag)) if !tag.contains('/') => (repository, tag),
+                _ => (rest, "latest"),
+            }
+        };
+        if repository.is_empty() || reference.is_empty() {
+            bail!("invalid OCI reference '{text}'");
+        }
+
+        Ok(Self {
+            registry: registry.to_string(),
+            repository: repository.to_string(),
+            reference: reference.to_string(),
+        })
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("https://{}/v2/{}/{path}", self.registry, self.repository)
+    }
+}
+
+impl fmt::Display for OciReference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let separator = if self.reference.contains(':') {
+            '@'
+        } else {
+            ':'
+        };
+        write!(
+            f,
+            "{}/{}{separator}{}",
+            self.registry, self.repository, self.reference
+        )
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct OciManifest {
+    #[serde(default)]
+    layers: Vec<OciDescriptor>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct OciDescriptor {
+    media_type: String,
+    digest: String,
+}
+
+#[derive(Deserialize)]
+struct OciToken {
+    #[serde(alias = "access_token")]
+    token: String,
+}
+
+/// Downloads the WASM layer of the artifact with the given reference, following the
+/// [OCI distribution spec](https://github.com/opencontainers/distribution-spec).
+///
+/// Registries that require a token for anonymous pulls are supported via the
+/// bearer token challenge in their `WWW-Authenticate` response header.
+pub(crate) async fn fetch_wasm_layer(
+    http: &dyn HttpClient,
+    reference: &OciReference,
+) -> Result<Vec<u8>> {
+    let manifest_url = reference.url(&format!("manifests/{}", reference.reference));
+    let accept = format!("{OCI_MANIFEST_MEDIA_TYPE}, {DOCKER_MANIFEST_MEDIA_TYPE}");
+
+    let mut token = None;
+    let mut response = get(http, &manifest_url, &accept, None).await?;
+    if response.status() == StatusCode::UNAUTHORIZED {
+        let new_token = fetch_token(http, &response).await?;
+        response = get(http, &manifest_url, &accept, Some(&new_token)).await?;
+        token = Some(new_token);
+    }
+    let manifest: OciManifest = serde_json::from_slice(&read_body(&manifest_url, response).await?)
+        .with_context(|| format!("invalid OCI manifest for {reference}"))?;
+
+    let layer = manifest
+        .layers
+        .iter()
+        .find(|layer| WASM_LAYER_MEDIA_TYPES.contains(&layer.media_type.as_str()))
+        .ok_or_else(|| anyhow!("{reference} has no WASM layer"))?;
+
+    let blob_url = reference.url(&format!("blobs/{}", layer.digest));
+    let response = get(http, &blob_url, "*/*", token.as_deref()).await?;
+    let content = read_body(&blob_url, response).await?;
+
+    let expected_digest = layer
+        .digest
+        .strip_prefix("sha256:")
+        .ok_or_else(|| anyhow!("unsupported digest algorithm in '{}'", layer.digest))?;
+    let actual_digest = format!("{:x}", Sha256::digest(&content));
+    if actual_digest != expected_digest {
+        bail!(
+            "digest mismatch for {reference}: expected sha256:{expected_digest}, got sha256:{actual_digest}"
+        );
+    }
+
+    Ok(content)
+}
+
+async fn get(
+    http: &dyn HttpClient,
+    url: &str,
+    accept: &str,
+    token: Option<&str>,
+) -> Result<Response<AsyncBody>> {
+    let mut request = Request::builder()
+        .redirect_policy(RedirectPolicy::Follow)
+        .uri(url)
+        .header("Accept", accept);
+    if let Some(token) = token {
+        request = request.header("Authorization", format!("Bearer {token}"));
+    }
+    Ok(http.send(request.body(AsyncBody::default())?).await?)
+}
+
+async fn read_body(url: &str, mut response: Response<AsyncBody>) -> Result<Vec<u8>> {
+    let mut content = Vec::new();
+    BufReader::new(response.body_mut())
+        .read_to_end(&mut content)
+        .await?;
+    if !response.status().is_success() {
+        bail!(
+            "request to {url} failed with status {}: {}",
+            response.status(),
+            String::from_utf8_lossy(&content)
+        );
+    }
+    Ok(content)
+}
+
+/// Requests an anonymous bearer token as described by the `WWW-Authenticate`
+/// header of an unauthorized response.
+async fn fetch_token(http: &dyn HttpClient, response: &Response<AsyncBody>) -> Result<String> {
+    let challenge = response
+        .headers()
+        .get("www-authenticate")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .ok_or_else(|| anyhow!("registry requires unsupported authentication"))?;
+
+    let mut realm = None;
+    let mut query = Vec::new();
+    let parameter_regex = Regex::new(r#"(\w+)="([^"]*)""#)?;
+    for parameter in parameter_regex.captures_iter(challenge) {
+        let (key, value) = (
+            parameter.get(1).unwrap().as_str(),
+            parameter.get(2).unwrap().as_str(),
+        );
+        match key {
+            "realm" => realm = Some(value),
+            "service" | "scope" => query.push((key, value)),
+            _ => {}
+        }
+    }
+    let realm = realm.ok_or_else(|| anyhow!("authentication challenge has no realm"))?;
+    let token_url = url::Url::parse_with_params(realm, query)?;
+
+    let response = get(http, token_url.as_str(), "application/json", None).await?;
+    let token: OciToken = serde_json::from_slice(&read_body(token_url.as_str(), response).await?)
+        .context("invalid token response")?;
+    Ok(token.token)
+}
@@ -0,0 +1,89 @@
+use anyhow::Result;
+use futures::Future;
+use gpui::BackgroundExecutor;
+use http_client::{AsyncBody, Response};
+use rand::Rng as _;
+use std::time::Duration;
+
+/// Determines how downloads of extensions and of the assets they install are
+/// retried after transient failures.
+///
+/// Requests are retried when they could not be sent, e.g. because the connection
+/// was reset, or when the server responds with a 5xx status. Other responses
+/// are returned as they are.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// The number of times a failed request is retried before giving up.
+    pub max_retries: u32,
+    /// The delay before the first retry, which doubles with each retry after it.
+    pub initial_delay: Duration,
+    /// The longest delay between two attempts.
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            initial_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(10),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// A policy under which failed requests are not retried.
+    pub fn none() -> Self {
+        Self {
+            max_retries: 0,
+            ..Default::default()
+        }
+    }
+
+    /// Returns the delay before the given retry, counting from zero, with a
+    /// random jitter of up to half of the delay so that clients that failed
+    /// together don't retry together.
+    fn delay(&self, retry: u32) -> Duration {
+        let delay = self
+            .initial_delay
+            .saturating_mul(2u32.saturating_pow(retry))
+            .min(self.max_delay);
+        delay.mul_f64(rand::thread_rng().gen_range(0.5..=1.0))
+    }
+
+    /// Sends a request with `send`, sending it again according to this policy
+    /// while it fails transiently.
+    ///
+    /// The result of the last attempt is returned once the retries are used up.
+    pub(crate) async fn send<F, Fut>(
+        &self,
+        executor: &BackgroundExecutor,
+        mut send: F,
+    ) -> Result<Response<AsyncBody>>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<Response<AsyncBody>>>,
+    {
+        let mut retry = 0;
+        loop {
+            let result = send().await;
+            let failure = match &result {
+                Ok(response) if response.status().is_server_error() => {
+                    format!("status {}", response.status())
+                }
+                Ok(_) => return result,
+                Err(error) => format!("{error:#}"),
+            };
+            if retry >= self.max_retries {
+                return result;
+            }
+
+            let delay = self.delay(retry);
+            log::warn!("request failed with {failure}, retrying in {delay:?}");
+            if !delay.is_zero() {
+                executor.timer(delay).await;
+            }
+            retry += 1;
+        }
+    }
+}
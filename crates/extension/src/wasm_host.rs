@@ -1,6 +1,8 @@
 pub(crate) mod wit;
 
-use crate::ExtensionManifest;
+use crate::extension_manifest::DownloadChecksum;
+use crate::lockfile::{ExtensionLockfile, ResolvedVersion};
+use crate::{ExtensionLoadError, ExtensionManifest, LoadErrorSeverity, RetryPolicy};
 use anyhow::{anyhow, bail, Context as _, Result};
 use fs::{normalize_path, Fs};
 use futures::future::LocalBoxFuture;
@@ -10,18 +12,24 @@ use futures::{
         oneshot,
     },
     future::BoxFuture,
-    Future, FutureExt, StreamExt as _,
+    io::BufReader,
+    select_biased, AsyncReadExt as _, Future, FutureExt, StreamExt as _,
 };
 use gpui::{AppContext, AsyncAppContext, BackgroundExecutor, Task};
 use http_client::HttpClient;
 use language::LanguageRegistry;
 use node_runtime::NodeRuntime;
+use parking_lot::Mutex;
 use release_channel::ReleaseChannel;
 use semantic_version::SemanticVersion;
+use sha2::{Digest as _, Sha256};
+use smol::lock::Semaphore;
 use std::{
-    path::{Path, PathBuf},
+    path::{self, Path, PathBuf},
     sync::{Arc, OnceLock},
+    time::Duration,
 };
+use util::ResultExt as _;
 use wasmtime::{
     component::{Component, ResourceTable},
     Engine, Store,
@@ -31,19 +39,69 @@ use wit::Extension;
 
 pub(crate) struct WasmHost {
     engine: Engine,
-    release_channel: ReleaseChannel,
+    /// The release channel, which determines the supported extension API
+    /// versions. It is `None` if `release_channel::init` was not called, in
+    /// which case no extensions can be loaded.
+    release_channel: Option<ReleaseChannel>,
     http_client: Arc<dyn HttpClient>,
+    /// How the downloads made by extensions are retried.
+    pub(crate) retry_policy: RetryPolicy,
+    pub(crate) executor: BackgroundExecutor,
     node_runtime: Arc<dyn NodeRuntime>,
     pub(crate) language_registry: Arc<LanguageRegistry>,
     fs: Arc<dyn Fs>,
     pub(crate) work_dir: PathBuf,
     _main_thread_message_task: Task<()>,
     main_thread_message_tx: mpsc::UnboundedSender<MainThreadCall>,
+    produced_grammar_tx: mpsc::UnboundedSender<ProducedGrammar>,
+    call_error_tx: mpsc::UnboundedSender<ExtensionLoadError>,
+    /// The lockfile of a reproducible installation, whose resolved versions
+    /// extensions use instead of resolving them again. It is `None` unless a
+    /// lockfile is configured.
+    lockfile: Option<Arc<Mutex<ExtensionLockfile>>>,
+    resolved_version_tx: mpsc::UnboundedSender<ResolvedVersion>,
+    call_limiter: CallLimiter,
 }
 
+/// Bounds the number of extension calls that run at once, across all of the
+/// extensions loaded by a [`WasmHost`].
+#[derive(Clone)]
+pub(crate) struct CallLimiter(Arc<Semaphore>);
+
+impl CallLimiter {
+    pub fn new(max_concurrent_calls: usize) -> Self {
+        Self(Arc::new(Semaphore::new(max_concurrent_calls)))
+    }
+
+    /// Runs the given future once fewer than the maximum number of calls are running.
+    pub async fn run<F: Future>(&self, future: F) -> F::Output {
+        let _guard = self.0.acquire().await;
+        future.await
+    }
+}
+
+/// A grammar that an extension produced (e.g. downloaded or compiled) while it
+/// was running, and that should now be registered.
+pub(crate) struct ProducedGrammar {
+    pub extension_id: Arc<str>,
+    pub grammar_name: Arc<str>,
+    pub path: PathBuf,
+}
+
+/// How long an extension call may run before it is cancelled, unless the
+/// caller specifies otherwise. Calls that do long-running work, such as
+/// indexing docs, use [`WasmExtension::call_with_timeout`] with a longer
+/// timeout instead.
+pub(crate) const DEFAULT_CALL_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How often the engine's epoch advances. Extensions yield to the executor
+/// whenever it does, so that calls that are busy computing can still time out.
+const EPOCH_INTERVAL: Duration = Duration::from_millis(100);
+
 #[derive(Clone)]
 pub struct WasmExtension {
     tx: UnboundedSender<ExtensionCall>,
+    executor: BackgroundExecutor,
     pub(crate) manifest: Arc<ExtensionManifest>,
     #[allow(unused)]
     pub zed_api_version: SemanticVersion,
@@ -71,39 +129,148 @@ fn wasm_engine() -> wasmtime::Engine {
             let mut config = wasmtime::Config::new();
             config.wasm_component_model(true);
             config.async_support(true);
-            wasmtime::Engine::new(&config).unwrap()
+            config.epoch_interruption(true);
+            let engine = wasmtime::Engine::new(&config).unwrap();
+
+            let ticking_engine = engine.clone();
+            std::thread::Builder::new()
+                .name("wasm-epoch".into())
+                .spawn(move || loop {
+                    std::thread::sleep(EPOCH_INTERVAL);
+                    ticking_engine.increment_epoch();
+                })
+                .log_err();
+
+            engine
         })
         .clone()
 }
 
+fn spawn_main_thread_message_task(
+    cx: &mut AppContext,
+) -> (mpsc::UnboundedSender<MainThreadCall>, Task<()>) {
+    let (tx, mut rx) = mpsc::unbounded::<MainThreadCall>();
+    let task = cx.spawn(|mut cx| async move {
+        while let Some(message) = rx.next().await {
+            message(&mut cx).await;
+        }
+    });
+    (tx, task)
+}
+
 impl WasmHost {
     pub fn new(
         fs: Arc<dyn Fs>,
         http_client: Arc<dyn HttpClient>,
+        retry_policy: RetryPolicy,
         node_runtime: Arc<dyn NodeRuntime>,
         language_registry: Arc<LanguageRegistry>,
         work_dir: PathBuf,
+        produced_grammar_tx: mpsc::UnboundedSender<ProducedGrammar>,
+        call_error_tx: mpsc::UnboundedSender<ExtensionLoadError>,
+        lockfile: Option<Arc<Mutex<ExtensionLockfile>>>,
+        resolved_version_tx: mpsc::UnboundedSender<ResolvedVersion>,
+        max_concurrent_calls: usize,
         cx: &mut AppContext,
     ) -> Arc<Self> {
-        let (tx, mut rx) = mpsc::unbounded::<MainThreadCall>();
-        let task = cx.spawn(|mut cx| async move {
-            while let Some(message) = rx.next().await {
-                message(&mut cx).await;
-            }
-        });
+        let (tx, task) = spawn_main_thread_message_task(cx);
         Arc::new(Self {
             engine: wasm_engine(),
             fs,
             work_dir,
             http_client,
+            retry_policy,
+            executor: cx.background_executor().clone(),
             node_runtime,
             language_registry,
-            release_channel: ReleaseChannel::global(cx),
+            release_channel: ReleaseChannel::try_global(cx),
+            _main_thread_message_task: task,
+            main_thread_message_tx: tx,
+            produced_grammar_tx,
+            call_error_tx,
+            lockfile,
+            resolved_version_tx,
+            call_limiter: CallLimiter::new(max_concurrent_calls),
+        })
+    }
+
+    /// Returns a host like this one, whose extensions work in the given directory.
+    ///
+    /// The two hosts share a limit on the number of concurrent extension calls.
+    pub fn relocated(&self, work_dir: PathBuf, cx: &mut AppContext) -> Arc<Self> {
+        let (tx, task) = spawn_main_thread_message_task(cx);
+        Arc::new(Self {
+            engine: self.engine.clone(),
+            fs: self.fs.clone(),
+            work_dir,
+            http_client: self.http_client.clone(),
+            retry_policy: self.retry_policy,
+            executor: self.executor.clone(),
+            node_runtime: self.node_runtime.clone(),
+            language_registry: self.language_registry.clone(),
+            release_channel: self.release_channel,
             _main_thread_message_task: task,
             main_thread_message_tx: tx,
+            produced_grammar_tx: self.produced_grammar_tx.clone(),
+            call_error_tx: self.call_error_tx.clone(),
+            lockfile: self.lockfile.clone(),
+            resolved_version_tx: self.resolved_version_tx.clone(),
+            call_limiter: self.call_limiter.clone(),
         })
     }
 
+    /// Reports an error that the given extension encountered while running, so
+    /// that it is listed among the extension's load errors.
+    ///
+    /// The message includes the error's causes, along with the backtrace of the
+    /// extension's code if it trapped.
+    pub fn report_call_error(&self, extension_id: &Arc<str>, error: &anyhow::Error) {
+        self.call_error_tx
+            .unbounded_send(ExtensionLoadError {
+                extension_id: extension_id.clone(),
+                severity: LoadErrorSeverity::Error,
+                message: format!("{error:?}"),
+            })
+            .ok();
+    }
+
+    /// Returns the version that the given extension resolved for a package or
+    /// release in the lockfile, if a lockfile is configured.
+    pub fn locked_version(&self, extension_id: &str, package: &str) -> Option<String> {
+        self.lockfile
+            .as_ref()?
+            .lock()
+            .resolved_version(extension_id, package)
+            .map(ToString::to_string)
+    }
+
+    /// Records the version that the given extension resolved for a package or
+    /// release, so that it is written to the lockfile. Does nothing unless a
+    /// lockfile is configured.
+    pub fn record_resolved_version(
+        &self,
+        extension_id: &Arc<str>,
+        package: String,
+        version: String,
+    ) {
+        if self.lockfile.is_none() {
+            return;
+        }
+        self.resolved_version_tx
+            .unbounded_send(ResolvedVersion {
+                extension_id: extension_id.clone(),
+                package,
+                version,
+            })
+            .ok();
+    }
+
+    /// Returns whether extensions can be loaded, which requires the release
+    /// channel to have been initialized.
+    pub fn can_load_extensions(&self) -> bool {
+        self.release_channel.is_some()
+    }
+
     pub fn load_extension(
         self: &Arc<Self>,
         wasm_bytes: Vec<u8>,
@@ -112,6 +279,9 @@ impl WasmHost {
     ) -> Task<Result<WasmExtension>> {
         let this = self.clone();
         executor.clone().spawn(async move {
+            let release_channel = this
+                .release_channel
+                .ok_or_else(|| anyhow!("the release channel has not been initialized"))?;
             let zed_api_version = parse_wasm_extension_version(&manifest.id, &wasm_bytes)?;
 
             let component = Component::from_binary(&this.engine, &wasm_bytes)
@@ -126,10 +296,12 @@ impl WasmHost {
                     host: this.clone(),
                 },
             );
+            store.set_epoch_deadline(1);
+            store.epoch_deadline_async_yield_and_update(1);
 
             let (mut extension, instance) = Extension::instantiate_async(
                 &mut store,
-                this.release_channel,
+                release_channel,
                 zed_api_version,
                 &component,
             )
@@ -141,11 +313,12 @@ impl WasmHost {
                 .context("failed to initialize wasm extension")?;
 
             let (tx, mut rx) = mpsc::unbounded::<ExtensionCall>();
+            let call_limiter = this.call_limiter.clone();
             executor
                 .spawn(async move {
                     let _instance = instance;
                     while let Some(call) = rx.next().await {
-                        (call)(&mut extension, &mut store).await;
+                        call_limiter.run((call)(&mut extension, &mut store)).await;
                     }
                 })
                 .detach();
@@ -153,6 +326,7 @@ impl WasmHost {
             Ok(WasmExtension {
                 manifest,
                 tx,
+                executor,
                 zed_api_version,
             })
         })
@@ -196,6 +370,162 @@ impl WasmHost {
             Err(anyhow!("cannot write to path {}", path.display()))
         }
     }
+
+    /// Returns whether the file at `destination` was already downloaded from
+    /// `url`, in which case it doesn't need to be downloaded again.
+    ///
+    /// This is only known for downloads whose checksum is configured in the
+    /// extension's manifest, which the existing file must match.
+    pub async fn is_download_cached(
+        &self,
+        manifest: &ExtensionManifest,
+        url: &str,
+        destination: &Path,
+    ) -> bool {
+        if manifest.download_checksum(url).is_none() {
+            return false;
+        }
+        let Ok(content) = self.fs.load_bytes(destination).await else {
+            return false;
+        };
+        verify_download_checksum(self.http_client.as_ref(), manifest, url, &content)
+            .await
+            .is_ok()
+    }
+
+    /// Notifies the extension store that the given extension has produced a
+    /// grammar at the given path, relative to the extension's work directory,
+    /// so that it gets registered.
+    ///
+    /// Extensions call this through the `register-grammar` import.
+    pub fn grammar_produced(
+        &self,
+        extension_id: &Arc<str>,
+        grammar_name: Arc<str>,
+        path: &Path,
+    ) -> Result<()> {
+        let path = self.writeable_path_from_extension(extension_id, path)?;
+        if path
+            .extension()
+            .map_or(true, |extension| extension != "wasm")
+        {
+            bail!("grammar {} is not a wasm file", path.display());
+        }
+
+        self.produced_grammar_tx
+            .unbounded_send(ProducedGrammar {
+                extension_id: extension_id.clone(),
+                grammar_name,
+                path,
+            })
+            .map_err(|_| anyhow!("extension store has been dropped"))
+    }
+}
+
+/// Verifies the contents of a file downloaded from `url` against the checksum
+/// that the extension's manifest configures for it.
+///
+/// Downloads without a configured checksum are accepted as-is.
+pub(crate) async fn verify_download_checksum(
+    http_client: &dyn HttpClient,
+    manifest: &ExtensionManifest,
+    url: &str,
+    content: &[u8],
+) -> Result<()> {
+    let expected = match manifest.download_checksum(url) {
+        Some(DownloadChecksum::Sha256(sha256)) => sha256,
+        Some(DownloadChecksum::Asset(checksum_url)) => {
+            let mut response = http_client
+                .get(&checksum_url, Default::default(), true)
+                .await
+                .with_context(|| format!("error downloading checksum from {checksum_url}"))?;
+            let mut body = String::new();
+            BufReader::new(response.body_mut())
+                .read_to_string(&mut body)
+                .await?;
+            if !response.status().is_success() {
+                bail!(
+                    "checksum download from {checksum_url} failed with status {}",
+                    response.status()
+                );
+            }
+            body.split_whitespace()
+                .next()
+                .ok_or_else(|| anyhow!("checksum file {checksum_url} is empty"))?
+                .to_ascii_lowercase()
+        }
+        None => return Ok(()),
+    };
+
+    let actual = format!("{:x}", Sha256::digest(content));
+    if actual != expected {
+        bail!("checksum mismatch for {url}: expected sha256 {expected}, got {actual}");
+    }
+    Ok(())
+}
+
+/// Describes a failure to look up the latest version of an npm package as
+/// `<code>: <message>`, so that extensions can tell a package that doesn't
+/// exist, or a registry that can't be reached, from other failures, and fall
+/// back to a bundled version of the package.
+///
+/// The code is one of `not_found`, `unreachable`, `invalid_response`, or `error`.
+pub(crate) fn npm_lookup_error(package_name: &str, error: &anyhow::Error) -> String {
+    const UNREACHABLE_ERROR_CODES: &[&str] = &[
+        "ENOTFOUND",
+        "ECONNREFUSED",
+        "ECONNRESET",
+        "ETIMEDOUT",
+        "EAI_AGAIN",
+    ];
+
+    let message = format!("{error:#}");
+    let code = if message.contains("E404") || message.contains("no version found") {
+        "not_found"
+    } else if UNREACHABLE_ERROR_CODES
+        .iter()
+        .any(|error_code| message.contains(error_code))
+    {
+        "unreachable"
+    } else if error.chain().any(|cause| cause.is::<serde_json::Error>()) {
+        "invalid_response"
+    } else {
+        "error"
+    };
+    format!("{code}: failed to look up npm package {package_name}: {message}")
+}
+
+/// Resolves a path that an extension passed to read a file in a worktree,
+/// which must be relative to the worktree's root and stay within it.
+pub(crate) fn worktree_relative_path(path: &str) -> Result<PathBuf> {
+    let mut relative_path = PathBuf::new();
+    for component in Path::new(path).components() {
+        match component {
+            path::Component::Normal(component) => relative_path.push(component),
+            path::Component::CurDir => {}
+            path::Component::ParentDir if relative_path.pop() => {}
+            path::Component::ParentDir | path::Component::RootDir | path::Component::Prefix(_) => {
+                bail!("path {path:?} is outside of the worktree")
+            }
+        }
+    }
+    Ok(relative_path)
+}
+
+/// Runs the given future, cancelling it with an error if it doesn't complete
+/// within the timeout.
+pub(crate) async fn with_timeout<T>(
+    executor: &BackgroundExecutor,
+    timeout: Duration,
+    future: impl Future<Output = Result<T>>,
+) -> Result<T> {
+    let future = future.fuse();
+    let timer = executor.timer(timeout).fuse();
+    futures::pin_mut!(future, timer);
+    select_biased! {
+        result = future => result,
+        _ = timer => Err(anyhow!("extension call timed out after {timeout:?}")),
+    }
 }
 
 pub fn parse_wasm_extension_version(
@@ -242,19 +572,47 @@ fn parse_wasm_extension_version_custom_section(data: &[u8]) -> Option<SemanticVe
 }
 
 impl WasmExtension {
-    pub async fn call<T, Fn>(&self, f: Fn) -> T
+    /// Returns an extension without a wasm module, whose calls all fail, so
+    /// that what the store registers for an extension can be tested.
+    #[cfg(test)]
+    pub(crate) fn fake(manifest: Arc<ExtensionManifest>, executor: BackgroundExecutor) -> Self {
+        let (tx, _) = mpsc::unbounded();
+        Self {
+            tx,
+            executor,
+            manifest,
+            zed_api_version: SemanticVersion::default(),
+        }
+    }
+
+    /// Calls the extension, cancelling the call if it doesn't complete within
+    /// [`DEFAULT_CALL_TIMEOUT`].
+    pub async fn call<T, Fn>(&self, f: Fn) -> Result<T>
     where
         T: 'static + Send,
         Fn: 'static
             + Send
-            + for<'a> FnOnce(&'a mut Extension, &'a mut Store<WasmState>) -> BoxFuture<'a, T>,
+            + for<'a> FnOnce(&'a mut Extension, &'a mut Store<WasmState>) -> BoxFuture<'a, Result<T>>,
+    {
+        self.call_with_timeout(DEFAULT_CALL_TIMEOUT, f).await
+    }
+
+    /// Calls the extension, cancelling the call if it doesn't complete within
+    /// the given timeout.
+    pub async fn call_with_timeout<T, Fn>(&self, timeout: Duration, f: Fn) -> Result<T>
+    where
+        T: 'static + Send,
+        Fn: 'static
+            + Send
+            + for<'a> FnOnce(&'a mut Extension, &'a mut Store<WasmState>) -> BoxFuture<'a, Result<T>>,
     {
         let (return_tx, return_rx) = oneshot::channel();
+        let executor = self.executor.clone();
         self.tx
             .clone()
             .unbounded_send(Box::new(move |extension, store| {
-                async {
-                    let result = f(extension, store).await;
+                async move {
+                    let result = with_timeout(&executor, timeout, f(extension, store)).await;
                     return_tx.send(result).ok();
                 }
                 .boxed()
@@ -265,6 +623,18 @@ impl WasmExtension {
 }
 
 impl WasmState {
+    /// Returns the state of an extension that isn't running, so that the
+    /// functions that extensions import from the host can be tested.
+    #[cfg(test)]
+    pub(crate) fn fake(manifest: Arc<ExtensionManifest>, host: Arc<WasmHost>) -> Self {
+        Self {
+            manifest,
+            table: ResourceTable::new(),
+            ctx: wasi::WasiCtxBuilder::new().build(),
+            host,
+        }
+    }
+
     fn on_main_thread<T, Fn>(&self, f: Fn) -> impl 'static + Future<Output = T>
     where
         T: 'static + Send,
@@ -299,3 +669,73 @@ impl wasi::WasiView for WasmState {
         &mut self.ctx
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_npm_lookup_error() {
+        let code = |error: anyhow::Error| {
+            let message = npm_lookup_error("typescript-language-server", &error);
+            message.split_once(": ").unwrap().0.to_string()
+        };
+
+        assert_eq!(
+            code(anyhow!(
+                "failed to execute npm info subcommand:\nstdout: \"\"\nstderr: \"npm ERR! code E404\""
+            )),
+            "not_found"
+        );
+        assert_eq!(
+            code(anyhow!(
+                "no version found for npm package typescript-language-server"
+            )),
+            "not_found"
+        );
+        assert_eq!(
+            code(anyhow!(
+                "stderr: \"npm ERR! code ENOTFOUND\nnpm ERR! request to https://registry.npmjs.org failed\""
+            )),
+            "unreachable"
+        );
+        assert_eq!(
+            code(
+                serde_json::from_str::<serde_json::Value>("<html>")
+                    .unwrap_err()
+                    .into()
+            ),
+            "invalid_response"
+        );
+        assert_eq!(code(anyhow!("missing npm file")), "error");
+
+        let message = npm_lookup_error("typescript-language-server", &anyhow!("missing npm file"));
+        assert_eq!(
+            message,
+            "error: failed to look up npm package typescript-language-server: missing npm file"
+        );
+    }
+
+    #[test]
+    fn test_worktree_relative_path() {
+        assert_eq!(
+            worktree_relative_path(".go-version").unwrap(),
+            PathBuf::from(".go-version")
+        );
+        assert_eq!(
+            worktree_relative_path("./packages/app/../web/package.json").unwrap(),
+            PathBuf::from("packages/web/package.json")
+        );
+
+        for path in [
+            "../package.json",
+            "packages/../../package.json",
+            "/etc/passwd",
+        ] {
+            assert!(
+                worktree_relative_path(path).is_err(),
+                "{path:?} should be rejected"
+            );
+        }
+    }
+}
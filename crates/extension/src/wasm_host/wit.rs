@@ -16,13 +16,13 @@ use wasmtime::{
     Store,
 };
 
-#[cfg(test)]
-pub use latest::CodeLabelSpanLiteral;
 pub use latest::{
     zed::extension::lsp::{Completion, CompletionKind, InsertTextFormat, Symbol, SymbolKind},
     zed::extension::slash_command::{SlashCommandArgumentCompletion, SlashCommandOutput},
     CodeLabel, CodeLabelSpan, Command, Range, SlashCommand,
 };
+#[cfg(test)]
+pub use latest::{CodeLabelSpanLiteral, ExtensionImports};
 pub use since_v0_0_4::LanguageServerConfig;
 
 pub fn new_linker(
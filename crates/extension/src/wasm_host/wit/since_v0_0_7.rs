@@ -1,12 +1,15 @@
-use crate::wasm_host::{wit::ToWasmtimeResult, WasmState};
+use crate::archive::{extract_archive, ArchiveFormat};
+use crate::wasm_host::{
+    npm_lookup_error, verify_download_checksum, wit::ToWasmtimeResult, worktree_relative_path,
+    WasmState,
+};
 use ::http_client::AsyncBody;
 use ::settings::Settings;
 use anyhow::{anyhow, bail, Context, Result};
 use async_compression::futures::bufread::GzipDecoder;
-use async_tar::Archive;
 use async_trait::async_trait;
-use futures::AsyncReadExt;
 use futures::{io::BufReader, FutureExt as _};
+use futures::{AsyncBufReadExt as _, AsyncReadExt};
 use indexed_docs::IndexedDocsDatabase;
 use language::{
     language_settings::AllLanguageSettings, LanguageServerBinaryStatus, LspAdapterDelegate,
@@ -91,10 +94,12 @@ impl HostWorktree for WasmState {
         path: String,
     ) -> wasmtime::Result<Result<String, String>> {
         let delegate = self.table.get(&delegate)?;
-        Ok(delegate
-            .read_text_file(path.into())
-            .await
-            .map_err(|error| error.to_string()))
+        Ok(maybe!(async {
+            let path = worktree_relative_path(&path)?;
+            delegate.read_text_file(path).await
+        })
+        .await
+        .map_err(|error| error.to_string()))
     }
 
     async fn shell_env(
@@ -176,11 +181,22 @@ impl nodejs::Host for WasmState {
         &mut self,
         package_name: String,
     ) -> wasmtime::Result<Result<String, String>> {
-        self.host
+        let package = format!("npm:{package_name}");
+        if let Some(version) = self.host.locked_version(&self.manifest.id, &package) {
+            return Ok(Ok(version));
+        }
+
+        let version = self
+            .host
             .node_runtime
             .npm_package_latest_version(&package_name)
             .await
-            .to_wasmtime_result()
+            .map_err(|error| npm_lookup_error(&package_name, &error));
+        if let Ok(version) = &version {
+            self.host
+                .record_resolved_version(&self.manifest.id, package, version.clone());
+        }
+        Ok(version)
     }
 
     async fn npm_package_installed_version(
@@ -236,6 +252,19 @@ impl github::Host for WasmState {
         options: github::GithubReleaseOptions,
     ) -> wasmtime::Result<Result<github::GithubRelease, String>> {
         maybe!(async {
+            // When installing reproducibly, the locked release is fetched by its
+            // tag instead of whichever release is now the latest.
+            let package = format!("github:{repo}");
+            if let Some(tag) = self.host.locked_version(&self.manifest.id, &package) {
+                let release = ::http_client::github::get_release_by_tag_name(
+                    &repo,
+                    &tag,
+                    self.host.http_client.clone(),
+                )
+                .await?;
+                return Ok(release.into());
+            }
+
             let release = ::http_client::github::latest_github_release(
                 &repo,
                 options.require_assets,
@@ -243,6 +272,8 @@ impl github::Host for WasmState {
                 self.host.http_client.clone(),
             )
             .await?;
+            self.host
+                .record_resolved_version(&self.manifest.id, package, release.tag_name.clone());
             Ok(release.into())
         })
         .await
@@ -385,10 +416,27 @@ impl ExtensionImports for WasmState {
                 .host
                 .writeable_path_from_extension(&self.manifest.id, &path)?;
 
+            // Uncompressed files are stored as they were downloaded, so an
+            // existing file with the expected checksum can be reused.
+            if matches!(file_type, DownloadedFileType::Uncompressed)
+                && self
+                    .host
+                    .is_download_cached(&self.manifest, &url, &destination_path)
+                    .await
+            {
+                return Ok(());
+            }
+
+            let http_client = self.host.http_client.as_ref();
+            let request_url = url.as_str();
             let mut response = self
                 .host
-                .http_client
-                .get(&url, Default::default(), true)
+                .retry_policy
+                .send(&self.host.executor, || async move {
+                    Ok(http_client
+                        .get(request_url, Default::default(), true)
+                        .await?)
+                })
                 .await
                 .map_err(|err| anyhow!("error downloading release: {}", err))?;
 
@@ -398,6 +446,20 @@ impl ExtensionImports for WasmState {
                     response.status().to_string()
                 ))?;
             }
+
+            if self.manifest.download_checksum(&url).is_some() {
+                let mut content = Vec::new();
+                response.body_mut().read_to_end(&mut content).await?;
+                verify_download_checksum(
+                    self.host.http_client.as_ref(),
+                    &self.manifest,
+                    &url,
+                    &content,
+                )
+                .await?;
+                *response.body_mut() = content.into();
+            }
+
             let body = BufReader::new(response.body_mut());
 
             match file_type {
@@ -416,19 +478,19 @@ impl ExtensionImports for WasmState {
                         .create_file_with(&destination_path, body)
                         .await?;
                 }
-                DownloadedFileType::GzipTar => {
-                    let body = GzipDecoder::new(body);
-                    futures::pin_mut!(body);
-                    self.host
-                        .fs
-                        .extract_tar_file(&destination_path, Archive::new(body))
-                        .await?;
-                }
-                DownloadedFileType::Zip => {
-                    futures::pin_mut!(body);
-                    node_runtime::extract_zip(&destination_path, body)
+                DownloadedFileType::GzipTar | DownloadedFileType::Zip => {
+                    // Archives are extracted according to their actual format,
+                    // so that extensions can also download formats like `.tar.xz`.
+                    let mut body = body;
+                    let format = ArchiveFormat::detect(&url, body.fill_buf().await?).unwrap_or(
+                        match file_type {
+                            DownloadedFileType::GzipTar => ArchiveFormat::TarGz,
+                            _ => ArchiveFormat::Zip,
+                        },
+                    );
+                    extract_archive(self.host.fs.as_ref(), format, body, &destination_path)
                         .await
-                        .with_context(|| format!("failed to unzip {} archive", path.display()))?;
+                        .with_context(|| format!("failed to extract {} archive", path.display()))?;
                 }
             }
 
@@ -457,4 +519,14 @@ impl ExtensionImports for WasmState {
         #[cfg(not(unix))]
         Ok(Ok(()))
     }
+
+    async fn register_grammar(
+        &mut self,
+        grammar_name: String,
+        path: String,
+    ) -> wasmtime::Result<Result<(), String>> {
+        self.host
+            .grammar_produced(&self.manifest.id, grammar_name.into(), Path::new(&path))
+            .to_wasmtime_result()
+    }
 }
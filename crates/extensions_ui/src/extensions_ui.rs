@@ -11,7 +11,7 @@ use client::telemetry::Telemetry;
 use client::ExtensionMetadata;
 use collections::{BTreeMap, BTreeSet};
 use editor::{Editor, EditorElement, EditorStyle};
-use extension::{ExtensionManifest, ExtensionOperation, ExtensionStore};
+use extension::{ExtensionManifest, ExtensionOperation, ExtensionStore, UninstallOptions};
 use fuzzy::{match_strings, StringMatchCandidate};
 use gpui::{
     actions, uniform_list, AppContext, EventEmitter, Flatten, FocusableView, InteractiveElement,
@@ -24,6 +24,7 @@ use release_channel::ReleaseChannel;
 use settings::Settings;
 use theme::ThemeSettings;
 use ui::{prelude::*, CheckboxWithLabel, ContextMenu, PopoverMenu, ToggleButton, Tooltip};
+use util::ResultExt as _;
 use vim::VimModeSetting;
 use workspace::{
     item::{Item, ItemEvent},
@@ -281,7 +282,10 @@ impl ExtensionsPage {
             Some(ExtensionOperation::Install) => ExtensionStatus::Installing,
             Some(ExtensionOperation::Remove) => ExtensionStatus::Removing,
             Some(ExtensionOperation::Upgrade) => ExtensionStatus::Upgrading,
-            None => match extension_store.installed_extensions().get(extension_id) {
+            None => match extension_store
+                .installed_extension_entries()
+                .get(extension_id)
+            {
                 Some(extension) => ExtensionStatus::Installed(extension.manifest.version.clone()),
                 None => ExtensionStatus::NotInstalled,
             },
@@ -441,7 +445,13 @@ impl ExtensionsPage {
                                         let extension_id = extension.id.clone();
                                         move |_, cx| {
                                             ExtensionStore::global(cx).update(cx, |store, cx| {
-                                                store.uninstall_extension(extension_id.clone(), cx)
+                                                store
+                                                    .uninstall_extension(
+                                                        extension_id.clone(),
+                                                        UninstallOptions::default(),
+                                                        cx,
+                                                    )
+                                                    .log_err();
                                             });
                                         }
                                     })
@@ -729,7 +739,13 @@ impl ExtensionsPage {
                             this.telemetry
                                 .report_app_event("extensions: uninstall extension".to_string());
                             ExtensionStore::global(cx).update(cx, |store, cx| {
-                                store.uninstall_extension(extension_id.clone(), cx)
+                                store
+                                    .uninstall_extension(
+                                        extension_id.clone(),
+                                        UninstallOptions::default(),
+                                        cx,
+                                    )
+                                    .log_err();
                             });
                         }
                     }),
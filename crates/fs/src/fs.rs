@@ -665,7 +665,9 @@ struct FakeFsState {
     events_paused: bool,
     buffered_events: Vec<PathBuf>,
     metadata_call_count: usize,
+    save_call_count: usize,
     read_dir_call_count: usize,
+    read_call_counts: BTreeMap<PathBuf, usize>,
 }
 
 #[cfg(any(test, feature = "test-support"))]
@@ -817,6 +819,8 @@ impl FakeFs {
                 events_paused: false,
                 read_dir_call_count: 0,
                 metadata_call_count: 0,
+                save_call_count: 0,
+                read_call_counts: BTreeMap::default(),
             }),
         })
     }
@@ -889,7 +893,8 @@ impl FakeFs {
         let path = path.as_ref();
         let path = normalize_path(path);
         self.simulate_random_delay().await;
-        let state = self.state.lock();
+        let mut state = self.state.lock();
+        *state.read_call_counts.entry(path.clone()).or_default() += 1;
         let entry = state.read_path(&path)?;
         let entry = entry.lock();
         entry.file_content(&path).cloned()
@@ -1117,11 +1122,27 @@ impl FakeFs {
         self.state.lock().read_dir_call_count
     }
 
+    /// How many times the contents of the given file have been read.
+    pub fn read_call_count(&self, path: impl AsRef<Path>) -> usize {
+        let path = normalize_path(path.as_ref());
+        self.state
+            .lock()
+            .read_call_counts
+            .get(&path)
+            .copied()
+            .unwrap_or(0)
+    }
+
     /// How many `metadata` calls have been issued.
     pub fn metadata_call_count(&self) -> usize {
         self.state.lock().metadata_call_count
     }
 
+    /// How many `save` calls have been issued.
+    pub fn save_call_count(&self) -> usize {
+        self.state.lock().save_call_count
+    }
+
     fn simulate_random_delay(&self) -> impl futures::Future<Output = ()> {
         self.executor.simulate_random_delay()
     }
@@ -1469,6 +1490,7 @@ impl Fs for FakeFs {
 
     async fn save(&self, path: &Path, text: &Rope, line_ending: LineEnding) -> Result<()> {
         self.simulate_random_delay().await;
+        self.state.lock().save_call_count += 1;
         let path = normalize_path(path);
         let content = chunks(text, line_ending).collect::<String>();
         if let Some(path) = path.parent() {
@@ -26,6 +26,9 @@ pub struct GithubReleaseAsset {
     pub browser_download_url: String,
 }
 
+/// Returns the newest release on the first page of the repository's releases,
+/// skipping those without assets if `require_assets` is set, and pre-releases
+/// unless `pre_release` is set.
 pub async fn latest_github_release(
     repo_name_with_owner: &str,
     require_assets: bool,
@@ -72,7 +75,7 @@ pub async fn latest_github_release(
     releases
         .into_iter()
         .filter(|release| !require_assets || !release.assets.is_empty())
-        .find(|release| release.pre_release == pre_release)
+        .find(|release| pre_release || !release.pre_release)
         .ok_or(anyhow!("Failed to find a release"))
 }
 
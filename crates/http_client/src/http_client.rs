@@ -10,7 +10,10 @@ pub use isahc::{
     AsyncBody, Error, HttpClient as IsahcHttpClient, Request, Response,
 };
 #[cfg(feature = "test-support")]
-use std::fmt;
+use std::{
+    fmt,
+    sync::atomic::{AtomicUsize, Ordering::SeqCst},
+};
 use std::{
     sync::{Arc, Mutex},
     time::Duration,
@@ -61,6 +64,11 @@ pub trait HttpClient: Send + Sync {
     }
 
     fn proxy(&self) -> Option<&Uri>;
+
+    #[cfg(feature = "test-support")]
+    fn as_fake(&self) -> &FakeHttpClient {
+        panic!("called as_fake on a real http client");
+    }
 }
 
 /// An [`HttpClient`] that may have a proxy.
@@ -101,6 +109,11 @@ impl HttpClient for HttpClientWithProxy {
     fn proxy(&self) -> Option<&Uri> {
         self.proxy.as_ref()
     }
+
+    #[cfg(feature = "test-support")]
+    fn as_fake(&self) -> &FakeHttpClient {
+        self.client.as_fake()
+    }
 }
 
 impl HttpClient for Arc<HttpClientWithProxy> {
@@ -114,6 +127,11 @@ impl HttpClient for Arc<HttpClientWithProxy> {
     fn proxy(&self) -> Option<&Uri> {
         self.proxy.as_ref()
     }
+
+    #[cfg(feature = "test-support")]
+    fn as_fake(&self) -> &FakeHttpClient {
+        self.client.as_fake()
+    }
 }
 
 /// An [`HttpClient`] that has a base URL.
@@ -188,6 +206,11 @@ impl HttpClient for Arc<HttpClientWithUrl> {
     fn proxy(&self) -> Option<&Uri> {
         self.client.proxy.as_ref()
     }
+
+    #[cfg(feature = "test-support")]
+    fn as_fake(&self) -> &FakeHttpClient {
+        self.client.as_fake()
+    }
 }
 
 impl HttpClient for HttpClientWithUrl {
@@ -201,6 +224,11 @@ impl HttpClient for HttpClientWithUrl {
     fn proxy(&self) -> Option<&Uri> {
         self.client.proxy.as_ref()
     }
+
+    #[cfg(feature = "test-support")]
+    fn as_fake(&self) -> &FakeHttpClient {
+        self.client.as_fake()
+    }
 }
 
 pub fn client(user_agent: Option<String>, proxy: Option<Uri>) -> Arc<dyn HttpClient> {
@@ -262,6 +290,7 @@ type FakeHttpHandler = Box<
 #[cfg(feature = "test-support")]
 pub struct FakeHttpClient {
     handler: FakeHttpHandler,
+    request_count: AtomicUsize,
 }
 
 #[cfg(feature = "test-support")]
@@ -276,6 +305,7 @@ impl FakeHttpClient {
             client: HttpClientWithProxy {
                 client: Arc::new(Self {
                     handler: Box::new(move |req| Box::pin(handler(req))),
+                    request_count: AtomicUsize::new(0),
                 }),
                 proxy: None,
             },
@@ -299,6 +329,11 @@ impl FakeHttpClient {
                 .unwrap())
         })
     }
+
+    /// Returns the number of requests that have been sent with this client.
+    pub fn request_count(&self) -> usize {
+        self.request_count.load(SeqCst)
+    }
 }
 
 #[cfg(feature = "test-support")]
@@ -314,6 +349,7 @@ impl HttpClient for FakeHttpClient {
         &self,
         req: Request<AsyncBody>,
     ) -> BoxFuture<'static, Result<Response<AsyncBody>, Error>> {
+        self.request_count.fetch_add(1, SeqCst);
         let future = (self.handler)(req);
         Box::pin(async move { future.await.map(Into::into) })
     }
@@ -321,4 +357,8 @@ impl HttpClient for FakeHttpClient {
     fn proxy(&self) -> Option<&Uri> {
         None
     }
+
+    fn as_fake(&self) -> &FakeHttpClient {
+        self
+    }
 }
@@ -153,6 +153,15 @@ async fn test_first_line_pattern(cx: &mut TestAppContext) {
         matcher: LanguageMatcher {
             path_suffixes: vec!["js".into()],
             first_line_pattern: Some(Regex::new(r"\bnode\b").unwrap()),
+            path_globs: Vec::new(),
+        },
+        ..Default::default()
+    });
+    languages.register_test_language(LanguageConfig {
+        name: "JSON".into(),
+        matcher: LanguageMatcher {
+            path_suffixes: vec!["json".into()],
+            ..Default::default()
         },
         ..Default::default()
     });
@@ -175,6 +184,87 @@ async fn test_first_line_pattern(cx: &mut TestAppContext) {
         .as_ref(),
         "JavaScript"
     );
+
+    // The path suffixes of other languages take precedence over the pattern.
+    assert_eq!(
+        cx.read(|cx| languages.language_for_file(
+            &file("the/data.json"),
+            Some(&"#!/bin/env node".into()),
+            cx
+        ))
+        .await
+        .unwrap()
+        .name()
+        .as_ref(),
+        "JSON"
+    );
+}
+
+#[gpui::test]
+async fn test_grammar_variants(cx: &mut TestAppContext) {
+    cx.update(|cx| init_settings(cx, |_| {}));
+
+    let languages = Arc::new(LanguageRegistry::test(cx.executor()));
+    languages.register_native_grammars([
+        ("ruby", tree_sitter_ruby::language()),
+        (
+            "embedded_template",
+            tree_sitter_embedded_template::language(),
+        ),
+    ]);
+    languages.register_test_language(LanguageConfig {
+        name: "Ruby".into(),
+        grammar: Some("ruby".into()),
+        matcher: LanguageMatcher {
+            path_suffixes: vec!["rb".into()],
+            ..Default::default()
+        },
+        ..Default::default()
+    });
+    languages.set_grammar_variants(
+        "Ruby".into(),
+        vec![(
+            Regex::new(r"^# template").unwrap(),
+            "embedded_template".into(),
+        )],
+    );
+
+    let ruby = cx
+        .read(|cx| languages.language_for_file(&file("a.rb"), Some(&"class A; end".into()), cx))
+        .await
+        .unwrap();
+    assert_eq!(ruby.config.grammar.as_deref(), Some("ruby"));
+
+    let template = cx
+        .read(|cx| {
+            languages.language_for_file(&file("b.rb"), Some(&"# template\n<%= 1 %>".into()), cx)
+        })
+        .await
+        .unwrap();
+    assert_eq!(template.name().as_ref(), "Ruby");
+    assert_eq!(
+        template.config.grammar.as_deref(),
+        Some("embedded_template")
+    );
+    assert_ne!(template.id, ruby.id);
+
+    // Looking the language up by name, or without the file's content, uses
+    // the language's own grammar.
+    let by_name = languages.language_for_name("Ruby").await.unwrap();
+    assert_eq!(by_name.id, ruby.id);
+    let without_content = cx
+        .read(|cx| languages.language_for_file(&file("b.rb"), None, cx))
+        .await
+        .unwrap();
+    assert_eq!(without_content.id, ruby.id);
+
+    // The variant is loaded once.
+    let template_again = cx
+        .read(|cx| languages.language_for_file(&file("c.rb"), Some(&"# template".into()), cx))
+        .await
+        .unwrap();
+    assert!(Arc::ptr_eq(&template, &template_again));
+    assert_eq!(languages.language_names(), vec!["Plain Text", "Ruby"]);
 }
 
 #[gpui::test]
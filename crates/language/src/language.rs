@@ -25,6 +25,7 @@ use anyhow::{anyhow, Context, Result};
 use async_trait::async_trait;
 use collections::{HashMap, HashSet};
 use futures::Future;
+use globset::{Glob, GlobMatcher};
 use gpui::{AppContext, AsyncAppContext, Model, SharedString, Task};
 pub use highlight_map::HighlightMap;
 use http_client::HttpClient;
@@ -67,8 +68,8 @@ pub use buffer::Operation;
 pub use buffer::*;
 pub use diagnostic_set::DiagnosticEntry;
 pub use language_registry::{
-    LanguageNotFound, LanguageQueries, LanguageRegistry, LanguageServerBinaryStatus,
-    PendingLanguageServer, QUERY_FILENAME_PREFIXES,
+    GrammarLoadFailure, LanguageNotFound, LanguageQueries, LanguageRegistry,
+    LanguageServerBinaryStatus, PendingLanguageServer, QUERY_FILENAME_PREFIXES,
 };
 pub use lsp::LanguageServerId;
 pub use outline::{render_item, Outline, OutlineItem};
@@ -596,6 +597,10 @@ pub struct LanguageConfig {
     /// or a whole-word search in buffer search.
     #[serde(default)]
     pub word_characters: HashSet<char>,
+    /// A list of character sequences, like `.` or `::`, that should trigger
+    /// completions when typed, in addition to those reported by language servers.
+    #[serde(default)]
+    pub completion_query_characters: Vec<Arc<str>>,
     /// Whether to indent lines using tab characters, as opposed to multiple
     /// spaces.
     #[serde(default)]
@@ -625,6 +630,30 @@ pub struct LanguageMatcher {
     )]
     #[schemars(schema_with = "regex_json_schema")]
     pub first_line_pattern: Option<Regex>,
+    /// Glob patterns, such as `*.config.js`, that determine whether the language should be
+    /// assigned to a file based on its path. These take precedence over the `path_suffixes`
+    /// of other languages.
+    #[serde(
+        default,
+        skip_serializing_if = "Vec::is_empty",
+        serialize_with = "serialize_globs",
+        deserialize_with = "deserialize_globs"
+    )]
+    #[schemars(schema_with = "globs_json_schema")]
+    pub path_globs: Vec<GlobMatcher>,
+}
+
+impl LanguageMatcher {
+    /// Returns whether the given path matches any of the language's `path_globs`.
+    ///
+    /// Globs are matched against both the whole path and the file name, so that
+    /// patterns like `Dockerfile.*` match files in any directory.
+    pub fn matches_path_glob(&self, path: &Path) -> bool {
+        let file_name = path.file_name();
+        self.path_globs.iter().any(|glob| {
+            glob.is_match(path) || file_name.map_or(false, |file_name| glob.is_match(file_name))
+        })
+    }
 }
 
 /// Represents a language for the given range. Some languages (e.g. HTML)
@@ -691,6 +720,7 @@ impl Default for LanguageConfig {
             scope_opt_in_language_servers: Default::default(),
             overrides: Default::default(),
             word_characters: Default::default(),
+            completion_query_characters: Default::default(),
             collapsed_placeholder: Default::default(),
             hard_tabs: None,
             tab_size: None,
@@ -720,6 +750,28 @@ fn regex_json_schema(_: &mut SchemaGenerator) -> Schema {
     })
 }
 
+fn deserialize_globs<'de, D: Deserializer<'de>>(d: D) -> Result<Vec<GlobMatcher>, D::Error> {
+    Vec::<String>::deserialize(d)?
+        .iter()
+        .map(|source| {
+            Glob::new(source)
+                .map(|glob| glob.compile_matcher())
+                .map_err(de::Error::custom)
+        })
+        .collect()
+}
+
+fn globs_json_schema(generator: &mut SchemaGenerator) -> Schema {
+    generator.subschema_for::<Vec<String>>()
+}
+
+fn serialize_globs<S>(globs: &[GlobMatcher], serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    serializer.collect_seq(globs.iter().map(|glob| glob.glob().glob()))
+}
+
 fn serialize_regex<S>(regex: &Option<Regex>, serializer: S) -> Result<S::Ok, S::Error>
 where
     S: Serializer,
@@ -803,7 +855,8 @@ pub struct BracketPair {
     #[serde(default = "default_true")]
     pub surround: bool,
     /// True if an extra newline should be inserted while the cursor is in the middle
-    /// of that bracket pair.
+    /// of that bracket pair. Defaults to false, as it is for pairs like quotes.
+    #[serde(default)]
     pub newline: bool,
 }
 
@@ -840,6 +893,7 @@ pub struct Grammar {
     pub(crate) brackets_config: Option<BracketConfig>,
     pub(crate) redactions_config: Option<RedactionConfig>,
     pub(crate) runnable_config: Option<RunnableConfig>,
+    pub(crate) text_objects_query: Option<Query>,
     pub(crate) indents_config: Option<IndentConfig>,
     pub outline_config: Option<OutlineConfig>,
     pub embedding_config: Option<EmbeddingConfig>,
@@ -943,6 +997,7 @@ impl Language {
                     override_config: None,
                     redactions_config: None,
                     runnable_config: None,
+                    text_objects_query: None,
                     error_query: Query::new(&ts_language, "(ERROR) @error").unwrap(),
                     ts_language,
                     highlight_map: Default::default(),
@@ -1003,6 +1058,11 @@ impl Language {
                 .with_runnable_query(query.as_ref())
                 .context("Error loading tests query")?;
         }
+        if let Some(query) = queries.text_objects {
+            self = self
+                .with_text_object_query(query.as_ref())
+                .context("Error loading text objects query")?;
+        }
         Ok(self)
     }
 
@@ -1014,6 +1074,14 @@ impl Language {
         Ok(self)
     }
 
+    pub fn with_text_object_query(mut self, source: &str) -> Result<Self> {
+        let grammar = self
+            .grammar_mut()
+            .ok_or_else(|| anyhow!("cannot mutate grammar"))?;
+        grammar.text_objects_query = Some(Query::new(&grammar.ts_language, source)?);
+        Ok(self)
+    }
+
     pub fn with_runnable_query(mut self, source: &str) -> Result<Self> {
         let grammar = self
             .grammar_mut()
@@ -1429,6 +1497,11 @@ impl LanguageScope {
         )
     }
 
+    /// Returns the character sequences that trigger completions in this language.
+    pub fn completion_query_characters(&self) -> &[Arc<str>] {
+        &self.language.config.completion_query_characters
+    }
+
     /// Returns a list of bracket pairs for a given language with an additional
     /// piece of information about whether the particular bracket pair is currently active for a given language.
     pub fn brackets(&self) -> impl Iterator<Item = (&BracketPair, bool)> {
@@ -1524,6 +1597,12 @@ impl Grammar {
         })
     }
 
+    /// The query whose captures delimit the text objects, e.g. `@function.inside`,
+    /// that Vim mode can select in this grammar.
+    pub fn text_objects_query(&self) -> Option<&Query> {
+        self.text_objects_query.as_ref()
+    }
+
     pub fn highlight_map(&self) -> HighlightMap {
         self.highlight_map.lock().clone()
     }
@@ -1564,12 +1643,24 @@ impl CodeLabel {
 
 impl Ord for LanguageMatcher {
     fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        self.path_suffixes.cmp(&other.path_suffixes).then_with(|| {
-            self.first_line_pattern
-                .as_ref()
-                .map(Regex::as_str)
-                .cmp(&other.first_line_pattern.as_ref().map(Regex::as_str))
-        })
+        self.path_suffixes
+            .cmp(&other.path_suffixes)
+            .then_with(|| {
+                self.first_line_pattern
+                    .as_ref()
+                    .map(Regex::as_str)
+                    .cmp(&other.first_line_pattern.as_ref().map(Regex::as_str))
+            })
+            .then_with(|| {
+                let glob_patterns = |matcher: &Self| {
+                    matcher
+                        .path_globs
+                        .iter()
+                        .map(|glob| glob.glob().glob().to_string())
+                        .collect::<Vec<_>>()
+                };
+                glob_patterns(self).cmp(&glob_patterns(other))
+            })
     }
 }
 
@@ -1586,6 +1677,11 @@ impl PartialEq for LanguageMatcher {
         self.path_suffixes == other.path_suffixes
             && self.first_line_pattern.as_ref().map(Regex::as_str)
                 == other.first_line_pattern.as_ref().map(Regex::as_str)
+            && self
+                .path_globs
+                .iter()
+                .map(GlobMatcher::glob)
+                .eq(other.path_globs.iter().map(GlobMatcher::glob))
     }
 }
 
@@ -1781,4 +1877,62 @@ mod tests {
         // Loading an unknown language returns an error.
         assert!(languages.language_for_name("Unknown").await.is_err());
     }
+
+    #[gpui::test]
+    async fn test_try_register_language(cx: &mut TestAppContext) {
+        let languages = Arc::new(LanguageRegistry::test(cx.executor()));
+        languages.register_test_language(LanguageConfig {
+            name: "Rust".into(),
+            ..Default::default()
+        });
+        let load = || Ok((LanguageConfig::default(), LanguageQueries::default(), None));
+
+        // Built-in and already registered languages are not replaced.
+        for name in ["Plain Text", "Rust"] {
+            assert!(languages
+                .try_register_language(name.into(), None, LanguageMatcher::default(), load)
+                .is_err());
+        }
+        assert!(languages
+            .try_register_language("Ruby".into(), None, LanguageMatcher::default(), load)
+            .is_ok());
+        assert_eq!(languages.language_names(), ["Plain Text", "Ruby", "Rust"]);
+
+        // Once a language is registered, its name is taken too.
+        assert!(languages
+            .try_register_language("Ruby".into(), None, LanguageMatcher::default(), load)
+            .is_err());
+    }
+
+    #[gpui::test]
+    async fn test_language_text_objects_query(cx: &mut TestAppContext) {
+        let languages = Arc::new(LanguageRegistry::test(cx.executor()));
+        languages.register_native_grammars([("rust", tree_sitter_rust::language())]);
+        let config = LanguageConfig {
+            name: "Rust".into(),
+            grammar: Some("rust".into()),
+            ..Default::default()
+        };
+        languages.register_language(
+            config.name.clone(),
+            config.grammar.clone(),
+            config.matcher.clone(),
+            move || {
+                let queries = LanguageQueries {
+                    text_objects: Some(
+                        "(function_item body: (_) @function.inside) @function.around".into(),
+                    ),
+                    ..Default::default()
+                };
+                Ok((config.clone(), queries, None))
+            },
+        );
+
+        let rust = languages.language_for_name("Rust").await.unwrap();
+        let query = rust.grammar().unwrap().text_objects_query().unwrap();
+        assert_eq!(
+            query.capture_names(),
+            &["function.inside", "function.around"]
+        );
+    }
 }
@@ -12,16 +12,16 @@ use futures::TryFutureExt;
 use futures::{
     channel::{mpsc, oneshot},
     future::Shared,
-    Future, FutureExt as _,
+    stream, Future, FutureExt as _, StreamExt as _,
 };
 use globset::GlobSet;
 use gpui::{AppContext, BackgroundExecutor, Task};
 use lsp::LanguageServerId;
 use parking_lot::{Mutex, RwLock};
 use postage::watch;
+use regex::Regex;
 use std::{
     borrow::Cow,
-    ffi::OsStr,
     ops::Not,
     path::{Path, PathBuf},
     sync::Arc,
@@ -38,6 +38,7 @@ pub struct LanguageRegistry {
     login_shell_env_loaded: Shared<Task<()>>,
     executor: BackgroundExecutor,
     lsp_binary_status_tx: LspBinaryStatusSender,
+    grammar_load_failure_txs: Mutex<Vec<mpsc::UnboundedSender<GrammarLoadFailure>>>,
 }
 
 struct LanguageRegistryState {
@@ -50,6 +51,12 @@ struct LanguageRegistryState {
     available_lsp_adapters:
         HashMap<LanguageServerName, Arc<dyn Fn() -> Arc<CachedLspAdapter> + 'static + Send + Sync>>,
     loading_languages: HashMap<LanguageId, Vec<oneshot::Sender<Result<Arc<Language>>>>>,
+    /// For each language, the grammars to load it with instead of its own
+    /// grammar, for files whose content matches the paired pattern.
+    grammar_variants: HashMap<Arc<str>, Vec<(Regex, Arc<str>)>>,
+    /// The ids of the languages loaded with one of their grammar variants,
+    /// keyed by the id of the language and the name of the grammar.
+    grammar_variant_ids: HashMap<(LanguageId, Arc<str>), LanguageId>,
     subscription: (watch::Sender<()>, watch::Receiver<()>),
     theme: Option<Arc<Theme>>,
     version: usize,
@@ -104,6 +111,14 @@ enum AvailableGrammar {
     LoadFailed(Arc<anyhow::Error>),
 }
 
+/// A WASM grammar that failed to load when it was first needed.
+#[derive(Clone, Debug)]
+pub struct GrammarLoadFailure {
+    pub grammar_name: Arc<str>,
+    pub wasm_path: PathBuf,
+    pub error: Arc<anyhow::Error>,
+}
+
 #[derive(Debug)]
 pub struct LanguageNotFound;
 
@@ -126,6 +141,7 @@ pub const QUERY_FILENAME_PREFIXES: &[(
     ("overrides", |q| &mut q.overrides),
     ("redactions", |q| &mut q.redactions),
     ("runnables", |q| &mut q.runnables),
+    ("text_objects", |q| &mut q.text_objects),
 ];
 
 /// Tree-sitter language queries for a given language.
@@ -140,6 +156,7 @@ pub struct LanguageQueries {
     pub overrides: Option<Cow<'static, str>>,
     pub redactions: Option<Cow<'static, str>>,
     pub runnables: Option<Cow<'static, str>>,
+    pub text_objects: Option<Cow<'static, str>>,
 }
 
 #[derive(Clone, Default)]
@@ -157,6 +174,8 @@ impl LanguageRegistry {
                 grammars: Default::default(),
                 language_settings: Default::default(),
                 loading_languages: Default::default(),
+                grammar_variants: Default::default(),
+                grammar_variant_ids: Default::default(),
                 lsp_adapters: Default::default(),
                 available_lsp_adapters: HashMap::default(),
                 subscription: watch::channel(),
@@ -170,6 +189,7 @@ impl LanguageRegistry {
             language_server_download_dir: None,
             login_shell_env_loaded: login_shell_env_loaded.shared(),
             lsp_binary_status_tx: Default::default(),
+            grammar_load_failure_txs: Default::default(),
             executor,
         };
         this.add(PLAIN_TEXT.clone());
@@ -215,6 +235,24 @@ impl LanguageRegistry {
             .remove_languages(languages_to_remove, grammars_to_remove)
     }
 
+    /// Sets the grammars that the given language is loaded with instead of its
+    /// own grammar, for files whose first line matches the paired pattern. The
+    /// first matching pattern wins.
+    pub fn set_grammar_variants(&self, language_name: Arc<str>, variants: Vec<(Regex, Arc<str>)>) {
+        let mut state = self.state.write();
+        if variants.is_empty() {
+            state.grammar_variants.remove(&language_name);
+        } else {
+            state.grammar_variants.insert(language_name, variants);
+        }
+    }
+
+    /// Reloads the specified languages the next time they are used, e.g. because
+    /// their queries have changed, without reloading their grammars.
+    pub fn reload_languages(&self, languages_to_reload: &[Arc<str>]) {
+        self.state.write().reload_languages(languages_to_reload)
+    }
+
     pub fn remove_lsp_adapter(&self, language_name: &str, name: &LanguageServerName) {
         let mut state = self.state.write();
         if let Some(adapters) = state.lsp_adapters.get_mut(language_name) {
@@ -346,6 +384,44 @@ impl LanguageRegistry {
         *state.subscription.0.borrow_mut() = ();
     }
 
+    /// Like [`Self::register_language`], but rejects the names of built-in
+    /// languages, and of languages that are already registered, instead of
+    /// replacing them.
+    pub fn try_register_language(
+        &self,
+        name: Arc<str>,
+        grammar_name: Option<Arc<str>>,
+        matcher: LanguageMatcher,
+        load: impl Fn() -> Result<(
+                LanguageConfig,
+                LanguageQueries,
+                Option<Arc<dyn ContextProvider>>,
+            )>
+            + 'static
+            + Send
+            + Sync,
+    ) -> Result<()> {
+        if name == PLAIN_TEXT.name() {
+            return Err(anyhow!("language name {name:?} is reserved"));
+        }
+        {
+            let state = self.state.read();
+            let is_registered = state
+                .available_languages
+                .iter()
+                .any(|language| language.name == name)
+                || state
+                    .languages
+                    .iter()
+                    .any(|language| language.config.name == name);
+            if is_registered {
+                return Err(anyhow!("language {name:?} is already registered"));
+            }
+        }
+        self.register_language(name, grammar_name, matcher, load);
+        Ok(())
+    }
+
     /// Adds grammars to the registry. Language configurations reference a grammar by name. The
     /// grammar controls how the source code is parsed.
     pub fn register_native_grammars(
@@ -388,9 +464,32 @@ impl LanguageRegistry {
             .chain(state.languages.iter().map(|l| l.config.name.to_string()))
             .collect::<Vec<_>>();
         result.sort_unstable_by_key(|language_name| language_name.to_lowercase());
+        // A language that was loaded with several of its grammar variants is
+        // listed once.
+        result.dedup();
         result
     }
 
+    /// Loads the given WASM grammars ahead of their first use, with at most
+    /// `parallelism` of them loading at once.
+    ///
+    /// A grammar that fails to load is reported in the same way as when it is
+    /// loaded on demand, and doesn't stop the others from loading.
+    pub fn load_wasm_grammars(
+        self: &Arc<Self>,
+        grammar_names: Vec<Arc<str>>,
+        parallelism: usize,
+    ) -> impl Future<Output = ()> {
+        let this = self.clone();
+        async move {
+            stream::iter(grammar_names)
+                .map(|grammar_name| this.get_or_load_grammar(grammar_name))
+                .buffer_unordered(parallelism.max(1))
+                .for_each(|_| async {})
+                .await;
+        }
+    }
+
     pub fn grammar_names(&self) -> Vec<Arc<str>> {
         let state = self.state.read();
         let mut result = state.grammars.keys().cloned().collect::<Vec<_>>();
@@ -444,13 +543,16 @@ impl LanguageRegistry {
         name: &str,
     ) -> impl Future<Output = Result<Arc<Language>>> {
         let name = UniCase::new(name);
-        let rx = self.get_or_load_language(|language_name, _| {
-            if UniCase::new(language_name) == name {
-                1
-            } else {
-                0
-            }
-        });
+        let rx = self.get_or_load_language(
+            |language_name, _| {
+                if UniCase::new(language_name) == name {
+                    1
+                } else {
+                    0
+                }
+            },
+            None,
+        );
         async move { rx.await? }
     }
 
@@ -459,18 +561,21 @@ impl LanguageRegistry {
         string: &str,
     ) -> impl Future<Output = Result<Arc<Language>>> {
         let string = UniCase::new(string);
-        let rx = self.get_or_load_language(|name, config| {
-            if UniCase::new(name) == string
-                || config
-                    .path_suffixes
-                    .iter()
-                    .any(|suffix| UniCase::new(suffix) == string)
-            {
-                1
-            } else {
-                0
-            }
-        });
+        let rx = self.get_or_load_language(
+            |name, config| {
+                if UniCase::new(name) == string
+                    || config
+                        .path_suffixes
+                        .iter()
+                        .any(|suffix| UniCase::new(suffix) == string)
+                {
+                    1
+                } else {
+                    0
+                }
+            },
+            None,
+        );
         async move { rx.await? }
     }
 
@@ -506,42 +611,56 @@ impl LanguageRegistry {
         let extension = path.extension_or_hidden_file_name();
         let path_suffixes = [extension, filename, path.to_str()];
         let empty = GlobSet::empty();
-
-        let rx = self.get_or_load_language(move |language_name, config| {
-            let path_matches_default_suffix = config
-                .path_suffixes
-                .iter()
-                .any(|suffix| path_suffixes.contains(&Some(suffix.as_str())));
-            let custom_suffixes = user_file_types
-                .and_then(|types| types.get(language_name))
-                .unwrap_or(&empty);
-            let path_matches_custom_suffix = path_suffixes
-                .iter()
-                .map(|suffix| suffix.unwrap_or(""))
-                .any(|suffix| custom_suffixes.is_match(suffix));
-            let content_matches = content.zip(config.first_line_pattern.as_ref()).map_or(
-                false,
-                |(content, pattern)| {
-                    let end = content.clip_point(Point::new(0, 256), Bias::Left);
-                    let end = content.point_to_offset(end);
-                    let text = content.chunks_in_range(0..end).collect::<String>();
-                    pattern.is_match(&text)
-                },
-            );
-            if path_matches_custom_suffix {
-                2
-            } else if path_matches_default_suffix || content_matches {
-                1
-            } else {
-                0
-            }
+        let first_line = content.map(|content| {
+            let end = content.clip_point(Point::new(0, 256), Bias::Left);
+            let end = content.point_to_offset(end);
+            content.chunks_in_range(0..end).collect::<String>()
         });
+
+        let rx = self.get_or_load_language(
+            |language_name, config| {
+                let path_matches_default_suffix = config
+                    .path_suffixes
+                    .iter()
+                    .any(|suffix| path_suffixes.contains(&Some(suffix.as_str())));
+                let path_matches_glob = config.matches_path_glob(path);
+                let custom_suffixes = user_file_types
+                    .and_then(|types| types.get(language_name))
+                    .unwrap_or(&empty);
+                let path_matches_custom_suffix = path_suffixes
+                    .iter()
+                    .map(|suffix| suffix.unwrap_or(""))
+                    .any(|suffix| custom_suffixes.is_match(suffix));
+                let content_matches = first_line
+                    .as_deref()
+                    .zip(config.first_line_pattern.as_ref())
+                    .map_or(false, |(first_line, pattern)| pattern.is_match(first_line));
+                // A language's first line pattern is only used when no language
+                // matches the file's path.
+                if path_matches_custom_suffix {
+                    4
+                } else if path_matches_glob {
+                    3
+                } else if path_matches_default_suffix {
+                    2
+                } else if content_matches {
+                    1
+                } else {
+                    0
+                }
+            },
+            first_line.as_deref(),
+        );
         async move { rx.await? }
     }
 
+    /// Loads the language that the callback scores highest. If the file's
+    /// first line is given and matches one of the language's grammar variants,
+    /// the language is loaded with the variant's grammar instead.
     fn get_or_load_language(
         self: &Arc<Self>,
         callback: impl Fn(&str, &LanguageMatcher) -> usize,
+        first_line: Option<&str>,
     ) -> oneshot::Receiver<Result<Arc<Language>>> {
         let (tx, rx) = oneshot::channel();
 
@@ -564,15 +683,31 @@ impl LanguageRegistry {
             return rx;
         };
 
+        let variant_grammar = first_line.and_then(|first_line| {
+            state
+                .grammar_variants
+                .get(&language.name)?
+                .iter()
+                .find(|(pattern, _)| pattern.is_match(first_line))
+                .map(|(_, grammar)| grammar.clone())
+        });
+        let id = match &variant_grammar {
+            Some(grammar) => *state
+                .grammar_variant_ids
+                .entry((language.id, grammar.clone()))
+                .or_insert_with(LanguageId::new),
+            None => language.id,
+        };
+
         // If the language is already loaded, resolve with it immediately.
         for loaded_language in state.languages.iter() {
-            if loaded_language.id == language.id {
+            if loaded_language.id == id {
                 let _ = tx.send(Ok(loaded_language.clone()));
                 return rx;
             }
         }
 
-        match state.loading_languages.entry(language.id) {
+        match state.loading_languages.entry(id) {
             // If the language is already being loaded, then add this
             // channel to a list that will be sent to when the load completes.
             hash_map::Entry::Occupied(mut entry) => entry.get_mut().push(tx),
@@ -582,10 +717,12 @@ impl LanguageRegistry {
                 let this = self.clone();
                 self.executor
                     .spawn(async move {
-                        let id = language.id;
                         let name = language.name.clone();
                         let language = async {
-                            let (config, queries, provider) = (language.load)()?;
+                            let (mut config, queries, provider) = (language.load)()?;
+                            if let Some(grammar) = variant_grammar {
+                                config.grammar = Some(grammar);
+                            }
 
                             if let Some(grammar) = config.grammar.clone() {
                                 let grammar = Some(this.get_or_load_grammar(grammar).await?);
@@ -662,13 +799,12 @@ impl LanguageRegistry {
                         .spawn(async move {
                             let grammar_result = maybe!({
                                 let wasm_bytes = std::fs::read(&wasm_path)?;
-                                let grammar_name = wasm_path
-                                    .file_stem()
-                                    .and_then(OsStr::to_str)
-                                    .ok_or_else(|| anyhow!("invalid grammar filename"))?;
+                                // The grammar's symbols, including those of its external
+                                // scanner, are prefixed with the grammar's name, which
+                                // the file is not necessarily named after.
                                 anyhow::Ok(with_parser(|parser| {
                                     let mut store = parser.take_wasm_store().unwrap();
-                                    let grammar = store.load_language(&grammar_name, &wasm_bytes);
+                                    let grammar = store.load_language(&name, &wasm_bytes);
                                     parser.set_wasm_store(store).unwrap();
                                     grammar
                                 })?)
@@ -677,7 +813,14 @@ impl LanguageRegistry {
 
                             let value = match &grammar_result {
                                 Ok(grammar) => AvailableGrammar::Loaded(wasm_path, grammar.clone()),
-                                Err(error) => AvailableGrammar::LoadFailed(error.clone()),
+                                Err(error) => {
+                                    this.report_grammar_load_failure(GrammarLoadFailure {
+                                        grammar_name: name.clone(),
+                                        wasm_path,
+                                        error: error.clone(),
+                                    });
+                                    AvailableGrammar::LoadFailed(error.clone())
+                                }
                             };
 
                             let old_value = this.state.write().grammars.insert(name, value);
@@ -853,6 +996,25 @@ impl LanguageRegistry {
         self.lsp_binary_status_tx.subscribe()
     }
 
+    /// Returns a stream of the WASM grammars that fail to load when they are first used.
+    pub fn grammar_load_failures(&self) -> mpsc::UnboundedReceiver<GrammarLoadFailure> {
+        let (tx, rx) = mpsc::unbounded();
+        self.grammar_load_failure_txs.lock().push(tx);
+        rx
+    }
+
+    fn report_grammar_load_failure(&self, failure: GrammarLoadFailure) {
+        log::error!(
+            "failed to load grammar {} from {:?}: {:?}",
+            failure.grammar_name,
+            failure.wasm_path,
+            failure.error
+        );
+        self.grammar_load_failure_txs
+            .lock()
+            .retain(|tx| tx.unbounded_send(failure.clone()).is_ok());
+    }
+
     pub fn delete_server_container(
         &self,
         adapter: Arc<CachedLspAdapter>,
@@ -956,6 +1118,8 @@ impl LanguageRegistryState {
             .retain(|language| !languages_to_remove.contains(&language.name()));
         self.available_languages
             .retain(|language| !languages_to_remove.contains(&language.name));
+        self.grammar_variants
+            .retain(|name, _| !languages_to_remove.contains(name));
         self.grammars
             .retain(|name, _| !grammars_to_remove.contains(&name));
         self.version += 1;
@@ -963,6 +1127,23 @@ impl LanguageRegistryState {
         *self.subscription.0.borrow_mut() = ();
     }
 
+    fn reload_languages(&mut self, languages_to_reload: &[Arc<str>]) {
+        if languages_to_reload.is_empty() {
+            return;
+        }
+
+        self.languages
+            .retain(|language| !languages_to_reload.contains(&language.name()));
+        for language in &mut self.available_languages {
+            if languages_to_reload.contains(&language.name) {
+                language.loaded = false;
+            }
+        }
+        self.version += 1;
+        self.reload_count += 1;
+        *self.subscription.0.borrow_mut() = ();
+    }
+
     /// Mark the given language as having been loaded, so that the
     /// language registry won't try to load it again.
     fn mark_language_loaded(&mut self, id: LanguageId) {
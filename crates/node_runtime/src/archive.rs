@@ -15,6 +15,8 @@ pub async fn extract_zip<R: AsyncRead + Unpin>(destination: &Path, reader: R) ->
         let entry_reader = item.reader_mut();
         let entry = entry_reader.entry();
         let path = destination.join(entry.filename().as_str().unwrap());
+        #[cfg(unix)]
+        let unix_permissions = entry.unix_permissions();
 
         if entry.dir().unwrap() {
             std::fs::create_dir_all(&path)?;
@@ -23,6 +25,18 @@ pub async fn extract_zip<R: AsyncRead + Unpin>(destination: &Path, reader: R) ->
             std::fs::create_dir_all(&parent_dir)?;
             let mut file = smol::fs::File::create(&path).await?;
             futures::io::copy(entry_reader, &mut file).await?;
+
+            // Preserve the permissions with which the file was archived, so
+            // that extracted binaries remain executable. Setuid, setgid, and
+            // sticky bits are dropped.
+            #[cfg(unix)]
+            if let Some(mode) = unix_permissions.filter(|mode| mode & 0o777 != 0) {
+                use std::os::unix::fs::PermissionsExt as _;
+                std::fs::set_permissions(
+                    &path,
+                    std::fs::Permissions::from_mode(mode as u32 & 0o777),
+                )?;
+            }
         }
 
         reader = item.skip().await?;
@@ -11,6 +11,8 @@ pub struct ExtensionApiManifest {
     pub repository: String,
     pub schema_version: Option<i32>,
     pub wasm_api_version: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
 }
 
 #[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
@@ -20,6 +22,9 @@ pub struct ExtensionMetadata {
     pub manifest: ExtensionApiManifest,
     pub published_at: DateTime<Utc>,
     pub download_count: u64,
+    /// The size of the extension's archive, in bytes, if known.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub archive_size: Option<u64>,
 }
 
 #[derive(Serialize, Deserialize)]
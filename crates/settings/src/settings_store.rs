@@ -162,6 +162,7 @@ pub struct SettingsStore {
     raw_default_settings: serde_json::Value,
     raw_user_settings: serde_json::Value,
     raw_extension_settings: serde_json::Value,
+    raw_extension_default_settings: BTreeMap<Arc<str>, serde_json::Value>,
     raw_local_settings: BTreeMap<(usize, Arc<Path>), serde_json::Value>,
     tab_size_callback: Option<(
         TypeId,
@@ -211,6 +212,7 @@ impl SettingsStore {
             raw_default_settings: serde_json::json!({}),
             raw_user_settings: serde_json::json!({}),
             raw_extension_settings: serde_json::json!({}),
+            raw_extension_default_settings: Default::default(),
             raw_local_settings: Default::default(),
             tab_size_callback: Default::default(),
             setting_file_updates_tx,
@@ -261,7 +263,7 @@ impl SettingsStore {
             }
 
             let extension_value = setting_value
-                .deserialize_setting(&self.raw_extension_settings)
+                .deserialize_setting(&self.extension_settings_json())
                 .log_err();
 
             if let Some(setting) = setting_value
@@ -550,6 +552,54 @@ impl SettingsStore {
         }
     }
 
+    /// Sets the default settings provided by the given extension, via a JSON string.
+    ///
+    /// These take precedence over Zed's own defaults, but not over the user's settings.
+    pub fn set_extension_default_settings(
+        &mut self,
+        extension_id: Arc<str>,
+        settings_content: &str,
+        cx: &mut AppContext,
+    ) -> Result<()> {
+        let settings: serde_json::Value = parse_json_with_comments(settings_content)?;
+        if settings.is_object() {
+            self.raw_extension_default_settings
+                .insert(extension_id, settings);
+            self.recompute_values(None, cx)?;
+            Ok(())
+        } else {
+            Err(anyhow!("settings must be an object"))
+        }
+    }
+
+    /// Removes the default settings provided by the given extension.
+    pub fn remove_extension_default_settings(
+        &mut self,
+        extension_id: &str,
+        cx: &mut AppContext,
+    ) -> Result<()> {
+        if self
+            .raw_extension_default_settings
+            .remove(extension_id)
+            .is_some()
+        {
+            self.recompute_values(None, cx)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the settings provided by extensions, with the settings set via
+    /// [`Self::set_extension_settings`] taking precedence over the default
+    /// settings of individual extensions.
+    fn extension_settings_json(&self) -> serde_json::Value {
+        let mut settings = serde_json::json!({});
+        for extension_default_settings in self.raw_extension_default_settings.values() {
+            merge_non_null_json_value_into(extension_default_settings.clone(), &mut settings);
+        }
+        merge_non_null_json_value_into(self.raw_extension_settings.clone(), &mut settings);
+        settings
+    }
+
     /// Add or remove a set of local settings via a JSON string.
     pub fn clear_local_settings(&mut self, root_id: usize, cx: &mut AppContext) -> Result<()> {
         self.raw_local_settings.retain(|k, _| k.0 != root_id);
@@ -676,13 +726,14 @@ impl SettingsStore {
         cx: &mut AppContext,
     ) -> Result<()> {
         // Reload the global and local values for every setting.
+        let raw_extension_settings = self.extension_settings_json();
         let mut project_settings_stack = Vec::<DeserializedSetting>::new();
         let mut paths_stack = Vec::<Option<(usize, &Path)>>::new();
         for setting_value in self.setting_values.values_mut() {
             let default_settings = setting_value.deserialize_setting(&self.raw_default_settings)?;
 
             let extension_settings = setting_value
-                .deserialize_setting(&self.raw_extension_settings)
+                .deserialize_setting(&raw_extension_settings)
                 .log_err();
 
             let user_settings = setting_value
@@ -83,9 +83,14 @@ impl RenderOnce for ThemeControl {
             "theme",
             value.clone(),
             ContextMenu::build(cx, |mut menu, cx| {
-                let theme_registry = ThemeRegistry::global(cx);
+                let mut theme_names = ThemeRegistry::global(cx)
+                    .list(false)
+                    .into_iter()
+                    .map(|theme| theme.name)
+                    .collect::<Vec<_>>();
+                theme_names.sort();
 
-                for theme in theme_registry.list_names(false) {
+                for theme in theme_names {
                     menu = menu.custom_entry(
                         {
                             let theme = theme.clone();
@@ -7,8 +7,8 @@ use crate::AccentColors;
 use crate::{
     default_color_scales,
     one_themes::{one_dark, one_family},
-    Appearance, PlayerColors, StatusColors, SyntaxTheme, SystemColors, Theme, ThemeColors,
-    ThemeFamily, ThemeStyles,
+    Appearance, PlayerColors, SemanticRoles, StatusColors, SyntaxTheme, SystemColors, Theme,
+    ThemeColors, ThemeFamily, ThemeStyles,
 };
 
 fn zed_pro_daylight() -> Theme {
@@ -24,6 +24,7 @@ fn zed_pro_daylight() -> Theme {
             player: PlayerColors::light(),
             syntax: Arc::new(SyntaxTheme::default()),
             accents: AccentColors::light(),
+            roles: SemanticRoles::default(),
         },
     }
 }
@@ -41,6 +42,7 @@ pub(crate) fn zed_pro_moonlight() -> Theme {
             player: PlayerColors::dark(),
             syntax: Arc::new(SyntaxTheme::default()),
             accents: AccentColors::dark(),
+            roles: SemanticRoles::default(),
         },
     }
 }
@@ -3,8 +3,8 @@ use std::sync::Arc;
 use gpui::{hsla, FontStyle, FontWeight, HighlightStyle, WindowBackgroundAppearance};
 
 use crate::{
-    default_color_scales, AccentColors, Appearance, PlayerColors, StatusColors, SyntaxTheme,
-    SystemColors, Theme, ThemeColors, ThemeFamily, ThemeStyles,
+    default_color_scales, AccentColors, Appearance, PlayerColors, SemanticRoles, StatusColors,
+    SyntaxTheme, SystemColors, Theme, ThemeColors, ThemeFamily, ThemeStyles,
 };
 
 // Note: This theme family is not the one you see in Zed at the moment.
@@ -43,6 +43,7 @@ pub(crate) fn one_dark() -> Theme {
             window_background_appearance: WindowBackgroundAppearance::Opaque,
             system: SystemColors::default(),
             accents: AccentColors(vec![blue, orange, purple, teal, red, green, yellow]),
+            roles: SemanticRoles::default(),
             colors: ThemeColors {
                 border: hsla(225. / 360., 13. / 100., 12. / 100., 1.),
                 border_variant: hsla(228. / 360., 8. / 100., 25. / 100., 1.),
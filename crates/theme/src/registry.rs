@@ -2,27 +2,50 @@ use std::sync::Arc;
 use std::{fmt::Debug, path::Path};
 
 use anyhow::{anyhow, Context, Result};
-use collections::HashMap;
+use collections::{HashMap, HashSet};
 use derive_more::{Deref, DerefMut};
 use fs::Fs;
 use futures::StreamExt;
 use gpui::{AppContext, AssetSource, Global, HighlightStyle, SharedString};
 use parking_lot::RwLock;
 use refineable::Refineable;
-use util::ResultExt;
+use util::{merge_json_value_into, ResultExt};
 
 use crate::{
-    try_parse_color, AccentColors, Appearance, AppearanceContent, PlayerColors, StatusColors,
-    SyntaxTheme, SystemColors, Theme, ThemeColors, ThemeContent, ThemeFamily, ThemeFamilyContent,
-    ThemeStyles,
+    try_parse_color, AccentColors, Appearance, AppearanceContent, PlayerColors, SemanticRoles,
+    StatusColors, SyntaxTheme, SystemColors, Theme, ThemeColors, ThemeContent, ThemeFamily,
+    ThemeFamilyContent, ThemeMode, ThemeSelection, ThemeStyles,
 };
 
+/// The appearance of the themes that don't specify one.
+pub const DEFAULT_FALLBACK_APPEARANCE: AppearanceContent = AppearanceContent::Dark;
+
 #[derive(Debug, Clone)]
 pub struct ThemeMeta {
     pub name: SharedString,
     pub appearance: Appearance,
 }
 
+/// A pairing of the light and dark themes of a theme family, which can be
+/// selected as a single theme that follows the system's appearance.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AutoTheme {
+    pub light: SharedString,
+    pub dark: SharedString,
+}
+
+impl AutoTheme {
+    /// Returns the theme selection that uses the light or dark theme depending
+    /// on the system's appearance.
+    pub fn theme_selection(&self) -> ThemeSelection {
+        ThemeSelection::Dynamic {
+            mode: ThemeMode::System,
+            light: self.light.to_string(),
+            dark: self.dark.to_string(),
+        }
+    }
+}
+
 /// The global [`ThemeRegistry`].
 ///
 /// This newtype exists for obtaining a unique [`TypeId`](std::any::TypeId) when
@@ -36,6 +59,25 @@ impl Global for GlobalThemeRegistry {}
 
 struct ThemeRegistryState {
     themes: HashMap<SharedString, Arc<Theme>>,
+    /// The names of the themes in each theme family, by family name.
+    families: HashMap<SharedString, Vec<SharedString>>,
+    /// The names of the themes and theme families that are listed, if only
+    /// some of them are.
+    allowlist: Option<HashSet<SharedString>>,
+}
+
+impl ThemeRegistryState {
+    /// Returns whether the theme is listed, either by its own name or by the
+    /// name of a family that it belongs to.
+    fn is_listed(&self, theme_name: &SharedString) -> bool {
+        let Some(allowlist) = &self.allowlist else {
+            return true;
+        };
+        allowlist.contains(theme_name)
+            || self.families.iter().any(|(family_name, theme_names)| {
+                allowlist.contains(family_name) && theme_names.contains(theme_name)
+            })
+    }
 }
 
 pub struct ThemeRegistry {
@@ -65,6 +107,8 @@ impl ThemeRegistry {
         let registry = Self {
             state: RwLock::new(ThemeRegistryState {
                 themes: HashMap::default(),
+                families: HashMap::default(),
+                allowlist: None,
             }),
             assets,
         };
@@ -81,6 +125,10 @@ impl ThemeRegistry {
 
     fn insert_theme_families(&self, families: impl IntoIterator<Item = ThemeFamily>) {
         for family in families.into_iter() {
+            self.register_family(
+                family.name.clone(),
+                family.themes.iter().map(|theme| theme.name.clone()),
+            );
             self.insert_themes(family.themes);
         }
     }
@@ -92,13 +140,62 @@ impl ThemeRegistry {
         }
     }
 
-    #[allow(unused)]
-    fn insert_user_theme_families(&self, families: impl IntoIterator<Item = ThemeFamilyContent>) {
+    /// Adds the themes of the given theme families to the registry, and records
+    /// which themes belong to each family.
+    pub fn insert_user_theme_families(
+        &self,
+        families: impl IntoIterator<Item = ThemeFamilyContent>,
+    ) {
         for family in families.into_iter() {
+            self.register_family(
+                family.name.clone().into(),
+                family.themes.iter().map(|theme| theme.name.clone().into()),
+            );
             self.insert_user_themes(family.themes);
         }
     }
 
+    /// Records which themes belong to the theme family with the given name,
+    /// replacing any themes previously recorded for it.
+    pub fn register_family(
+        &self,
+        family_name: SharedString,
+        theme_names: impl IntoIterator<Item = SharedString>,
+    ) {
+        self.state
+            .write()
+            .families
+            .insert(family_name, theme_names.into_iter().collect());
+    }
+
+    /// Returns the pairing of the family's themes to use when following the
+    /// system's appearance, if the family has exactly one light and one dark
+    /// theme in the registry.
+    pub fn auto_theme_for_family(&self, family_name: &str) -> Option<AutoTheme> {
+        let state = self.state.read();
+        let themes = state
+            .families
+            .get(family_name)?
+            .iter()
+            .filter_map(|theme_name| state.themes.get(theme_name));
+
+        let mut light = Vec::new();
+        let mut dark = Vec::new();
+        for theme in themes {
+            match theme.appearance {
+                Appearance::Light => light.push(theme.name.clone()),
+                Appearance::Dark => dark.push(theme.name.clone()),
+            }
+        }
+        match (light.as_slice(), dark.as_slice()) {
+            ([light], [dark]) => Some(AutoTheme {
+                light: light.clone(),
+                dark: dark.clone(),
+            }),
+            _ => None,
+        }
+    }
+
     pub fn insert_user_themes(&self, themes: impl IntoIterator<Item = ThemeContent>) {
         self.insert_themes(themes.into_iter().map(|user_theme| {
             let mut theme_colors = match user_theme.appearance {
@@ -125,6 +222,9 @@ impl ThemeRegistry {
             };
             accent_colors.merge(&user_theme.style.accents);
 
+            let mut roles = SemanticRoles::default();
+            roles.merge(&user_theme.style.roles);
+
             let syntax_highlights = user_theme
                 .style
                 .syntax
@@ -168,6 +268,7 @@ impl ThemeRegistry {
                     system: SystemColors::default(),
                     window_background_appearance,
                     accents: accent_colors,
+                    roles,
                     colors: theme_colors,
                     status: status_colors,
                     player: player_colors,
@@ -179,14 +280,20 @@ impl ThemeRegistry {
 
     /// Removes the themes with the given names from the registry.
     pub fn remove_user_themes(&self, themes_to_remove: &[SharedString]) {
-        self.state
-            .write()
+        let mut state = self.state.write();
+        state
             .themes
-            .retain(|name, _| !themes_to_remove.contains(name))
+            .retain(|name, _| !themes_to_remove.contains(name));
+        state.families.retain(|_, theme_names| {
+            theme_names.retain(|name| !themes_to_remove.contains(name));
+            !theme_names.is_empty()
+        });
     }
 
     pub fn clear(&mut self) {
-        self.state.write().themes.clear();
+        let mut state = self.state.write();
+        state.themes.clear();
+        state.families.clear();
     }
 
     pub fn list_names(&self, _staff: bool) -> Vec<SharedString> {
@@ -195,11 +302,23 @@ impl ThemeRegistry {
         names
     }
 
+    /// Restricts the themes that are listed by [`Self::list`] to those with the
+    /// given names, or that belong to theme families with the given names.
+    ///
+    /// The other themes remain in the registry, and can still be retrieved by
+    /// name. All themes are listed if the allowlist is `None`.
+    pub fn set_allowlist(&self, allowlist: Option<impl IntoIterator<Item = SharedString>>) {
+        self.state.write().allowlist = allowlist.map(|names| names.into_iter().collect());
+    }
+
+    /// Returns the themes to offer for selection, which are those on the
+    /// allowlist if one is set.
     pub fn list(&self, _staff: bool) -> Vec<ThemeMeta> {
-        self.state
-            .read()
+        let state = self.state.read();
+        state
             .themes
             .values()
+            .filter(|theme| state.is_listed(&theme.name))
             .map(|theme| ThemeMeta {
                 name: theme.name.clone(),
                 appearance: theme.appearance(),
@@ -262,8 +381,22 @@ impl ThemeRegistry {
     }
 
     pub async fn read_user_theme(theme_path: &Path, fs: Arc<dyn Fs>) -> Result<ThemeFamilyContent> {
+        Self::read_user_theme_with_fallback_appearance(theme_path, fs, DEFAULT_FALLBACK_APPEARANCE)
+            .await
+    }
+
+    /// Reads a theme family, giving the given appearance to its themes that
+    /// don't specify one.
+    pub async fn read_user_theme_with_fallback_appearance(
+        theme_path: &Path,
+        fs: Arc<dyn Fs>,
+        fallback_appearance: AppearanceContent,
+    ) -> Result<ThemeFamilyContent> {
         let reader = fs.open_sync(theme_path).await?;
-        let theme_family: ThemeFamilyContent = serde_json_lenient::from_reader(reader)?;
+        let mut theme_family: serde_json::Value = serde_json_lenient::from_reader(reader)?;
+        apply_family_style(&mut theme_family);
+        apply_fallback_appearance(&mut theme_family, fallback_appearance, theme_path);
+        let theme_family: ThemeFamilyContent = serde_json::from_value(theme_family)?;
 
         for theme in &theme_family.themes {
             if theme
@@ -284,7 +417,21 @@ impl ThemeRegistry {
 
     /// Loads the user theme from the specified path and adds it to the registry.
     pub async fn load_user_theme(&self, theme_path: &Path, fs: Arc<dyn Fs>) -> Result<()> {
-        let theme = Self::read_user_theme(theme_path, fs).await?;
+        self.load_user_theme_with_fallback_appearance(theme_path, fs, DEFAULT_FALLBACK_APPEARANCE)
+            .await
+    }
+
+    /// Loads a theme family, giving the given appearance to its themes that
+    /// don't specify one.
+    pub async fn load_user_theme_with_fallback_appearance(
+        &self,
+        theme_path: &Path,
+        fs: Arc<dyn Fs>,
+        fallback_appearance: AppearanceContent,
+    ) -> Result<()> {
+        let theme =
+            Self::read_user_theme_with_fallback_appearance(theme_path, fs, fallback_appearance)
+                .await?;
 
         self.insert_user_theme_families([theme]);
 
@@ -292,8 +439,203 @@ impl ThemeRegistry {
     }
 }
 
+/// Gives the themes in the family that don't specify an appearance the given
+/// fallback appearance, logging a warning for each of them.
+fn apply_fallback_appearance(
+    theme_family: &mut serde_json::Value,
+    fallback_appearance: AppearanceContent,
+    theme_path: &Path,
+) {
+    let Some(themes) = theme_family
+        .get_mut("themes")
+        .and_then(serde_json::Value::as_array_mut)
+    else {
+        return;
+    };
+
+    for theme in themes {
+        let Some(theme) = theme.as_object_mut() else {
+            continue;
+        };
+        if theme.contains_key("appearance") {
+            continue;
+        }
+        log::warn!(
+            "theme {:?} in {theme_path:?} has no appearance, using {fallback_appearance:?}",
+            theme
+                .get("name")
+                .and_then(serde_json::Value::as_str)
+                .unwrap_or_default(),
+        );
+        if let Ok(appearance) = serde_json::to_value(fallback_appearance) {
+            theme.insert("appearance".into(), appearance);
+        }
+    }
+}
+
+/// Merges the family-level `style` block, if any, underneath the `style` of
+/// each theme in the family, so that variants only need to specify the
+/// properties that differ from the shared defaults.
+fn apply_family_style(theme_family: &mut serde_json::Value) {
+    let Some(family) = theme_family.as_object_mut() else {
+        return;
+    };
+    let Some(family_style) = family.remove("style") else {
+        return;
+    };
+    let Some(themes) = family
+        .get_mut("themes")
+        .and_then(serde_json::Value::as_array_mut)
+    else {
+        return;
+    };
+
+    for theme in themes {
+        let Some(theme) = theme.as_object_mut() else {
+            continue;
+        };
+        let mut style = family_style.clone();
+        if let Some(theme_style) = theme.remove("style") {
+            merge_json_value_into(theme_style, &mut style);
+        }
+        theme.insert("style".into(), style);
+    }
+}
+
 impl Default for ThemeRegistry {
     fn default() -> Self {
         Self::new(Box::new(()))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use fs::FakeFs;
+    use gpui::TestAppContext;
+    use serde_json::json;
+
+    use super::*;
+
+    #[gpui::test]
+    async fn test_read_user_theme_with_family_style(cx: &mut TestAppContext) {
+        let fs = FakeFs::new(cx.executor());
+        fs.insert_tree(
+            "/themes",
+            json!({
+                "family.json": json!({
+                    "name": "Family",
+                    "author": "Someone",
+                    "style": {
+                        "border": "#111111ff",
+                        "text": "#222222ff",
+                        "syntax": {
+                            "comment": { "color": "#333333ff" }
+                        }
+                    },
+                    "themes": [
+                        {
+                            "name": "Family Dark",
+                            "appearance": "dark",
+                            "style": {
+                                "text": "#eeeeeeff"
+                            }
+                        },
+                        {
+                            "name": "Family Light",
+                            "appearance": "light"
+                        }
+                    ]
+                })
+                .to_string(),
+            }),
+        )
+        .await;
+
+        let theme_family = ThemeRegistry::read_user_theme(Path::new("/themes/family.json"), fs)
+            .await
+            .unwrap();
+
+        let dark = &theme_family.themes[0].style;
+        assert_eq!(dark.colors.border.as_deref(), Some("#111111ff"));
+        assert_eq!(dark.colors.text.as_deref(), Some("#eeeeeeff"));
+        assert_eq!(dark.syntax["comment"].color.as_deref(), Some("#333333ff"));
+
+        let light = &theme_family.themes[1].style;
+        assert_eq!(light.colors.border.as_deref(), Some("#111111ff"));
+        assert_eq!(light.colors.text.as_deref(), Some("#222222ff"));
+    }
+
+    #[gpui::test]
+    async fn test_read_user_theme_without_appearance(cx: &mut TestAppContext) {
+        let fs = FakeFs::new(cx.executor());
+        fs.insert_tree(
+            "/themes",
+            json!({
+                "family.json": json!({
+                    "name": "Family",
+                    "author": "Someone",
+                    "themes": [
+                        { "name": "Family Unspecified", "style": {} },
+                        { "name": "Family Light", "appearance": "light", "style": {} }
+                    ]
+                })
+                .to_string(),
+            }),
+        )
+        .await;
+
+        let path = Path::new("/themes/family.json");
+        let theme_family = ThemeRegistry::read_user_theme(path, fs.clone())
+            .await
+            .unwrap();
+        assert_eq!(theme_family.themes[0].appearance, AppearanceContent::Dark);
+        assert_eq!(theme_family.themes[1].appearance, AppearanceContent::Light);
+
+        let theme_family = ThemeRegistry::read_user_theme_with_fallback_appearance(
+            path,
+            fs,
+            AppearanceContent::Light,
+        )
+        .await
+        .unwrap();
+        assert_eq!(theme_family.themes[0].appearance, AppearanceContent::Light);
+    }
+
+    #[test]
+    fn test_theme_allowlist() {
+        let registry = ThemeRegistry::new(Box::new(()));
+        registry.insert_user_theme_families(["Gruvbox", "Monokai"].map(|family_name| {
+            serde_json::from_value::<ThemeFamilyContent>(json!({
+                "name": family_name,
+                "author": "Someone",
+                "themes": [
+                    { "name": format!("{family_name} Dark"), "appearance": "dark", "style": {} },
+                    { "name": format!("{family_name} Light"), "appearance": "light", "style": {} }
+                ]
+            }))
+            .unwrap()
+        }));
+        let listed_names = || {
+            let mut names = registry
+                .list(false)
+                .into_iter()
+                .map(|theme| theme.name)
+                .collect::<Vec<_>>();
+            names.sort();
+            names
+        };
+
+        registry.set_allowlist(Some(["Gruvbox".into(), "One Dark".into()]));
+        assert_eq!(
+            listed_names(),
+            ["Gruvbox Dark", "Gruvbox Light", "One Dark"]
+        );
+        // The themes that aren't listed can still be loaded by name.
+        assert!(registry.get("Monokai Dark").is_ok());
+        assert!(registry.get("One Light").is_ok());
+        assert_eq!(registry.list_names(false).len(), 6);
+
+        registry.set_allowlist(None::<Vec<SharedString>>);
+        assert_eq!(listed_names().len(), 6);
+    }
+}
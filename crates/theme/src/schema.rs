@@ -26,7 +26,9 @@ pub(crate) fn try_parse_color(color: &str) -> Result<Hsla> {
     Ok(hsla)
 }
 
-#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize, JsonSchema)]
+#[derive(
+    Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy, Serialize, Deserialize, JsonSchema,
+)]
 #[serde(rename_all = "snake_case")]
 pub enum AppearanceContent {
     Light,
@@ -78,6 +80,10 @@ pub struct ThemeStyleContent {
     #[serde(default)]
     pub accents: Vec<AccentContent>,
 
+    /// The colors for named semantic roles, like `primary` or `danger`.
+    #[serde(default)]
+    pub roles: IndexMap<String, String>,
+
     #[serde(flatten, default)]
     pub colors: ThemeColorsContent,
 
@@ -105,6 +111,23 @@ impl ThemeStyleContent {
         self.status.status_colors_refinement()
     }
 
+    /// Returns a description of each accent and role color in the [`ThemeContent`]
+    /// that is not a valid color.
+    pub fn invalid_accent_and_role_colors(&self) -> Vec<String> {
+        let invalid_accents = self.accents.iter().enumerate().filter_map(|(ix, accent)| {
+            let color = accent.0.as_ref()?;
+            try_parse_color(color)
+                .is_err()
+                .then(|| format!("accents[{ix}] ({color:?})"))
+        });
+        let invalid_roles = self.roles.iter().filter_map(|(role, color)| {
+            try_parse_color(color)
+                .is_err()
+                .then(|| format!("roles.{role} ({color:?})"))
+        });
+        invalid_accents.chain(invalid_roles).collect()
+    }
+
     /// Returns the syntax style overrides in the [`ThemeContent`].
     pub fn syntax_overrides(&self) -> Vec<(String, HighlightStyle)> {
         self.syntax
@@ -4,7 +4,7 @@ use anyhow::Result;
 use derive_more::{Deref, DerefMut};
 use gpui::{
     px, AppContext, Font, FontFallbacks, FontFeatures, FontStyle, FontWeight, Global, Pixels,
-    Subscription, ViewContext, WindowContext,
+    SharedString, Subscription, ViewContext, WindowContext,
 };
 use refineable::Refineable;
 use schemars::{
@@ -91,6 +91,9 @@ pub struct ThemeSettings {
     pub active_theme: Arc<Theme>,
     pub theme_overrides: Option<ThemeStyleContent>,
     pub ui_density: UiDensity,
+    /// The names of the themes and theme families that are offered for
+    /// selection, if only some of them are.
+    pub theme_allowlist: Option<Vec<SharedString>>,
 }
 
 impl ThemeSettings {
@@ -286,6 +289,14 @@ pub struct ThemeSettingsContent {
     /// These values will override the ones on the current theme specified in `theme`.
     #[serde(rename = "experimental.theme_overrides", default)]
     pub theme_overrides: Option<ThemeStyleContent>,
+
+    /// The names of the themes, or of the theme families, to offer in the theme
+    /// selector. The other themes are still loaded, and can be used by setting
+    /// `theme` to their name.
+    ///
+    /// All themes are offered when this is not set.
+    #[serde(default)]
+    pub theme_allowlist: Option<Vec<String>>,
 }
 
 impl ThemeSettingsContent {
@@ -408,6 +419,7 @@ impl ThemeSettings {
                 .refine(&theme_overrides.status_colors_refinement());
             base_theme.styles.player.merge(&theme_overrides.players);
             base_theme.styles.accents.merge(&theme_overrides.accents);
+            base_theme.styles.roles.merge(&theme_overrides.roles);
             base_theme.styles.syntax =
                 SyntaxTheme::merge(base_theme.styles.syntax, theme_overrides.syntax_overrides());
 
@@ -544,6 +556,7 @@ impl settings::Settings for ThemeSettings {
                 .unwrap(),
             theme_overrides: None,
             ui_density: defaults.ui_density.unwrap_or(UiDensity::Default),
+            theme_allowlist: None,
         };
 
         for value in sources.user.into_iter().chain(sources.release_channel) {
@@ -590,6 +603,10 @@ impl settings::Settings for ThemeSettings {
             this.theme_overrides.clone_from(&value.theme_overrides);
             this.apply_theme_overrides();
 
+            if let Some(value) = &value.theme_allowlist {
+                this.theme_allowlist = Some(value.iter().cloned().map(Into::into).collect());
+            }
+
             merge(&mut this.ui_font_size, value.ui_font_size.map(Into::into));
             merge(
                 &mut this.buffer_font_size,
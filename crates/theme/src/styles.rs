@@ -1,6 +1,7 @@
 mod accents;
 mod colors;
 mod players;
+mod roles;
 mod status;
 mod syntax;
 mod system;
@@ -11,6 +12,7 @@ mod stories;
 pub use accents::*;
 pub use colors::*;
 pub use players::*;
+pub use roles::*;
 pub use status::*;
 pub use syntax::*;
 pub use system::*;
@@ -3,7 +3,8 @@ use refineable::Refineable;
 use std::sync::Arc;
 
 use crate::{
-    AccentColors, PlayerColors, StatusColors, StatusColorsRefinement, SyntaxTheme, SystemColors,
+    AccentColors, PlayerColors, SemanticRoles, StatusColors, StatusColorsRefinement, SyntaxTheme,
+    SystemColors,
 };
 
 #[derive(Refineable, Clone, Debug)]
@@ -249,6 +250,9 @@ pub struct ThemeStyles {
     /// Example: Player colors, rainbow brackets and indent guides, etc.
     pub accents: AccentColors,
 
+    /// The colors the theme assigns to named semantic roles.
+    pub roles: SemanticRoles,
+
     #[refineable]
     pub colors: ThemeColors,
 
@@ -0,0 +1,29 @@
+use gpui::{Hsla, SharedString};
+use indexmap::IndexMap;
+
+use crate::try_parse_color;
+
+/// A set of colors keyed by the semantic role they fill, like `primary` or `danger`.
+///
+/// Unlike the fixed set of [`ThemeColors`](crate::ThemeColors), the roles are
+/// chosen by the theme author.
+#[derive(Clone, Debug, Default)]
+pub struct SemanticRoles(pub IndexMap<SharedString, Hsla>);
+
+impl SemanticRoles {
+    /// Returns the color for the given role, if the theme defines one.
+    pub fn get(&self, role: &str) -> Option<Hsla> {
+        self.0.get(role).copied()
+    }
+
+    /// Merges the given role colors into this [`SemanticRoles`] instance.
+    ///
+    /// Roles whose color fails to parse are ignored.
+    pub fn merge(&mut self, roles: &IndexMap<String, String>) {
+        for (role, color) in roles {
+            if let Ok(color) = try_parse_color(color) {
+                self.0.insert(role.clone().into(), color);
+            }
+        }
+    }
+}
@@ -87,12 +87,20 @@ pub fn init(themes_to_load: LoadThemes, cx: &mut AppContext) {
     FontFamilyCache::init_global(cx);
 
     let mut prev_buffer_font_size = ThemeSettings::get_global(cx).buffer_font_size;
+    let mut prev_theme_allowlist = ThemeSettings::get_global(cx).theme_allowlist.clone();
+    ThemeRegistry::global(cx).set_allowlist(prev_theme_allowlist.clone());
     cx.observe_global::<SettingsStore>(move |cx| {
         let buffer_font_size = ThemeSettings::get_global(cx).buffer_font_size;
         if buffer_font_size != prev_buffer_font_size {
             prev_buffer_font_size = buffer_font_size;
             reset_buffer_font_size(cx);
         }
+
+        let theme_allowlist = &ThemeSettings::get_global(cx).theme_allowlist;
+        if *theme_allowlist != prev_theme_allowlist {
+            prev_theme_allowlist.clone_from(theme_allowlist);
+            ThemeRegistry::global(cx).set_allowlist(prev_theme_allowlist.clone());
+        }
     })
     .detach();
 }
@@ -138,6 +146,12 @@ impl Theme {
         &self.styles.accents
     }
 
+    /// Returns the [`SemanticRoles`] for the theme.
+    #[inline(always)]
+    pub fn roles(&self) -> &SemanticRoles {
+        &self.styles.roles
+    }
+
     /// Returns the [`PlayerColors`] for the theme.
     #[inline(always)]
     pub fn players(&self) -> &PlayerColors {